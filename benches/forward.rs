@@ -0,0 +1,63 @@
+//! Compares the forward pass of training, which records the output of
+//! every layer once, with recording the inputs of every layer as a copy
+//! of the previous output as it used to. Also times a few epochs of
+//! `SGDTrainer`. Run with `cargo bench --bench forward`.
+extern crate scarecrow;
+
+use scarecrow::layers::{DenseLayer, HyperbolicLayer};
+use scarecrow::random::SeededRng;
+use scarecrow::sgd::{forward, SGDTrainer};
+use scarecrow::traits::{SupervisedTrainer, WeightedLayer};
+use scarecrow::utils::normal_vector;
+
+use std::collections::LinkedList;
+use std::time::Instant;
+
+const SIZE: usize = 256;
+const LAYERS: usize = 6;
+const SAMPLES: usize = 2000;
+const ROUNDS: u32 = 5;
+
+/// The inputs and output of every layer, each in its own vector.
+fn cloning_forward(layers: &LinkedList<Box<dyn WeightedLayer>>, x: &[f32]) -> Vec<(Vec<f32>, Vec<f32>)> {
+    let mut outputs: Vec<(Vec<f32>, Vec<f32>)> = Vec::new();
+    for l in layers.iter() {
+        let inputs = outputs.last().map_or(x.to_vec(), |o| o.1.clone());
+        let output = l.output(&inputs);
+        outputs.push((inputs, output));
+    }
+    outputs
+}
+
+fn time<F: FnMut() -> f32>(name: &str, mut f: F) {
+    let start = Instant::now();
+    let mut checksum = 0.0;
+    for _ in 0..ROUNDS {
+        checksum += f();
+    }
+    println!("{:>16}: {:?} per round ({})", name, start.elapsed() / ROUNDS, checksum);
+}
+
+fn main() {
+    let mut rng = SeededRng::new(1);
+    let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+    for _ in 0..LAYERS {
+        // Wide elementwise layers are where the copies cost the most
+        // compared to the work of the layer
+        layers.push_back(Box::new(DenseLayer::random_with(SIZE, SIZE, &mut rng)));
+        layers.push_back(Box::new(HyperbolicLayer { size: SIZE }));
+    }
+    let inputs = normal_vector(SAMPLES * SIZE, &mut rng);
+    let targets = normal_vector(SAMPLES * SIZE, &mut rng);
+
+    println!("{} samples through {} dense and tanh layers of {}:", SAMPLES, LAYERS, SIZE);
+    time("cloning forward", || inputs.chunks(SIZE).map(|x| cloning_forward(&layers, x).last().unwrap().1[0]).sum());
+    time("forward", || inputs.chunks(SIZE).map(|x| forward(&layers, x).last()[0]).sum());
+
+    let mut trainer = SGDTrainer::new(1, 1e-4);
+    trainer.batch_size = Some(100);
+    time("training epoch", || {
+        trainer.train(&mut layers, &inputs, &targets);
+        layers.front().and_then(|l| l.weights()).map_or(0.0, |w| w[0])
+    });
+}
@@ -0,0 +1,56 @@
+//! Compares the two weight layouts of `DenseLayer` in the forward
+//! pass, and in the backward pass with its delta signal and weight
+//! gradient, for a few shapes. Run with `cargo bench --bench layout`,
+//! and with `--features matrixmultiply` to see how that changes.
+extern crate scarecrow;
+
+use scarecrow::layers::{DenseLayer, WeightLayout};
+use scarecrow::random::SeededRng;
+use scarecrow::sgd::weight_gradient;
+use scarecrow::traits::Layer;
+use scarecrow::utils::normal_vector;
+
+use std::time::{Duration, Instant};
+
+const SAMPLES: usize = 200;
+
+fn time<F: FnMut() -> f32>(mut f: F) -> Duration {
+    let start = Instant::now();
+    let mut checksum = 0.0;
+    for _ in 0..SAMPLES {
+        checksum += f();
+    }
+    assert!(checksum.is_finite());
+    start.elapsed() / SAMPLES as u32
+}
+
+fn main() {
+    println!("Time per sample of the neuron major and input major layouts:");
+    println!("{:>12} {:>13} {:>13} {:>13} {:>13}",
+             "shape",
+             "fwd neurons",
+             "fwd inputs",
+             "bwd neurons",
+             "bwd inputs");
+    for &(inputs, neurons) in &[(1024, 1024), (64, 4096), (4096, 64), (784, 10)] {
+        let mut rng = SeededRng::new(1);
+        let x = normal_vector(inputs, &mut rng);
+        let delta = normal_vector(neurons, &mut rng);
+        let mut forward = Vec::new();
+        let mut backward = Vec::new();
+        for layout in &[WeightLayout::NeuronMajor, WeightLayout::InputMajor] {
+            let l = DenseLayer::random_with(inputs, neurons, &mut SeededRng::new(2)).with_layout(*layout);
+            forward.push(time(|| l.output(&x)[0]));
+            backward.push(time(|| {
+                let d = l.delta_from_inputs(&delta, &x).unwrap();
+                weight_gradient(&l, &x, &delta)[0] + d[0]
+            }));
+        }
+        println!("{:>12} {:>13?} {:>13?} {:>13?} {:>13?}",
+                 format!("{}x{}", inputs, neurons),
+                 forward[0],
+                 forward[1],
+                 backward[0],
+                 backward[1]);
+    }
+}
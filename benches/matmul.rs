@@ -0,0 +1,46 @@
+//! Times the forward pass and gradient of a large dense layer. Run
+//! with `cargo bench --bench matmul`, and again with
+//! `--features matrixmultiply` to compare the two matrix products.
+extern crate scarecrow;
+
+use scarecrow::layers::DenseLayer;
+use scarecrow::random::SeededRng;
+use scarecrow::utils::{normal_vector, Matrix};
+use scarecrow::traits::Layer;
+
+use std::time::Instant;
+
+const ROUNDS: usize = 20;
+
+fn time<F: Fn() -> f32>(name: &str, f: F) {
+    let start = Instant::now();
+    let mut checksum = 0.0;
+    for _ in 0..ROUNDS {
+        checksum += f();
+    }
+    let elapsed = start.elapsed();
+    println!("{:>16}: {:?} per round ({})", name, elapsed / ROUNDS as u32, checksum);
+}
+
+fn main() {
+    let backend = if cfg!(feature = "matrixmultiply") {
+        "matrixmultiply"
+    } else {
+        "pure Rust"
+    };
+    println!("Matrix products using {}", backend);
+
+    let mut rng = SeededRng::new(1);
+    for size in &[64, 256, 1024] {
+        let size = *size;
+        let dense = DenseLayer::random_with(size, size, &mut rng);
+        let x = normal_vector(size, &mut rng);
+        let batch = normal_vector(size * 64, &mut rng);
+        let weights = Matrix::view(&dense.weights, size, size);
+        let inputs = Matrix::view(&batch, 64, size);
+
+        println!("{}x{}:", size, size);
+        time("output", || dense.output(&x)[0]);
+        time("batch of 64", || inputs.matmul(&weights.transpose()).get(0, 0));
+    }
+}
@@ -0,0 +1,45 @@
+//! Compares batch inference on a single thread with
+//! `predict_batch_parallel`. Run with `cargo bench --bench parallel`.
+extern crate scarecrow;
+
+use scarecrow::layers::{DenseLayer, HyperbolicLayer};
+use scarecrow::network::{predict_batch, predict_batch_parallel};
+use scarecrow::random::SeededRng;
+use scarecrow::traits::WeightedLayer;
+use scarecrow::utils::normal_vector;
+
+use std::collections::LinkedList;
+use std::time::Instant;
+
+const SIZE: usize = 256;
+const SAMPLES: usize = 2000;
+const ROUNDS: u32 = 5;
+
+fn time<F: Fn() -> Vec<f32>>(name: &str, f: F) {
+    let start = Instant::now();
+    let mut checksum = 0.0;
+    for _ in 0..ROUNDS {
+        checksum += f()[0];
+    }
+    println!("{:>12}: {:?} per batch ({})", name, start.elapsed() / ROUNDS, checksum);
+}
+
+fn main() {
+    let mut rng = SeededRng::new(1);
+    let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+    layers.push_back(Box::new(DenseLayer::random_with(SIZE, SIZE, &mut rng)));
+    layers.push_back(Box::new(HyperbolicLayer { size: SIZE }));
+    layers.push_back(Box::new(DenseLayer::random_with(SIZE, 10, &mut rng)));
+    let inputs = normal_vector(SAMPLES * SIZE, &mut rng);
+
+    println!("{} samples through a {}-{}-10 network:", SAMPLES, SIZE, SIZE);
+    time("1 thread", || predict_batch(&layers, &inputs));
+    for threads in &[2, 4, 0] {
+        let name = if *threads == 0 {
+            "all cpus".to_string()
+        } else {
+            format!("{} threads", threads)
+        };
+        time(&name, || predict_batch_parallel(&layers, &inputs, *threads));
+    }
+}
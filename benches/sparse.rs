@@ -0,0 +1,46 @@
+//! Compares inference speed of a pruned dense layer in dense and in
+//! sparse (CSR) form. Run with `cargo bench --bench sparse`.
+extern crate scarecrow;
+
+use scarecrow::layers::{DenseLayer, SparseDenseLayer};
+use scarecrow::random::SeededRng;
+use scarecrow::traits::Layer;
+use scarecrow::utils::normal_vector;
+
+use std::time::Instant;
+
+const SIZE: usize = 512;
+const ROUNDS: usize = 200;
+
+fn time<F: Fn() -> Vec<f32>>(name: &str, f: F) {
+    let start = Instant::now();
+    let mut checksum = 0.0;
+    for _ in 0..ROUNDS {
+        checksum += f()[0];
+    }
+    let elapsed = start.elapsed();
+    println!("{:>8}: {:?} per output ({})",
+             name,
+             elapsed / ROUNDS as u32,
+             checksum);
+}
+
+fn main() {
+    for sparsity in &[0.0f32, 0.5, 0.9, 0.99] {
+        let mut rng = SeededRng::new(1);
+        let mut dense = DenseLayer::random_with(SIZE, SIZE, &mut rng);
+        // Keep every n-th weight to get the requested sparsity
+        let keep_every = (1.0 / (1.0 - sparsity)).round() as usize;
+        for (i, w) in dense.weights.iter_mut().enumerate() {
+            if i % keep_every != 0 {
+                *w = 0.0;
+            }
+        }
+        let sparse = SparseDenseLayer::from_dense(&dense);
+        let x = normal_vector(SIZE, &mut rng);
+
+        println!("sparsity {}", sparsity);
+        time("dense", || dense.output(&x));
+        time("sparse", || sparse.output(&x));
+    }
+}
@@ -0,0 +1,38 @@
+//! The 8-3-8 encoder: a network learns to reproduce eight one-hot
+//! patterns through a bottleneck of three units, which forces it to
+//! invent a compact code for them, often close to three bits.
+//!
+//! Run with `cargo run --example autoencoder`.
+extern crate scarecrow;
+
+use scarecrow::data::Dataset;
+use scarecrow::network;
+use scarecrow::projection;
+use scarecrow::serialize::LayerRegistry;
+use scarecrow::sgd::SGDTrainer;
+use scarecrow::traits::SupervisedTrainer;
+use scarecrow::utils::argmax;
+
+fn main() {
+    let patterns: Vec<f32> = (0..8 * 8).map(|i| if i % 9 == 0 { 1.0 } else { 0.0 }).collect();
+    // The targets are the inputs themselves
+    let data = Dataset::new(patterns.clone(), patterns, 8, 8);
+
+    let mut layers = LayerRegistry::new()
+        .parse_spec("dense inputs=8 neurons=3; sigmoid size=3  # the encoder
+                     dense inputs=3 neurons=8; sigmoid size=8  # the decoder")
+        .expect("the spec is valid");
+
+    let trainer = SGDTrainer::new(20000, 0.5).stop_when_loss_below(0.01);
+    let history = trainer.fit(&mut layers, &data, None);
+    let last = history.last().expect("at least one epoch");
+    println!("Trained for {} epochs to a loss of {:.4}", last.epoch + 1, last.train.loss);
+
+    // The output of the encoder, the second layer, for every pattern
+    let codes = projection::hidden_activations(&layers, &data.inputs, 1);
+    for (i, (x, code)) in data.inputs.chunks(8).zip(codes.chunks(3)).enumerate() {
+        let bits: Vec<u8> = code.iter().map(|c| (*c > 0.5) as u8).collect();
+        let decoded = argmax(&network::output(&layers, x));
+        println!("Pattern {}: code {:.2?}, roughly {:?}, decoded as pattern {}", i, code, bits, decoded);
+    }
+}
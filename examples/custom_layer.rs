@@ -0,0 +1,92 @@
+//! A layer defined outside of the crate, which squares its inputs,
+//! checked against finite differences, trained end to end and saved.
+//!
+//! Two dense neurons followed by squares can multiply two numbers, as
+//! `a b = ((a + b)^2 - (a - b)^2) / 4`, which the network learns here.
+//!
+//! Run with `cargo run --example custom_layer`.
+extern crate rand;
+extern crate scarecrow;
+
+use rand::Rng;
+use scarecrow::layers::DenseLayer;
+use scarecrow::network;
+use scarecrow::random::SeededRng;
+use scarecrow::serialize::{self, LayerRegistry, LayerSpec};
+use scarecrow::loss::Reduction;
+use scarecrow::sgd::SGDTrainer;
+use scarecrow::testing::assert_layer_contract;
+use scarecrow::traits::{Layer, SupervisedTrainer, WeightedLayer};
+
+use std::collections::LinkedList;
+
+/// Squares every input.
+struct SquareLayer {
+    size: usize,
+}
+
+impl Layer for SquareLayer {
+    fn input_count(&self) -> usize {
+        self.size
+    }
+
+    fn output_count(&self) -> usize {
+        self.size
+    }
+
+    fn output(&self, inputs: &[f32]) -> Vec<f32> {
+        inputs.iter().map(|x| x * x).collect()
+    }
+
+    // The derivative of x^2 is 2x, which is simplest in terms of the
+    // inputs. Every output depends on one input only, so nothing is
+    // summed.
+    fn delta_from_inputs(&self, delta: &[f32], inputs: &[f32]) -> Option<Vec<f32>> {
+        Some(delta.iter().zip(inputs).map(|(d, x)| d * 2.0 * x).collect())
+    }
+
+    fn spec(&self) -> Option<LayerSpec> {
+        Some(LayerSpec::new("square").with("size", self.size))
+    }
+}
+
+// Without weights, the defaults of every method apply
+impl WeightedLayer for SquareLayer {}
+
+fn mean_squared_error(layers: &LinkedList<Box<dyn WeightedLayer>>, inputs: &[f32], targets: &[f32]) -> f32 {
+    let outputs = network::predict_batch(layers, inputs);
+    outputs.iter().zip(targets).map(|(y, t)| (y - t) * (y - t)).sum::<f32>() / targets.len() as f32
+}
+
+fn main() {
+    // Panics with every broken contract, such as a wrong derivative
+    assert_layer_contract(&mut SquareLayer { size: 3 });
+
+    let mut rng = SeededRng::new(2);
+    let inputs: Vec<f32> = (0..2 * 200).map(|_| rng.gen_range(-1.0, 1.0)).collect();
+    let targets: Vec<f32> = inputs.chunks(2).map(|x| x[0] * x[1]).collect();
+
+    let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+    layers.push_back(Box::new(DenseLayer::random_with(2, 2, &mut rng)));
+    layers.push_back(Box::new(SquareLayer { size: 2 }));
+    layers.push_back(Box::new(DenseLayer::random_with(2, 1, &mut rng)));
+
+    println!("Error before training: {}", mean_squared_error(&layers, &inputs, &targets));
+    let mut trainer = SGDTrainer::new(300, 0.05);
+    trainer.batch_size = Some(10);
+    trainer.reduction = Reduction::Mean;
+    trainer.train(&mut layers, &inputs, &targets);
+    println!("Error after training: {}", mean_squared_error(&layers, &inputs, &targets));
+    for x in [[0.5, 0.5], [-0.8, 0.25], [0.9, -0.9]].iter() {
+        println!("{} * {} = {}", x[0], x[1], network::output(&layers, x)[0]);
+    }
+
+    // The registry must know the layer to load it again
+    let mut registry = LayerRegistry::new();
+    registry.register("square", |s| Ok(Box::new(SquareLayer { size: s.parse("size")? })));
+    let mut saved = Vec::new();
+    serialize::save(&layers, &mut saved).expect("saving to memory cannot fail");
+    println!("\n{}", String::from_utf8_lossy(&saved));
+    let loaded = registry.load(&saved[..]).expect("the saved network loads");
+    assert_eq!(network::predict_batch(&loaded, &inputs), network::predict_batch(&layers, &inputs));
+}
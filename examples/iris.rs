@@ -0,0 +1,65 @@
+//! Classifies the species of iris flowers from their measurements with
+//! a softmax classifier, the canonical multiclass problem.
+//!
+//! Run with `cargo run --example iris`.
+extern crate scarecrow;
+
+use scarecrow::data::iris::{self, SPECIES};
+use scarecrow::data::Dataset;
+use scarecrow::loss::{Reduction, SoftmaxCrossEntropy};
+use scarecrow::serialize::LayerRegistry;
+use scarecrow::sgd::SGDTrainer;
+use scarecrow::traits::SupervisedTrainer;
+use scarecrow::utils::{argmax, softmax};
+
+/// The samples of the data whose index satisfies `keep`.
+fn select<F: Fn(usize) -> bool>(data: &Dataset, keep: F) -> Dataset {
+    let (mut inputs, mut targets) = (Vec::new(), Vec::new());
+    for (_, (x, t)) in data.iter().enumerate().filter(|(i, _)| keep(*i)) {
+        inputs.extend_from_slice(x);
+        targets.extend_from_slice(t);
+    }
+    Dataset::new(inputs, targets, data.input_count, data.output_count)
+}
+
+fn main() {
+    // Every fifth flower is held out, ten of each species
+    let data = iris::iris();
+    let train = select(&data, |i| i % 5 != 0);
+    let test = select(&data, |i| i % 5 == 0);
+
+    // A single dense layer outputs a logit per species, which the loss
+    // turns into probabilities with a softmax
+    let mut layers = LayerRegistry::new().parse_spec("dense inputs=4 neurons=3").expect("the spec is valid");
+    let mut trainer = SGDTrainer::new(500, 0.05);
+    trainer.loss = Box::new(SoftmaxCrossEntropy);
+    trainer.batch_size = Some(10);
+    trainer.reduction = Reduction::Mean;
+    trainer.shuffle = true;
+    let history = trainer.fit(&mut layers, &train, Some(&test));
+    for epoch in history.epochs.iter().filter(|e| (e.epoch + 1) % 100 == 0) {
+        let test = epoch.validation.as_ref().expect("test data was given");
+        println!("Epoch {:3}: training loss {:.4}, training accuracy {:.3}, test accuracy {:.3}",
+                 epoch.epoch + 1,
+                 epoch.train.loss,
+                 epoch.train.accuracy,
+                 test.accuracy);
+    }
+
+    // Rows are the true species, columns the predicted ones
+    let mut confusion = [[0; 3]; 3];
+    for (i, y) in trainer.predict(&layers, &test.inputs).chunks(3).enumerate() {
+        confusion[test.class(i)][argmax(y)] += 1;
+    }
+    println!("\n{:>12} {:>10} {:>10} {:>10}", "", SPECIES[0], SPECIES[1], SPECIES[2]);
+    for (species, row) in SPECIES.iter().zip(&confusion) {
+        println!("{:>12} {:>10} {:>10} {:>10}", species, row[0], row[1], row[2]);
+    }
+
+    let flower = [6.0, 2.9, 4.5, 1.5];
+    let probabilities = softmax(&trainer.predict(&layers, &flower));
+    println!("\nA flower measuring {:?} cm is", flower);
+    for (species, p) in SPECIES.iter().zip(&probabilities) {
+        println!("  {} with probability {:.3}", species, p);
+    }
+}
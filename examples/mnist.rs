@@ -0,0 +1,84 @@
+//! Classifies handwritten digits from a subset of MNIST, and draws the
+//! patterns the hidden units have learned to look for.
+//!
+//! The data is not included. Download and unzip the four files of
+//! http://yann.lecun.com/exdb/mnist/ into a directory and run
+//! `cargo run --release --example mnist -- <directory>`. The receptive
+//! fields of the hidden units are written to `mnist_fields.ppm` in the
+//! working directory.
+extern crate scarecrow;
+
+use scarecrow::data::Dataset;
+use scarecrow::image;
+use scarecrow::loss::{Reduction, SoftmaxCrossEntropy};
+use scarecrow::serialize::LayerRegistry;
+use scarecrow::sgd::SGDTrainer;
+use scarecrow::traits::SupervisedTrainer;
+
+use std::env;
+use std::fs::{self, File};
+use std::path::Path;
+use std::process;
+
+/// Reads at most `limit` images and their labels from the IDX files
+/// with the given prefix, as pixels in [0, 1] and one-hot targets.
+fn load(dir: &Path, prefix: &str, limit: usize) -> Result<Dataset, String> {
+    let read = |name: String, magic: u32| {
+        let bytes = fs::read(dir.join(&name)).map_err(|e| format!("cannot read {}: {}", name, e))?;
+        if bytes.get(..4) != Some(&magic.to_be_bytes()[..]) {
+            return Err(format!("{} is not an IDX file of the expected kind", name));
+        }
+        Ok(bytes)
+    };
+    let images = read(format!("{}-images-idx3-ubyte", prefix), 0x803)?;
+    let labels = read(format!("{}-labels-idx1-ubyte", prefix), 0x801)?;
+    let pixels = images.get(16..).unwrap_or(&[]);
+    let labels = labels.get(8..).unwrap_or(&[]);
+
+    let count = labels.len().min(pixels.len() / 784).min(limit);
+    let inputs = pixels[..784 * count].iter().map(|p| *p as f32 / 255.0).collect();
+    let targets = labels[..count].iter().flat_map(|l| (0..10).map(move |c| (c == *l as usize) as usize as f32)).collect();
+    Ok(Dataset::new(inputs, targets, 784, 10))
+}
+
+fn main() {
+    let dir = match env::args().nth(1) {
+        Some(dir) => dir,
+        None => {
+            eprintln!("usage: cargo run --release --example mnist -- <directory with the MNIST files>");
+            process::exit(2);
+        }
+    };
+    let (train, test) = match (load(Path::new(&dir), "train", 5000), load(Path::new(&dir), "t10k", 1000)) {
+        (Ok(train), Ok(test)) => (train, test),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    println!("Training on {} digits, testing on {}", train.len(), test.len());
+
+    // The last layer outputs logits, which the loss turns into
+    // probabilities of the ten digits
+    let mut layers = LayerRegistry::new()
+        .parse_spec("dense inputs=784 neurons=36; tanh size=36; dense inputs=36 neurons=10")
+        .expect("the spec is valid");
+
+    let mut trainer = SGDTrainer::new(10, 0.05);
+    trainer.loss = Box::new(SoftmaxCrossEntropy);
+    trainer.batch_size = Some(20);
+    trainer.reduction = Reduction::Mean;
+    trainer.shuffle = true;
+    let history = trainer.fit(&mut layers, &train, Some(&test));
+    for epoch in &history.epochs {
+        let test = epoch.validation.as_ref().expect("test data was given");
+        println!("Epoch {:2}: training loss {:.4}, test accuracy {:.3}",
+                 epoch.epoch + 1,
+                 epoch.train.loss,
+                 test.accuracy);
+    }
+
+    let fields = image::receptive_fields(&layers, 28, 28).expect("the first layer is dense");
+    let mut out = File::create("mnist_fields.ppm").expect("the image can be created");
+    fields.scaled(4).write_ppm(&mut out).expect("the image can be written");
+}
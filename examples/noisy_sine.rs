@@ -0,0 +1,52 @@
+//! Regression on noisy measurements of `sin(x)` with a few gross
+//! outliers, comparing the squared error with the absolute error and
+//! the Huber loss, which outliers pull far less.
+//!
+//! Run with `cargo run --example noisy_sine`.
+extern crate scarecrow;
+
+use scarecrow::loss::{AbsoluteError, HuberLoss, Reduction, SquaredError};
+use scarecrow::metrics;
+use scarecrow::network;
+use scarecrow::random::SeededRng;
+use scarecrow::serialize::LayerRegistry;
+use scarecrow::sgd::SGDTrainer;
+use scarecrow::testing;
+use scarecrow::traits::{DifferentiableLossFunction, SupervisedTrainer};
+
+fn main() {
+    let mut rng = SeededRng::new(1);
+    let mut train = testing::noisy_sine(100, 0.1, &mut rng);
+    // Every tenth measurement is off by far more than the noise
+    for t in train.targets.iter_mut().step_by(10) {
+        *t += 3.0;
+    }
+    // The network is judged against the true curve
+    let truth = testing::sine(200);
+
+    // Nothing follows the last dense layer, so its output is linear and
+    // can take any value, as a regression needs
+    let registry = LayerRegistry::new();
+    let spec = "dense inputs=1 neurons=16; tanh size=16; dense inputs=16 neurons=1";
+    let start = network::get_weights(&registry.parse_spec(spec).expect("the spec is valid"));
+
+    let losses: Vec<(&str, Box<dyn DifferentiableLossFunction>)> = vec![("squared error", Box::new(SquaredError)),
+                                                                        ("absolute error", Box::new(AbsoluteError)),
+                                                                        ("Huber loss", Box::new(HuberLoss::new(0.2)))];
+    println!("{:>15} {:>8} {:>8} {:>8}", "trained with", "MAE", "RMSE", "R²");
+    for (name, loss) in losses {
+        // Every loss starts from the same weights
+        let mut layers = registry.parse_spec(spec).expect("the spec is valid");
+        network::set_weights(&mut layers, &start);
+
+        let mut trainer = SGDTrainer::new(1500, 0.02);
+        trainer.loss = loss;
+        trainer.batch_size = Some(10);
+        trainer.reduction = Reduction::Mean;
+        trainer.shuffle = true;
+        trainer.train(&mut layers, &train.inputs, &train.targets);
+
+        let m = metrics::evaluate(&layers, &SquaredError, &truth);
+        println!("{:>15} {:8.4} {:8.4} {:8.4}", name, m.mean_absolute_error, m.root_mean_squared_error, m.r_squared);
+    }
+}
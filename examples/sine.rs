@@ -0,0 +1,47 @@
+//! Fits `sin(x)` on [-π, π], a regression with a single linear output,
+//! and checks the fit between the training points.
+//!
+//! Run with `cargo run --example sine`.
+extern crate scarecrow;
+
+use scarecrow::data::Dataset;
+use scarecrow::loss::Reduction;
+use scarecrow::serialize::LayerRegistry;
+use scarecrow::sgd::SGDTrainer;
+use scarecrow::testing;
+use scarecrow::traits::SupervisedTrainer;
+
+use std::f32::consts::PI;
+
+fn main() {
+    let train = testing::sine(64);
+    // Points halfway between the training points
+    let inputs: Vec<f32> = train.inputs.windows(2).map(|w| (w[0] + w[1]) / 2.0).collect();
+    let targets = inputs.iter().map(|x| x.sin()).collect();
+    let validation = Dataset::new(inputs, targets, 1, 1);
+
+    // No activation after the last layer, so the output can take any value
+    let mut layers = LayerRegistry::new()
+        .parse_spec("dense inputs=1 neurons=16; tanh size=16; dense inputs=16 neurons=1")
+        .expect("the spec is valid");
+
+    let mut trainer = SGDTrainer::new(2000, 0.02);
+    trainer.batch_size = Some(8);
+    trainer.reduction = Reduction::Mean;
+    trainer.shuffle = true;
+    let history = trainer.fit(&mut layers, &train, Some(&validation));
+    for epoch in history.epochs.iter().filter(|e| (e.epoch + 1) % 250 == 0) {
+        let validation = epoch.validation.as_ref().expect("validation data was given");
+        println!("Epoch {:4}: training loss {:.6}, validation loss {:.6}",
+                 epoch.epoch + 1,
+                 epoch.train.loss,
+                 validation.loss);
+    }
+
+    let metrics = trainer.evaluate(&layers, &validation);
+    println!("Mean absolute error between the training points: {:.4}", metrics.mean_absolute_error);
+    for i in 0..=8 {
+        let x = -PI + 2.0 * PI * i as f32 / 8.0;
+        println!("sin({:6.3}) = {:6.3}, network: {:6.3}", x, x.sin(), trainer.predict(&layers, &[x])[0]);
+    }
+}
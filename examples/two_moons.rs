@@ -0,0 +1,53 @@
+//! Classifies points on two interleaved half circles, which no straight
+//! line separates, and draws the learned decision boundary.
+//!
+//! Run with `cargo run --example two_moons`. The boundary is also
+//! written as an image to `two_moons.ppm` in the working directory.
+extern crate scarecrow;
+
+use scarecrow::boundary;
+use scarecrow::image;
+use scarecrow::loss::Reduction;
+use scarecrow::random::SeededRng;
+use scarecrow::serialize::LayerRegistry;
+use scarecrow::sgd::SGDTrainer;
+use scarecrow::testing;
+use scarecrow::traits::SupervisedTrainer;
+
+use std::fs::File;
+
+fn main() {
+    let mut rng = SeededRng::new(1);
+    let train = testing::two_moons(200, 0.1, &mut rng);
+    let test = testing::two_moons(100, 0.1, &mut rng);
+
+    let mut layers = LayerRegistry::new()
+        .parse_spec("dense inputs=2 neurons=16; tanh size=16
+                     dense inputs=16 neurons=1; sigmoid size=1")
+        .expect("the spec is valid");
+
+    let mut trainer = SGDTrainer::new(500, 0.5);
+    trainer.batch_size = Some(10);
+    trainer.reduction = Reduction::Mean;
+    trainer.shuffle = true;
+    let history = trainer.fit(&mut layers, &train, Some(&test));
+    for epoch in history.epochs.iter().filter(|e| (e.epoch + 1) % 100 == 0) {
+        let test = epoch.validation.as_ref().expect("test data was given");
+        println!("Epoch {:3}: training accuracy {:.3}, test accuracy {:.3}",
+                 epoch.epoch + 1,
+                 epoch.train.accuracy,
+                 test.accuracy);
+    }
+
+    // The class of the network over the plane, with the upper moon as
+    // `.` and the lower one as `#`
+    let grid = boundary::evaluate_grid(&layers, (-1.5, 2.5), (-1.0, 1.5), 60, 20);
+    for row in 0..grid.height {
+        let line: String = (0..grid.width).map(|col| if grid.output(col, row)[0] < 0.5 { '.' } else { '#' }).collect();
+        println!("{}", line);
+    }
+
+    let fine = boundary::evaluate_grid(&layers, (-1.5, 2.5), (-1.0, 1.5), 320, 200);
+    let mut out = File::create("two_moons.ppm").expect("the image can be created");
+    image::render_grid(&fine, 0).write_ppm(&mut out).expect("the image can be written");
+}
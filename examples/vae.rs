@@ -0,0 +1,71 @@
+//! A variational autoencoder of points on a noisy circle. The encoder
+//! maps a point to the mean and log variance of a two dimensional code,
+//! `ReparameterizeLayer` draws the code from them while training, and
+//! the decoder maps it back to a point. The KL term pulls the codes
+//! towards the standard normal distribution, so that the decoder also
+//! turns codes drawn from it into new points near the circle.
+//!
+//! Run with `cargo run --release --example vae`.
+extern crate rand;
+extern crate scarecrow;
+
+use rand::Rng;
+use scarecrow::data::Dataset;
+use scarecrow::loss::{gaussian_kl, Reduction};
+use scarecrow::network;
+use scarecrow::projection;
+use scarecrow::random::SeededRng;
+use scarecrow::serialize::LayerRegistry;
+use scarecrow::sgd::SGDTrainer;
+use scarecrow::traits::SupervisedTrainer;
+use scarecrow::utils::normal_vector;
+
+use std::f32::consts::PI;
+
+/// The weight of the KL term relative to the reconstruction loss.
+const KL_WEIGHT: f32 = 0.05;
+
+fn main() {
+    let mut rng = SeededRng::new(1);
+    let mut points = Vec::new();
+    for _ in 0..500 {
+        let angle = 2.0 * PI * rng.gen::<f32>();
+        let noise = normal_vector(2, &mut rng);
+        points.push(angle.cos() + 0.05 * noise[0]);
+        points.push(angle.sin() + 0.05 * noise[1]);
+    }
+    // The targets are the inputs themselves
+    let data = Dataset::new(points.clone(), points, 2, 2);
+
+    let spec = format!("dense inputs=2 neurons=32; tanh size=32; dense inputs=32 neurons=4  # the encoder
+                        reparameterize size=2 kl_weight={}
+                        dense inputs=2 neurons=32; tanh size=32; dense inputs=32 neurons=2  # the decoder",
+                       KL_WEIGHT);
+    let mut layers = LayerRegistry::new().parse_spec(&spec).expect("the spec is valid");
+
+    let mut trainer = SGDTrainer::new(300, 0.01);
+    trainer.batch_size = Some(10);
+    trainer.reduction = Reduction::Mean;
+    trainer.shuffle = true;
+    let history = trainer.fit(&mut layers, &data, None);
+
+    // The loss of `fit` is that of the reconstruction, while the KL term
+    // only reaches the training through the delta of the layer. It is
+    // computed here from the means and log variances of the encoder,
+    // the outputs of its third layer.
+    let codes = projection::hidden_activations(&layers, &data.inputs, 2);
+    let kl = codes.chunks(4).map(|c| KL_WEIGHT * gaussian_kl(&c[..2], &c[2..])).sum::<f32>() / data.len() as f32;
+    let last = history.last().expect("at least one epoch");
+    println!("Reconstruction loss {:.4} and KL term {:.4} per sample", last.train.loss, kl);
+
+    // The decoder alone turns codes from the prior into new points
+    let decoder = layers.split_off(4);
+    for _ in 0..8 {
+        let code = normal_vector(2, &mut rng);
+        let point = network::output(&decoder, &code);
+        println!("Code {:5.2?} gives the point {:5.2?}, at a distance of {:.2} from the center",
+                 code,
+                 point,
+                 point[0].hypot(point[1]));
+    }
+}
@@ -0,0 +1,34 @@
+//! Learns exclusive or, the classic problem which no network without a
+//! hidden layer can solve.
+//!
+//! Run with `cargo run --example xor`.
+extern crate scarecrow;
+
+use scarecrow::network;
+use scarecrow::serialize::LayerRegistry;
+use scarecrow::sgd::SGDTrainer;
+use scarecrow::testing;
+use scarecrow::traits::SupervisedTrainer;
+
+fn main() {
+    let data = testing::xor();
+
+    // Two inputs, a hidden layer of six tanh units and a sigmoid output
+    let mut layers = LayerRegistry::new()
+        .parse_spec("dense inputs=2 neurons=6; tanh size=6
+                     dense inputs=6 neurons=1; sigmoid size=1")
+        .expect("the spec is valid");
+
+    // Stops once the average squared error of an epoch is below 0.001
+    let trainer = SGDTrainer::new(5000, 0.1).stop_when_loss_below(0.001);
+    let history = trainer.fit(&mut layers, &data, None);
+    let last = history.last().expect("at least one epoch");
+    println!("Trained for {} epochs to a loss of {} and an accuracy of {}",
+             last.epoch + 1,
+             last.train.loss,
+             last.train.accuracy);
+
+    for (x, t) in data.iter() {
+        println!("X: {:?}, Y: {:?}, T: {:?}", x, network::output(&layers, x), t);
+    }
+}
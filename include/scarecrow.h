@@ -0,0 +1,38 @@
+/* C interface for inference with scarecrow networks. Build the library
+ * with `cargo rustc --release --features ffi --crate-type cdylib` and
+ * link against libscarecrow. See the `ffi` module for details. */
+#ifndef SCARECROW_H
+#define SCARECROW_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define SCARECROW_OK 0
+#define SCARECROW_NULL -1
+#define SCARECROW_FAILED -2
+
+typedef struct ScarecrowModel ScarecrowModel;
+
+/* Loads a model written by `serialize::save`, or returns NULL. */
+ScarecrowModel *scarecrow_load_model(const char *path);
+
+size_t scarecrow_input_count(const ScarecrowModel *model);
+
+size_t scarecrow_output_count(const ScarecrowModel *model);
+
+/* Writes samples * scarecrow_output_count(model) values to outputs. */
+int scarecrow_predict(const ScarecrowModel *model,
+                      const float *inputs,
+                      size_t samples,
+                      float *outputs);
+
+void scarecrow_free(ScarecrowModel *model);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
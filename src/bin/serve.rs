@@ -0,0 +1,210 @@
+//! Serves the predictions of a network written by `serialize::save`
+//! over HTTP. Built with the `server` feature:
+//!
+//! ```text
+//! cargo run --release --features server --bin scarecrow-serve -- xor.net 127.0.0.1:8080
+//! ```
+//!
+//! `GET /model` describes the network and `POST /predict` with a body
+//! such as `{"inputs": [[0, 1], [1, 1]]}` answers with the outputs of
+//! every sample, `{"outputs": [[0.98], [0.03]]}`. Requests are handled
+//! by several threads which share the network. Bodies larger than
+//! `MAX_BODY` are refused with 413, and a request whose prediction
+//! panics is answered with 500 without taking its thread down.
+extern crate scarecrow;
+extern crate serde_json;
+extern crate tiny_http;
+
+use scarecrow::network;
+use scarecrow::serialize::{self, LayerRegistry};
+use scarecrow::traits::WeightedLayer;
+
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use std::collections::LinkedList;
+use std::env;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+use std::thread;
+
+const USAGE: &str = "usage: scarecrow-serve MODEL [ADDRESS] [THREADS]";
+
+/// The largest request body read, in bytes.
+const MAX_BODY: u64 = 16 << 20;
+
+/// The status and body of the answer to a request.
+fn handle(layers: &LinkedList<Box<dyn WeightedLayer>>, method: &Method, url: &str, body: &str) -> (u16, Value) {
+    let input_count = layers.front().map_or(0, |l| l.input_count());
+    let output_count = layers.back().map_or(0, |l| l.output_count());
+    match (method, url) {
+        (&Method::Get, "/model") => {
+            let spec = serialize::to_spec(layers).ok();
+            (200,
+             json!({
+                "spec": spec,
+                "inputs": input_count,
+                "outputs": output_count,
+                "parameters": network::parameter_count(layers),
+            }))
+        }
+        (&Method::Post, "/predict") => {
+            match parse_inputs(body, input_count) {
+                Ok(inputs) => {
+                    let outputs = network::predict_batch(layers, &inputs);
+                    let rows: Vec<&[f32]> = outputs.chunks(output_count.max(1)).collect();
+                    (200, json!({ "outputs": rows }))
+                }
+                Err(e) => (400, json!({ "error": e })),
+            }
+        }
+        (_, "/model") | (_, "/predict") => (405, json!({ "error": "method not allowed" })),
+        _ => (404, json!({ "error": "not found" })),
+    }
+}
+
+/// The answer of `handle`, or 500 if it panics.
+fn answer(layers: &LinkedList<Box<dyn WeightedLayer>>, method: &Method, url: &str, body: &str) -> (u16, Value) {
+    panic::catch_unwind(AssertUnwindSafe(|| handle(layers, method, url, body)))
+        .unwrap_or_else(|_| (500, json!({ "error": "the request could not be handled" })))
+}
+
+/// Reads a body of at most `limit` bytes, or gives the status and
+/// answer to refuse it with.
+fn read_body<R: Read>(reader: R, limit: u64) -> Result<String, (u16, Value)> {
+    let mut body = String::new();
+    match reader.take(limit + 1).read_to_string(&mut body) {
+        Ok(_) if body.len() as u64 > limit => {
+            Err((413, json!({ "error": format!("the body is larger than {} bytes", limit) })))
+        }
+        Ok(_) => Ok(body),
+        Err(e) => Err((400, json!({ "error": e.to_string() }))),
+    }
+}
+
+/// The samples of a predict request, one after another.
+fn parse_inputs(body: &str, input_count: usize) -> Result<Vec<f32>, String> {
+    let request: Value = serde_json::from_str(body).map_err(|e| e.to_string())?;
+    let rows = request.get("inputs").and_then(Value::as_array).ok_or("expected an `inputs` array")?;
+    let mut inputs = Vec::with_capacity(rows.len() * input_count);
+    for (i, row) in rows.iter().enumerate() {
+        let row = row.as_array().ok_or_else(|| format!("sample {} is not an array", i))?;
+        if row.len() != input_count {
+            return Err(format!("sample {} has {} values but the network has {} inputs", i, row.len(), input_count));
+        }
+        for v in row {
+            inputs.push(v.as_f64().ok_or_else(|| format!("sample {} has a value which is not a number", i))? as f32);
+        }
+    }
+    Ok(inputs)
+}
+
+fn serve(layers: &LinkedList<Box<dyn WeightedLayer>>, mut request: Request) {
+    let (status, answer) = match read_body(request.as_reader(), MAX_BODY) {
+        Ok(body) => answer(layers, request.method(), request.url(), &body),
+        Err(refusal) => refusal,
+    };
+    let content_type = Header::from_bytes("Content-Type", "application/json").expect("a valid header");
+    let response = Response::from_string(answer.to_string()).with_status_code(status).with_header(content_type);
+    if let Err(e) = request.respond(response) {
+        eprintln!("failed to respond: {}", e);
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let path = args.first().unwrap_or_else(|| fail(USAGE));
+    let address = args.get(1).map_or("127.0.0.1:8080", |a| a.as_str());
+    let threads = match args.get(2) {
+        Some(t) => t.parse().unwrap_or_else(|_| fail(USAGE)),
+        None => thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+    };
+
+    let file = File::open(path).unwrap_or_else(|e| fail(&format!("{}: {}", path, e)));
+    let mut layers = LayerRegistry::new()
+        .load(BufReader::new(file))
+        .unwrap_or_else(|e| fail(&format!("{}: {}", path, e)));
+    network::set_training(&mut layers, false);
+    let server = Server::http(address).unwrap_or_else(|e| fail(&format!("{}: {}", address, e)));
+    eprintln!("serving {} on http://{} with {} threads", path, address, threads);
+
+    let layers = &layers;
+    let server = &server;
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(move || {
+                for request in server.incoming_requests() {
+                    serve(layers, request);
+                }
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scarecrow::traits::Layer;
+
+    #[test]
+    fn predict_and_describe() {
+        let mut layers = LayerRegistry::new().parse_spec("dense inputs=2 neurons=1").unwrap();
+        network::set_weights(&mut layers, &[0.5, 0.25, 0.1]);
+
+        let (status, answer) = handle(&layers, &Method::Post, "/predict", r#"{"inputs": [[1, 0], [0, 2]]}"#);
+        assert_eq!(status, 200);
+        let outputs: Vec<Vec<f32>> = serde_json::from_value(answer["outputs"].clone()).unwrap();
+        assert_eq!(outputs, vec![vec![0.6], vec![0.6]]);
+
+        let (status, answer) = handle(&layers, &Method::Get, "/model", "");
+        assert_eq!(status, 200);
+        assert_eq!(answer["inputs"], 2);
+        assert_eq!(answer["parameters"], 3);
+
+        assert_eq!(handle(&layers, &Method::Post, "/predict", r#"{"inputs": [[1]]}"#).0, 400);
+        assert_eq!(handle(&layers, &Method::Post, "/predict", "not json").0, 400);
+        assert_eq!(handle(&layers, &Method::Get, "/predict", "").0, 405);
+        assert_eq!(handle(&layers, &Method::Get, "/", "").0, 404);
+    }
+
+    #[test]
+    fn large_bodies_are_refused() {
+        assert_eq!(read_body(&b"{}"[..], 2).unwrap(), "{}");
+        assert_eq!(read_body(&b"{ }"[..], 2).unwrap_err().0, 413);
+    }
+
+    /// Panics on every input.
+    struct Broken;
+
+    impl Layer for Broken {
+        fn input_count(&self) -> usize {
+            1
+        }
+
+        fn output_count(&self) -> usize {
+            1
+        }
+
+        fn output(&self, _inputs: &[f32]) -> Vec<f32> {
+            panic!("broken layer")
+        }
+    }
+
+    impl WeightedLayer for Broken {}
+
+    #[test]
+    fn panics_are_answered() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(Broken));
+
+        assert_eq!(answer(&layers, &Method::Post, "/predict", r#"{"inputs": [[1]]}"#).0, 500);
+        assert_eq!(answer(&layers, &Method::Get, "/model", "").0, 200);
+    }
+}
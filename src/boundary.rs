@@ -0,0 +1,123 @@
+//! Evaluation of a network with two inputs over a regular grid, which
+//! gives the data needed to plot its decision boundary.
+use network;
+use traits::WeightedLayer;
+
+use std::collections::LinkedList;
+use std::io;
+use std::io::Write;
+
+/// The outputs of a network over a regular 2D grid of inputs. Row 0
+/// is the top of the grid, where the second input is at its maximum,
+/// so that the grid can be drawn as an image directly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid {
+    /// Range of the first input, (min, max)
+    pub x_range: (f32, f32),
+    /// Range of the second input, (min, max)
+    pub y_range: (f32, f32),
+    /// The number of points along the first input
+    pub width: usize,
+    /// The number of points along the second input
+    pub height: usize,
+    /// The number of outputs of the network per point
+    pub output_count: usize,
+    /// All outputs, row by row and point by point
+    pub outputs: Vec<f32>,
+}
+
+impl Grid {
+    /// The input at the given column and row.
+    pub fn point(&self, col: usize, row: usize) -> (f32, f32) {
+        (lerp(self.x_range, col, self.width), lerp((self.y_range.1, self.y_range.0), row, self.height))
+    }
+
+    /// The outputs of the network at the given column and row.
+    pub fn output(&self, col: usize, row: usize) -> &[f32] {
+        let i = (row * self.width + col) * self.output_count;
+        &self.outputs[i..i + self.output_count]
+    }
+
+    /// Writes the grid as CSV with the columns `x,y` followed by one
+    /// column per output.
+    pub fn write_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "x,y")?;
+        for i in 0..self.output_count {
+            write!(out, ",y{}", i)?;
+        }
+        writeln!(out)?;
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let (x, y) = self.point(col, row);
+                write!(out, "{},{}", x, y)?;
+                for o in self.output(col, row) {
+                    write!(out, ",{}", o)?;
+                }
+                writeln!(out)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Evaluates a network with two inputs at `width` x `height` evenly
+/// spaced points spanning the given ranges, including the end points.
+pub fn evaluate_grid(layers: &LinkedList<Box<dyn WeightedLayer>>,
+                     x_range: (f32, f32),
+                     y_range: (f32, f32),
+                     width: usize,
+                     height: usize)
+                     -> Grid {
+    assert_eq!(layers.front().map(|l| l.input_count()), Some(2),
+               "a decision boundary grid needs a network with two inputs");
+    let output_count = layers.back().map(|l| l.output_count()).unwrap_or(0);
+    let mut grid = Grid {
+        x_range,
+        y_range,
+        width,
+        height,
+        output_count,
+        outputs: Vec::with_capacity(width * height * output_count),
+    };
+    for row in 0..height {
+        for col in 0..width {
+            let (x, y) = grid.point(col, row);
+            let o = network::output(layers, &[x, y]);
+            grid.outputs.extend(o);
+        }
+    }
+    grid
+}
+
+/// The `i`th of `n` evenly spaced values from `range.0` to `range.1`.
+fn lerp(range: (f32, f32), i: usize, n: usize) -> f32 {
+    if n <= 1 {
+        return range.0;
+    }
+    range.0 + (range.1 - range.0) * i as f32 / (n - 1) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::DenseLayer;
+
+    #[test]
+    fn grid_of_sum() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(1.0, 2, 1)));
+
+        let grid = evaluate_grid(&layers, (0.0, 1.0), (0.0, 2.0), 2, 3);
+
+        assert_eq!(grid.point(0, 0), (0.0, 2.0));
+        assert_eq!(grid.point(1, 2), (1.0, 0.0));
+        // Output is x + y + 1
+        assert_eq!(grid.outputs, vec![3.0, 4.0, 2.0, 3.0, 1.0, 2.0]);
+        assert_eq!(grid.output(1, 1), &[3.0]);
+
+        let mut csv = Vec::new();
+        grid.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("x,y,y0\n0,2,3\n1,2,4\n"));
+    }
+}
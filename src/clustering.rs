@@ -0,0 +1,151 @@
+//! K-means clustering, for use on its own, for comparison with
+//! learned representations, and for initializing the centers of
+//! `RBFLayer`s.
+use layers::RBFLayer;
+use random::RngProvider;
+
+use rand::Rng;
+
+/// Fits k-means clusters with Lloyd's algorithm, starting from `k`
+/// distinct samples chosen at random.
+pub struct KMeans {
+    /// The number of clusters
+    pub k: usize,
+    /// The maximum number of iterations. Fitting stops early once no
+    /// sample changes cluster.
+    pub iterations: usize,
+    /// Source of randomness for choosing the starting centroids
+    pub rng: RngProvider,
+}
+
+/// The result of fitting k-means.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KMeansModel {
+    /// The length of each sample
+    pub dim: usize,
+    /// The centroids of the clusters, one after another
+    pub centroids: Vec<f32>,
+}
+
+impl KMeans {
+    pub fn new(k: usize) -> KMeans {
+        KMeans {
+            k,
+            iterations: 100,
+            rng: RngProvider::from_entropy(),
+        }
+    }
+
+    /// Clusters the samples, which are laid out one after another with
+    /// `dim` values each.
+    pub fn fit(&self, data: &[f32], dim: usize) -> KMeansModel {
+        let samples: Vec<&[f32]> = data.chunks(dim).collect();
+        assert!(self.k > 0 && self.k <= samples.len(),
+                "k-means needs between 1 and the number of samples clusters");
+
+        let mut order: Vec<usize> = (0..samples.len()).collect();
+        self.rng.with_rng(|rng| rng.shuffle(&mut order));
+        let mut model = KMeansModel {
+            dim,
+            centroids: order.iter().take(self.k).flat_map(|i| samples[*i].to_vec()).collect(),
+        };
+
+        let mut assignments = vec![usize::MAX; samples.len()];
+        for _ in 0..self.iterations {
+            let mut changed = false;
+            for (a, x) in assignments.iter_mut().zip(samples.iter()) {
+                let c = model.predict(x);
+                changed = changed || c != *a;
+                *a = c;
+            }
+            if !changed {
+                break;
+            }
+
+            let mut sums = vec![0.0; self.k * dim];
+            let mut counts = vec![0; self.k];
+            for (a, x) in assignments.iter().zip(samples.iter()) {
+                counts[*a] += 1;
+                for (s, v) in sums[a * dim..(a + 1) * dim].iter_mut().zip(x.iter()) {
+                    *s += v;
+                }
+            }
+            for (c, (sum, count)) in model.centroids.chunks_mut(dim).zip(sums.chunks(dim).zip(counts)) {
+                // Empty clusters keep their centroid
+                if count > 0 {
+                    for (c, s) in c.iter_mut().zip(sum) {
+                        *c = s / count as f32;
+                    }
+                }
+            }
+        }
+        model
+    }
+}
+
+impl KMeansModel {
+    /// The number of clusters.
+    pub fn k(&self) -> usize {
+        self.centroids.len() / self.dim
+    }
+
+    /// The index of the cluster closest to the sample.
+    pub fn predict(&self, x: &[f32]) -> usize {
+        assert_eq!(self.dim, x.len());
+        let mut best = 0;
+        let mut best_distance = f32::INFINITY;
+        for (i, c) in self.centroids.chunks(self.dim).enumerate() {
+            let d = squared_distance(c, x);
+            if d < best_distance {
+                best = i;
+                best_distance = d;
+            }
+        }
+        best
+    }
+
+    /// The sum of squared distances between the samples and their
+    /// closest centroids.
+    pub fn inertia(&self, data: &[f32]) -> f32 {
+        data.chunks(self.dim)
+            .map(|x| squared_distance(&self.centroids[self.predict(x) * self.dim..][..self.dim], x))
+            .sum()
+    }
+
+    /// An RBF layer with one unit centered on each centroid, all with
+    /// the given width.
+    pub fn to_rbf_layer(&self, width: f32) -> RBFLayer {
+        assert!(width > 0.0, "the width of a radial basis function must be positive");
+        RBFLayer {
+            centers: self.centroids.clone(),
+            log_widths: vec![width.ln(); self.k()],
+            shape: (self.dim, self.k()),
+        }
+    }
+}
+
+fn squared_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use traits::Layer;
+
+    #[test]
+    fn finds_two_clusters() {
+        let data = [0.0, 0.0, 0.0, 1.0, 10.0, 10.0, 10.0, 11.0];
+        let mut kmeans = KMeans::new(2);
+        kmeans.rng = RngProvider::seeded(1);
+        let model = kmeans.fit(&data, 2);
+
+        assert_eq!(model.predict(&[0.0, 0.5]), model.predict(&[0.0, 0.0]));
+        assert!(model.predict(&[0.0, 0.5]) != model.predict(&[10.0, 10.5]));
+        assert_eq!(model.inertia(&data), 1.0);
+
+        let rbf = model.to_rbf_layer(1.0);
+        assert_eq!(rbf.output_count(), 2);
+        assert_eq!(rbf.output(&[10.0, 10.5])[model.predict(&[10.0, 10.5])], 1.0);
+    }
+}
@@ -0,0 +1,112 @@
+//! Training in stages, such as first training the output layer with
+//! the hidden layers frozen and then fine tuning all of them, or
+//! training on easy samples before the harder ones.
+use data::Dataset;
+use metrics::History;
+use sgd::SGDTrainer;
+use traits::{SupervisedTrainer, WeightedLayer};
+
+use std::collections::LinkedList;
+
+/// One stage of a curriculum.
+pub struct Stage {
+    /// Trains the network during the stage. Layers are frozen with
+    /// its `frozen` field.
+    pub trainer: SGDTrainer,
+    /// The data of the stage, or `None` to train on the data given to
+    /// `Curriculum::run`
+    pub data: Option<Dataset>,
+}
+
+impl Stage {
+    pub fn new(trainer: SGDTrainer) -> Stage {
+        Stage { trainer, data: None }
+    }
+
+    /// Leaves the parameters of the given layers as they are during
+    /// the stage.
+    pub fn freeze(mut self, layers: &[usize]) -> Stage {
+        self.trainer.frozen = layers.to_vec();
+        self
+    }
+
+    /// Trains on the data instead of the data of the curriculum.
+    pub fn with_data(mut self, data: Dataset) -> Stage {
+        self.data = Some(data);
+        self
+    }
+}
+
+/// Stages of training which are run one after another on the same
+/// network.
+pub struct Curriculum {
+    pub stages: Vec<Stage>,
+}
+
+impl Curriculum {
+    pub fn new(stages: Vec<Stage>) -> Curriculum {
+        Curriculum { stages }
+    }
+
+    /// Runs every stage with `SupervisedTrainer::fit` and returns the
+    /// history of every stage.
+    pub fn run(&self,
+               layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+               data: &Dataset,
+               validation: Option<&Dataset>)
+               -> Vec<History> {
+        self.run_with(layers, data, validation, |_, _, _| {})
+    }
+
+    /// Like `run`, but calls `on_stage` with the index of the stage,
+    /// the network and the history of the stage at the end of every
+    /// stage, such as to report progress or to change the network
+    /// before the next stage.
+    pub fn run_with<F>(&self,
+                       layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+                       data: &Dataset,
+                       validation: Option<&Dataset>,
+                       mut on_stage: F)
+                       -> Vec<History>
+        where F: FnMut(usize, &mut LinkedList<Box<dyn WeightedLayer>>, &History)
+    {
+        let mut histories = Vec::with_capacity(self.stages.len());
+        for (i, stage) in self.stages.iter().enumerate() {
+            let history = stage.trainer.fit(layers, stage.data.as_ref().unwrap_or(data), validation);
+            on_stage(i, layers, &history);
+            histories.push(history);
+        }
+        histories
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::DenseLayer;
+    use network;
+
+    #[test]
+    fn frozen_layers_are_kept() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(0.5, 1, 2)));
+        layers.push_back(Box::new(DenseLayer::uniform(0.5, 2, 1)));
+        let data = Dataset::new(vec![0.0, 1.0, 2.0], vec![1.0, 3.0, 5.0], 1, 1);
+        let easy = Dataset::new(vec![0.0], vec![1.0], 1, 1);
+
+        let curriculum = Curriculum::new(vec![Stage::new(SGDTrainer::new(20, 0.01)).freeze(&[0]).with_data(easy),
+                                              Stage::new(SGDTrainer::new(20, 0.01))]);
+        let mut stages = Vec::new();
+        let histories = curriculum.run_with(&mut layers, &data, None, |i, layers, history| {
+            stages.push((i, network::get_weights(layers)[..4].to_vec(), history.train_loss().len()));
+        });
+
+        assert_eq!(histories.len(), 2);
+        assert_eq!(histories[0].last().unwrap().train.samples, 1);
+        assert_eq!(stages[0], (0, vec![0.5; 4], 20));
+        assert_eq!(stages[1].0, 1);
+        assert_ne!(stages[1].1, vec![0.5; 4]);
+        let losses = histories[1].train_loss();
+        assert!(losses[19] < losses[0]);
+    }
+}
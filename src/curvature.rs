@@ -0,0 +1,112 @@
+//! Curvature diagnostics based on Hessian-vector products. The full
+//! Hessian of the loss has one row and column per weight, so instead
+//! its product with a vector is computed by finite differences of the
+//! gradient: `H v = (g(w + e v) - g(w - e v)) / 2e`.
+use network;
+use traits::{DifferentiableLossFunction, WeightedLayer};
+use utils::{dot, normal_vector};
+
+use rand::Rng;
+use std::collections::LinkedList;
+
+/// The product of the Hessian of the average loss with the vector
+/// `v`, which must have one element per weight and bias as laid out
+/// by `network::get_weights`. `epsilon` is the finite difference step
+/// size. The weights of the network are restored afterwards.
+pub fn hessian_vector_product(layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+                              loss: &dyn DifferentiableLossFunction,
+                              inputs: &[f32],
+                              targets: &[f32],
+                              v: &[f32],
+                              epsilon: f32)
+                              -> Vec<f32> {
+    let weights = network::get_weights(layers);
+    assert_eq!(weights.len(), v.len());
+
+    let shifted = |sign: f32| -> Vec<f32> {
+        weights.iter().zip(v).map(|(w, d)| w + sign * epsilon * d).collect()
+    };
+
+    network::set_weights(layers, &shifted(1.0));
+    let plus = network::gradient(layers, loss, inputs, targets);
+    network::set_weights(layers, &shifted(-1.0));
+    let minus = network::gradient(layers, loss, inputs, targets);
+    network::set_weights(layers, &weights);
+
+    plus.iter().zip(minus).map(|(p, m)| (p - m) / (2.0 * epsilon)).collect()
+}
+
+/// The curvature of the loss along the direction `v`, i.e. the
+/// Rayleigh quotient `v' H v / v' v`.
+pub fn directional_curvature(layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+                             loss: &dyn DifferentiableLossFunction,
+                             inputs: &[f32],
+                             targets: &[f32],
+                             v: &[f32],
+                             epsilon: f32)
+                             -> f32 {
+    let hv = hessian_vector_product(layers, loss, inputs, targets, v, epsilon);
+    dot(v, &hv) / dot(v, v)
+}
+
+/// Estimates the eigenvalue of the Hessian with the largest magnitude
+/// by power iteration, starting from a random direction drawn from
+/// `rng`. This is the sharpest curvature of the loss, which limits the
+/// largest stable learning rate of gradient descent to about
+/// `2 / eigenvalue`.
+pub fn largest_eigenvalue<R: Rng>(layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+                                  loss: &dyn DifferentiableLossFunction,
+                                  inputs: &[f32],
+                                  targets: &[f32],
+                                  iterations: usize,
+                                  epsilon: f32,
+                                  rng: &mut R)
+                                  -> f32 {
+    let mut v = normal_vector(network::parameter_count(layers), rng);
+    let mut eigenvalue = 0.0;
+    for _ in 0..iterations {
+        let norm = dot(&v, &v).sqrt();
+        if norm == 0.0 {
+            return 0.0;
+        }
+        for x in v.iter_mut() {
+            *x /= norm;
+        }
+        let hv = hessian_vector_product(layers, loss, inputs, targets, &v, epsilon);
+        eigenvalue = dot(&v, &hv);
+        v = hv;
+    }
+    eigenvalue
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::DenseLayer;
+    use loss::SquaredError;
+    use random::SeededRng;
+
+    #[test]
+    fn hessian_of_linear_regression() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(0.5, 1, 1)));
+        let inputs = [1.0, 2.0];
+        let targets = [0.0, 0.0];
+
+        // e = mean((w x + b)^2), so H = 2 * mean([[x^2, x], [x, 1]])
+        // = [[5, 3], [3, 2]]
+        let hv = hessian_vector_product(&mut layers, &SquaredError, &inputs, &targets, &[1.0, 0.0], 0.01);
+        assert!((hv[0] - 5.0).abs() < 0.01);
+        assert!((hv[1] - 3.0).abs() < 0.01);
+        // The weights are restored
+        assert_eq!(network::get_weights(&layers), vec![0.5, 0.5]);
+
+        let c = directional_curvature(&mut layers, &SquaredError, &inputs, &targets, &[0.0, 1.0], 0.01);
+        assert!((c - 2.0).abs() < 0.01);
+
+        // Eigenvalues of [[5, 3], [3, 2]] are (7 +- sqrt(45)) / 2
+        let mut rng = SeededRng::new(1);
+        let e = largest_eigenvalue(&mut layers, &SquaredError, &inputs, &targets, 50, 0.01, &mut rng);
+        assert!((e - (7.0 + 45.0f32.sqrt()) / 2.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,245 @@
+//! Random augmentations of images stored as flat buffers of height x
+//! width x channels values, row by row with the channels of a pixel
+//! next to each other.
+use data::Dataset;
+use random::{RngProvider, SeededRng};
+
+use rand::Rng;
+use rand::distributions::{IndependentSample, Normal};
+use std::cmp;
+
+/// A random change of a single sample, made in place. Transforms are
+/// shared with the background thread of a `DataLoader`.
+pub trait Transform: Send + Sync {
+    fn apply(&self, sample: &mut [f32], rng: &mut SeededRng);
+}
+
+/// Any closure taking a sample and the random number generator is a
+/// transform, for perturbations which have no type of their own.
+impl<F> Transform for F
+    where F: Fn(&mut [f32], &mut SeededRng) + Send + Sync
+{
+    fn apply(&self, sample: &mut [f32], rng: &mut SeededRng) {
+        self(sample, rng)
+    }
+}
+
+/// The shape of an image, (height, width, channels).
+pub type ImageShape = (usize, usize, usize);
+
+/// Mirrors the image left to right with the given probability.
+pub struct HorizontalFlip {
+    pub shape: ImageShape,
+    pub probability: f32,
+}
+
+impl Transform for HorizontalFlip {
+    fn apply(&self, sample: &mut [f32], rng: &mut SeededRng) {
+        let (h, w, c) = self.shape;
+        assert_eq!(h * w * c, sample.len());
+        if rng.gen::<f32>() >= self.probability {
+            return;
+        }
+        for row in sample.chunks_mut(w * c) {
+            for col in 0..w / 2 {
+                for ch in 0..c {
+                    row.swap(col * c + ch, (w - 1 - col) * c + ch);
+                }
+            }
+        }
+    }
+}
+
+/// Pads the image with zeros by `padding` pixels on every side and
+/// crops out an image of the original size at a random position. This
+/// shifts the image by up to `padding` pixels in each direction.
+pub struct RandomCrop {
+    pub shape: ImageShape,
+    pub padding: usize,
+}
+
+impl Transform for RandomCrop {
+    fn apply(&self, sample: &mut [f32], rng: &mut SeededRng) {
+        let (h, w, c) = self.shape;
+        assert_eq!(h * w * c, sample.len());
+        let p = self.padding as isize;
+        let dy = rng.gen_range(-p, p + 1);
+        let dx = rng.gen_range(-p, p + 1);
+
+        let original = sample.to_vec();
+        for row in 0..h as isize {
+            for col in 0..w as isize {
+                let (sr, sc) = (row + dy, col + dx);
+                let inside = sr >= 0 && sr < h as isize && sc >= 0 && sc < w as isize;
+                let to = (row as usize * w + col as usize) * c;
+                for ch in 0..c {
+                    sample[to + ch] = if inside {
+                        original[(sr as usize * w + sc as usize) * c + ch]
+                    } else {
+                        0.0
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Adds the same random offset, uniform in `[-amount, amount]`, to
+/// every value of the image.
+pub struct BrightnessJitter {
+    pub amount: f32,
+}
+
+impl Transform for BrightnessJitter {
+    fn apply(&self, sample: &mut [f32], rng: &mut SeededRng) {
+        let offset = (rng.gen::<f32>() * 2.0 - 1.0) * self.amount;
+        for v in sample.iter_mut() {
+            *v += offset;
+        }
+    }
+}
+
+/// Adds independent Gaussian noise with standard deviation `std` to
+/// every value, which makes the network robust to small changes of its
+/// inputs. Unlike the other transforms it suits any kind of input.
+pub struct GaussianJitter {
+    pub std: f32,
+}
+
+impl Transform for GaussianJitter {
+    fn apply(&self, sample: &mut [f32], rng: &mut SeededRng) {
+        let normal = Normal::new(0.0, self.std as f64);
+        for v in sample.iter_mut() {
+            *v += normal.ind_sample(rng) as f32;
+        }
+    }
+}
+
+/// A list of transforms applied in order to every sample.
+pub struct Augmentation {
+    pub transforms: Vec<Box<dyn Transform>>,
+    /// Source of randomness for the transforms
+    pub rng: RngProvider,
+}
+
+impl Augmentation {
+    pub fn new(transforms: Vec<Box<dyn Transform>>) -> Augmentation {
+        Augmentation {
+            transforms,
+            rng: RngProvider::from_entropy(),
+        }
+    }
+
+    /// Returns augmented copies of the samples, which are laid out one
+    /// after another with `input_count` values each.
+    pub fn apply_batch(&self, inputs: &[f32], input_count: usize) -> Vec<f32> {
+        let mut rng = self.rng.fork();
+        let mut result = inputs.to_vec();
+        for sample in result.chunks_mut(input_count) {
+            for t in self.transforms.iter() {
+                t.apply(sample, &mut rng);
+            }
+        }
+        result
+    }
+
+    /// Iterates over the dataset in batches, augmenting each batch of
+    /// inputs only when it is reached. Every pass over the dataset thus
+    /// sees new augmentations.
+    pub fn batches<'a>(&'a self, data: &'a Dataset, batch_size: usize) -> AugmentedBatches<'a> {
+        AugmentedBatches {
+            augmentation: self,
+            data,
+            batch_size: cmp::max(1, batch_size),
+            next: 0,
+        }
+    }
+}
+
+/// Iterator over augmented batches of (inputs, targets). Created by
+/// `Augmentation::batches`.
+pub struct AugmentedBatches<'a> {
+    augmentation: &'a Augmentation,
+    data: &'a Dataset,
+    batch_size: usize,
+    next: usize,
+}
+
+impl<'a> Iterator for AugmentedBatches<'a> {
+    type Item = (Vec<f32>, &'a [f32]);
+
+    fn next(&mut self) -> Option<(Vec<f32>, &'a [f32])> {
+        if self.next >= self.data.len() {
+            return None;
+        }
+        let end = cmp::min(self.next + self.batch_size, self.data.len());
+        let (ic, oc) = (self.data.input_count, self.data.output_count);
+        let inputs = self.augmentation.apply_batch(&self.data.inputs[self.next * ic..end * ic], ic);
+        let targets = &self.data.targets[self.next * oc..end * oc];
+        self.next = end;
+        Some((inputs, targets))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jitter_and_closures() {
+        let mut x = [1.0; 1000];
+        GaussianJitter { std: 0.1 }.apply(&mut x, &mut SeededRng::new(1));
+        let mean = x.iter().sum::<f32>() / 1000.0;
+        let var = x.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / 1000.0;
+        assert!((mean - 1.0).abs() < 0.02);
+        assert!((var.sqrt() - 0.1).abs() < 0.02);
+
+        let aug = Augmentation::new(vec![Box::new(|x: &mut [f32], _: &mut SeededRng| x[0] = -x[0])]);
+        assert_eq!(aug.apply_batch(&[1.0, 2.0, 3.0, 4.0], 2), vec![-1.0, 2.0, -3.0, 4.0]);
+    }
+
+    #[test]
+    fn flip() {
+        let t = HorizontalFlip {
+            shape: (1, 3, 2),
+            probability: 1.0,
+        };
+        let mut x = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        t.apply(&mut x, &mut SeededRng::new(1));
+        assert_eq!(x, [5.0, 6.0, 3.0, 4.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn crop_shifts_within_padding() {
+        let t = RandomCrop {
+            shape: (3, 3, 1),
+            padding: 1,
+        };
+        let mut rng = SeededRng::new(1);
+        for _ in 0..10 {
+            let mut x = [0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0];
+            t.apply(&mut x, &mut rng);
+            // The center pixel moves at most one step
+            assert_eq!(x.iter().sum::<f32>(), 1.0);
+        }
+    }
+
+    #[test]
+    fn batches_are_augmented_lazily() {
+        let data = Dataset::new(vec![1.0, 2.0, 3.0], vec![0.0, 1.0, 2.0], 1, 1);
+        let mut augmentation = Augmentation::new(vec![Box::new(BrightnessJitter { amount: 0.5 })]);
+        augmentation.rng = RngProvider::seeded(1);
+
+        let batches: Vec<(Vec<f32>, &[f32])> = augmentation.batches(&data, 2).collect();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[1].1, &[2.0]);
+        let first: Vec<f32> = batches.into_iter().flat_map(|(x, _)| x).collect();
+        for (x, original) in first.iter().zip(&data.inputs) {
+            assert!((x - original).abs() <= 0.5);
+        }
+
+        // Another pass gives different augmentations
+        let again: Vec<f32> = augmentation.batches(&data, 2).flat_map(|(x, _)| x).collect();
+        assert!(again != first);
+    }
+}
@@ -0,0 +1,274 @@
+//! Batching of datasets on a background thread, so that shuffling and
+//! augmentation overlap with training.
+use data::augment::Augmentation;
+use data::Dataset;
+use random::RngProvider;
+
+use rand::Rng;
+use std::panic;
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+
+/// A batch of samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Batch {
+    pub inputs: Vec<f32>,
+    pub targets: Vec<f32>,
+}
+
+/// How the samples of an epoch are chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sampling {
+    /// Every sample once
+    Uniform,
+    /// Every sample once, with the samples of every class spread evenly
+    /// over the epoch, so that every batch has about the proportions of
+    /// the classes in the dataset
+    Stratified,
+    /// As many samples as the dataset has, drawn with replacement in
+    /// equal numbers from every class and spread evenly over the epoch,
+    /// so that rare classes are seen as often as common ones
+    Oversampled,
+}
+
+/// Splits a dataset into batches, optionally shuffled and augmented.
+/// The batches of an epoch are prepared on a background thread, which
+/// stays up to `prefetch` batches ahead of the training loop.
+pub struct DataLoader {
+    pub data: Arc<Dataset>,
+    pub batch_size: usize,
+    /// Whether to visit the samples in a new random order every epoch
+    pub shuffle: bool,
+    /// The number of batches prepared ahead of time
+    pub prefetch: usize,
+    /// How the samples of every epoch are chosen. The classes of the
+    /// samples are given by `Dataset::class`.
+    pub sampling: Sampling,
+    /// Augmentation applied to the inputs of every batch
+    pub augmentation: Option<Arc<Augmentation>>,
+    /// Source of randomness for shuffling
+    pub rng: RngProvider,
+}
+
+impl DataLoader {
+    pub fn new(data: Arc<Dataset>, batch_size: usize) -> DataLoader {
+        assert!(batch_size > 0, "the batch size must be positive");
+        DataLoader {
+            data,
+            batch_size,
+            shuffle: true,
+            prefetch: 2,
+            sampling: Sampling::Uniform,
+            augmentation: None,
+            rng: RngProvider::from_entropy(),
+        }
+    }
+
+    /// Starts preparing the batches of one pass over the dataset.
+    pub fn epoch(&self) -> Batches {
+        let order = self.order();
+
+        let (sender, receiver) = sync_channel(self.prefetch);
+        let data = self.data.clone();
+        let augmentation = self.augmentation.clone();
+        let batch_size = self.batch_size;
+        let handle = thread::spawn(move || {
+            for indices in order.chunks(batch_size) {
+                let mut inputs = Vec::with_capacity(indices.len() * data.input_count);
+                let mut targets = Vec::with_capacity(indices.len() * data.output_count);
+                for i in indices {
+                    inputs.extend_from_slice(data.input(*i));
+                    targets.extend_from_slice(data.target(*i));
+                }
+                if let Some(ref a) = augmentation {
+                    inputs = a.apply_batch(&inputs, data.input_count);
+                }
+                // The receiver is gone if the epoch was abandoned
+                if sender.send(Batch { inputs, targets }).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Batches {
+            receiver: Some(receiver),
+            handle: Some(handle),
+        }
+    }
+}
+
+impl DataLoader {
+    /// The indices of the samples of an epoch, in order.
+    fn order(&self) -> Vec<usize> {
+        let mut rng = self.rng.fork();
+        let mut order: Vec<usize> = (0..self.data.len()).collect();
+        if self.shuffle {
+            rng.shuffle(&mut order);
+        }
+        if self.sampling == Sampling::Uniform {
+            return order;
+        }
+
+        // The samples of every class, in the order so far
+        let mut classes: Vec<Vec<usize>> = Vec::new();
+        for i in order {
+            let class = self.data.class(i);
+            if classes.len() <= class {
+                classes.resize(class + 1, Vec::new());
+            }
+            classes[class].push(i);
+        }
+        classes.retain(|c| !c.is_empty());
+        if self.sampling == Sampling::Oversampled {
+            let per_class = self.data.len().div_ceil(classes.len().max(1));
+            for c in classes.iter_mut() {
+                let drawn = (0..per_class).map(|_| c[rng.gen_range(0, c.len())]).collect();
+                *c = drawn;
+            }
+        }
+
+        // Spreads the samples of every class evenly by placing the k:th
+        // of n samples at (k + 0.5) / n of the way through the epoch
+        let mut placed: Vec<(f32, usize)> = classes.iter()
+            .flat_map(|c| c.iter().enumerate().map(move |(k, i)| ((k as f32 + 0.5) / c.len() as f32, *i)))
+            .collect();
+        placed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        placed.into_iter().map(|(_, i)| i).collect()
+    }
+}
+
+/// Iterator over the batches of one epoch. Created by
+/// `DataLoader::epoch`. A panic of the background thread, such as in
+/// an augmentation, is raised again by `next`.
+pub struct Batches {
+    receiver: Option<Receiver<Batch>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Iterator for Batches {
+    type Item = Batch;
+
+    fn next(&mut self) -> Option<Batch> {
+        if let Some(batch) = self.receiver.as_ref().and_then(|r| r.recv().ok()) {
+            return Some(batch);
+        }
+        // The channel is closed once the thread is done, whether it
+        // sent every batch or panicked
+        self.receiver.take();
+        if let Some(handle) = self.handle.take() {
+            if let Err(payload) = handle.join() {
+                panic::resume_unwind(payload);
+            }
+        }
+        None
+    }
+}
+
+impl Drop for Batches {
+    /// Stops the background thread, which notices that the receiver
+    /// is gone the next time it sends a batch. A panic of the thread is
+    /// raised again, unless this thread is already panicking.
+    fn drop(&mut self) {
+        self.receiver.take();
+        if let Some(handle) = self.handle.take() {
+            if let Err(payload) = handle.join() {
+                if !thread::panicking() {
+                    panic::resume_unwind(payload);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dataset() -> Arc<Dataset> {
+        Arc::new(Dataset::new((0..10).map(|i| i as f32).collect(), (0..10).map(|i| -i as f32).collect(), 1, 1))
+    }
+
+    #[test]
+    fn batches_cover_dataset() {
+        let mut loader = DataLoader::new(dataset(), 3);
+        loader.rng = RngProvider::seeded(1);
+
+        let batches: Vec<Batch> = loader.epoch().collect();
+        assert_eq!(batches.iter().map(|b| b.inputs.len()).collect::<Vec<usize>>(), vec![3, 3, 3, 1]);
+        let mut seen: Vec<f32> = batches.iter().flat_map(|b| b.inputs.clone()).collect();
+        assert!(seen != (0..10).map(|i| i as f32).collect::<Vec<f32>>());
+        for b in batches.iter() {
+            for (x, t) in b.inputs.iter().zip(&b.targets) {
+                assert_eq!(*x, -t);
+            }
+        }
+        seen.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(seen, (0..10).map(|i| i as f32).collect::<Vec<f32>>());
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range for slice")]
+    fn panic_of_the_thread_is_raised() {
+        // Half of the targets are missing
+        let data = Dataset {
+            inputs: (0..10).map(|i| i as f32).collect(),
+            targets: vec![0.0; 5],
+            input_count: 1,
+            output_count: 1,
+        };
+        let mut loader = DataLoader::new(Arc::new(data), 2);
+        loader.shuffle = false;
+        for _ in loader.epoch() {}
+    }
+
+    /// Eight samples of class 0 and two of class 1.
+    fn imbalanced() -> Arc<Dataset> {
+        Arc::new(Dataset::new((0..10).map(|i| i as f32).collect(),
+                              (0..10).map(|i| if i < 8 { 0.0 } else { 1.0 }).collect(),
+                              1,
+                              1))
+    }
+
+    #[test]
+    fn stratified_batches() {
+        let mut loader = DataLoader::new(imbalanced(), 5);
+        loader.sampling = Sampling::Stratified;
+        loader.rng = RngProvider::seeded(1);
+
+        let batches: Vec<Batch> = loader.epoch().collect();
+        assert_eq!(batches.len(), 2);
+        for b in batches.iter() {
+            assert_eq!(b.targets.iter().filter(|t| **t == 1.0).count(), 1);
+        }
+    }
+
+    #[test]
+    fn oversampled_batches() {
+        let mut loader = DataLoader::new(imbalanced(), 2);
+        loader.sampling = Sampling::Oversampled;
+        loader.rng = RngProvider::seeded(1);
+
+        let batches: Vec<Batch> = loader.epoch().collect();
+        assert_eq!(batches.len(), 5);
+        for b in batches.iter() {
+            assert_eq!(b.targets.iter().sum::<f32>(), 1.0);
+            for (x, t) in b.inputs.iter().zip(&b.targets) {
+                assert_eq!(*t == 1.0, *x >= 8.0);
+            }
+        }
+    }
+
+    #[test]
+    fn unshuffled_and_abandoned() {
+        let mut loader = DataLoader::new(dataset(), 4);
+        loader.shuffle = false;
+        loader.prefetch = 0;
+
+        let mut batches = loader.epoch();
+        assert_eq!(batches.next().unwrap().inputs, vec![0.0, 1.0, 2.0, 3.0]);
+        // Dropping the iterator early stops the thread
+        drop(batches);
+    }
+}
@@ -0,0 +1,330 @@
+//! Datasets stored in a binary file which is memory-mapped rather than
+//! read, so that datasets larger than memory can be trained on. The
+//! operating system pages the samples in as they are used.
+//!
+//! The file starts with a header of four little-endian 64-bit values:
+//! the magic number, the number of samples, the input count and the
+//! output count. It is followed by the inputs of all samples and then
+//! the targets of all samples, as little-endian `f32` values.
+//!
+//! Requires the `mmap` feature.
+use data::Dataset;
+
+use memmap2::{Mmap, MmapMut};
+use std::convert::TryFrom;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+use std::slice::Chunks;
+use std::{mem, slice};
+
+const MAGIC: u64 = 0x5441_4457_4f52_4353;
+const HEADER_LEN: usize = 4 * 8;
+
+/// A dataset backed by a memory-mapped file.
+pub struct MmapDataset {
+    map: Mmap,
+    samples: usize,
+    /// The number of values in each input
+    pub input_count: usize,
+    /// The number of values in each target
+    pub output_count: usize,
+}
+
+impl MmapDataset {
+    /// Maps a dataset file written by `write_dataset` or
+    /// `MmapDatasetWriter`. The file must not be modified while it is
+    /// mapped.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<MmapDataset> {
+        check_endian()?;
+        let file = File::open(path)?;
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() < HEADER_LEN || read_u64(&map, 0) != MAGIC {
+            return Err(invalid("not a dataset file"));
+        }
+        let samples = read_count(&map, 1)?;
+        let input_count = read_count(&map, 2)?;
+        let output_count = read_count(&map, 3)?;
+        if input_count == 0 || output_count == 0 {
+            return Err(invalid("inputs and targets must not be empty"));
+        }
+        if map.len() != file_len(samples, input_count, output_count)? {
+            return Err(invalid("the file size does not match the header"));
+        }
+        Ok(MmapDataset {
+            map,
+            samples,
+            input_count,
+            output_count,
+        })
+    }
+
+    /// The number of samples.
+    pub fn len(&self) -> usize {
+        self.samples
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples == 0
+    }
+
+    /// The inputs of all samples, one after another. These can be
+    /// passed directly to a trainer.
+    pub fn inputs(&self) -> &[f32] {
+        self.values(0, self.samples * self.input_count)
+    }
+
+    /// The targets of all samples, one after another.
+    pub fn targets(&self) -> &[f32] {
+        self.values(self.samples * self.input_count, self.samples * self.output_count)
+    }
+
+    /// The input of sample `i`.
+    pub fn input(&self, i: usize) -> &[f32] {
+        &self.inputs()[i * self.input_count..(i + 1) * self.input_count]
+    }
+
+    /// The target of sample `i`.
+    pub fn target(&self, i: usize) -> &[f32] {
+        &self.targets()[i * self.output_count..(i + 1) * self.output_count]
+    }
+
+    /// Iterates over the (input, target) pairs of the samples.
+    pub fn iter(&self) -> ::std::iter::Zip<Chunks<'_, f32>, Chunks<'_, f32>> {
+        self.inputs().chunks(self.input_count).zip(self.targets().chunks(self.output_count))
+    }
+
+    /// Copies the samples into memory.
+    pub fn to_dataset(&self) -> Dataset {
+        Dataset::new(self.inputs().to_vec(),
+                     self.targets().to_vec(),
+                     self.input_count,
+                     self.output_count)
+    }
+
+    /// `len` values starting `start` values after the header.
+    fn values(&self, start: usize, len: usize) -> &[f32] {
+        // The map is page aligned and the header a multiple of four
+        // bytes, so the values are aligned.
+        unsafe {
+            let ptr = self.map.as_ptr().add(HEADER_LEN) as *const f32;
+            slice::from_raw_parts(ptr.add(start), len)
+        }
+    }
+}
+
+/// Creates a dataset file of a known number of samples, which are
+/// then filled in one at a time without holding them all in memory.
+pub struct MmapDatasetWriter {
+    map: MmapMut,
+    samples: usize,
+    input_count: usize,
+    output_count: usize,
+}
+
+impl MmapDatasetWriter {
+    /// Creates, or truncates, the file at `path` with room for
+    /// `samples` samples, which are initially zero.
+    pub fn create<P: AsRef<Path>>(path: P,
+                                  samples: usize,
+                                  input_count: usize,
+                                  output_count: usize)
+                                  -> io::Result<MmapDatasetWriter> {
+        check_endian()?;
+        if input_count == 0 || output_count == 0 {
+            return Err(invalid("inputs and targets must not be empty"));
+        }
+        let len = file_len(samples, input_count, output_count)?;
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        file.set_len(len as u64)?;
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        for (i, v) in [MAGIC, samples as u64, input_count as u64, output_count as u64].iter().enumerate() {
+            map[i * 8..(i + 1) * 8].copy_from_slice(&v.to_le_bytes());
+        }
+        Ok(MmapDatasetWriter {
+            map,
+            samples,
+            input_count,
+            output_count,
+        })
+    }
+
+    /// Sets the input and target of sample `i`.
+    pub fn set(&mut self, i: usize, input: &[f32], target: &[f32]) {
+        assert!(i < self.samples, "sample {} is out of range for {} samples", i, self.samples);
+        assert_eq!(input.len(), self.input_count, "the input has the wrong size");
+        assert_eq!(target.len(), self.output_count, "the target has the wrong size");
+        let targets_start = self.samples * self.input_count;
+        self.write_values(i * self.input_count, input);
+        self.write_values(targets_start + i * self.output_count, target);
+    }
+
+    /// Writes the samples to the file.
+    pub fn finish(self) -> io::Result<()> {
+        self.map.flush()
+    }
+
+    fn write_values(&mut self, start: usize, values: &[f32]) {
+        let offset = HEADER_LEN + start * mem::size_of::<f32>();
+        for (i, v) in values.iter().enumerate() {
+            let at = offset + i * mem::size_of::<f32>();
+            self.map[at..at + mem::size_of::<f32>()].copy_from_slice(&v.to_le_bytes());
+        }
+    }
+}
+
+/// Writes an in-memory dataset to a file which can be opened with
+/// `MmapDataset::open`.
+pub fn write_dataset<P: AsRef<Path>>(path: P, data: &Dataset) -> io::Result<()> {
+    let mut writer = MmapDatasetWriter::create(path, data.len(), data.input_count, data.output_count)?;
+    for (i, (x, t)) in data.iter().enumerate() {
+        writer.set(i, x, t);
+    }
+    writer.finish()
+}
+
+/// The size in bytes of a file of the given dimensions, or an error if
+/// it does not fit in memory. The header of a file is not trusted, so
+/// that a file cannot claim more values than it holds.
+fn file_len(samples: usize, input_count: usize, output_count: usize) -> io::Result<usize> {
+    input_count.checked_add(output_count)
+        .and_then(|n| n.checked_mul(samples))
+        .and_then(|n| n.checked_mul(mem::size_of::<f32>()))
+        .and_then(|n| n.checked_add(HEADER_LEN))
+        .ok_or_else(|| invalid("the dataset is too large to map"))
+}
+
+fn read_u64(bytes: &[u8], index: usize) -> u64 {
+    let mut value = [0; 8];
+    value.copy_from_slice(&bytes[index * 8..(index + 1) * 8]);
+    u64::from_le_bytes(value)
+}
+
+/// A count in the header, which must fit in a `usize`.
+fn read_count(bytes: &[u8], index: usize) -> io::Result<usize> {
+    usize::try_from(read_u64(bytes, index)).map_err(|_| invalid("the dataset is too large to map"))
+}
+
+/// The values are mapped directly as `f32`, which requires the byte
+/// order of the file and the machine to agree.
+fn check_endian() -> io::Result<()> {
+    if cfg!(target_endian = "little") {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::Unsupported,
+                           "memory-mapped datasets require a little-endian machine"))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{DenseLayer, SigmoidLayer, WeightLayout};
+    use sgd::SGDTrainer;
+    use traits::{SupervisedTrainer, WeightedLayer};
+
+    use std::collections::LinkedList;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("scarecrow-{}-{}.bin", name, ::std::process::id()))
+    }
+
+    fn dataset() -> Dataset {
+        Dataset::new(vec![0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 1.0],
+                     vec![0.0, 1.0, 1.0, 0.0],
+                     2,
+                     1)
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = temp_path("round-trip");
+        write_dataset(&path, &dataset()).unwrap();
+
+        let mapped = MmapDataset::open(&path).unwrap();
+        assert_eq!(mapped.len(), 4);
+        assert_eq!(mapped.input(2), &[1.0, 0.0]);
+        assert_eq!(mapped.target(2), &[1.0]);
+        assert_eq!(mapped.iter().count(), 4);
+        assert_eq!(mapped.to_dataset(), dataset());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn written_sample_by_sample() {
+        let path = temp_path("writer");
+        let mut writer = MmapDatasetWriter::create(&path, 2, 3, 1).unwrap();
+        writer.set(1, &[4.0, 5.0, 6.0], &[-1.0]);
+        writer.set(0, &[1.0, 2.0, 3.0], &[0.5]);
+        writer.finish().unwrap();
+
+        let mapped = MmapDataset::open(&path).unwrap();
+        assert_eq!(mapped.inputs(), &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(mapped.targets(), &[0.5, -1.0]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let path = temp_path("invalid");
+        fs::write(&path, b"not a dataset at all, but long enough").unwrap();
+        let err = MmapDataset::open(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        write_dataset(&path, &dataset()).unwrap();
+        let truncated = fs::read(&path).unwrap();
+        fs::write(&path, &truncated[..truncated.len() - 4]).unwrap();
+        assert!(MmapDataset::open(&path).is_err());
+
+        // A header whose size overflows is not mistaken for an empty
+        // dataset
+        let mut header = Vec::new();
+        for v in &[MAGIC, 1 << 61, 1, 1] {
+            header.extend_from_slice(&v.to_le_bytes());
+        }
+        fs::write(&path, &header).unwrap();
+        let err = MmapDataset::open(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(MmapDatasetWriter::create(&path, usize::MAX, 1, 1).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn network() -> LinkedList<Box<dyn WeightedLayer>> {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer {
+            weights: vec![0.5, -0.3],
+            bias: vec![0.1],
+            shape: (2, 1),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
+        }));
+        layers.push_back(Box::new(SigmoidLayer { size: 1 }));
+        layers
+    }
+
+    #[test]
+    fn trains_like_in_memory_data() {
+        let path = temp_path("train");
+        let data = dataset();
+        write_dataset(&path, &data).unwrap();
+        let mapped = MmapDataset::open(&path).unwrap();
+
+        let trainer = SGDTrainer::new(5, 0.1);
+        let mut from_file = network();
+        trainer.train(&mut from_file, mapped.inputs(), mapped.targets());
+        let mut from_memory = network();
+        trainer.train(&mut from_memory, &data.inputs, &data.targets);
+
+        assert_eq!(from_file.front().unwrap().weights(), from_memory.front().unwrap().weights());
+        assert_ne!(from_file.front().unwrap().weights().unwrap(), &[0.5, -0.3]);
+        fs::remove_file(&path).unwrap();
+    }
+}
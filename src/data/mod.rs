@@ -0,0 +1,251 @@
+//! Containers for training data, and transforms of the data.
+use tensor::Tensor;
+use utils::argmax;
+
+use std::slice::Chunks;
+
+pub mod augment;
+pub mod iris;
+pub mod loader;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod target;
+
+/// Samples with fixed size input and target vectors, stored one after
+/// another in flat buffers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dataset {
+    pub inputs: Vec<f32>,
+    pub targets: Vec<f32>,
+    /// The number of values in each input
+    pub input_count: usize,
+    /// The number of values in each target
+    pub output_count: usize,
+}
+
+impl Dataset {
+    pub fn new(inputs: Vec<f32>, targets: Vec<f32>, input_count: usize, output_count: usize) -> Dataset {
+        assert!(input_count > 0 && output_count > 0, "inputs and targets must not be empty");
+        assert_eq!(inputs.len() % input_count, 0, "the inputs do not divide into samples");
+        assert_eq!(inputs.len() / input_count, targets.len() / output_count,
+                   "the number of inputs and targets differ");
+        Dataset {
+            inputs,
+            targets,
+            input_count,
+            output_count,
+        }
+    }
+
+    /// The number of samples.
+    pub fn len(&self) -> usize {
+        self.inputs.len() / self.input_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// The input of sample `i`.
+    pub fn input(&self, i: usize) -> &[f32] {
+        &self.inputs[i * self.input_count..(i + 1) * self.input_count]
+    }
+
+    /// The target of sample `i`.
+    pub fn target(&self, i: usize) -> &[f32] {
+        &self.targets[i * self.output_count..(i + 1) * self.output_count]
+    }
+
+    /// The class of sample `i`: the index of the largest target value,
+    /// or for a single target, 1 if it is at least 0.5 and otherwise 0.
+    pub fn class(&self, i: usize) -> usize {
+        let target = self.target(i);
+        if target.len() == 1 {
+            (target[0] >= 0.5) as usize
+        } else {
+            argmax(target)
+        }
+    }
+
+    /// The mean of every target value over the samples, zero if there
+    /// are none.
+    pub fn target_mean(&self) -> Vec<f32> {
+        column_mean(&self.targets, self.output_count)
+    }
+
+    /// The variance of every target value over the samples, zero if
+    /// there are none.
+    pub fn target_variance(&self) -> Vec<f32> {
+        column_variance(&self.targets, self.output_count)
+    }
+
+    /// Iterates over the (input, target) pairs of the samples.
+    pub fn iter(&self) -> ::std::iter::Zip<Chunks<'_, f32>, Chunks<'_, f32>> {
+        self.inputs.chunks(self.input_count).zip(self.targets.chunks(self.output_count))
+    }
+}
+
+/// The mean of every column of the rows, which are stored one after
+/// another.
+pub(crate) fn column_mean(values: &[f32], columns: usize) -> Vec<f32> {
+    let rows = (values.len() / columns).max(1) as f32;
+    let mut mean = vec![0.0; columns];
+    for row in values.chunks(columns) {
+        for (m, v) in mean.iter_mut().zip(row) {
+            *m += v / rows;
+        }
+    }
+    mean
+}
+
+/// The variance of every column of the rows, which are stored one
+/// after another.
+pub(crate) fn column_variance(values: &[f32], columns: usize) -> Vec<f32> {
+    let rows = (values.len() / columns).max(1) as f32;
+    let mean = column_mean(values, columns);
+    let mut variance = vec![0.0; columns];
+    for row in values.chunks(columns) {
+        for ((s, m), v) in variance.iter_mut().zip(&mean).zip(row) {
+            *s += (v - m) * (v - m) / rows;
+        }
+    }
+    variance
+}
+
+/// Samples where the input is a sequence of vectors and the target a
+/// single vector. Sequences of different lengths are padded with
+/// zeros to the length of the longest one, and a mask tells which time
+/// steps are real.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SequenceDataset {
+    /// The padded sequences, sample by sample and step by step
+    pub inputs: Vec<f32>,
+    /// Which steps of each sample are real, sample by sample
+    pub mask: Vec<bool>,
+    pub targets: Vec<f32>,
+    /// The number of steps of every padded sequence
+    pub steps: usize,
+    /// The number of values in each step of a sequence
+    pub input_count: usize,
+    /// The number of values in each target
+    pub output_count: usize,
+}
+
+impl SequenceDataset {
+    /// Pads the sequences, each a flat list of steps with `input_count`
+    /// values, to the same length.
+    pub fn from_sequences(sequences: &[Vec<f32>],
+                          targets: Vec<f32>,
+                          input_count: usize,
+                          output_count: usize)
+                          -> SequenceDataset {
+        assert!(input_count > 0 && output_count > 0, "inputs and targets must not be empty");
+        assert_eq!(sequences.len(), targets.len() / output_count,
+                   "the number of sequences and targets differ");
+        let steps = sequences.iter().map(|s| s.len() / input_count).max().unwrap_or(0);
+        let mut inputs = Vec::with_capacity(sequences.len() * steps * input_count);
+        let mut mask = Vec::with_capacity(sequences.len() * steps);
+        for s in sequences {
+            assert_eq!(s.len() % input_count, 0, "a sequence does not divide into steps");
+            let length = s.len() / input_count;
+            inputs.extend_from_slice(s);
+            inputs.extend(vec![0.0; (steps - length) * input_count]);
+            mask.extend((0..steps).map(|t| t < length));
+        }
+        SequenceDataset {
+            inputs,
+            mask,
+            targets,
+            steps,
+            input_count,
+            output_count,
+        }
+    }
+
+    /// The number of samples.
+    pub fn len(&self) -> usize {
+        self.targets.len() / self.output_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// The padded sequence of sample `i`.
+    pub fn sequence(&self, i: usize) -> &[f32] {
+        let size = self.steps * self.input_count;
+        &self.inputs[i * size..(i + 1) * size]
+    }
+
+    /// The padded sequence of sample `i`, with shape (steps,
+    /// input_count).
+    pub fn sequence_tensor(&self, i: usize) -> Tensor {
+        Tensor::new(self.sequence(i).to_vec(), &[self.steps, self.input_count])
+    }
+
+    /// Which steps of sample `i` are real.
+    pub fn mask(&self, i: usize) -> &[bool] {
+        &self.mask[i * self.steps..(i + 1) * self.steps]
+    }
+
+    /// The padded sequence of sample `i` followed by its mask, 1 for a
+    /// real step and 0 for padding, which is the input of
+    /// `layers::MaskLayer`.
+    pub fn masked_sequence(&self, i: usize) -> Vec<f32> {
+        let mut values = self.sequence(i).to_vec();
+        values.extend(self.mask(i).iter().map(|m| if *m { 1.0 } else { 0.0 }));
+        values
+    }
+
+    /// The samples as a `Dataset` of masked sequences, to train a
+    /// network which starts with a `layers::MaskLayer`.
+    pub fn masked_dataset(&self) -> Dataset {
+        let inputs = (0..self.len()).flat_map(|i| self.masked_sequence(i)).collect();
+        Dataset::new(inputs,
+                     self.targets.clone(),
+                     self.steps * (self.input_count + 1),
+                     self.output_count)
+    }
+
+    /// The number of real steps of sample `i`.
+    pub fn length(&self, i: usize) -> usize {
+        self.mask(i).iter().filter(|m| **m).count()
+    }
+
+    /// The target of sample `i`.
+    pub fn target(&self, i: usize) -> &[f32] {
+        &self.targets[i * self.output_count..(i + 1) * self.output_count]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dataset_samples() {
+        let d = Dataset::new(vec![1.0, 2.0, 3.0, 4.0], vec![0.0, 1.0], 2, 1);
+
+        assert_eq!(d.len(), 2);
+        assert_eq!(d.input(1), &[3.0, 4.0]);
+        assert_eq!(d.target(1), &[1.0]);
+        assert_eq!(d.iter().count(), 2);
+        assert_eq!(d.target_mean(), vec![0.5]);
+        assert_eq!((d.class(0), d.class(1)), (0, 1));
+        assert_eq!(d.target_variance(), vec![0.25]);
+    }
+
+    #[test]
+    fn sequences_are_padded() {
+        let d = SequenceDataset::from_sequences(&[vec![1.0, 2.0, 3.0, 4.0], vec![5.0, 6.0]], vec![0.0, 1.0], 2, 1);
+
+        assert_eq!(d.steps, 2);
+        assert_eq!(d.sequence(1), &[5.0, 6.0, 0.0, 0.0]);
+        assert_eq!(d.sequence_tensor(1).row(0).to_vec(), vec![5.0, 6.0]);
+        assert_eq!(d.mask(1), &[true, false]);
+        assert_eq!(d.length(0), 2);
+        assert_eq!(d.target(1), &[1.0]);
+        assert_eq!(d.masked_sequence(1), vec![5.0, 6.0, 0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(d.masked_dataset().input(0), &[1.0, 2.0, 3.0, 4.0, 1.0, 1.0]);
+    }
+}
@@ -0,0 +1,106 @@
+//! Transformations of regression targets, such as standardizing them
+//! or taking their logarithm, so that the network learns values of a
+//! convenient scale. The outputs of the network are mapped back with
+//! the inverse transformation.
+use data::Dataset;
+
+/// An invertible change of the targets of every sample.
+pub trait TargetTransform: Send + Sync {
+    /// Transforms a target, in place.
+    fn forward(&self, target: &mut [f32]);
+
+    /// Maps an output of the network back to the units of the
+    /// original targets, in place.
+    fn inverse(&self, output: &mut [f32]);
+
+    /// Transforms the targets of many samples, one after another.
+    fn forward_all(&self, targets: &[f32], output_count: usize) -> Vec<f32> {
+        let mut result = targets.to_vec();
+        for t in result.chunks_mut(output_count) {
+            self.forward(t);
+        }
+        result
+    }
+}
+
+/// Shifts and scales every output to zero mean and unit variance over
+/// the training targets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Standardize {
+    pub mean: Vec<f32>,
+    /// The standard deviation of every output, or 1 where it is zero
+    pub std: Vec<f32>,
+}
+
+impl Standardize {
+    /// Measures the mean and standard deviation of every output of the
+    /// targets of the dataset.
+    pub fn fit(data: &Dataset) -> Standardize {
+        Standardize {
+            mean: data.target_mean(),
+            std: data.target_variance().iter().map(|v| if *v > 0.0 { v.sqrt() } else { 1.0 }).collect(),
+        }
+    }
+}
+
+impl TargetTransform for Standardize {
+    fn forward(&self, target: &mut [f32]) {
+        for ((v, m), s) in target.iter_mut().zip(&self.mean).zip(&self.std) {
+            *v = (*v - m) / s;
+        }
+    }
+
+    fn inverse(&self, output: &mut [f32]) {
+        for ((v, m), s) in output.iter_mut().zip(&self.mean).zip(&self.std) {
+            *v = *v * s + m;
+        }
+    }
+}
+
+/// `ln(1 + y)`, for positive targets which span orders of magnitude,
+/// such as prices or counts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LogTransform;
+
+impl TargetTransform for LogTransform {
+    fn forward(&self, target: &mut [f32]) {
+        for v in target.iter_mut() {
+            *v = v.ln_1p();
+        }
+    }
+
+    fn inverse(&self, output: &mut [f32]) {
+        for v in output.iter_mut() {
+            *v = v.exp_m1();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standardize_round_trip() {
+        let data = Dataset::new(vec![0.0, 1.0, 2.0], vec![1.0, 5.0, 3.0, 5.0, 5.0, 5.0], 1, 2);
+        let s = Standardize::fit(&data);
+        assert_eq!(s.mean, vec![3.0, 5.0]);
+        assert_eq!(s.std[1], 1.0);
+
+        let transformed = s.forward_all(&data.targets, 2);
+        assert_eq!(transformed[1], 0.0);
+        let mut first = transformed[..2].to_vec();
+        s.inverse(&mut first);
+        assert_eq!(first, vec![1.0, 5.0]);
+    }
+
+    #[test]
+    fn log_round_trip() {
+        let mut v = vec![0.0, 99.0];
+        LogTransform.forward(&mut v);
+        assert_eq!(v[0], 0.0);
+        assert!((v[1] - 100.0f32.ln()).abs() < 1e-6);
+        LogTransform.inverse(&mut v);
+        assert!((v[1] - 99.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,128 @@
+//! Diagnostics of how the units of a network behave on a dataset.
+use layers::Activation;
+use sgd::forward;
+use traits::WeightedLayer;
+
+use std::collections::LinkedList;
+
+/// Tanh outputs with a magnitude above this, and sigmoid outputs this
+/// close to 0 or 1, are considered saturated. Saturated units have
+/// near zero derivatives and barely learn.
+pub const SATURATION_THRESHOLD: f32 = 0.95;
+
+/// Saturation statistics of one activation layer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerSaturation {
+    /// Index of the layer in the network
+    pub layer: usize,
+    pub activation: Activation,
+    /// Fraction of unit outputs, over all units and samples, which
+    /// are saturated. Always zero for rectified and SELU layers.
+    pub saturated: f32,
+    /// Fraction of units which output zero for every sample. Such
+    /// rectified units are dead, since no gradient flows through them.
+    /// Always zero for other layers.
+    pub dead: f32,
+}
+
+/// Feeds the inputs through the network and reports saturated and
+/// dead units for every activation layer.
+pub fn saturation(layers: &LinkedList<Box<dyn WeightedLayer>>, inputs: &[f32]) -> Vec<LayerSaturation> {
+    let input_count = layers.front().map(|l| l.input_count()).unwrap_or(0);
+    let mut saturated: Vec<usize> = vec![0; layers.len()];
+    let mut alive: Vec<Vec<bool>> = layers.iter().map(|l| vec![false; l.output_count()]).collect();
+    let mut samples = 0;
+
+    for x in inputs.chunks(input_count) {
+        for (i, (l, (_, output))) in layers.iter().zip(forward(layers, x).iter()).enumerate() {
+            match l.activation() {
+                Some(Activation::Tanh) => {
+                    saturated[i] += output.iter().filter(|y| y.abs() > SATURATION_THRESHOLD).count();
+                }
+                Some(Activation::Sigmoid) => {
+                    saturated[i] += output
+                        .iter()
+                        .filter(|y| **y > SATURATION_THRESHOLD || **y < 1.0 - SATURATION_THRESHOLD)
+                        .count();
+                }
+                Some(Activation::Rectified) => {
+                    for (a, y) in alive[i].iter_mut().zip(output) {
+                        *a = *a || *y != 0.0;
+                    }
+                }
+                Some(Activation::Selu) | None => {}
+            }
+        }
+        samples += 1;
+    }
+
+    let mut result = Vec::new();
+    for (i, l) in layers.iter().enumerate() {
+        if let Some(activation) = l.activation() {
+            let units = l.output_count();
+            let (sat, dead) = match activation {
+                Activation::Rectified => {
+                    (0.0, alive[i].iter().filter(|a| !**a).count() as f32 / units.max(1) as f32)
+                }
+                _ => (saturated[i] as f32 / (units * samples).max(1) as f32, 0.0),
+            };
+            result.push(LayerSaturation {
+                layer: i,
+                activation,
+                saturated: sat,
+                dead,
+            });
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{DenseLayer, HyperbolicLayer, RectifiedLayer, SigmoidLayer, WeightLayout};
+
+    #[test]
+    fn saturated_and_dead_units() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer {
+            weights: vec![10.0, 0.1, -1.0, -1.0],
+            bias: vec![0.0; 4],
+            shape: (1, 4),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
+        }));
+        layers.push_back(Box::new(HyperbolicLayer { size: 4 }));
+        layers.push_back(Box::new(SigmoidLayer { size: 4 }));
+        layers.push_back(Box::new(RectifiedLayer { size: 4 }));
+
+        let report = saturation(&layers, &[1.0, 2.0]);
+
+        assert_eq!(report.len(), 3);
+        assert_eq!((report[0].layer, report[0].activation), (1, Activation::Tanh));
+        // The first unit saturates for both samples, the third and
+        // fourth only for the second sample where tanh(-2) < -0.95
+        assert_eq!(report[0].saturated, 4.0 / 8.0);
+        assert_eq!(report[1].saturated, 0.0);
+        // Sigmoid outputs are always positive, so no units are dead
+        assert_eq!(report[2].dead, 0.0);
+    }
+
+    #[test]
+    fn dead_rectified_units() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer {
+            weights: vec![1.0, -1.0],
+            bias: vec![0.0, 0.0],
+            shape: (1, 2),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
+        }));
+        layers.push_back(Box::new(RectifiedLayer { size: 2 }));
+
+        let report = saturation(&layers, &[1.0, 2.0]);
+        assert_eq!(report[0].dead, 0.5);
+    }
+}
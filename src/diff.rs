@@ -0,0 +1,190 @@
+//! Comparison of two networks of the same architecture, such as a
+//! network before and after training, or a network and its restored
+//! checkpoint.
+use traits::WeightedLayer;
+
+use std::collections::LinkedList;
+use std::fmt;
+
+/// How the parameters of one layer differ between two networks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerDiff {
+    /// Index of the layer in the network
+    pub layer: usize,
+    /// The number of weights and biases compared
+    pub parameters: usize,
+    /// The euclidean norm of the parameters of the first network
+    pub norm: f32,
+    /// The euclidean norm of the change from the first network to the
+    /// second
+    pub delta_norm: f32,
+    /// The largest absolute change of any parameter
+    pub max_change: f32,
+    /// The cosine similarity of the parameters of the two networks,
+    /// which is 1 if they point in the same direction. Defined as 1
+    /// if both are zero.
+    pub cosine: f32,
+}
+
+/// The differences between two networks, layer by layer. Layers
+/// without parameters are left out. Displays as a table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelDiff {
+    pub layers: Vec<LayerDiff>,
+}
+
+impl ModelDiff {
+    /// True if no parameter differs.
+    pub fn is_identical(&self) -> bool {
+        self.layers.iter().all(|l| l.max_change == 0.0)
+    }
+}
+
+/// Compares the weights and biases of two networks. Panics if the
+/// networks do not have the same architecture.
+pub fn diff(a: &LinkedList<Box<dyn WeightedLayer>>, b: &LinkedList<Box<dyn WeightedLayer>>) -> ModelDiff {
+    assert_eq!(a.len(), b.len(), "the networks have different numbers of layers");
+    let mut layers = Vec::new();
+    for (i, (la, lb)) in a.iter().zip(b.iter()).enumerate() {
+        let pa = parameters(la.as_ref());
+        let pb = parameters(lb.as_ref());
+        assert!(la.input_count() == lb.input_count() && la.output_count() == lb.output_count() &&
+                pa.len() == pb.len(),
+                "layer {} has a different shape in the two networks",
+                i);
+        if pa.is_empty() {
+            continue;
+        }
+        layers.push(layer_diff(i, &pa, &pb));
+    }
+    ModelDiff { layers }
+}
+
+/// The weights, one row per neuron so that layers of different
+/// layouts compare, followed by the biases of a layer.
+fn parameters(layer: &dyn WeightedLayer) -> Vec<f32> {
+    let mut p = layer.neuron_weights().map_or(Vec::new(), |w| w.into_owned());
+    if let Some(b) = layer.bias() {
+        p.extend_from_slice(b);
+    }
+    p
+}
+
+fn layer_diff(layer: usize, a: &[f32], b: &[f32]) -> LayerDiff {
+    let norm = |v: &mut dyn Iterator<Item = f32>| v.map(|x| x * x).sum::<f32>().sqrt();
+    let norm_a = norm(&mut a.iter().cloned());
+    let norm_b = norm(&mut b.iter().cloned());
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let cosine = if norm_a == 0.0 && norm_b == 0.0 {
+        1.0
+    } else if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    };
+    LayerDiff {
+        layer,
+        parameters: a.len(),
+        norm: norm_a,
+        delta_norm: norm(&mut a.iter().zip(b).map(|(x, y)| y - x)),
+        max_change: a.iter().zip(b).fold(0.0f32, |m, (x, y)| m.max((y - x).abs())),
+        cosine,
+    }
+}
+
+impl fmt::Display for ModelDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f,
+                 "{:>5} {:>10} {:>12} {:>12} {:>12} {:>8}",
+                 "layer",
+                 "params",
+                 "norm",
+                 "delta norm",
+                 "max change",
+                 "cosine")?;
+        for l in &self.layers {
+            writeln!(f,
+                     "{:>5} {:>10} {:>12.6} {:>12.6} {:>12.6} {:>8.5}",
+                     l.layer,
+                     l.parameters,
+                     l.norm,
+                     l.delta_norm,
+                     l.max_change,
+                     l.cosine)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{DenseLayer, SigmoidLayer, WeightLayout};
+    use random::SeededRng;
+
+    fn network(first: f32) -> LinkedList<Box<dyn WeightedLayer>> {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(first, 2, 1)));
+        layers.push_back(Box::new(SigmoidLayer { size: 1 }));
+        layers.push_back(Box::new(DenseLayer::uniform(1.0, 1, 1)));
+        layers
+    }
+
+    #[test]
+    fn identical_networks() {
+        let d = diff(&network(1.0), &network(1.0));
+        assert_eq!(d.layers.len(), 2);
+        assert!(d.is_identical());
+        assert_eq!(d.layers[0].cosine, 1.0);
+        assert_eq!(d.layers[1].layer, 2);
+    }
+
+    #[test]
+    fn layouts_compare_by_neuron() {
+        let network = |layout| {
+            let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+            layers.push_back(Box::new(DenseLayer::random_with(3, 2, &mut SeededRng::new(2)).with_layout(layout)));
+            layers
+        };
+        assert!(diff(&network(WeightLayout::NeuronMajor), &network(WeightLayout::InputMajor)).is_identical());
+    }
+
+    #[test]
+    fn tied_layers_compare_shared_weights() {
+        let network = |weight: f32| {
+            let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+            layers.push_back(Box::new(DenseLayer::uniform(weight, 2, 1).tied()));
+            layers
+        };
+        let d = diff(&network(1.0), &network(2.0));
+        assert_eq!(d.layers[0].parameters, 3);
+        assert_eq!(d.layers[0].max_change, 1.0);
+    }
+
+    #[test]
+    fn changed_layer() {
+        let d = diff(&network(1.0), &network(-2.0));
+        assert!(!d.is_identical());
+
+        let l = &d.layers[0];
+        assert_eq!(l.parameters, 3);
+        assert_eq!(l.norm, 3.0f32.sqrt());
+        assert_eq!(l.delta_norm, 27.0f32.sqrt());
+        assert_eq!(l.max_change, 3.0);
+        assert!((l.cosine + 1.0).abs() < 1e-6);
+        assert_eq!(d.layers[1].max_change, 0.0);
+
+        let table = d.to_string();
+        assert_eq!(table.lines().count(), 3);
+        assert!(table.lines().nth(1).unwrap().contains("-1.00000"));
+    }
+
+    #[test]
+    #[should_panic(expected = "different shape")]
+    fn different_architectures() {
+        let mut other = network(1.0);
+        other.pop_back();
+        other.push_back(Box::new(DenseLayer::uniform(1.0, 1, 2)));
+        diff(&network(1.0), &other);
+    }
+}
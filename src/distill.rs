@@ -0,0 +1,112 @@
+//! Knowledge distillation, where a smaller student network is
+//! trained to mimic the softened outputs of a larger teacher network.
+use loss::{KLDivergence, SoftmaxCrossEntropy};
+use sgd::{forward, SGDTrainer};
+use traits::{LossFunction, DifferentiableLossFunction, WeightedLayer};
+use utils::{softmax_with_temperature, sub};
+
+use std::collections::LinkedList;
+
+/// Trains the student to match the outputs of the teacher on the
+/// given inputs. Both networks should output logits, i.e. they should
+/// not end with a softmax layer. The teacher's logits are turned into
+/// soft targets with `softmax_with_temperature`, and the student is
+/// trained to minimize `T^2 * KL(soft targets || softmax(z / T))` where
+/// `z` are the student's logits and `T` is the temperature.
+///
+/// If `hard` is given as `(targets, weight)`, the loss is mixed with
+/// the softmax cross-entropy against the true targets:
+/// `weight * hard loss + (1 - weight) * soft loss`.
+///
+/// Uses the rate and epochs of the trainer, with one update per
+/// epoch. Returns the average loss per sample for each epoch.
+pub fn distill(trainer: &SGDTrainer,
+               teacher: &LinkedList<Box<dyn WeightedLayer>>,
+               student: &mut LinkedList<Box<dyn WeightedLayer>>,
+               inputs: &[f32],
+               temperature: f32,
+               hard: Option<(&[f32], f32)>)
+               -> Vec<f32> {
+    let input_count = student.front().map(|l| l.input_count()).unwrap_or(0);
+    let output_count = student.back().map(|l| l.output_count()).unwrap_or(0);
+    let (hard_targets, hard_weight) = hard.unwrap_or((&[], 0.0));
+
+    // The teacher doesn't change, so its soft targets are computed once
+    let mut soft_targets: Vec<f32> = Vec::with_capacity(inputs.len() / input_count * output_count);
+    for x in inputs.chunks(input_count) {
+        let outputs = forward(teacher, x);
+        let logits = outputs.last();
+        soft_targets.extend(softmax_with_temperature(logits, temperature));
+    }
+
+    let soft_loss = KLDivergence;
+    let hard_loss = SoftmaxCrossEntropy;
+    let mut history = Vec::with_capacity(trainer.epochs);
+
+    for _ in 0..trainer.epochs {
+        let mut updates = trainer.new_updates(student);
+        let mut total = 0.0;
+        let mut count = 0;
+        for (i, (x, p)) in inputs.chunks(input_count).zip(soft_targets.chunks(output_count)).enumerate() {
+            let outputs = forward(student, x);
+            let delta;
+            {
+                let z = outputs.last();
+                let q = softmax_with_temperature(z, temperature);
+
+                // The derivative of the KL term with respect to the
+                // logits simplifies to (q - p) / T, which is scaled
+                // by T^2 to keep its magnitude independent of T.
+                let mut loss = temperature * temperature * soft_loss.loss_vec(&q, p);
+                let mut d: Vec<f32> = sub(&q, p).iter().map(|v| v * temperature).collect();
+
+                if hard_weight > 0.0 {
+                    let t = &hard_targets[i * output_count..(i + 1) * output_count];
+                    loss = hard_weight * hard_loss.loss_vec(z, t) + (1.0 - hard_weight) * loss;
+                    let dh = hard_loss.deriv_vec(z, t);
+                    for (ds, dh) in d.iter_mut().zip(dh) {
+                        *ds = hard_weight * dh + (1.0 - hard_weight) * *ds;
+                    }
+                }
+                total += loss;
+                delta = d;
+            }
+            count += 1;
+            trainer.backward(student, &outputs, delta, &mut updates);
+        }
+        trainer.apply_updates(student, &mut updates, count);
+        history.push(total / count.max(1) as f32);
+    }
+    history
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{DenseLayer, WeightLayout};
+
+    #[test]
+    fn student_approaches_teacher() {
+        let mut teacher: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        teacher.push_back(Box::new(DenseLayer {
+            weights: vec![2.0, -1.0, -1.0, 2.0],
+            bias: vec![0.0, 0.0],
+            shape: (2, 2),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
+        }));
+        let mut student: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        student.push_back(Box::new(DenseLayer::uniform(0.0, 2, 2)));
+
+        let inputs = vec![1.0, 0.0, 0.0, 1.0];
+        let trainer = SGDTrainer::new(200, 0.5);
+
+        let history = distill(&trainer, &teacher, &mut student, &inputs, 2.0, None);
+        assert!(history[199] < history[0] * 0.1);
+
+        let hard = vec![1.0, 0.0, 0.0, 1.0];
+        let history = distill(&trainer, &teacher, &mut student, &inputs, 2.0, Some((&hard, 0.5)));
+        assert!(history[199] <= history[0]);
+    }
+}
@@ -0,0 +1,221 @@
+//! Losses and training for learning embeddings, where the same
+//! network (with shared weights) is applied to pairs or triplets of
+//! samples and trained so that similar samples end up close together.
+use sgd::{forward, SGDTrainer};
+use traits::WeightedLayer;
+use utils::dot;
+
+use std::collections::LinkedList;
+
+/// Contrastive loss for a pair of embeddings `a` and `b` at distance
+/// `d = |a - b|`. Similar pairs are pulled together with `e = d^2`,
+/// while dissimilar pairs are pushed apart until they are at least
+/// `margin` apart, with `e = max(0, margin - d)^2`.
+pub struct ContrastiveLoss {
+    pub margin: f32,
+}
+
+impl ContrastiveLoss {
+    /// Loss of a single pair.
+    pub fn loss(&self, a: &[f32], b: &[f32], similar: bool) -> f32 {
+        let d = distance(a, b);
+        if similar {
+            d * d
+        } else {
+            let m = (self.margin - d).max(0.0);
+            m * m
+        }
+    }
+
+    /// Derivatives of the loss with respect to `a` and `b`.
+    pub fn deriv(&self, a: &[f32], b: &[f32], similar: bool) -> (Vec<f32>, Vec<f32>) {
+        let diff: Vec<f32> = a.iter().zip(b).map(|(x, y)| x - y).collect();
+        let d = dot(&diff, &diff).sqrt();
+        let scale = if similar {
+            2.0
+        } else if d < self.margin && d > 0.0 {
+            -2.0 * (self.margin - d) / d
+        } else {
+            0.0
+        };
+        let da: Vec<f32> = diff.iter().map(|x| scale * x).collect();
+        let db: Vec<f32> = da.iter().map(|x| -x).collect();
+        (da, db)
+    }
+}
+
+/// Triplet loss for an anchor `a`, a positive `p` of the same kind
+/// and a negative `n` of a different kind: `e = max(0, |a - p|^2 -
+/// |a - n|^2 + margin)`. The negative should be further away from the
+/// anchor than the positive by at least `margin`.
+pub struct TripletLoss {
+    pub margin: f32,
+}
+
+impl TripletLoss {
+    /// Loss of a single triplet.
+    pub fn loss(&self, a: &[f32], p: &[f32], n: &[f32]) -> f32 {
+        let dp = distance(a, p);
+        let dn = distance(a, n);
+        (dp * dp - dn * dn + self.margin).max(0.0)
+    }
+
+    /// Derivatives of the loss with respect to `a`, `p` and `n`.
+    pub fn deriv(&self, a: &[f32], p: &[f32], n: &[f32]) -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+        if self.loss(a, p, n) <= 0.0 {
+            let zeros = vec![0.0; a.len()];
+            return (zeros.clone(), zeros.clone(), zeros);
+        }
+        let da = n.iter().zip(p).map(|(n, p)| 2.0 * (n - p)).collect();
+        let dp = a.iter().zip(p).map(|(a, p)| -2.0 * (a - p)).collect();
+        let dn = a.iter().zip(n).map(|(a, n)| 2.0 * (a - n)).collect();
+        (da, dp, dn)
+    }
+}
+
+/// Euclidean distance between two vectors.
+fn distance(a: &[f32], b: &[f32]) -> f32 {
+    let diff: Vec<f32> = a.iter().zip(b).map(|(x, y)| x - y).collect();
+    dot(&diff, &diff).sqrt()
+}
+
+/// Trains the layers on pairs of samples using the contrastive loss.
+/// Sample `i` of `first` is paired with sample `i` of `second`, and
+/// `similar[i]` tells whether the pair should be close together. Both
+/// samples are fed through the same layers and the gradients of both
+/// are summed before updating. Uses the rate and epochs of the
+/// trainer, with one update per epoch. Returns the average loss per
+/// pair for each epoch.
+pub fn train_pairs(trainer: &SGDTrainer,
+                   layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+                   loss: &ContrastiveLoss,
+                   first: &[f32],
+                   second: &[f32],
+                   similar: &[bool])
+                   -> Vec<f32> {
+    let input_count = layers.front().map(|l| l.input_count()).unwrap_or(0);
+    let mut history = Vec::with_capacity(trainer.epochs);
+
+    for _ in 0..trainer.epochs {
+        let mut updates = trainer.new_updates(layers);
+        let mut total = 0.0;
+        let mut count = 0;
+        for ((a, b), s) in first.chunks(input_count).zip(second.chunks(input_count)).zip(similar) {
+            let oa = forward(layers, a);
+            let ob = forward(layers, b);
+            let ya = oa.last();
+            let yb = ob.last();
+
+            total += loss.loss(ya, yb, *s);
+            let (da, db) = loss.deriv(ya, yb, *s);
+            trainer.backward(layers, &oa, da, &mut updates);
+            trainer.backward(layers, &ob, db, &mut updates);
+            count += 1;
+        }
+        trainer.apply_updates(layers, &mut updates, count);
+        history.push(total / count.max(1) as f32);
+    }
+    history
+}
+
+/// Trains the layers on triplets of samples using the triplet loss.
+/// Sample `i` of `anchors`, `positives` and `negatives` make up a
+/// triplet. All three are fed through the same layers and their
+/// gradients are summed before updating. Uses the rate and epochs of
+/// the trainer, with one update per epoch. Returns the average loss
+/// per triplet for each epoch.
+pub fn train_triplets(trainer: &SGDTrainer,
+                      layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+                      loss: &TripletLoss,
+                      anchors: &[f32],
+                      positives: &[f32],
+                      negatives: &[f32])
+                      -> Vec<f32> {
+    let input_count = layers.front().map(|l| l.input_count()).unwrap_or(0);
+    let mut history = Vec::with_capacity(trainer.epochs);
+
+    for _ in 0..trainer.epochs {
+        let mut updates = trainer.new_updates(layers);
+        let mut total = 0.0;
+        let mut count = 0;
+        for ((a, p), n) in anchors.chunks(input_count)
+            .zip(positives.chunks(input_count))
+            .zip(negatives.chunks(input_count)) {
+            let oa = forward(layers, a);
+            let op = forward(layers, p);
+            let on = forward(layers, n);
+            let ya = oa.last();
+            let yp = op.last();
+            let yn = on.last();
+
+            total += loss.loss(ya, yp, yn);
+            let (da, dp, dn) = loss.deriv(ya, yp, yn);
+            trainer.backward(layers, &oa, da, &mut updates);
+            trainer.backward(layers, &op, dp, &mut updates);
+            trainer.backward(layers, &on, dn, &mut updates);
+            count += 1;
+        }
+        trainer.apply_updates(layers, &mut updates, count);
+        history.push(total / count.max(1) as f32);
+    }
+    history
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{DenseLayer, WeightLayout};
+
+    #[test]
+    fn contrastive_loss() {
+        let l = ContrastiveLoss { margin: 1.0 };
+
+        assert_eq!(l.loss(&[0.0, 0.0], &[0.0, 2.0], true), 4.0);
+        assert_eq!(l.loss(&[0.0, 0.0], &[0.0, 2.0], false), 0.0);
+        assert_eq!(l.loss(&[0.0, 0.0], &[0.0, 0.5], false), 0.25);
+
+        assert_eq!(l.deriv(&[0.0, 0.0], &[0.0, 2.0], true),
+                   (vec![0.0, -4.0], vec![0.0, 4.0]));
+        assert_eq!(l.deriv(&[0.0, 0.0], &[0.0, 0.5], false),
+                   (vec![0.0, 1.0], vec![0.0, -1.0]));
+    }
+
+    #[test]
+    fn triplet_loss() {
+        let l = TripletLoss { margin: 1.0 };
+
+        // Negative is far enough away
+        assert_eq!(l.loss(&[0.0], &[1.0], &[3.0]), 0.0);
+        assert_eq!(l.deriv(&[0.0], &[1.0], &[3.0]),
+                   (vec![0.0], vec![0.0], vec![0.0]));
+        // Negative is too close
+        assert_eq!(l.loss(&[0.0], &[1.0], &[1.0]), 1.0);
+        assert_eq!(l.deriv(&[0.0], &[1.0], &[1.0]),
+                   (vec![0.0], vec![2.0], vec![-2.0]));
+    }
+
+    #[test]
+    fn pairs_move_apart() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer {
+            weights: vec![1.0, 0.0, 0.0, 1.0],
+            bias: vec![0.0, 0.0],
+            shape: (2, 2),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
+        }));
+
+        let trainer = SGDTrainer::new(50, 0.05);
+        let loss = ContrastiveLoss { margin: 2.0 };
+        let history = train_pairs(&trainer,
+                                  &mut layers,
+                                  &loss,
+                                  &[1.0, 0.0],
+                                  &[0.0, 1.0],
+                                  &[false]);
+
+        assert_eq!(history.len(), 50);
+        assert!(history[49] < history[0]);
+    }
+}
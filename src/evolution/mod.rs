@@ -0,0 +1,119 @@
+//! Training by neuroevolution. Instead of following gradients, a
+//! population of weight vectors is evolved through selection,
+//! crossover and mutation. This works for any layers, including ones
+//! without derivatives, but scales poorly with the number of weights.
+use loss::SquaredError;
+use network;
+use random::RngProvider;
+use traits::{LossFunction, SupervisedTrainer, WeightedLayer};
+
+use std::collections::LinkedList;
+
+pub mod population;
+
+use self::population::{crossover, mutate, Individual, Population};
+
+/// A genetic algorithm trainer.
+pub struct EvolutionTrainer {
+    /// The number of generations to evolve
+    pub generations: usize,
+    /// The number of individuals in each generation
+    pub population_size: usize,
+    /// The number of best individuals copied unchanged to the next
+    /// generation
+    pub elites: usize,
+    /// The number of individuals competing in each tournament
+    /// selection
+    pub tournament_size: usize,
+    /// The probability of mutating each weight
+    pub mutation_rate: f32,
+    /// The standard deviation of mutations
+    pub mutation_strength: f32,
+    /// The loss function to minimize
+    pub loss: Box<dyn LossFunction>,
+    /// Source of randomness for mutation and selection
+    pub rng: RngProvider,
+}
+
+impl EvolutionTrainer {
+    pub fn new(generations: usize, population_size: usize) -> EvolutionTrainer {
+        EvolutionTrainer {
+            generations,
+            population_size,
+            elites: 2,
+            tournament_size: 3,
+            mutation_rate: 0.1,
+            mutation_strength: 0.5,
+            loss: Box::new(SquaredError),
+            rng: RngProvider::from_entropy(),
+        }
+    }
+}
+
+impl SupervisedTrainer for EvolutionTrainer {
+    fn train(&self, layers: &mut LinkedList<Box<dyn WeightedLayer>>, inputs: &[f32], targets: &[f32]) {
+        let mut rng = self.rng.fork();
+        let seed = network::get_weights(layers);
+        let mut population = Population::from_seed(&seed,
+                                                   self.population_size.max(1),
+                                                   self.mutation_strength,
+                                                   &mut rng);
+
+        let evaluate = |population: &mut Population, layers: &mut LinkedList<Box<dyn WeightedLayer>>| {
+            population.evaluate(|genes| {
+                network::set_weights(layers, genes);
+                network::loss(layers, self.loss.as_ref(), inputs, targets)
+            });
+        };
+        evaluate(&mut population, layers);
+
+        for _ in 0..self.generations {
+            let mut next: Vec<Individual> = population.individuals
+                .iter()
+                .take(self.elites)
+                .cloned()
+                .collect();
+            while next.len() < population.individuals.len() {
+                let mut genes = {
+                    let a = population.tournament(self.tournament_size, &mut rng);
+                    let b = population.tournament(self.tournament_size, &mut rng);
+                    crossover(&a.genes, &b.genes, &mut rng)
+                };
+                mutate(&mut genes, self.mutation_rate, self.mutation_strength, &mut rng);
+                next.push(Individual {
+                    genes,
+                    loss: f32::INFINITY,
+                });
+            }
+            population.individuals = next;
+            evaluate(&mut population, layers);
+        }
+
+        network::set_weights(layers, &population.best().genes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::DenseLayer;
+
+    #[test]
+    fn evolve_linear_function() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(0.0, 1, 1)));
+
+        // y = 2x + 1
+        let inputs = vec![-1.0, 0.0, 1.0, 2.0];
+        let targets = vec![-1.0, 1.0, 3.0, 5.0];
+
+        let before = network::loss(&layers, &SquaredError, &inputs, &targets);
+        let mut trainer = EvolutionTrainer::new(100, 30);
+        trainer.rng = RngProvider::seeded(1);
+        trainer.train(&mut layers, &inputs, &targets);
+        let after = network::loss(&layers, &SquaredError, &inputs, &targets);
+
+        assert!(after < before);
+        assert!(after < 0.5);
+    }
+}
@@ -0,0 +1,112 @@
+//! A population of candidate weight vectors, and the genetic
+//! operators used to evolve it.
+use rand::Rng;
+use rand::distributions::{Normal, IndependentSample};
+
+/// A candidate solution: a weight vector and its loss.
+#[derive(Debug, Clone)]
+pub struct Individual {
+    pub genes: Vec<f32>,
+    /// The loss of the genes. Lower is better.
+    pub loss: f32,
+}
+
+/// A set of individuals, kept sorted by loss once evaluated.
+#[derive(Debug, Clone)]
+pub struct Population {
+    pub individuals: Vec<Individual>,
+}
+
+impl Population {
+    /// Creates a population of `size` individuals, the first being
+    /// `seed` and the rest mutated copies of it.
+    pub fn from_seed<R: Rng>(seed: &[f32], size: usize, strength: f32, rng: &mut R) -> Population {
+        let mut individuals = Vec::with_capacity(size);
+        for i in 0..size {
+            let mut genes = seed.to_vec();
+            if i > 0 {
+                mutate(&mut genes, 1.0, strength, rng);
+            }
+            individuals.push(Individual {
+                genes,
+                loss: f32::INFINITY,
+            });
+        }
+        Population { individuals }
+    }
+
+    /// Evaluates every individual with the given loss function and
+    /// sorts the population from best to worst.
+    pub fn evaluate<F: FnMut(&[f32]) -> f32>(&mut self, mut loss: F) {
+        for i in self.individuals.iter_mut() {
+            i.loss = loss(&i.genes);
+            if i.loss.is_nan() {
+                i.loss = f32::INFINITY;
+            }
+        }
+        self.individuals.sort_by(|a, b| a.loss.partial_cmp(&b.loss).unwrap());
+    }
+
+    /// The individual with the lowest loss. Only valid after
+    /// `evaluate`.
+    pub fn best(&self) -> &Individual {
+        &self.individuals[0]
+    }
+
+    /// Picks the best of `size` randomly chosen individuals.
+    pub fn tournament<R: Rng>(&self, size: usize, rng: &mut R) -> &Individual {
+        let mut best = &self.individuals[rng.gen_range(0, self.individuals.len())];
+        for _ in 1..size {
+            let other = &self.individuals[rng.gen_range(0, self.individuals.len())];
+            if other.loss < best.loss {
+                best = other;
+            }
+        }
+        best
+    }
+}
+
+/// Uniform crossover: every gene is taken from either parent with
+/// equal probability.
+pub fn crossover<R: Rng>(a: &[f32], b: &[f32], rng: &mut R) -> Vec<f32> {
+    a.iter().zip(b).map(|(x, y)| if rng.gen() { *x } else { *y }).collect()
+}
+
+/// Adds gaussian noise with standard deviation `strength` to each gene
+/// with probability `rate`.
+pub fn mutate<R: Rng>(genes: &mut [f32], rate: f32, strength: f32, rng: &mut R) {
+    let normal = Normal::new(0.0, strength as f64);
+    for g in genes.iter_mut() {
+        if rng.gen::<f32>() < rate {
+            *g += normal.ind_sample(rng) as f32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use random::SeededRng;
+
+    #[test]
+    fn evaluate_sorts_by_loss() {
+        let mut rng = SeededRng::new(1);
+        let mut p = Population::from_seed(&[0.0, 0.0], 10, 1.0, &mut rng);
+        p.evaluate(|g| g[0].abs() + g[1].abs());
+
+        assert_eq!(p.individuals.len(), 10);
+        for w in p.individuals.windows(2) {
+            assert!(w[0].loss <= w[1].loss);
+        }
+        // The unmutated seed is optimal
+        assert_eq!(p.best().genes, vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn crossover_picks_from_parents() {
+        let mut rng = SeededRng::new(1);
+        let child = crossover(&[1.0; 20], &[2.0; 20], &mut rng);
+
+        assert!(child.iter().all(|g| *g == 1.0 || *g == 2.0));
+    }
+}
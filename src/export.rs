@@ -0,0 +1,205 @@
+//! Export of the weights and activations of a network to CSV files,
+//! for inspecting them in a spreadsheet or loading them into other
+//! tools.
+use data::Dataset;
+use sgd::forward;
+use traits::WeightedLayer;
+
+use std::collections::LinkedList;
+use std::fs::{self, File};
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Writes the weights of a layer as CSV with one row per neuron and
+/// the columns `neuron,bias,w0,w1,...`, where the weights are those
+/// of the neuron's inputs in order. The bias column is empty for
+/// layers without biases. Returns false without writing anything if
+/// the layer has no weights.
+pub fn write_layer_csv<W: Write>(layer: &dyn WeightedLayer, out: &mut W) -> io::Result<bool> {
+    let weights = match layer.neuron_weights() {
+        Some(w) if layer.neuron_count() > 0 => w,
+        _ => return Ok(false),
+    };
+    let per_neuron = weights.len() / layer.neuron_count();
+    let bias = layer.bias();
+
+    write!(out, "neuron,bias")?;
+    for i in 0..per_neuron {
+        write!(out, ",w{}", i)?;
+    }
+    writeln!(out)?;
+    for (n, w) in weights.chunks(per_neuron).enumerate() {
+        write!(out, "{},", n)?;
+        if let Some(b) = bias {
+            write!(out, "{}", b[n])?;
+        }
+        for v in w {
+            write!(out, ",{}", v)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(true)
+}
+
+/// Writes the weights of every layer which has any to `layer_<i>.csv`
+/// in the directory, where `i` is the index of the layer in the
+/// network, in the format of `write_layer_csv`. The directory is
+/// created if needed. An index, `layers.csv`, describes the shape of
+/// every layer with the columns
+/// `layer,file,inputs,outputs,neurons,weights,bias`, where `file` is
+/// empty for layers without weights. Returns the paths of the files
+/// written, with the index last.
+pub fn export_weights_csv(layers: &LinkedList<Box<dyn WeightedLayer>>, dir: &Path) -> io::Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+    let mut written = Vec::new();
+    let index_path = dir.join("layers.csv");
+    let mut index = BufWriter::new(File::create(&index_path)?);
+    writeln!(index, "layer,file,inputs,outputs,neurons,weights,bias")?;
+
+    for (i, l) in layers.iter().enumerate() {
+        let name = format!("layer_{}.csv", i);
+        let path = dir.join(&name);
+        let mut out = Vec::new();
+        let file = if write_layer_csv(l.as_ref(), &mut out)? {
+            fs::write(&path, out)?;
+            written.push(path);
+            name
+        } else {
+            String::new()
+        };
+        writeln!(index,
+                 "{},{},{},{},{},{},{}",
+                 i,
+                 file,
+                 l.input_count(),
+                 l.output_count(),
+                 l.neuron_count(),
+                 l.weights().map_or(0, |w| w.len()),
+                 l.bias().is_some())?;
+    }
+    index.flush()?;
+    written.push(index_path);
+    Ok(written)
+}
+
+/// Feeds every sample of the data through the network and writes the
+/// outputs of every layer to `activations_<i>.csv` in the directory,
+/// where `i` is the index of the layer. Every file has one row per
+/// sample with the columns `sample,t0,t1,...,a0,a1,...`, the targets
+/// of the sample followed by the outputs of the layer, so that the
+/// learned representations can be plotted by target. The directory is
+/// created if needed. Returns the paths of the files written, in the
+/// order of the layers.
+pub fn export_activations_csv(layers: &LinkedList<Box<dyn WeightedLayer>>,
+                              data: &Dataset,
+                              dir: &Path)
+                              -> io::Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+    let paths: Vec<PathBuf> = (0..layers.len()).map(|i| dir.join(format!("activations_{}.csv", i))).collect();
+    let mut files = Vec::with_capacity(layers.len());
+    for (path, l) in paths.iter().zip(layers.iter()) {
+        let mut out = BufWriter::new(File::create(path)?);
+        write!(out, "sample")?;
+        for t in 0..data.output_count {
+            write!(out, ",t{}", t)?;
+        }
+        for a in 0..l.output_count() {
+            write!(out, ",a{}", a)?;
+        }
+        writeln!(out)?;
+        files.push(out);
+    }
+
+    for (i, (x, t)) in data.iter().enumerate() {
+        for (out, (_, activation)) in files.iter_mut().zip(forward(layers, x).iter()) {
+            write!(out, "{}", i)?;
+            for v in t.iter().chain(activation) {
+                write!(out, ",{}", v)?;
+            }
+            writeln!(out)?;
+        }
+    }
+    for mut out in files {
+        out.flush()?;
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{DenseLayer, SigmoidLayer, WeightLayout};
+
+    use std::env;
+
+    #[test]
+    fn layer_to_csv() {
+        let l = DenseLayer {
+            weights: vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0],
+            bias: vec![0.5, -0.5],
+            shape: (3, 2),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
+        };
+        let mut csv = Vec::new();
+        assert!(write_layer_csv(&l, &mut csv).unwrap());
+        assert_eq!(String::from_utf8(csv).unwrap(),
+                   "neuron,bias,w0,w1,w2\n0,0.5,1,2,3\n1,-0.5,4,5,6\n");
+
+        let mut csv = Vec::new();
+        write_layer_csv(&l.without_bias(), &mut csv).unwrap();
+        assert!(String::from_utf8(csv).unwrap().contains("\n1,,4,5,6\n"));
+
+        // The rows are neurons whatever the layout
+        let mut csv = Vec::new();
+        let input_major = DenseLayer {
+            weights: vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0],
+            bias: vec![0.5, -0.5],
+            layout: WeightLayout::InputMajor,
+            ..DenseLayer::uniform(0.0, 3, 2)
+        };
+        write_layer_csv(&input_major, &mut csv).unwrap();
+        assert!(String::from_utf8(csv).unwrap().ends_with("\n0,0.5,1,2,3\n1,-0.5,4,5,6\n"));
+
+        assert!(!write_layer_csv(&SigmoidLayer { size: 2 }, &mut Vec::new()).unwrap());
+        let tied = DenseLayer::uniform(1.0, 3, 2).tied();
+        assert!(write_layer_csv(&tied, &mut Vec::new()).unwrap());
+        assert!(!write_layer_csv(&tied.transpose(), &mut Vec::new()).unwrap());
+    }
+
+    #[test]
+    fn network_to_directory() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(1.0, 2, 3)));
+        layers.push_back(Box::new(SigmoidLayer { size: 3 }));
+        layers.push_back(Box::new(DenseLayer::uniform(1.0, 3, 1)));
+
+        let dir = env::temp_dir().join(format!("scarecrow-export-{}", ::std::process::id()));
+        let files = export_weights_csv(&layers, &dir).unwrap();
+        assert_eq!(files, vec![dir.join("layer_0.csv"), dir.join("layer_2.csv"), dir.join("layers.csv")]);
+
+        let index = fs::read_to_string(dir.join("layers.csv")).unwrap();
+        assert_eq!(index.lines().nth(2), Some("1,,3,3,0,0,false"));
+        assert_eq!(index.lines().nth(3), Some("2,layer_2.csv,3,1,1,3,true"));
+        assert_eq!(fs::read_to_string(dir.join("layer_2.csv")).unwrap().lines().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn activations_to_directory() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(1.0, 2, 3)));
+        layers.push_back(Box::new(SigmoidLayer { size: 3 }));
+        let data = Dataset::new(vec![0.0, 0.0, 1.0, -2.0], vec![1.0, 0.0], 2, 1);
+
+        let dir = env::temp_dir().join(format!("scarecrow-activations-{}", ::std::process::id()));
+        let files = export_activations_csv(&layers, &data, &dir).unwrap();
+        assert_eq!(files, vec![dir.join("activations_0.csv"), dir.join("activations_1.csv")]);
+        assert_eq!(fs::read_to_string(&files[0]).unwrap(), "sample,t0,a0,a1,a2\n0,1,1,1,1\n1,0,0,0,0\n");
+        let sigmoid = fs::read_to_string(&files[1]).unwrap();
+        assert_eq!(sigmoid.lines().nth(2), Some("1,0,0.5,0.5,0.5"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,195 @@
+//! A C interface for inference, built with the `ffi` feature. A model
+//! is loaded from a file written by `serialize::save` and is kept in
+//! inference mode; it can not be trained through this interface. The
+//! shared library is built on demand with
+//! `cargo rustc --release --features ffi --crate-type cdylib`, and the
+//! declarations are in `include/scarecrow.h`:
+//!
+//! ```c
+//! ScarecrowModel *model = scarecrow_load_model("xor.net");
+//! float inputs[4] = {0, 1, 1, 1};
+//! float outputs[2];
+//! if (model && scarecrow_predict(model, inputs, 2, outputs) == SCARECROW_OK) {
+//!     printf("%f %f\n", outputs[0], outputs[1]);
+//! }
+//! scarecrow_free(model);
+//! ```
+//!
+//! Every function accepts a null model, and no function unwinds into
+//! the caller.
+use network;
+use serialize::LayerRegistry;
+use traits::WeightedLayer;
+
+use std::collections::LinkedList;
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::BufReader;
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+use std::slice;
+
+/// Returned by `scarecrow_predict` on success.
+pub const SCARECROW_OK: c_int = 0;
+/// Returned by `scarecrow_predict` when given a null pointer.
+pub const SCARECROW_NULL: c_int = -1;
+/// Returned by `scarecrow_predict` when the prediction failed.
+pub const SCARECROW_FAILED: c_int = -2;
+
+/// A loaded network in inference mode. Opaque to C.
+pub struct ScarecrowModel {
+    layers: LinkedList<Box<dyn WeightedLayer>>,
+}
+
+impl ScarecrowModel {
+    fn load(path: &Path) -> Option<ScarecrowModel> {
+        let file = File::open(path).ok()?;
+        let mut layers = LayerRegistry::new().load(BufReader::new(file)).ok()?;
+        network::set_training(&mut layers, false);
+        Some(ScarecrowModel { layers })
+    }
+
+    fn input_count(&self) -> usize {
+        self.layers.front().map_or(0, |l| l.input_count())
+    }
+
+    fn output_count(&self) -> usize {
+        self.layers.back().map_or(0, |l| l.output_count())
+    }
+}
+
+/// Loads a model written by `serialize::save`. Returns null if the
+/// file can not be read or parsed. The model is released with
+/// `scarecrow_free`.
+///
+/// # Safety
+///
+/// `path` must be null or point to a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn scarecrow_load_model(path: *const c_char) -> *mut ScarecrowModel {
+    if path.is_null() {
+        return ptr::null_mut();
+    }
+    let path = match CStr::from_ptr(path).to_str() {
+        Ok(p) => p,
+        Err(_) => return ptr::null_mut(),
+    };
+    match panic::catch_unwind(|| ScarecrowModel::load(Path::new(path))) {
+        Ok(Some(model)) => Box::into_raw(Box::new(model)),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// The number of inputs of every sample, or 0 for a null model.
+///
+/// # Safety
+///
+/// `model` must be null or returned by `scarecrow_load_model`.
+#[no_mangle]
+pub unsafe extern "C" fn scarecrow_input_count(model: *const ScarecrowModel) -> usize {
+    model.as_ref().map_or(0, |m| m.input_count())
+}
+
+/// The number of outputs of every sample, or 0 for a null model.
+///
+/// # Safety
+///
+/// `model` must be null or returned by `scarecrow_load_model`.
+#[no_mangle]
+pub unsafe extern "C" fn scarecrow_output_count(model: *const ScarecrowModel) -> usize {
+    model.as_ref().map_or(0, |m| m.output_count())
+}
+
+/// Feeds `samples` samples through the model and writes their outputs,
+/// one sample after another, to `outputs`. Returns `SCARECROW_OK` on
+/// success. A model may be used from several threads at once.
+///
+/// # Safety
+///
+/// `model` must be null or returned by `scarecrow_load_model`,
+/// `inputs` must hold `samples` times `scarecrow_input_count` values
+/// and `outputs` must have room for `samples` times
+/// `scarecrow_output_count` values.
+#[no_mangle]
+pub unsafe extern "C" fn scarecrow_predict(model: *const ScarecrowModel,
+                                           inputs: *const f32,
+                                           samples: usize,
+                                           outputs: *mut f32)
+                                           -> c_int {
+    let model = match model.as_ref() {
+        Some(m) => m,
+        None => return SCARECROW_NULL,
+    };
+    if samples == 0 {
+        return SCARECROW_OK;
+    }
+    if inputs.is_null() || outputs.is_null() {
+        return SCARECROW_NULL;
+    }
+    let inputs = slice::from_raw_parts(inputs, samples * model.input_count());
+    let outputs = slice::from_raw_parts_mut(outputs, samples * model.output_count());
+    let result = panic::catch_unwind(AssertUnwindSafe(|| network::predict_batch(&model.layers, inputs)));
+    match result {
+        Ok(ref values) if values.len() == outputs.len() => {
+            outputs.copy_from_slice(values);
+            SCARECROW_OK
+        }
+        _ => SCARECROW_FAILED,
+    }
+}
+
+/// Releases a model. Does nothing for a null model.
+///
+/// # Safety
+///
+/// `model` must be null or returned by `scarecrow_load_model`, and not
+/// be used again.
+#[no_mangle]
+pub unsafe extern "C" fn scarecrow_free(model: *mut ScarecrowModel) {
+    if !model.is_null() {
+        drop(Box::from_raw(model));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serialize;
+    use std::env;
+    use std::ffi::CString;
+    use std::fs;
+
+    #[test]
+    fn load_predict_free() {
+        let mut layers = LayerRegistry::new().parse_spec("dense inputs=2 neurons=3; tanh size=3").unwrap();
+        network::set_weights(&mut layers, &[0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.0, 0.1, -0.1]);
+        let path = env::temp_dir().join(format!("scarecrow-ffi-{}.net", ::std::process::id()));
+        serialize::save(&layers, &mut File::create(&path).unwrap()).unwrap();
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+
+        unsafe {
+            let model = scarecrow_load_model(c_path.as_ptr());
+            assert!(!model.is_null());
+            assert_eq!(scarecrow_input_count(model), 2);
+            assert_eq!(scarecrow_output_count(model), 3);
+
+            let inputs = [1.0, 0.0, 0.5, -1.0];
+            let mut outputs = [0.0; 6];
+            assert_eq!(scarecrow_predict(model, inputs.as_ptr(), 2, outputs.as_mut_ptr()), SCARECROW_OK);
+            assert_eq!(outputs.to_vec(), network::predict_batch(&layers, &inputs));
+
+            assert_eq!(scarecrow_predict(model, ptr::null(), 2, outputs.as_mut_ptr()), SCARECROW_NULL);
+            assert_eq!(scarecrow_predict(ptr::null(), inputs.as_ptr(), 2, outputs.as_mut_ptr()),
+                       SCARECROW_NULL);
+            scarecrow_free(model);
+            scarecrow_free(ptr::null_mut());
+
+            let missing = CString::new("/nonexistent/model.net").unwrap();
+            assert!(scarecrow_load_model(missing.as_ptr()).is_null());
+            assert!(scarecrow_load_model(ptr::null()).is_null());
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,273 @@
+//! Generative adversarial networks. A generator turns gaussian noise
+//! into samples, and a discriminator, which outputs a single logit,
+//! learns to tell them apart from real samples. The two are trained in
+//! turns, each with its own learning rate, until the generator's
+//! samples look like the real ones.
+//!
+//! `ToyDistribution` holds a few low dimensional distributions which
+//! are easy to inspect, to try out the adversarial training on.
+use loss::SigmoidCrossEntropy;
+use network;
+use random::{RngProvider, SeededRng};
+use sgd::{self, LayerGrads};
+use traits::{DifferentiableLossFunction, LossFunction, WeightedLayer};
+use utils::normal_vector;
+
+use rand::Rng;
+use std::collections::LinkedList;
+use std::f32::consts::PI;
+
+/// What the generator is trained to minimize, where `D(x)` is the
+/// probability that the discriminator gives to `x` being real.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GeneratorLoss {
+    /// `-log D(G(z))`, which has strong gradients early on when the
+    /// discriminator easily rejects the generated samples
+    #[default]
+    NonSaturating,
+    /// `log(1 - D(G(z)))`, the original minimax objective
+    Minimax,
+}
+
+/// The losses of one training step, averaged over the samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GanLosses {
+    pub discriminator: f32,
+    pub generator: f32,
+}
+
+/// Low dimensional distributions to train on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToyDistribution {
+    /// A one dimensional normal distribution
+    Gaussian { mean: f32, std: f32 },
+    /// A two dimensional mixture of `modes` normal distributions,
+    /// evenly spaced around a circle
+    Ring { modes: usize, radius: f32, std: f32 },
+}
+
+impl ToyDistribution {
+    /// The number of values in every sample.
+    pub fn dimensions(&self) -> usize {
+        match *self {
+            ToyDistribution::Gaussian { .. } => 1,
+            ToyDistribution::Ring { .. } => 2,
+        }
+    }
+
+    /// Draws `count` samples, one after another.
+    pub fn sample<R: Rng>(&self, count: usize, rng: &mut R) -> Vec<f32> {
+        let noise = normal_vector(count * self.dimensions(), rng);
+        match *self {
+            ToyDistribution::Gaussian { mean, std } => noise.iter().map(|n| mean + std * n).collect(),
+            ToyDistribution::Ring { modes, radius, std } => {
+                noise.chunks(2)
+                    .flat_map(|n| {
+                        let angle = 2.0 * PI * rng.gen_range(0, modes) as f32 / modes as f32;
+                        vec![radius * angle.cos() + std * n[0], radius * angle.sin() + std * n[1]]
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Trains a generator and a discriminator against each other.
+pub struct Gan {
+    /// The number of noise values the generator takes as input
+    pub latent: usize,
+    /// The number of real and of generated samples in every step
+    pub batch_size: usize,
+    pub generator_rate: f32,
+    pub discriminator_rate: f32,
+    /// The number of discriminator updates before every generator
+    /// update
+    pub discriminator_steps: usize,
+    pub generator_loss: GeneratorLoss,
+    /// Source of the noise and of the samples of `train`
+    pub rng: RngProvider,
+}
+
+/// Sums the gradients of several samples.
+fn add_grads(total: &mut Option<Vec<LayerGrads>>, grads: Vec<LayerGrads>) {
+    match *total {
+        Some(ref mut total) => {
+            for (t, g) in total.iter_mut().zip(&grads) {
+                t.add(g);
+            }
+        }
+        None => *total = Some(grads),
+    }
+}
+
+/// Takes a step with the mean of the summed gradients of `count`
+/// samples.
+fn apply_mean(layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+              grads: Option<Vec<LayerGrads>>,
+              count: usize,
+              rate: f32) {
+    if let Some(grads) = grads {
+        sgd::apply_gradients(layers, &grads, rate / count as f32);
+    }
+}
+
+impl Gan {
+    pub fn new(latent: usize) -> Gan {
+        Gan {
+            latent,
+            batch_size: 32,
+            generator_rate: 0.01,
+            discriminator_rate: 0.01,
+            discriminator_steps: 1,
+            generator_loss: GeneratorLoss::NonSaturating,
+            rng: RngProvider::from_entropy(),
+        }
+    }
+
+    /// Feeds `count` samples of noise through the generator.
+    pub fn generate(&self, generator: &LinkedList<Box<dyn WeightedLayer>>, count: usize) -> Vec<f32> {
+        let noise = self.rng.with_rng(|rng| normal_vector(count * self.latent, rng));
+        network::predict_batch(generator, &noise)
+    }
+
+    /// Updates the discriminator to output 1 for the real samples and 0
+    /// for as many generated ones. Returns the mean loss.
+    pub fn discriminator_step(&self,
+                              generator: &LinkedList<Box<dyn WeightedLayer>>,
+                              discriminator: &mut LinkedList<Box<dyn WeightedLayer>>,
+                              real: &[f32])
+                              -> f32 {
+        let dimensions = discriminator.front().map_or(1, |l| l.input_count());
+        let count = real.len() / dimensions;
+        let fake = self.generate(generator, count);
+        let discriminator = &mut *network::train_mode(discriminator);
+        let loss = SigmoidCrossEntropy;
+        let mut total = 0.0;
+        let mut grads = None;
+        for (samples, target) in [(real, 1.0), (&fake[..], 0.0)] {
+            for x in samples.chunks(dimensions) {
+                let y = network::output(discriminator, x);
+                total += loss.loss_vec(&y, &[target]);
+                add_grads(&mut grads, sgd::backward(discriminator, x, &[target], &loss));
+            }
+        }
+        apply_mean(discriminator, grads, 2 * count, self.discriminator_rate);
+        total / (2 * count).max(1) as f32
+    }
+
+    /// Updates the generator to make the discriminator take `count` of
+    /// its samples for real ones, leaving the discriminator as it is.
+    /// Returns the mean loss.
+    pub fn generator_step(&self,
+                          generator: &mut LinkedList<Box<dyn WeightedLayer>>,
+                          discriminator: &LinkedList<Box<dyn WeightedLayer>>,
+                          count: usize)
+                          -> f32 {
+        let noise = self.rng.with_rng(|rng| normal_vector(count * self.latent, rng));
+        let generator = &mut *network::train_mode(generator);
+        let loss = SigmoidCrossEntropy;
+        let mut total = 0.0;
+        let mut grads = None;
+        for z in noise.chunks(self.latent) {
+            let x = network::output(generator, z);
+            let y = network::output(discriminator, &x);
+            let delta = match self.generator_loss {
+                GeneratorLoss::NonSaturating => {
+                    total += loss.loss_vec(&y, &[1.0]);
+                    loss.deriv_vec(&y, &[1.0])
+                }
+                GeneratorLoss::Minimax => {
+                    total -= loss.loss_vec(&y, &[0.0]);
+                    loss.deriv_vec(&y, &[0.0]).iter().map(|d| -d).collect()
+                }
+            };
+            let (_, input_delta) = sgd::backward_delta(discriminator, &x, &delta);
+            add_grads(&mut grads, sgd::backward_delta(generator, z, &input_delta).0);
+        }
+        apply_mean(generator, grads, count, self.generator_rate);
+        total / count.max(1) as f32
+    }
+
+    /// Takes `discriminator_steps` discriminator steps on batches of the
+    /// real samples, followed by one generator step. `real` must hold
+    /// `discriminator_steps` batches of `batch_size` samples.
+    pub fn step(&self,
+                generator: &mut LinkedList<Box<dyn WeightedLayer>>,
+                discriminator: &mut LinkedList<Box<dyn WeightedLayer>>,
+                real: &[f32])
+                -> GanLosses {
+        let dimensions = discriminator.front().map_or(1, |l| l.input_count());
+        let mut loss = 0.0;
+        let mut batches = 0;
+        for batch in real.chunks(self.batch_size * dimensions).take(self.discriminator_steps) {
+            loss += self.discriminator_step(generator, discriminator, batch);
+            batches += 1;
+        }
+        GanLosses {
+            discriminator: loss / batches.max(1) as f32,
+            generator: self.generator_step(generator, discriminator, self.batch_size),
+        }
+    }
+
+    /// Trains for a number of steps on samples from a distribution, and
+    /// returns the losses of every step.
+    pub fn train(&self,
+                 generator: &mut LinkedList<Box<dyn WeightedLayer>>,
+                 discriminator: &mut LinkedList<Box<dyn WeightedLayer>>,
+                 distribution: &ToyDistribution,
+                 steps: usize)
+                 -> Vec<GanLosses> {
+        let count = self.batch_size * self.discriminator_steps;
+        (0..steps)
+            .map(|_| {
+                let real = self.rng.with_rng(|rng: &mut SeededRng| distribution.sample(count, rng));
+                self.step(generator, discriminator, &real)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{DenseLayer, HyperbolicLayer};
+
+    fn stats(values: &[f32]) -> (f32, f32) {
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        let var = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / values.len() as f32;
+        (mean, var.sqrt())
+    }
+
+    #[test]
+    fn toy_distributions() {
+        let mut rng = SeededRng::new(0);
+        let (mean, std) = stats(&ToyDistribution::Gaussian { mean: 2.0, std: 0.5 }.sample(2000, &mut rng));
+        assert!((mean - 2.0).abs() < 0.05 && (std - 0.5).abs() < 0.05);
+
+        let ring = ToyDistribution::Ring { modes: 8, radius: 2.0, std: 0.01 };
+        for p in ring.sample(100, &mut rng).chunks(2) {
+            assert!(((p[0] * p[0] + p[1] * p[1]).sqrt() - 2.0).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn generator_matches_gaussian() {
+        let mut rng = SeededRng::new(4);
+        let mut generator: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        generator.push_back(Box::new(DenseLayer::uniform(1.0, 1, 1)));
+        let mut discriminator: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        discriminator.push_back(Box::new(DenseLayer::random_with(1, 8, &mut rng)));
+        discriminator.push_back(Box::new(HyperbolicLayer { size: 8 }));
+        discriminator.push_back(Box::new(DenseLayer::random_with(8, 1, &mut rng)));
+        let mut gan = Gan::new(1);
+        gan.rng = RngProvider::from_rng(rng);
+        gan.discriminator_rate = 0.05;
+        gan.generator_rate = 0.05;
+        let real = ToyDistribution::Gaussian { mean: 3.0, std: 0.5 };
+
+        // The generator starts out with a mean of 1
+        gan.train(&mut generator, &mut discriminator, &real, 1500);
+        let (mean, _) = stats(&gan.generate(&generator, 1000));
+        assert!((mean - 3.0).abs() < 0.3, "generated mean {}", mean);
+    }
+}
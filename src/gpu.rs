@@ -0,0 +1,307 @@
+//! Experimental GPU inference for dense layers, using wgpu compute
+//! shaders. Enabled with the `gpu` feature.
+//!
+//! The weights of a layer are uploaded once when the GPU layer is
+//! created, so that only the inputs and outputs move between the CPU
+//! and the GPU for every batch. Only the forward pass runs on the GPU;
+//! the layers are trained as usual and then copied with
+//! `GpuDenseLayer::new`.
+use layers::{Activation, DenseLayer};
+use traits::Layer;
+
+use std::borrow::Cow;
+use std::error;
+use std::fmt;
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+
+const WORKGROUP_SIZE: usize = 64;
+
+const SHADER: &str = r#"
+struct Dims {
+    inputs: u32,
+    neurons: u32,
+    samples: u32,
+    activation: u32,
+}
+
+@group(0) @binding(0) var<uniform> dims: Dims;
+@group(0) @binding(1) var<storage, read> weights: array<f32>;
+@group(0) @binding(2) var<storage, read> bias: array<f32>;
+@group(0) @binding(3) var<storage, read> x: array<f32>;
+@group(0) @binding(4) var<storage, read_write> y: array<f32>;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if (index >= dims.neurons * dims.samples) {
+        return;
+    }
+    let sample = index / dims.neurons;
+    let neuron = index % dims.neurons;
+    var sum = bias[neuron];
+    for (var i = 0u; i < dims.inputs; i++) {
+        sum += weights[neuron * dims.inputs + i] * x[sample * dims.inputs + i];
+    }
+    switch dims.activation {
+        case 1u: { sum = tanh(sum); }
+        case 2u: { sum = 1.0 / (1.0 + exp(-sum)); }
+        case 3u: { sum = max(sum, 0.0); }
+        case 4u: {
+            if (sum <= 0.0) {
+                sum = 1.6732632 * (exp(sum) - 1.0);
+            }
+            sum *= 1.050701;
+        }
+        default: {}
+    }
+    y[index] = sum;
+}
+"#;
+
+/// Why a GPU could not be used.
+#[derive(Debug)]
+pub enum GpuError {
+    /// No suitable graphics adapter was found
+    NoAdapter,
+    /// The adapter did not provide a device
+    Device(wgpu::RequestDeviceError),
+}
+
+impl fmt::Display for GpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GpuError::NoAdapter => write!(f, "no GPU adapter found"),
+            GpuError::Device(ref e) => write!(f, "could not open GPU device: {}", e),
+        }
+    }
+}
+
+impl error::Error for GpuError {}
+
+/// A GPU device and the compiled dense layer shader, shared by all GPU
+/// layers.
+pub struct GpuContext {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl GpuContext {
+    /// Opens the default GPU adapter.
+    pub fn new() -> Result<Arc<GpuContext>, GpuError> {
+        let instance = wgpu::Instance::default();
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+            .ok_or(GpuError::NoAdapter)?;
+        let descriptor = wgpu::DeviceDescriptor {
+            label: None,
+            required_features: wgpu::Features::empty(),
+            required_limits: wgpu::Limits::downlevel_defaults(),
+        };
+        let (device, queue) = pollster::block_on(adapter.request_device(&descriptor, None))
+            .map_err(GpuError::Device)?;
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("dense"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(SHADER)),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("dense"),
+            layout: None,
+            module: &shader,
+            entry_point: "main",
+        });
+        Ok(Arc::new(GpuContext {
+            device,
+            queue,
+            pipeline,
+        }))
+    }
+
+    fn buffer(&self, values: &[u8], usage: wgpu::BufferUsages) -> wgpu::Buffer {
+        self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: values,
+            usage,
+        })
+    }
+}
+
+/// A dense layer, optionally followed by an activation function, whose
+/// output is computed on the GPU.
+pub struct GpuDenseLayer {
+    context: Arc<GpuContext>,
+    weights: wgpu::Buffer,
+    bias: wgpu::Buffer,
+    /// (inputs per neuron, number of neurons)
+    shape: (usize, usize),
+    activation: Option<Activation>,
+}
+
+impl GpuDenseLayer {
+    /// Uploads the weights of the layer to the GPU. Later changes to
+    /// the layer are not seen by the GPU copy.
+    pub fn new(context: Arc<GpuContext>, layer: &DenseLayer, activation: Option<Activation>) -> GpuDenseLayer {
+        let bias = if layer.use_bias {
+            layer.bias.clone()
+        } else {
+            vec![0.0; layer.shape.1]
+        };
+        let weights = context.buffer(&to_bytes(&layer.neuron_weights()), wgpu::BufferUsages::STORAGE);
+        let bias = context.buffer(&to_bytes(&bias), wgpu::BufferUsages::STORAGE);
+        GpuDenseLayer {
+            context,
+            weights,
+            bias,
+            shape: layer.shape,
+            activation,
+        }
+    }
+
+    /// The outputs of a batch of samples, one after another, computed
+    /// in a single dispatch.
+    pub fn output_batch(&self, inputs: &[f32]) -> Vec<f32> {
+        let (input_count, neurons) = self.shape;
+        assert_eq!(inputs.len() % input_count, 0, "the inputs do not divide into samples");
+        let samples = inputs.len() / input_count;
+        let outputs = samples * neurons;
+        if outputs == 0 {
+            return Vec::new();
+        }
+        let workgroups = outputs.div_ceil(WORKGROUP_SIZE);
+        assert!(workgroups <= 65535, "the batch is too large for a single dispatch");
+
+        let ctx = &self.context;
+        let activation = match self.activation {
+            None => 0u32,
+            Some(Activation::Tanh) => 1,
+            Some(Activation::Sigmoid) => 2,
+            Some(Activation::Rectified) => 3,
+            Some(Activation::Selu) => 4,
+        };
+        let dims: Vec<u8> = [input_count as u32, neurons as u32, samples as u32, activation]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let dims = ctx.buffer(&dims, wgpu::BufferUsages::UNIFORM);
+        let x = ctx.buffer(&to_bytes(inputs), wgpu::BufferUsages::STORAGE);
+        let size = (outputs * 4) as wgpu::BufferAddress;
+        let y = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let layout = ctx.pipeline.get_bind_group_layout(0);
+        let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: dims.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: self.weights.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: self.bias.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: x.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 4,
+                            resource: y.as_entire_binding(),
+                        }],
+        });
+
+        let mut encoder = ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&ctx.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(workgroups as u32, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&y, 0, &staging, 0, size);
+        ctx.queue.submit(Some(encoder.finish()));
+
+        let slice = staging.slice(..);
+        let (tx, rx) = channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+        ctx.device.poll(wgpu::Maintain::Wait);
+        rx.recv().expect("the GPU did not respond").expect("could not read the outputs from the GPU");
+
+        let result = slice.get_mapped_range()
+            .chunks(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        staging.unmap();
+        result
+    }
+}
+
+impl Layer for GpuDenseLayer {
+    fn input_count(&self) -> usize {
+        self.shape.0
+    }
+
+    fn output_count(&self) -> usize {
+        self.shape.1
+    }
+
+    fn output(&self, inputs: &[f32]) -> Vec<f32> {
+        assert_eq!(self.shape.0, inputs.len());
+        self.output_batch(inputs)
+    }
+}
+
+fn to_bytes(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use random::SeededRng;
+    use utils::normal_vector;
+
+    #[test]
+    fn matches_cpu_output() {
+        let context = match GpuContext::new() {
+            Ok(context) => context,
+            // Nothing to compare against on machines without a GPU
+            Err(_) => return,
+        };
+        let mut rng = SeededRng::new(1);
+        let dense = DenseLayer::random_with(5, 3, &mut rng);
+        let inputs = normal_vector(5 * 4, &mut rng);
+
+        for activation in &[None, Some(Activation::Tanh), Some(Activation::Sigmoid), Some(Activation::Selu)] {
+            let gpu = GpuDenseLayer::new(context.clone(), &dense, *activation);
+            let outputs = gpu.output_batch(&inputs);
+            let expected: Vec<f32> = inputs.chunks(5)
+                .flat_map(|x| dense.output(x))
+                .map(|v| activation.map_or(v, |a| a.apply(v)))
+                .collect();
+            for (y, e) in outputs.iter().zip(&expected) {
+                assert!((y - e).abs() < 1e-4, "{} != {}", y, e);
+            }
+        }
+    }
+}
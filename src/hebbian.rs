@@ -0,0 +1,115 @@
+//! Hebbian learning, where weights grow with the correlation between
+//! the inputs and outputs of a layer. It is unsupervised and needs no
+//! gradients, only the forward pass of the layer.
+use traits::WeightedLayer;
+
+/// The rule used to update the weights.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HebbianRule {
+    /// Plain Hebbian learning, `dw = rate * y * x`. The weights grow
+    /// without bound, so it is mainly of historical interest.
+    Hebb,
+    /// Oja's rule, `dw = rate * y * (x - y * w)`, which keeps the
+    /// weights of each neuron normalized. A single linear neuron
+    /// converges to the first principal component of its inputs.
+    Oja,
+}
+
+/// Unsupervised trainer which updates the weights of a single layer
+/// after every sample. The biases are left untouched, so the layer is
+/// usually created without them.
+pub struct HebbianTrainer {
+    /// The number of passes over the data
+    pub epochs: usize,
+    /// The learning rate
+    pub rate: f32,
+    pub rule: HebbianRule,
+}
+
+impl HebbianTrainer {
+    pub fn new(epochs: usize, rate: f32, rule: HebbianRule) -> HebbianTrainer {
+        HebbianTrainer { epochs, rate, rule }
+    }
+
+    /// Trains the layer on the inputs, which are laid out sample by
+    /// sample.
+    pub fn train(&self, layer: &mut dyn WeightedLayer, inputs: &[f32]) {
+        let input_count = layer.input_count();
+        let shape = (input_count, layer.neuron_count());
+        let layout = layer.weight_layout();
+        for _ in 0..self.epochs {
+            for x in inputs.chunks(input_count) {
+                let y = layer.output(x);
+                let rate = self.rate;
+                let rule = self.rule;
+                let mut weights = match layer.weights_mut() {
+                    Some(w) => w,
+                    None => return,
+                };
+                for (n, y) in y.iter().enumerate() {
+                    for (i, x) in x.iter().enumerate() {
+                        let w = &mut weights[layout.index(n, i, shape)];
+                        *w += match rule {
+                            HebbianRule::Hebb => rate * y * x,
+                            HebbianRule::Oja => rate * y * (x - y * *w),
+                        };
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{DenseLayer, WeightLayout};
+    use random::SeededRng;
+    use utils::normal_vector;
+
+    #[test]
+    fn oja_finds_principal_component() {
+        // Samples spread along (1, 1) with little spread across it
+        let mut rng = SeededRng::new(3);
+        let along = normal_vector(500, &mut rng);
+        let across = normal_vector(500, &mut rng);
+        let mut inputs = Vec::new();
+        for (a, b) in along.iter().zip(across) {
+            inputs.push(a + 0.1 * b);
+            inputs.push(a - 0.1 * b);
+        }
+
+        let mut layer = DenseLayer {
+            weights: vec![1.0, 0.0],
+            ..DenseLayer::uniform(0.0, 2, 1).without_bias()
+        };
+        HebbianTrainer::new(10, 0.01, HebbianRule::Oja).train(&mut layer, &inputs);
+
+        let w = &layer.weights;
+        assert!((w[0] * w[0] + w[1] * w[1] - 1.0).abs() < 0.05);
+        assert!((w[0] - w[1]).abs() < 0.05);
+    }
+
+    #[test]
+    fn hebb_grows_correlated_weights() {
+        let mut layer = DenseLayer::uniform(0.5, 2, 1).without_bias();
+        HebbianTrainer::new(1, 0.1, HebbianRule::Hebb).train(&mut layer, &[1.0, 0.0]);
+
+        assert_eq!(layer.weights, vec![0.55, 0.5]);
+    }
+
+    #[test]
+    fn input_major_layer() {
+        let inputs = [1.0, 0.5, -1.0, 0.25, 2.0, -0.5];
+        let layer = || DenseLayer::random_with(3, 2, &mut SeededRng::new(4)).without_bias();
+        let mut neuron_major = layer();
+        let mut input_major = layer().with_layout(WeightLayout::InputMajor);
+        let trainer = HebbianTrainer::new(3, 0.05, HebbianRule::Oja);
+        trainer.train(&mut neuron_major, &inputs);
+        trainer.train(&mut input_major, &inputs);
+
+        for (a, b) in input_major.neuron_weights().iter().zip(&neuron_major.weights) {
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+}
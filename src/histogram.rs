@@ -0,0 +1,220 @@
+//! Histograms of weights and activations, recorded during training
+//! and written to CSV or JSON for visualizing training dynamics.
+use sgd::forward;
+use traits::WeightedLayer;
+
+use std::collections::LinkedList;
+use std::io;
+use std::io::Write;
+
+/// Counts of values in equally wide bins between `min` and `max`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub min: f32,
+    pub max: f32,
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    /// Bins the values into the given number of bins spanning the
+    /// range of the values. Non-finite values are ignored.
+    pub fn new(values: &[f32], bins: usize) -> Histogram {
+        assert!(bins > 0, "a histogram needs at least one bin");
+        let finite = values.iter().filter(|v| v.is_finite());
+        let min = finite.clone().fold(f32::INFINITY, |m, v| m.min(*v));
+        let max = finite.clone().fold(f32::NEG_INFINITY, |m, v| m.max(*v));
+        let mut counts = vec![0; bins];
+        if min > max {
+            // No finite values
+            return Histogram {
+                min: 0.0,
+                max: 0.0,
+                counts,
+            };
+        }
+
+        let width = (max - min) / bins as f32;
+        for v in finite {
+            let i = if width > 0.0 {
+                ((v - min) / width) as usize
+            } else {
+                0
+            };
+            // The maximum value belongs to the last bin
+            counts[i.min(bins - 1)] += 1;
+        }
+        Histogram { min, max, counts }
+    }
+
+    /// The edges of the bins, one more than the number of bins.
+    pub fn edges(&self) -> Vec<f32> {
+        let bins = self.counts.len();
+        let width = (self.max - self.min) / bins as f32;
+        (0..bins + 1).map(|i| self.min + i as f32 * width).collect()
+    }
+}
+
+/// What a recorded histogram describes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HistogramKind {
+    Weights,
+    Bias,
+    Activations,
+}
+
+impl HistogramKind {
+    fn name(&self) -> &'static str {
+        match *self {
+            HistogramKind::Weights => "weights",
+            HistogramKind::Bias => "bias",
+            HistogramKind::Activations => "activations",
+        }
+    }
+}
+
+/// A histogram of one layer at one epoch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramRecord {
+    pub epoch: usize,
+    /// Index of the layer in the network
+    pub layer: usize,
+    pub kind: HistogramKind,
+    pub histogram: Histogram,
+}
+
+/// Collects histograms of a network over the course of training.
+pub struct HistogramRecorder {
+    /// The number of bins of each histogram
+    pub bins: usize,
+    pub records: Vec<HistogramRecord>,
+}
+
+impl HistogramRecorder {
+    pub fn new(bins: usize) -> HistogramRecorder {
+        HistogramRecorder {
+            bins,
+            records: Vec::new(),
+        }
+    }
+
+    /// Records histograms of the weights and biases of every layer,
+    /// and of the outputs of every layer over the given inputs.
+    pub fn record(&mut self, epoch: usize, layers: &LinkedList<Box<dyn WeightedLayer>>, inputs: &[f32]) {
+        let input_count = layers.front().map(|l| l.input_count()).unwrap_or(0);
+        let mut activations: Vec<Vec<f32>> = vec![Vec::new(); layers.len()];
+        for x in inputs.chunks(input_count) {
+            for (a, (_, output)) in activations.iter_mut().zip(forward(layers, x).iter()) {
+                a.extend_from_slice(output);
+            }
+        }
+
+        for (i, (l, a)) in layers.iter().zip(activations).enumerate() {
+            if let Some(w) = l.weights() {
+                self.push(epoch, i, HistogramKind::Weights, &w);
+            }
+            if let Some(b) = l.bias() {
+                self.push(epoch, i, HistogramKind::Bias, b);
+            }
+            if !a.is_empty() {
+                self.push(epoch, i, HistogramKind::Activations, &a);
+            }
+        }
+    }
+
+    fn push(&mut self, epoch: usize, layer: usize, kind: HistogramKind, values: &[f32]) {
+        self.records.push(HistogramRecord {
+            epoch,
+            layer,
+            kind,
+            histogram: Histogram::new(values, self.bins),
+        });
+    }
+
+    /// Writes the histograms as CSV with one row per bin and the
+    /// columns `epoch,layer,kind,bin_start,bin_end,count`.
+    pub fn write_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "epoch,layer,kind,bin_start,bin_end,count")?;
+        for r in &self.records {
+            let edges = r.histogram.edges();
+            for (i, c) in r.histogram.counts.iter().enumerate() {
+                writeln!(out,
+                         "{},{},{},{},{},{}",
+                         r.epoch,
+                         r.layer,
+                         r.kind.name(),
+                         edges[i],
+                         edges[i + 1],
+                         c)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the histograms as a JSON array with one object per
+    /// histogram, holding `epoch`, `layer`, `kind`, `min`, `max` and
+    /// `counts`.
+    pub fn write_json<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "[")?;
+        for (i, r) in self.records.iter().enumerate() {
+            if i > 0 {
+                write!(out, ",")?;
+            }
+            let counts: Vec<String> = r.histogram.counts.iter().map(|c| c.to_string()).collect();
+            write!(out,
+                   "\n  {{\"epoch\": {}, \"layer\": {}, \"kind\": \"{}\", \"min\": {}, \"max\": {}, \
+                    \"counts\": [{}]}}",
+                   r.epoch,
+                   r.layer,
+                   r.kind.name(),
+                   r.histogram.min,
+                   r.histogram.max,
+                   counts.join(", "))?;
+        }
+        writeln!(out, "\n]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{DenseLayer, SigmoidLayer};
+
+    #[test]
+    fn histogram_bins() {
+        let h = Histogram::new(&[0.0, 0.1, 0.5, 0.9, 1.0, f32::NAN], 2);
+
+        assert_eq!(h.counts, vec![2, 3]);
+        assert_eq!(h.edges(), vec![0.0, 0.5, 1.0]);
+        assert_eq!(Histogram::new(&[3.0, 3.0], 4).counts, vec![2, 0, 0, 0]);
+        assert_eq!(Histogram::new(&[], 2).counts, vec![0, 0]);
+    }
+
+    #[test]
+    fn record_and_write() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(1.0, 2, 2)));
+        layers.push_back(Box::new(SigmoidLayer { size: 2 }));
+
+        let mut recorder = HistogramRecorder::new(3);
+        recorder.record(0, &layers, &[0.0, 1.0, 1.0, 1.0]);
+
+        let kinds: Vec<(usize, HistogramKind)> = recorder.records.iter().map(|r| (r.layer, r.kind)).collect();
+        assert_eq!(kinds,
+                   vec![(0, HistogramKind::Weights),
+                        (0, HistogramKind::Bias),
+                        (0, HistogramKind::Activations),
+                        (1, HistogramKind::Activations)]);
+
+        let mut csv = Vec::new();
+        recorder.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 1 + 4 * 3);
+        assert!(csv.starts_with("epoch,layer,kind,bin_start,bin_end,count\n0,0,weights,1,1,4\n"));
+
+        let mut json = Vec::new();
+        recorder.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains("{\"epoch\": 0, \"layer\": 0, \"kind\": \"weights\", \"min\": 1, \"max\": 1, \
+                               \"counts\": [4, 0, 0]}"));
+    }
+}
@@ -0,0 +1,220 @@
+//! Rendering of decision boundary grids and weight matrices to PPM
+//! images, which most image viewers and converters can read.
+use boundary::Grid;
+use layers::{DenseActivationLayer, DenseLayer};
+use traits::WeightedLayer;
+
+use std::collections::LinkedList;
+use std::io;
+use std::io::Write;
+
+/// An RGB image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    /// Pixels row by row, starting at the top left
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl Image {
+    /// Scales the image up by an integer factor, repeating each pixel.
+    /// Useful for small weight matrices.
+    pub fn scaled(&self, factor: usize) -> Image {
+        let width = self.width * factor;
+        let height = self.height * factor;
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in 0..height {
+            for col in 0..width {
+                pixels.push(self.pixels[(row / factor) * self.width + col / factor]);
+            }
+        }
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Writes the image in the binary PPM (P6) format.
+    pub fn write_ppm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for p in &self.pixels {
+            out.write_all(p)?;
+        }
+        Ok(())
+    }
+}
+
+/// Maps a value to a diverging blue-white-red color, where `min` is
+/// blue, the midpoint white and `max` red.
+pub fn colormap(value: f32, min: f32, max: f32) -> [u8; 3] {
+    let t = if max > min {
+        ((value - min) / (max - min)).clamp(0.0, 1.0)
+    } else {
+        0.5
+    };
+    if t < 0.5 {
+        let c = (255.0 * t * 2.0) as u8;
+        [c, c, 255]
+    } else {
+        let c = (255.0 * (1.0 - t) * 2.0) as u8;
+        [255, c, c]
+    }
+}
+
+/// Renders one of the outputs of a decision boundary grid, with one
+/// pixel per grid point. The colors span the range of the output.
+pub fn render_grid(grid: &Grid, output: usize) -> Image {
+    assert!(output < grid.output_count);
+    let values: Vec<f32> = grid.outputs.iter().skip(output).step_by(grid.output_count).cloned().collect();
+    let min = values.iter().fold(f32::INFINITY, |m, v| m.min(*v));
+    let max = values.iter().fold(f32::NEG_INFINITY, |m, v| m.max(*v));
+    Image {
+        width: grid.width,
+        height: grid.height,
+        pixels: values.iter().map(|v| colormap(*v, min, max)).collect(),
+    }
+}
+
+/// Renders the weight matrix of a dense layer with one row per neuron
+/// and one column per input. Zero is white, and the colors are
+/// symmetric around zero so that positive weights are red and
+/// negative weights are blue.
+pub fn render_weights(layer: &DenseLayer) -> Image {
+    let weights = layer.neuron_weights();
+    let limit = weights.iter().fold(0.0f32, |m, w| m.max(w.abs()));
+    Image {
+        width: layer.shape.0,
+        height: layer.shape.1,
+        pixels: weights.iter().map(|w| colormap(*w, -limit, limit)).collect(),
+    }
+}
+
+/// The color of the lines between the tiles of a mosaic.
+const BORDER: [u8; 3] = [128, 128, 128];
+
+/// Renders the weights of every neuron of a dense layer whose inputs
+/// are `height`x`width` images as a tile of that shape, which shows the
+/// pattern the neuron responds to, its receptive field. The tiles are
+/// laid out row by row in a mosaic `columns` tiles wide, separated by
+/// gray lines. Each tile is colored like `render_weights`, scaled to
+/// its own largest weight so that neurons with small weights still
+/// show their pattern.
+pub fn render_receptive_fields(layer: &DenseLayer, height: usize, width: usize, columns: usize) -> Image {
+    let (inputs, neurons) = layer.shape;
+    assert_eq!(height * width, inputs, "{}x{} images do not fit the {} inputs of the layer", height, width, inputs);
+    assert!(columns > 0, "the mosaic needs at least one column");
+    let rows = neurons.div_ceil(columns);
+    let mosaic_width = columns * (width + 1) + 1;
+    let mosaic_height = rows * (height + 1) + 1;
+    let mut pixels = vec![BORDER; mosaic_width * mosaic_height];
+    for (n, weights) in layer.neuron_weights().chunks(inputs).enumerate() {
+        let limit = weights.iter().fold(0.0f32, |m, w| m.max(w.abs()));
+        let top = (n / columns) * (height + 1) + 1;
+        let left = (n % columns) * (width + 1) + 1;
+        for (r, row) in weights.chunks(width).enumerate() {
+            for (c, w) in row.iter().enumerate() {
+                pixels[(top + r) * mosaic_width + left + c] = colormap(*w, -limit, limit);
+            }
+        }
+    }
+    Image {
+        width: mosaic_width,
+        height: mosaic_height,
+        pixels,
+    }
+}
+
+/// Renders the receptive fields of the first layer of a network, as
+/// `render_receptive_fields` does, in a roughly square mosaic. Returns
+/// `None` if the first layer is not a `DenseLayer` or a
+/// `DenseActivationLayer`.
+pub fn receptive_fields(layers: &LinkedList<Box<dyn WeightedLayer>>, height: usize, width: usize) -> Option<Image> {
+    let first = layers.front()?;
+    let dense = first.downcast_ref::<DenseLayer>()
+        .or_else(|| first.downcast_ref::<DenseActivationLayer>().map(|l| &l.dense))?;
+    let columns = ((dense.shape.1 as f32).sqrt().ceil() as usize).max(1);
+    Some(render_receptive_fields(dense, height, width, columns))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{Activation, SigmoidLayer, WeightLayout};
+
+    #[test]
+    fn colors() {
+        assert_eq!(colormap(0.0, 0.0, 1.0), [0, 0, 255]);
+        assert_eq!(colormap(0.5, 0.0, 1.0), [255, 255, 255]);
+        assert_eq!(colormap(1.0, 0.0, 1.0), [255, 0, 0]);
+        assert_eq!(colormap(5.0, 0.0, 1.0), [255, 0, 0]);
+    }
+
+    #[test]
+    fn weights_to_ppm() {
+        let l = DenseLayer {
+            weights: vec![-1.0, 0.0, 1.0, 0.5],
+            bias: vec![0.0, 0.0],
+            shape: (2, 2),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
+        };
+        let image = render_weights(&l);
+        assert_eq!(image.pixels,
+                   vec![[0, 0, 255], [255, 255, 255], [255, 0, 0], [255, 127, 127]]);
+
+        let image = image.scaled(2);
+        assert_eq!((image.width, image.height), (4, 4));
+        assert_eq!(image.pixels[0..4].to_vec(),
+                   vec![[0, 0, 255], [0, 0, 255], [255, 255, 255], [255, 255, 255]]);
+
+        let mut ppm = Vec::new();
+        image.write_ppm(&mut ppm).unwrap();
+        assert!(ppm.starts_with(b"P6\n4 4\n255\n"));
+        assert_eq!(ppm.len(), 11 + 4 * 4 * 3);
+    }
+
+    #[test]
+    fn receptive_field_mosaic() {
+        // Three neurons looking at 2x2 images
+        let layer = || DenseLayer {
+            weights: vec![1.0, 0.0, 0.0, -1.0, 2.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            bias: vec![0.0; 3],
+            shape: (4, 3),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
+        };
+        let image = render_receptive_fields(&layer(), 2, 2, 2);
+        assert_eq!((image.width, image.height), (7, 7));
+        let (red, white, blue) = ([255, 0, 0], [255, 255, 255], [0, 0, 255]);
+        assert_eq!(image.pixels[7..14].to_vec(), vec![BORDER, red, white, BORDER, red, red, BORDER]);
+        assert_eq!(image.pixels[14..21].to_vec(), vec![BORDER, white, blue, BORDER, white, white, BORDER]);
+        // The third tile starts a new row, and the fourth is left empty
+        assert_eq!(image.pixels[28..35].to_vec(), vec![BORDER, white, white, BORDER, BORDER, BORDER, BORDER]);
+        assert_eq!(render_receptive_fields(&layer().with_layout(WeightLayout::InputMajor), 2, 2, 2), image);
+
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseActivationLayer::new(layer(), Activation::Sigmoid)));
+        assert_eq!(receptive_fields(&layers, 2, 2), Some(image));
+        layers.push_front(Box::new(SigmoidLayer { size: 4 }));
+        assert_eq!(receptive_fields(&layers, 2, 2), None);
+    }
+
+    #[test]
+    fn grid_to_image() {
+        let grid = Grid {
+            x_range: (0.0, 1.0),
+            y_range: (0.0, 1.0),
+            width: 2,
+            height: 1,
+            output_count: 2,
+            outputs: vec![0.0, 5.0, 1.0, 5.0],
+        };
+
+        assert_eq!(render_grid(&grid, 0).pixels, vec![[0, 0, 255], [255, 0, 0]]);
+        assert_eq!(render_grid(&grid, 1).pixels, vec![[255, 255, 255], [255, 255, 255]]);
+    }
+}
@@ -0,0 +1,136 @@
+//! Permutation importance of the input features. Shuffling the values
+//! of one feature between the samples breaks its relation to the
+//! targets while keeping its distribution, and the more the network
+//! relies on the feature, the worse it does on the shuffled data. This
+//! needs nothing but the outputs of the network, so it works for any
+//! layers. Features which are correlated share their importance, as
+//! the network can fall back on the ones left intact.
+use data::Dataset;
+use metrics::{self, Metrics};
+use traits::{LossFunction, WeightedLayer};
+
+use rand::Rng;
+use std::collections::LinkedList;
+
+/// The measure whose drop is the importance of a feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Measure {
+    /// The average loss, which rises when a feature is shuffled
+    #[default]
+    Loss,
+    /// The accuracy, which falls when a feature is shuffled
+    Accuracy,
+}
+
+impl Measure {
+    /// How much worse the metrics are than the baseline.
+    fn drop(&self, baseline: &Metrics, shuffled: &Metrics) -> f32 {
+        match *self {
+            Measure::Loss => shuffled.loss - baseline.loss,
+            Measure::Accuracy => baseline.accuracy - shuffled.accuracy,
+        }
+    }
+}
+
+/// The importance of one input feature.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureImportance {
+    /// Index of the feature in the inputs
+    pub feature: usize,
+    /// The average drop of the measure over the shuffles. Close to
+    /// zero, or even negative, for features the network ignores.
+    pub importance: f32,
+    /// The standard deviation of the drop over the shuffles
+    pub std: f32,
+}
+
+/// Shuffles every input feature of the data `repeats` times, and
+/// returns the importance of every feature, in the order of the
+/// features. The data is usually held out from training, so that the
+/// importances show what the network has learned to generalize rather
+/// than what it has memorized.
+pub fn permutation_importance<R: Rng>(layers: &LinkedList<Box<dyn WeightedLayer>>,
+                                      loss: &dyn LossFunction,
+                                      data: &Dataset,
+                                      measure: Measure,
+                                      repeats: usize,
+                                      rng: &mut R)
+                                      -> Vec<FeatureImportance> {
+    assert!(repeats > 0, "every feature must be shuffled at least once");
+    let baseline = metrics::evaluate(layers, loss, data);
+    let mut shuffled = data.clone();
+    let mut column: Vec<f32> = Vec::with_capacity(data.len());
+    (0..data.input_count)
+        .map(|feature| {
+            let drops: Vec<f32> = (0..repeats)
+                .map(|_| {
+                    column.clear();
+                    column.extend(data.inputs.chunks(data.input_count).map(|x| x[feature]));
+                    rng.shuffle(&mut column);
+                    for (x, v) in shuffled.inputs.chunks_mut(data.input_count).zip(&column) {
+                        x[feature] = *v;
+                    }
+                    measure.drop(&baseline, &metrics::evaluate(layers, loss, &shuffled))
+                })
+                .collect();
+            for (x, original) in shuffled.inputs.chunks_mut(data.input_count).zip(data.inputs.chunks(data.input_count)) {
+                x[feature] = original[feature];
+            }
+            let mean = drops.iter().sum::<f32>() / repeats as f32;
+            let variance = drops.iter().map(|d| (d - mean) * (d - mean)).sum::<f32>() / repeats as f32;
+            FeatureImportance {
+                feature,
+                importance: mean,
+                std: variance.sqrt(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{DenseLayer, SigmoidLayer};
+    use loss::SquaredError;
+    use network;
+    use random::SeededRng;
+    use utils::normal_vector;
+
+    #[test]
+    fn ignored_features_are_unimportant() {
+        let mut rng = SeededRng::new(1);
+        let inputs = normal_vector(3 * 200, &mut rng);
+        // y = 2 x0 + 0.5 x2, which does not depend on x1
+        let targets: Vec<f32> = inputs.chunks(3).map(|x| 2.0 * x[0] + 0.5 * x[2]).collect();
+        let data = Dataset::new(inputs, targets, 3, 1);
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(0.0, 3, 1)));
+        network::set_weights(&mut layers, &[2.0, 0.0, 0.5, 0.0]);
+
+        let importances = permutation_importance(&layers, &SquaredError, &data, Measure::Loss, 5, &mut rng);
+        assert_eq!(importances.iter().map(|i| i.feature).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!((importances[1].importance, importances[1].std), (0.0, 0.0));
+        // Shuffling a feature with weight w adds about 2 w^2 to the
+        // squared error of unit variance inputs
+        assert!((importances[0].importance - 8.0).abs() < 1.5, "{:?}", importances[0]);
+        assert!((importances[2].importance - 0.5).abs() < 0.2, "{:?}", importances[2]);
+        assert!(importances[0].std > 0.0);
+    }
+
+    #[test]
+    fn accuracy_drop() {
+        let mut rng = SeededRng::new(2);
+        let inputs = normal_vector(2 * 200, &mut rng);
+        let targets: Vec<f32> = inputs.chunks(2).map(|x| (x[1] > 0.0) as usize as f32).collect();
+        let data = Dataset::new(inputs, targets, 2, 1);
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(0.0, 2, 1)));
+        layers.push_back(Box::new(SigmoidLayer { size: 1 }));
+        network::set_weights(&mut layers, &[0.0, 10.0, 0.0]);
+
+        let importances = permutation_importance(&layers, &SquaredError, &data, Measure::Accuracy, 3, &mut rng);
+        assert_eq!(importances[0].importance, 0.0);
+        // A shuffled sign is right half of the time
+        assert!((importances[1].importance - 0.5).abs() < 0.1, "{:?}", importances[1]);
+    }
+}
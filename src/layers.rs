@@ -1,22 +1,192 @@
 //! Implementation of different kinds of layers.
-use super::traits::{Layer, WeightedLayer};
-use super::utils::{dot, normal_vector};
+use super::loss::{gaussian_kl, gaussian_kl_deriv};
+use super::traits::{Layer, WeightedLayer, Weights, WeightsMut};
+use super::random::{RngProvider, SeededRng};
+use super::serialize::LayerSpec;
+use super::sgd::{bias_gradient, LayerGrads};
+use super::tensor::Tensor;
+use super::utils::{add_mut, dot, normal_vector, sigmoid, softmax, Matrix};
 
-pub struct LayerOut {
-    pub inputs: Vec<f32>,
-    pub output: Vec<f32>,
+use rand::Rng;
+
+use std::borrow::Cow;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// The element-wise activation functions provided by the crate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Activation {
+    /// Hyperbolic tangent, see `HyperbolicLayer`
+    Tanh,
+    /// Logistic sigmoid, see `SigmoidLayer`
+    Sigmoid,
+    /// Rectified linear unit, see `RectifiedLayer`
+    Rectified,
+    /// Scaled exponential linear unit, see `SELULayer`
+    Selu,
+}
+
+impl Activation {
+    /// Applies the activation function to a single value.
+    pub fn apply(&self, x: f32) -> f32 {
+        match *self {
+            Activation::Tanh => x.tanh(),
+            Activation::Sigmoid => sigmoid(x),
+            Activation::Rectified => if x < 0.0 { 0.0 } else { x },
+            Activation::Selu => selu(x),
+        }
+    }
+
+    /// The derivative of the activation function, given its input `x`
+    /// and output `y`. Uses the same derivatives as the activation
+    /// layers.
+    pub fn derivative(&self, x: f32, y: f32) -> f32 {
+        match *self {
+            Activation::Tanh => 1.0 - y * y,
+            Activation::Sigmoid => y * (1.0 - y),
+            Activation::Rectified => sigmoid(x),
+            Activation::Selu => selu_derivative(y),
+        }
+    }
+
+    /// The name of the activation function, as used in layer specs.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Activation::Tanh => "tanh",
+            Activation::Sigmoid => "sigmoid",
+            Activation::Rectified => "relu",
+            Activation::Selu => "selu",
+        }
+    }
+
+    /// The activation function with the given name. See `name`.
+    pub fn from_name(name: &str) -> Option<Activation> {
+        [Activation::Tanh, Activation::Sigmoid, Activation::Rectified, Activation::Selu]
+            .iter()
+            .cloned()
+            .find(|a| a.name() == name)
+    }
+
+    /// True if the derivative can be calculated from the output alone.
+    fn derivative_from_output(&self) -> bool {
+        *self != Activation::Rectified
+    }
 }
 
+/// Steps for the weights and biases of a layer, summed over a number
+/// of samples until they are applied.
+#[derive(Debug, Clone, PartialEq)]
 pub struct LayerUpdates {
     pub ws: Vec<f32>,
     pub bs: Vec<f32>,
+    /// The number of samples whose steps have been summed
+    pub count: usize,
+}
+
+impl LayerUpdates {
+    /// Zeroed steps for the weights and biases of the layer.
+    pub fn zeros(layer: &dyn WeightedLayer) -> LayerUpdates {
+        LayerUpdates {
+            ws: vec![0.0; layer.weight_count()],
+            bs: vec![0.0; layer.bias().map_or(layer.neuron_count(), |b| b.len())],
+            count: 0,
+        }
+    }
+
+    /// Adds the steps and the count of other updates of the same layer,
+    /// such as those of another batch or another thread.
+    pub fn accumulate(&mut self, other: &LayerUpdates) {
+        add_mut(&mut self.ws, &other.ws);
+        add_mut(&mut self.bs, &other.bs);
+        self.count += other.count;
+    }
+
+    /// Updates the layer with the summed steps multiplied by `scale`,
+    /// such as `1 / count` to average them.
+    pub fn apply(&self, layer: &mut dyn WeightedLayer, scale: f32) {
+        if scale == 1.0 {
+            layer.update(&self.ws, &self.bs);
+        } else {
+            let ws: Vec<f32> = self.ws.iter().map(|w| w * scale).collect();
+            let bs: Vec<f32> = self.bs.iter().map(|b| b * scale).collect();
+            layer.update(&ws, &bs);
+        }
+    }
+
+    /// Sets the steps and the count back to zero.
+    pub fn clear(&mut self) {
+        for w in self.ws.iter_mut().chain(self.bs.iter_mut()) {
+            *w = 0.0;
+        }
+        self.count = 0;
+    }
+}
+
+/// How the weights of a `DenseLayer` are stored. The forward pass
+/// reads the weights in rows of the weight matrix, and the backward
+/// pass in its columns, so which layout is faster depends on the sizes
+/// and on which pass dominates; `cargo bench --bench layout` compares
+/// them. Code which works on any layer finds the layout with
+/// `WeightedLayer::weight_layout`, and reads the weights one row per
+/// neuron with `WeightedLayer::neuron_weights`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WeightLayout {
+    /// One row of weights per neuron, so that the weights of a neuron
+    /// are next to each other
+    #[default]
+    NeuronMajor,
+    /// One row of weights per input, the transpose of `NeuronMajor`
+    InputMajor,
+}
+
+impl WeightLayout {
+    /// The name of the layout, as used in layer specs.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            WeightLayout::NeuronMajor => "neurons",
+            WeightLayout::InputMajor => "inputs",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<WeightLayout> {
+        match name {
+            "neurons" => Some(WeightLayout::NeuronMajor),
+            "inputs" => Some(WeightLayout::InputMajor),
+            _ => None,
+        }
+    }
+
+    /// The index of the weight of input `input` of neuron `neuron`, in
+    /// weights of the given shape, (inputs per neuron, number of
+    /// neurons), stored in this layout.
+    pub fn index(&self, neuron: usize, input: usize, shape: (usize, usize)) -> usize {
+        match *self {
+            WeightLayout::NeuronMajor => neuron * shape.0 + input,
+            WeightLayout::InputMajor => input * shape.1 + neuron,
+        }
+    }
+
+    /// Weights of the given shape stored in this layout, with one row
+    /// per neuron.
+    pub fn neuron_rows<'a>(&self, weights: &'a [f32], shape: (usize, usize)) -> Cow<'a, [f32]> {
+        match *self {
+            WeightLayout::NeuronMajor => Cow::Borrowed(weights),
+            WeightLayout::InputMajor => Cow::Owned(transposed(weights, shape.0, shape.1)),
+        }
+    }
 }
 
 pub struct DenseLayer {
+    /// The weights, laid out as given by `layout`
     pub weights: Vec<f32>,
     pub bias: Vec<f32>,
     /// (inputs per neuron, number of neurons)
     pub shape: (usize, usize),
+    /// Weights which are masked out stay at zero during training, in
+    /// the same layout as the weights
+    pub mask: Option<Vec<bool>>,
+    /// If false, the bias is neither added to the output nor trained
+    pub use_bias: bool,
+    pub layout: WeightLayout,
 }
 
 impl DenseLayer {
@@ -25,243 +195,1831 @@ impl DenseLayer {
             weights: vec!(val; inputs * neurons),
             bias: vec!(val; neurons),
             shape: (inputs, neurons),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
         }
     }
 
+    /// Creates a layer with gaussian random weights and biases, using
+    /// a generator seeded from the operating system.
     pub fn random(inputs: usize, neurons: usize) -> DenseLayer {
+        DenseLayer::random_with(inputs, neurons, &mut SeededRng::from_entropy())
+    }
+
+    /// Creates a layer with gaussian random weights and biases drawn
+    /// from the given generator, for reproducible initialization.
+    pub fn random_with<R: Rng>(inputs: usize, neurons: usize, rng: &mut R) -> DenseLayer {
         DenseLayer {
-            weights: normal_vector(inputs * neurons),
-            bias: normal_vector(neurons),
+            weights: normal_vector(inputs * neurons, rng),
+            bias: normal_vector(neurons, rng),
             shape: (inputs, neurons),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
+        }
+    }
+
+    /// Turns off the bias of the layer, so that each neuron outputs
+    /// only the dot product of its weights and inputs.
+    pub fn without_bias(mut self) -> DenseLayer {
+        self.use_bias = false;
+        for b in self.bias.iter_mut() {
+            *b = 0.0;
+        }
+        self
+    }
+
+    /// Stores the weights, and the mask, in the given layout. The layer
+    /// computes the same as before.
+    pub fn with_layout(mut self, layout: WeightLayout) -> DenseLayer {
+        if layout != self.layout {
+            let (rows, cols) = match self.layout {
+                WeightLayout::NeuronMajor => (self.shape.1, self.shape.0),
+                WeightLayout::InputMajor => (self.shape.0, self.shape.1),
+            };
+            self.weights = transposed(&self.weights, rows, cols);
+            self.mask = self.mask.map(|m| transposed(&m, rows, cols));
+            self.layout = layout;
+        }
+        self
+    }
+
+    /// The weights with one row per neuron, whatever the layout.
+    pub fn neuron_weights(&self) -> Cow<'_, [f32]> {
+        self.layout.neuron_rows(&self.weights, self.shape)
+    }
+
+    /// The delta signal at the inputs and the gradients, computed in a
+    /// single pass over the weights from the delta signal at the
+    /// neurons.
+    fn sweep(&self, delta: &[f32], inputs: &[f32]) -> (Vec<f32>, LayerGrads) {
+        check_len("dense layer", "inputs", self.shape.0, inputs.len());
+        check_len("dense layer", "delta values", self.shape.1, delta.len());
+        let mut input_delta = vec![0.0; self.shape.0];
+        let mut weights = vec![0.0; self.weights.len()];
+        match self.layout {
+            WeightLayout::NeuronMajor => {
+                let rows = self.weights.chunks(self.shape.0).zip(weights.chunks_mut(self.shape.0));
+                for ((row, grads), d) in rows.zip(delta) {
+                    for (((w, g), x), id) in row.iter().zip(grads).zip(inputs).zip(input_delta.iter_mut()) {
+                        *g = x * d;
+                        *id += w * d;
+                    }
+                }
+            }
+            WeightLayout::InputMajor => {
+                let rows = self.weights.chunks(self.shape.1).zip(weights.chunks_mut(self.shape.1));
+                for ((row, grads), (x, id)) in rows.zip(inputs.iter().zip(input_delta.iter_mut())) {
+                    for ((w, g), d) in row.iter().zip(grads).zip(delta) {
+                        *g = x * d;
+                        *id += w * d;
+                    }
+                }
+            }
+        }
+        let bias = bias_gradient(self, delta);
+        (input_delta, LayerGrads { weights, bias })
+    }
+}
+
+/// The transpose of a matrix of `rows` rows stored row by row.
+fn transposed<T: Copy>(values: &[T], rows: usize, cols: usize) -> Vec<T> {
+    (0..cols).flat_map(|c| (0..rows).map(move |r| values[r * cols + c])).collect()
+}
+
+impl Layer for DenseLayer {
+    fn input_count(self: &DenseLayer) -> usize {
+        self.shape.0
+    }
+
+    fn output_count(self: &DenseLayer) -> usize {
+        self.shape.1
+    }
+
+    /// Output of this layer is a vector of weight and input dot products.
+    fn output(self: &DenseLayer, inputs: &[f32]) -> Vec<f32> {
+        let bias = if self.use_bias {
+            Some(&self.bias[..])
+        } else {
+            None
+        };
+        match self.layout {
+            WeightLayout::NeuronMajor => dense_output(&self.weights, bias, self.shape, inputs),
+            WeightLayout::InputMajor => {
+                check_len("dense layer", "inputs", self.shape.0, inputs.len());
+                let mut out = Matrix::view(&self.weights, self.shape.0, self.shape.1).vecmat(inputs);
+                if let Some(b) = bias {
+                    add_mut(&mut out, b);
+                }
+                out
+            }
+        }
+    }
+
+    fn delta_from_inputs(self: &DenseLayer, delta: &[f32], inputs: &[f32]) -> Option<Vec<f32>> {
+        check_len("DenseLayer", "inputs", self.shape.0, inputs.len());
+        Some(match self.layout {
+            WeightLayout::NeuronMajor => dense_delta(&self.weights, self.shape, delta),
+            WeightLayout::InputMajor => {
+                check_len("dense layer", "delta values", self.shape.1, delta.len());
+                Matrix::view(&self.weights, self.shape.0, self.shape.1).matvec(delta)
+            }
+        })
+    }
+
+    /// Vector of derivatives with respect to the weights for each
+    /// neuron, in the layout of the weights.
+    fn derivw(self: &DenseLayer, inputs: &[f32]) -> Option<Vec<f32>> {
+        Some(match self.layout {
+            WeightLayout::NeuronMajor => dense_derivw(self.shape, inputs),
+            WeightLayout::InputMajor => {
+                check_len("dense layer", "inputs", self.shape.0, inputs.len());
+                Matrix::outer(inputs, &vec![1.0; self.shape.1]).into_vec()
+            }
+        })
+    }
+
+    fn spec(&self) -> Option<LayerSpec> {
+        Some(with_layout(LayerSpec::new("dense")
+                             .with("inputs", self.shape.0)
+                             .with("neurons", self.shape.1)
+                             .with("bias", self.use_bias),
+                         self.layout))
+    }
+}
+
+/// Adds the layout to the spec of a dense layer, unless it is the
+/// default.
+fn with_layout(spec: LayerSpec, layout: WeightLayout) -> LayerSpec {
+    match layout {
+        WeightLayout::NeuronMajor => spec,
+        layout => spec.with("layout", layout.name()),
+    }
+}
+
+/// Panics with a message naming the layer unless a vector passed to it
+/// has the expected length.
+fn check_len(layer: &str, what: &str, expected: usize, actual: usize) {
+    assert!(expected == actual, "{} expects {} {} but got {}", layer, expected, what, actual);
+}
+
+/// Weight and input dot products of a dense layer, plus its biases.
+/// The weights form a matrix with one row per neuron.
+fn dense_output(weights: &[f32], bias: Option<&[f32]>, shape: (usize, usize), inputs: &[f32]) -> Vec<f32> {
+    check_len("dense layer", "inputs", shape.0, inputs.len());
+    let mut out = Matrix::view(weights, shape.1, shape.0).matvec(inputs);
+    if let Some(b) = bias {
+        add_mut(&mut out, b);
+    }
+    out
+}
+
+/// Propagates the delta signal of the neurons of a dense layer back
+/// to its inputs.
+fn dense_delta(weights: &[f32], shape: (usize, usize), delta: &[f32]) -> Vec<f32> {
+    check_len("dense layer", "delta values", shape.1, delta.len());
+    Matrix::view(weights, shape.1, shape.0).vecmat(delta)
+}
+
+/// The derivatives of a dense layer with respect to its weights, which
+/// are the inputs repeated once per neuron.
+fn dense_derivw(shape: (usize, usize), inputs: &[f32]) -> Vec<f32> {
+    check_len("dense layer", "inputs", shape.0, inputs.len());
+    Matrix::outer(&vec![1.0; shape.1], inputs).into_vec()
+}
+
+impl WeightedLayer for DenseLayer {
+    fn weight_count(self: &DenseLayer) -> usize {
+        self.weights.len()
+    }
+
+    fn neuron_count(self: &DenseLayer) -> usize {
+        self.output_count()
+    }
+    fn weights_mut(self: &mut DenseLayer) -> Option<WeightsMut<'_>> {
+        Some(WeightsMut::Borrowed(&mut self.weights))
+    }
+
+    fn bias_mut(self: &mut DenseLayer) -> Option<&mut Vec<f32>> {
+        if self.use_bias {
+            Some(&mut self.bias)
+        } else {
+            None
+        }
+    }
+
+    fn weights(self: &DenseLayer) -> Option<Weights<'_>> {
+        Some(Weights::Borrowed(&self.weights))
+    }
+
+    fn weight_layout(self: &DenseLayer) -> WeightLayout {
+        self.layout
+    }
+
+    fn bias(self: &DenseLayer) -> Option<&[f32]> {
+        if self.use_bias {
+            Some(&self.bias)
+        } else {
+            None
+        }
+    }
+
+    /// With one row per input, the gradient is the outer product of the
+    /// inputs and the delta signal.
+    fn weight_grad(self: &DenseLayer, inputs: &[f32], delta: &[f32]) -> Option<Vec<f32>> {
+        match self.layout {
+            WeightLayout::NeuronMajor => None,
+            WeightLayout::InputMajor => Some(Matrix::outer(inputs, delta).into_vec()),
+        }
+    }
+
+    /// Computes the delta signal at the inputs and the gradients in a
+    /// single pass over the weights.
+    fn backward(self: &DenseLayer, delta: &[f32], inputs: &[f32], _: &[f32]) -> (Vec<f32>, LayerGrads) {
+        self.sweep(delta, inputs)
+    }
+
+    fn weight_mask(self: &DenseLayer) -> Option<&[bool]> {
+        self.mask.as_deref()
+    }
+
+    fn set_weight_mask(self: &mut DenseLayer, mask: Option<Vec<bool>>) {
+        if let Some(ref m) = mask {
+            assert_eq!(self.weights.len(), m.len(), "the mask must have one value per weight");
+        }
+        self.mask = mask;
+    }
+}
+
+/// A dense layer and an element-wise activation function fused into
+/// a single layer. Behaves like a `DenseLayer` followed by the
+/// matching activation layer, but halves the number of layers in a
+/// stack and the intermediate vectors stored during training.
+pub struct DenseActivationLayer {
+    pub dense: DenseLayer,
+    pub activation: Activation,
+}
+
+impl DenseActivationLayer {
+    pub fn new(dense: DenseLayer, activation: Activation) -> DenseActivationLayer {
+        DenseActivationLayer { dense, activation }
+    }
+}
+
+impl Layer for DenseActivationLayer {
+    fn input_count(&self) -> usize {
+        self.dense.input_count()
+    }
+
+    fn output_count(&self) -> usize {
+        self.dense.output_count()
+    }
+
+    fn output(&self, inputs: &[f32]) -> Vec<f32> {
+        let mut out = self.dense.output(inputs);
+        for y in out.iter_mut() {
+            *y = self.activation.apply(*y);
+        }
+        out
+    }
+
+    /// Multiplies the delta signal with the derivative of the
+    /// activation function. The weighted sums are only recomputed for
+    /// activations whose derivative needs them.
+    fn neuron_delta(&self, delta: &[f32], inputs: &[f32], outputs: &[f32]) -> Vec<f32> {
+        check_len("DenseActivationLayer", "delta values", self.dense.shape.1, delta.len());
+        check_len("DenseActivationLayer", "outputs", self.dense.shape.1, outputs.len());
+        let sums = if self.activation.derivative_from_output() {
+            None
+        } else {
+            Some(self.dense.output(inputs))
+        };
+        let mut result = Vec::with_capacity(delta.len());
+        for (i, (d, y)) in delta.iter().zip(outputs).enumerate() {
+            let x = sums.as_ref().map_or(0.0, |s| s[i]);
+            result.push(d * self.activation.derivative(x, *y));
+        }
+        result
+    }
+
+    fn delta(&self, delta: &[f32], inputs: &[f32], outputs: &[f32]) -> Vec<f32> {
+        let neuron_delta = self.neuron_delta(delta, inputs, outputs);
+        self.dense.delta_from_inputs(&neuron_delta, inputs).unwrap()
+    }
+
+    fn derivw(&self, inputs: &[f32]) -> Option<Vec<f32>> {
+        self.dense.derivw(inputs)
+    }
+
+    fn activation(&self) -> Option<Activation> {
+        Some(self.activation)
+    }
+
+    fn spec(&self) -> Option<LayerSpec> {
+        Some(with_layout(LayerSpec::new("dense_activation")
+                             .with("inputs", self.dense.shape.0)
+                             .with("neurons", self.dense.shape.1)
+                             .with("bias", self.dense.use_bias)
+                             .with("activation", self.activation.name()),
+                         self.dense.layout))
+    }
+}
+
+impl WeightedLayer for DenseActivationLayer {
+    fn weight_count(&self) -> usize {
+        self.dense.weight_count()
+    }
+
+    fn neuron_count(&self) -> usize {
+        self.dense.neuron_count()
+    }
+
+    fn weights_mut(&mut self) -> Option<WeightsMut<'_>> {
+        self.dense.weights_mut()
+    }
+
+    fn bias_mut(&mut self) -> Option<&mut Vec<f32>> {
+        self.dense.bias_mut()
+    }
+
+    fn weights(&self) -> Option<Weights<'_>> {
+        self.dense.weights()
+    }
+
+    fn weight_layout(&self) -> WeightLayout {
+        self.dense.layout
+    }
+
+    fn bias(&self) -> Option<&[f32]> {
+        self.dense.bias()
+    }
+
+    fn weight_grad(&self, inputs: &[f32], delta: &[f32]) -> Option<Vec<f32>> {
+        self.dense.weight_grad(inputs, delta)
+    }
+
+    /// Applies the derivative of the activation once, and sweeps over
+    /// the weights of the dense layer once.
+    fn backward(&self, delta: &[f32], inputs: &[f32], outputs: &[f32]) -> (Vec<f32>, LayerGrads) {
+        let neuron_delta = self.neuron_delta(delta, inputs, outputs);
+        self.dense.sweep(&neuron_delta, inputs)
+    }
+
+    fn weight_mask(&self) -> Option<&[bool]> {
+        self.dense.weight_mask()
+    }
+
+    fn set_weight_mask(&mut self, mask: Option<Vec<bool>>) {
+        self.dense.set_weight_mask(mask)
+    }
+}
+
+/// Weights which are shared between layers. Clones refer to the same
+/// buffer, so updates made through one layer are seen by all others.
+#[derive(Debug, Clone)]
+pub struct SharedWeights {
+    weights: Arc<RwLock<Vec<f32>>>,
+}
+
+impl SharedWeights {
+    pub fn new(weights: Vec<f32>) -> SharedWeights {
+        SharedWeights { weights: Arc::new(RwLock::new(weights)) }
+    }
+
+    /// Read access to the shared weights.
+    pub fn read(&self) -> RwLockReadGuard<'_, Vec<f32>> {
+        self.weights.read().unwrap()
+    }
+
+    /// Write access to the shared weights.
+    pub fn write(&self) -> RwLockWriteGuard<'_, Vec<f32>> {
+        self.weights.write().unwrap()
+    }
+
+    /// Adds the updates to the shared weights.
+    pub fn add(&self, updates: &[f32]) {
+        let mut weights = self.weights.write().unwrap();
+        for (w, dw) in weights.iter_mut().zip(updates) {
+            *w += *dw;
+        }
+    }
+
+    /// True if both refer to the same buffer.
+    pub fn same(&self, other: &SharedWeights) -> bool {
+        Arc::ptr_eq(&self.weights, &other.weights)
+    }
+}
+
+/// A dense layer whose weights are shared with other layers, created
+/// with `DenseLayer::tied` and `TiedDenseLayer::tie`. The weight
+/// updates of every tied layer accumulate into the shared weights,
+/// while each layer keeps its own biases.
+///
+/// The layer created by `DenseLayer::tied` owns the shared weights,
+/// and gives them through `weights()` and `weights_mut()`, so that
+/// functions of a whole network such as `network::get_weights` see
+/// them once. The layers created from it by `tie` and `transpose` give
+/// only their biases.
+pub struct TiedDenseLayer {
+    pub weights: SharedWeights,
+    pub bias: Vec<f32>,
+    /// (inputs per neuron, number of neurons)
+    pub shape: (usize, usize),
+    /// If false, the bias is neither added to the output nor trained
+    pub use_bias: bool,
+    /// Whether this is the layer which gives the shared weights
+    owner: bool,
+}
+
+impl DenseLayer {
+    /// Converts the layer into one whose weights can be tied to other
+    /// layers, and keeps its bias if it has one. The shared weights
+    /// have one row per neuron, whatever the layout of this layer.
+    /// Panics if the layer has a weight mask, which tied layers do not
+    /// support.
+    pub fn tied(self) -> TiedDenseLayer {
+        assert!(self.mask.is_none(), "a layer with a weight mask cannot be tied");
+        let dense = self.with_layout(WeightLayout::NeuronMajor);
+        TiedDenseLayer {
+            weights: SharedWeights::new(dense.weights),
+            bias: dense.bias,
+            shape: dense.shape,
+            use_bias: dense.use_bias,
+            owner: true,
+        }
+    }
+}
+
+impl TiedDenseLayer {
+    /// Creates another layer sharing the weights of this one, with its
+    /// own biases initialized to zero if this one has biases. The new
+    /// layer does not own the weights.
+    pub fn tie(&self) -> TiedDenseLayer {
+        TiedDenseLayer {
+            weights: self.weights.clone(),
+            bias: vec![0.0; self.shape.1],
+            shape: self.shape,
+            use_bias: self.use_bias,
+            owner: false,
+        }
+    }
+
+    /// Creates a layer which uses the transpose of the weights of this
+    /// one, mapping its outputs back to its inputs. Useful as the
+    /// decoder of an autoencoder.
+    pub fn transpose(&self) -> TransposedDenseLayer {
+        TransposedDenseLayer::new(self.weights.clone(), self.shape)
+    }
+}
+
+impl Layer for TiedDenseLayer {
+    fn input_count(&self) -> usize {
+        self.shape.0
+    }
+
+    fn output_count(&self) -> usize {
+        self.shape.1
+    }
+
+    fn output(&self, inputs: &[f32]) -> Vec<f32> {
+        let bias = if self.use_bias {
+            Some(&self.bias[..])
+        } else {
+            None
+        };
+        dense_output(&self.weights.read(), bias, self.shape, inputs)
+    }
+
+    fn delta_from_inputs(&self, delta: &[f32], inputs: &[f32]) -> Option<Vec<f32>> {
+        check_len("TiedDenseLayer", "inputs", self.shape.0, inputs.len());
+        Some(dense_delta(&self.weights.read(), self.shape, delta))
+    }
+
+    fn derivw(&self, inputs: &[f32]) -> Option<Vec<f32>> {
+        Some(dense_derivw(self.shape, inputs))
+    }
+}
+
+impl WeightedLayer for TiedDenseLayer {
+    fn weight_count(&self) -> usize {
+        self.shape.0 * self.shape.1
+    }
+
+    fn neuron_count(&self) -> usize {
+        self.shape.1
+    }
+
+    fn weights_mut(&mut self) -> Option<WeightsMut<'_>> {
+        if self.owner {
+            Some(WeightsMut::Locked(self.weights.write()))
+        } else {
+            None
+        }
+    }
+
+    fn bias_mut(&mut self) -> Option<&mut Vec<f32>> {
+        if self.use_bias {
+            Some(&mut self.bias)
+        } else {
+            None
+        }
+    }
+
+    fn weights(&self) -> Option<Weights<'_>> {
+        if self.owner {
+            Some(Weights::Locked(self.weights.read()))
+        } else {
+            None
+        }
+    }
+
+    fn shared_weights(&self) -> Option<&SharedWeights> {
+        Some(&self.weights)
+    }
+
+    fn bias(&self) -> Option<&[f32]> {
+        if self.use_bias {
+            Some(&self.bias)
+        } else {
+            None
+        }
+    }
+
+    fn update(&mut self, weight_updates: &[f32], bias_updates: &[f32]) {
+        self.weights.add(weight_updates);
+        if let Some(bias) = self.bias_mut() {
+            for (b, db) in bias.iter_mut().zip(bias_updates) {
+                *b += *db;
+            }
         }
     }
 }
 
-impl Layer for DenseLayer {
-    fn input_count(self: &DenseLayer) -> usize {
-        self.shape.0
+/// A dense layer which uses the transpose of the shared weights of
+/// another layer, so that symmetric autoencoders can be built without
+/// duplicating parameters. Seen from this layer, the shared weights
+/// have one row per input, so its weight gradients are laid out like
+/// those of a `DenseLayer` with `WeightLayout::InputMajor`, and
+/// accumulate into the shared weights as they are. The layer keeps its
+/// own biases. Created with `TiedDenseLayer::transpose`.
+pub struct TransposedDenseLayer {
+    pub weights: SharedWeights,
+    pub bias: Vec<f32>,
+    /// The shape of the layer whose weights are used, (inputs per
+    /// neuron, number of neurons). This layer thus has `shape.1`
+    /// inputs and `shape.0` outputs.
+    pub shape: (usize, usize),
+}
+
+impl TransposedDenseLayer {
+    /// Creates a layer using the transpose of weights of the given
+    /// shape, with biases initialized to zero.
+    pub fn new(weights: SharedWeights, shape: (usize, usize)) -> TransposedDenseLayer {
+        assert_eq!(weights.read().len(), shape.0 * shape.1, "the shared weights do not have the shape {:?}", shape);
+        TransposedDenseLayer {
+            weights,
+            bias: vec![0.0; shape.0],
+            shape,
+        }
+    }
+}
+
+impl Layer for TransposedDenseLayer {
+    fn input_count(&self) -> usize {
+        self.shape.1
+    }
+
+    fn output_count(&self) -> usize {
+        self.shape.0
+    }
+
+    /// Multiplying with the transposed weights is the same as
+    /// propagating a delta signal through the original layer.
+    fn output(&self, inputs: &[f32]) -> Vec<f32> {
+        let mut out = dense_delta(&self.weights.read(), self.shape, inputs);
+        for (o, b) in out.iter_mut().zip(&self.bias) {
+            *o += *b;
+        }
+        out
+    }
+
+    fn delta_from_inputs(&self, delta: &[f32], inputs: &[f32]) -> Option<Vec<f32>> {
+        check_len("TransposedDenseLayer", "inputs", self.shape.1, inputs.len());
+        Some(dense_output(&self.weights.read(), None, self.shape, delta))
+    }
+
+    /// The derivatives in the layout of the shared weights, one row
+    /// per input of this layer.
+    fn derivw(&self, inputs: &[f32]) -> Option<Vec<f32>> {
+        check_len("TransposedDenseLayer", "inputs", self.shape.1, inputs.len());
+        Some(Matrix::outer(inputs, &vec![1.0; self.shape.0]).into_vec())
+    }
+}
+
+impl WeightedLayer for TransposedDenseLayer {
+    fn weight_count(&self) -> usize {
+        self.shape.0 * self.shape.1
+    }
+
+    fn neuron_count(&self) -> usize {
+        self.shape.0
+    }
+
+    fn bias_mut(&mut self) -> Option<&mut Vec<f32>> {
+        Some(&mut self.bias)
+    }
+
+    fn shared_weights(&self) -> Option<&SharedWeights> {
+        Some(&self.weights)
+    }
+
+    fn weight_layout(&self) -> WeightLayout {
+        WeightLayout::InputMajor
+    }
+
+    fn bias(&self) -> Option<&[f32]> {
+        Some(&self.bias)
+    }
+
+    /// With one row per input, the gradient is the outer product of the
+    /// inputs and the delta signal.
+    fn weight_grad(&self, inputs: &[f32], delta: &[f32]) -> Option<Vec<f32>> {
+        Some(Matrix::outer(inputs, delta).into_vec())
+    }
+
+    fn update(&mut self, weight_updates: &[f32], bias_updates: &[f32]) {
+        self.weights.add(weight_updates);
+        for (b, db) in self.bias.iter_mut().zip(bias_updates) {
+            *b += *db;
+        }
+    }
+}
+
+/// A maxout layer, where each unit computes `pieces` linear functions
+/// of the inputs and outputs the largest of them. The gradient of a
+/// unit only flows through its winning piece.
+pub struct MaxoutLayer {
+    /// The weights of all pieces, piece by piece, with the pieces of a
+    /// unit stored next to each other
+    pub weights: Vec<f32>,
+    /// One bias per piece
+    pub bias: Vec<f32>,
+    pub inputs: usize,
+    pub units: usize,
+    /// The number of linear pieces per unit
+    pub pieces: usize,
+}
+
+impl MaxoutLayer {
+    /// Creates a layer with gaussian random weights and biases drawn
+    /// from the given generator.
+    pub fn random_with<R: Rng>(inputs: usize, units: usize, pieces: usize, rng: &mut R) -> MaxoutLayer {
+        assert!(pieces > 0, "a maxout unit needs at least one piece");
+        MaxoutLayer {
+            weights: normal_vector(inputs * units * pieces, rng),
+            bias: normal_vector(units * pieces, rng),
+            inputs,
+            units,
+            pieces,
+        }
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        (self.inputs, self.units * self.pieces)
+    }
+
+    /// The index of the winning piece of every unit, given the outputs
+    /// of all pieces.
+    fn winners(&self, pieces: &[f32]) -> Vec<usize> {
+        pieces.chunks(self.pieces)
+            .map(|p| {
+                let mut best = 0;
+                for (i, v) in p.iter().enumerate() {
+                    if *v > p[best] {
+                        best = i;
+                    }
+                }
+                best
+            })
+            .collect()
+    }
+}
+
+impl Layer for MaxoutLayer {
+    fn input_count(&self) -> usize {
+        self.inputs
+    }
+
+    fn output_count(&self) -> usize {
+        self.units
+    }
+
+    fn output(&self, inputs: &[f32]) -> Vec<f32> {
+        let pieces = dense_output(&self.weights, Some(&self.bias), self.shape(), inputs);
+        pieces.chunks(self.pieces)
+            .zip(self.winners(&pieces))
+            .map(|(p, w)| p[w])
+            .collect()
+    }
+
+    /// Routes the delta signal of each unit to its winning piece, and
+    /// gives the other pieces a zero delta.
+    #[allow(unused_variables)]
+    fn neuron_delta(&self, delta: &[f32], inputs: &[f32], outputs: &[f32]) -> Vec<f32> {
+        check_len("MaxoutLayer", "delta values", self.units, delta.len());
+        let pieces = dense_output(&self.weights, Some(&self.bias), self.shape(), inputs);
+        let mut result = vec![0.0; self.units * self.pieces];
+        for (u, (d, w)) in delta.iter().zip(self.winners(&pieces)).enumerate() {
+            result[u * self.pieces + w] = *d;
+        }
+        result
+    }
+
+    fn delta(&self, delta: &[f32], inputs: &[f32], outputs: &[f32]) -> Vec<f32> {
+        let neuron_delta = self.neuron_delta(delta, inputs, outputs);
+        dense_delta(&self.weights, self.shape(), &neuron_delta)
+    }
+
+    fn derivw(&self, inputs: &[f32]) -> Option<Vec<f32>> {
+        Some(dense_derivw(self.shape(), inputs))
+    }
+
+    fn spec(&self) -> Option<LayerSpec> {
+        Some(LayerSpec::new("maxout")
+            .with("inputs", self.inputs)
+            .with("units", self.units)
+            .with("pieces", self.pieces))
+    }
+}
+
+impl WeightedLayer for MaxoutLayer {
+    fn weight_count(&self) -> usize {
+        self.weights.len()
+    }
+
+    /// Every piece counts as a neuron, with its own weights and bias.
+    fn neuron_count(&self) -> usize {
+        self.units * self.pieces
+    }
+
+    fn weights_mut(&mut self) -> Option<WeightsMut<'_>> {
+        Some(WeightsMut::Borrowed(&mut self.weights))
+    }
+
+    fn bias_mut(&mut self) -> Option<&mut Vec<f32>> {
+        Some(&mut self.bias)
+    }
+
+    fn weights(&self) -> Option<Weights<'_>> {
+        Some(Weights::Borrowed(&self.weights))
+    }
+
+    fn bias(&self) -> Option<&[f32]> {
+        Some(&self.bias)
+    }
+}
+
+/// A layer of gaussian radial basis functions. Each unit outputs
+/// `exp(-|x - c|^2 / (2 w^2))` for its center `c` and width `w`, both
+/// of which are trained.
+///
+/// The centers are the weights of the layer. The widths are stored as
+/// their logarithms in the biases, which keeps them positive during
+/// training.
+pub struct RBFLayer {
+    /// The centers of the units, unit by unit
+    pub centers: Vec<f32>,
+    /// The logarithm of the width of every unit
+    pub log_widths: Vec<f32>,
+    /// (inputs per unit, number of units)
+    pub shape: (usize, usize),
+}
+
+impl RBFLayer {
+    /// Creates a layer with gaussian random centers drawn from the
+    /// given generator, and all widths set to `width`.
+    pub fn random_with<R: Rng>(inputs: usize, units: usize, width: f32, rng: &mut R) -> RBFLayer {
+        assert!(width > 0.0, "the width of a radial basis function must be positive");
+        RBFLayer {
+            centers: normal_vector(inputs * units, rng),
+            log_widths: vec![width.ln(); units],
+            shape: (inputs, units),
+        }
+    }
+
+    /// The squared distance between the inputs and every center.
+    fn distances(&self, inputs: &[f32]) -> Vec<f32> {
+        check_len("RBFLayer", "inputs", self.shape.0, inputs.len());
+        self.centers
+            .chunks(self.shape.0)
+            .map(|c| c.iter().zip(inputs).map(|(c, x)| (x - c) * (x - c)).sum())
+            .collect()
+    }
+}
+
+impl Layer for RBFLayer {
+    fn input_count(&self) -> usize {
+        self.shape.0
+    }
+
+    fn output_count(&self) -> usize {
+        self.shape.1
+    }
+
+    fn output(&self, inputs: &[f32]) -> Vec<f32> {
+        self.distances(inputs)
+            .iter()
+            .zip(&self.log_widths)
+            .map(|(d, lw)| (-d / (2.0 * (2.0 * lw).exp())).exp())
+            .collect()
+    }
+
+    /// The derivative of a unit with respect to its log width is
+    /// `y |x - c|^2 / w^2`. It is multiplied into the delta signal
+    /// here, which makes it the gradient of the log width, and
+    /// `derivw` is divided by the same factor.
+    fn neuron_delta(&self, delta: &[f32], inputs: &[f32], outputs: &[f32]) -> Vec<f32> {
+        check_len("RBFLayer", "delta values", self.shape.1, delta.len());
+        let distances = self.distances(inputs);
+        delta.iter()
+            .zip(outputs)
+            .zip(distances.iter().zip(&self.log_widths))
+            .map(|((d, y), (dist, lw))| d * y * dist / (2.0 * lw).exp())
+            .collect()
+    }
+
+    /// dy / dx = -y (x - c) / w^2, summed over the units
+    fn delta(&self, delta: &[f32], inputs: &[f32], outputs: &[f32]) -> Vec<f32> {
+        check_len("RBFLayer", "inputs", self.shape.0, inputs.len());
+        check_len("RBFLayer", "delta values", self.shape.1, delta.len());
+        let mut result = vec![0.0; self.shape.0];
+        for ((c, (d, y)), lw) in self.centers.chunks(self.shape.0).zip(delta.iter().zip(outputs)).zip(&self.log_widths) {
+            let scale = d * y / (2.0 * lw).exp();
+            for ((r, x), c) in result.iter_mut().zip(inputs).zip(c) {
+                *r -= scale * (x - c);
+            }
+        }
+        result
+    }
+
+    /// The derivative with respect to the centers, `y (x - c) / w^2`,
+    /// divided by the factor which `neuron_delta` multiplies in.
+    fn derivw(&self, inputs: &[f32]) -> Option<Vec<f32>> {
+        let distances = self.distances(inputs);
+        let mut derivs = Vec::with_capacity(self.centers.len());
+        for (c, dist) in self.centers.chunks(self.shape.0).zip(distances) {
+            for (x, c) in inputs.iter().zip(c) {
+                // The gradient is zero at the center
+                derivs.push(if dist > 0.0 { (x - c) / dist } else { 0.0 });
+            }
+        }
+        Some(derivs)
+    }
+
+    fn spec(&self) -> Option<LayerSpec> {
+        Some(LayerSpec::new("rbf").with("inputs", self.shape.0).with("units", self.shape.1))
+    }
+}
+
+impl WeightedLayer for RBFLayer {
+    fn weight_count(&self) -> usize {
+        self.centers.len()
+    }
+
+    fn neuron_count(&self) -> usize {
+        self.shape.1
+    }
+
+    fn weights_mut(&mut self) -> Option<WeightsMut<'_>> {
+        Some(WeightsMut::Borrowed(&mut self.centers))
+    }
+
+    fn bias_mut(&mut self) -> Option<&mut Vec<f32>> {
+        Some(&mut self.log_widths)
+    }
+
+    fn weights(&self) -> Option<Weights<'_>> {
+        Some(Weights::Borrowed(&self.centers))
+    }
+
+    fn bias(&self) -> Option<&[f32]> {
+        Some(&self.log_widths)
+    }
+}
+
+/// A dense layer with its weights stored in compressed sparse row
+/// (CSR) format, so that outputs are computed only over the nonzero
+/// weights. Mostly useful for inference with pruned layers, created
+/// with `SparseDenseLayer::from_dense`. The layer is not trained; its
+/// weights and biases are left untouched by trainers.
+pub struct SparseDenseLayer {
+    /// The nonzero weights, neuron by neuron
+    pub values: Vec<f32>,
+    /// The input index of each of the nonzero weights
+    pub columns: Vec<usize>,
+    /// Offsets into `values` where each neuron's weights start, with
+    /// a final entry equal to the number of nonzero weights
+    pub row_offsets: Vec<usize>,
+    pub bias: Vec<f32>,
+    /// (inputs per neuron, number of neurons)
+    pub shape: (usize, usize),
+}
+
+impl SparseDenseLayer {
+    /// Converts a dense layer, keeping only its nonzero weights.
+    pub fn from_dense(dense: &DenseLayer) -> SparseDenseLayer {
+        let mut values = Vec::new();
+        let mut columns = Vec::new();
+        let mut row_offsets = vec![0];
+        for neuron_weights in dense.neuron_weights().chunks(dense.shape.0) {
+            for (i, w) in neuron_weights.iter().enumerate() {
+                if *w != 0.0 {
+                    values.push(*w);
+                    columns.push(i);
+                }
+            }
+            row_offsets.push(values.len());
+        }
+        SparseDenseLayer {
+            values,
+            columns,
+            row_offsets,
+            bias: if dense.use_bias {
+                dense.bias.clone()
+            } else {
+                vec![0.0; dense.shape.1]
+            },
+            shape: dense.shape,
+        }
+    }
+
+    /// Converts back to a dense layer.
+    pub fn to_dense(&self) -> DenseLayer {
+        let mut weights = vec![0.0; self.shape.0 * self.shape.1];
+        for n in 0..self.shape.1 {
+            for k in self.row_offsets[n]..self.row_offsets[n + 1] {
+                weights[n * self.shape.0 + self.columns[k]] = self.values[k];
+            }
+        }
+        DenseLayer {
+            weights,
+            bias: self.bias.clone(),
+            shape: self.shape,
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
+        }
+    }
+}
+
+impl Layer for SparseDenseLayer {
+    fn input_count(&self) -> usize {
+        self.shape.0
+    }
+
+    fn output_count(&self) -> usize {
+        self.shape.1
+    }
+
+    fn output(&self, inputs: &[f32]) -> Vec<f32> {
+        check_len("SparseDenseLayer", "inputs", self.shape.0, inputs.len());
+        let mut out: Vec<f32> = Vec::with_capacity(self.shape.1);
+        for n in 0..self.shape.1 {
+            let mut sum = 0.0;
+            for k in self.row_offsets[n]..self.row_offsets[n + 1] {
+                sum += self.values[k] * inputs[self.columns[k]];
+            }
+            out.push(sum + self.bias[n]);
+        }
+        out
+    }
+
+    fn delta_from_inputs(&self, delta: &[f32], inputs: &[f32]) -> Option<Vec<f32>> {
+        check_len("SparseDenseLayer", "inputs", self.shape.0, inputs.len());
+        check_len("SparseDenseLayer", "delta values", self.shape.1, delta.len());
+        let mut result: Vec<f32> = vec![0.0; self.shape.0];
+        for (n, d) in delta.iter().enumerate() {
+            for k in self.row_offsets[n]..self.row_offsets[n + 1] {
+                result[self.columns[k]] += d * self.values[k];
+            }
+        }
+        Some(result)
+    }
+}
+
+impl WeightedLayer for SparseDenseLayer {}
+
+pub struct HyperbolicLayer {
+    pub size: usize,
+}
+
+impl WeightedLayer for HyperbolicLayer {}
+
+impl Layer for HyperbolicLayer {
+    fn input_count(self: &HyperbolicLayer) -> usize {
+        self.size
+    }
+
+    fn output_count(self: &HyperbolicLayer) -> usize {
+        self.size
+    }
+
+    fn output(self: &HyperbolicLayer, inputs: &[f32]) -> Vec<f32> {
+        let mut out: Vec<f32> = Vec::new();
+        for x in inputs {
+            out.push(x.tanh());
+        }
+        out
+    }
+
+    /// y = tanh(x) and dy / dx = 1 - y^2
+    fn delta_from_outputs(self: &HyperbolicLayer,
+                          delta: &[f32],
+                          outputs: &[f32])
+                          -> Option<Vec<f32>> {
+        check_len("HyperbolicLayer", "outputs", self.size, outputs.len());
+        check_len("HyperbolicLayer", "delta values", self.size, delta.len());
+        let mut derivs: Vec<f32> = vec![0.0; self.size];
+        for ((d, y), yd) in delta.iter().zip(outputs).zip(derivs.iter_mut()) {
+            *yd = d * (1.0 - y * y);
+        }
+        Some(derivs)
+    }
+
+    fn activation(&self) -> Option<Activation> {
+        Some(Activation::Tanh)
+    }
+
+    fn spec(&self) -> Option<LayerSpec> {
+        Some(LayerSpec::new("tanh").with("size", self.size))
+    }
+}
+
+pub struct SigmoidLayer {
+    pub size: usize,
+}
+
+impl Layer for SigmoidLayer {
+    fn input_count(self: &SigmoidLayer) -> usize {
+        self.size
+    }
+
+    fn output_count(self: &SigmoidLayer) -> usize {
+        self.size
+    }
+
+    fn output(self: &SigmoidLayer, inputs: &[f32]) -> Vec<f32> {
+        let mut out: Vec<f32> = Vec::new();
+        for x in inputs {
+            out.push(sigmoid(*x));
+        }
+        out
+    }
+
+    /// dy / dx = y ( 1 - y )
+    fn delta_from_outputs(self: &SigmoidLayer, delta: &[f32], outputs: &[f32]) -> Option<Vec<f32>> {
+        check_len("SigmoidLayer", "outputs", self.size, outputs.len());
+        check_len("SigmoidLayer", "delta values", self.size, delta.len());
+        let mut derivs: Vec<f32> = vec![0.0; self.size];
+        for ((d, y), yd) in delta.iter().zip(outputs).zip(derivs.iter_mut()) {
+            *yd = d * (y * (1.0 - y));
+        }
+        Some(derivs)
+    }
+
+    fn activation(&self) -> Option<Activation> {
+        Some(Activation::Sigmoid)
+    }
+
+    fn spec(&self) -> Option<LayerSpec> {
+        Some(LayerSpec::new("sigmoid").with("size", self.size))
+    }
+}
+
+impl WeightedLayer for SigmoidLayer {}
+
+pub struct RectifiedLayer {
+    pub size: usize,
+}
+
+impl WeightedLayer for RectifiedLayer {}
+
+impl Layer for RectifiedLayer {
+    fn input_count(self: &RectifiedLayer) -> usize {
+        self.size
+    }
+
+    fn output_count(self: &RectifiedLayer) -> usize {
+        self.size
+    }
+
+    fn output(self: &RectifiedLayer, inputs: &[f32]) -> Vec<f32> {
+        let mut out: Vec<f32> = Vec::new();
+        for x in inputs {
+            out.push(if *x < 0.0 { 0.0 } else { *x });
+        }
+        out
+    }
+
+    /// dy / dx = sigmoid function
+    fn delta_from_inputs(self: &RectifiedLayer, delta: &[f32], inputs: &[f32]) -> Option<Vec<f32>> {
+        check_len("RectifiedLayer", "inputs", self.size, inputs.len());
+        check_len("RectifiedLayer", "delta values", self.size, delta.len());
+        let mut derivs: Vec<f32> = Vec::new();
+        for (d, x) in delta.iter().zip(inputs) {
+            derivs.push(d * sigmoid(*x));
+        }
+        Some(derivs)
+    }
+
+    fn activation(&self) -> Option<Activation> {
+        Some(Activation::Rectified)
+    }
+
+    fn spec(&self) -> Option<LayerSpec> {
+        Some(LayerSpec::new("relu").with("size", self.size))
+    }
+}
+
+/// The scale of the SELU activation, which together with `SELU_ALPHA`
+/// makes the activations of a network converge towards zero mean and
+/// unit variance.
+pub const SELU_LAMBDA: f32 = 1.050_701;
+/// The saturation value of the SELU activation for negative inputs,
+/// divided by `SELU_LAMBDA`.
+pub const SELU_ALPHA: f32 = 1.673_263_2;
+
+fn selu(x: f32) -> f32 {
+    if x > 0.0 {
+        SELU_LAMBDA * x
+    } else {
+        SELU_LAMBDA * SELU_ALPHA * (x.exp() - 1.0)
+    }
+}
+
+/// The derivative of the SELU activation in terms of its output.
+fn selu_derivative(y: f32) -> f32 {
+    if y > 0.0 {
+        SELU_LAMBDA
+    } else {
+        y + SELU_LAMBDA * SELU_ALPHA
+    }
+}
+
+/// Scaled exponential linear units, for self-normalizing networks.
+/// Works best with weights initialized with zero mean and variance
+/// `1 / inputs`, and with `AlphaDropoutLayer` instead of dropout.
+pub struct SELULayer {
+    pub size: usize,
+}
+
+impl Layer for SELULayer {
+    fn input_count(&self) -> usize {
+        self.size
+    }
+
+    fn output_count(&self) -> usize {
+        self.size
+    }
+
+    fn output(&self, inputs: &[f32]) -> Vec<f32> {
+        inputs.iter().map(|x| selu(*x)).collect()
+    }
+
+    /// dy / dx = lambda for positive inputs, and y + lambda * alpha
+    /// otherwise
+    fn delta_from_outputs(&self, delta: &[f32], outputs: &[f32]) -> Option<Vec<f32>> {
+        check_len("SELULayer", "outputs", self.size, outputs.len());
+        check_len("SELULayer", "delta values", self.size, delta.len());
+        Some(delta.iter().zip(outputs).map(|(d, y)| d * selu_derivative(*y)).collect())
+    }
+
+    fn activation(&self) -> Option<Activation> {
+        Some(Activation::Selu)
+    }
+
+    fn spec(&self) -> Option<LayerSpec> {
+        Some(LayerSpec::new("selu").with("size", self.size))
+    }
+}
+
+impl WeightedLayer for SELULayer {}
+
+/// Dropout for self-normalizing networks. While training, each input
+/// is replaced by the negative saturation value of SELU with
+/// probability `rate`, and the result is scaled and shifted so that
+/// the mean and variance of the inputs are kept. During inference the
+/// inputs are passed through unchanged.
+pub struct AlphaDropoutLayer {
+    pub size: usize,
+    /// The probability of dropping an input
+    pub rate: f32,
+    training: bool,
+    rng: RngProvider,
+}
+
+impl AlphaDropoutLayer {
+    pub fn new(size: usize, rate: f32, rng: RngProvider) -> AlphaDropoutLayer {
+        assert!((0.0..1.0).contains(&rate), "the dropout rate must be in [0, 1)");
+        AlphaDropoutLayer {
+            size,
+            rate,
+            training: false,
+            rng,
+        }
+    }
+
+    /// The scale and shift applied after dropping inputs.
+    fn affine(&self) -> (f32, f32) {
+        let alpha = -SELU_LAMBDA * SELU_ALPHA;
+        let q = 1.0 - self.rate;
+        let a = 1.0 / (q * (1.0 + self.rate * alpha * alpha)).sqrt();
+        (a, -a * alpha * self.rate)
+    }
+}
+
+impl Layer for AlphaDropoutLayer {
+    fn input_count(&self) -> usize {
+        self.size
+    }
+
+    fn output_count(&self) -> usize {
+        self.size
+    }
+
+    fn output(&self, inputs: &[f32]) -> Vec<f32> {
+        check_len("AlphaDropoutLayer", "inputs", self.size, inputs.len());
+        if !self.training {
+            return inputs.to_vec();
+        }
+        let alpha = -SELU_LAMBDA * SELU_ALPHA;
+        let (a, b) = self.affine();
+        let kept: Vec<bool> = self.rng.with_rng(|rng| (0..self.size).map(|_| rng.gen::<f32>() >= self.rate).collect());
+        inputs.iter()
+            .zip(kept)
+            .map(|(x, k)| a * if k { *x } else { alpha } + b)
+            .collect()
+    }
+
+    /// Only kept inputs pass on the delta signal, scaled like the
+    /// outputs. A dropped input always outputs exactly `a * alpha + b`,
+    /// so which inputs were kept is recovered from the outputs, and
+    /// any number of forward passes may come before the backward one.
+    /// An input which equals the saturation value is taken as dropped,
+    /// which is what it would have been replaced by.
+    #[allow(unused_variables)]
+    fn delta(&self, delta: &[f32], inputs: &[f32], outputs: &[f32]) -> Vec<f32> {
+        check_len("AlphaDropoutLayer", "delta values", self.size, delta.len());
+        if !self.training {
+            return delta.to_vec();
+        }
+        let alpha = -SELU_LAMBDA * SELU_ALPHA;
+        let (a, b) = self.affine();
+        let dropped = a * alpha + b;
+        delta.iter().zip(outputs).map(|(d, y)| if *y == dropped { 0.0 } else { a * d }).collect()
+    }
+
+    fn spec(&self) -> Option<LayerSpec> {
+        Some(LayerSpec::new("alpha_dropout").with("size", self.size).with("rate", self.rate))
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+
+    fn is_training(&self) -> bool {
+        self.training
+    }
+}
+
+impl WeightedLayer for AlphaDropoutLayer {}
+
+/// Samples from a normal distribution with the reparameterization
+/// trick, for variational autoencoders. The inputs are `size` means
+/// followed by as many log variances, such as the outputs of a dense
+/// layer of `2 * size` neurons. While training the outputs are
+/// `mu + exp(logvar / 2) * eps` with standard normal noise `eps`, and
+/// during inference they are the means. The delta signal reaches both
+/// the means and the log variances, since the noise only scales the
+/// deviation from the means.
+///
+/// While training, the layer also adds the gradient of `kl_weight`
+/// times `loss::gaussian_kl` to the delta signal, which pulls the
+/// distribution towards the standard normal prior. A network with this
+/// layer between an encoder and a decoder is thus a VAE which can be
+/// trained to reconstruct its inputs with an ordinary loss, as in
+/// `examples/vae.rs`. The loss reported by `SGDTrainer::fit` and
+/// `evaluate` is that of the reconstruction alone; `kl` gives the term
+/// which the layer adds for a sample.
+pub struct ReparameterizeLayer {
+    pub size: usize,
+    /// The weight of the KL divergence relative to the loss of the
+    /// network
+    pub kl_weight: f32,
+    training: bool,
+    rng: RngProvider,
+}
+
+impl ReparameterizeLayer {
+    pub fn new(size: usize, kl_weight: f32, rng: RngProvider) -> ReparameterizeLayer {
+        ReparameterizeLayer {
+            size,
+            kl_weight,
+            training: false,
+            rng,
+        }
+    }
+
+    /// The KL term of the loss for the inputs of a sample, `kl_weight`
+    /// times `loss::gaussian_kl` of its means and log variances.
+    pub fn kl(&self, inputs: &[f32]) -> f32 {
+        check_len("ReparameterizeLayer", "inputs", 2 * self.size, inputs.len());
+        let (mu, logvar) = inputs.split_at(self.size);
+        self.kl_weight * gaussian_kl(mu, logvar)
+    }
+}
+
+impl Layer for ReparameterizeLayer {
+    fn input_count(&self) -> usize {
+        2 * self.size
+    }
+
+    fn output_count(&self) -> usize {
+        self.size
+    }
+
+    fn output(&self, inputs: &[f32]) -> Vec<f32> {
+        check_len("ReparameterizeLayer", "inputs", 2 * self.size, inputs.len());
+        let (mu, logvar) = inputs.split_at(self.size);
+        if !self.training {
+            return mu.to_vec();
+        }
+        let eps = self.rng.with_rng(|rng| normal_vector(self.size, rng));
+        mu.iter().zip(logvar).zip(eps).map(|((m, v), e)| m + (0.5 * v).exp() * e).collect()
+    }
+
+    /// The noise is recovered from the outputs, as
+    /// `dz/dlogvar = exp(logvar / 2) * eps / 2 = (z - mu) / 2`.
+    fn delta(&self, delta: &[f32], inputs: &[f32], outputs: &[f32]) -> Vec<f32> {
+        check_len("ReparameterizeLayer", "delta values", self.size, delta.len());
+        let (mu, logvar) = inputs.split_at(self.size);
+        let mut result = delta.to_vec();
+        result.extend(delta.iter().zip(outputs.iter().zip(mu)).map(|(d, (z, m))| 0.5 * d * (z - m)));
+        if self.training && self.kl_weight != 0.0 {
+            let (dmu, dlogvar) = gaussian_kl_deriv(mu, logvar);
+            for (r, d) in result.iter_mut().zip(dmu.iter().chain(&dlogvar)) {
+                *r += self.kl_weight * d;
+            }
+        }
+        result
+    }
+
+    fn spec(&self) -> Option<LayerSpec> {
+        Some(LayerSpec::new("reparameterize").with("size", self.size).with("kl_weight", self.kl_weight))
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+
+    fn is_training(&self) -> bool {
+        self.training
+    }
+}
+
+impl WeightedLayer for ReparameterizeLayer {}
+
+/// Binary step activation, outputting 1 for inputs of at least zero
+/// and 0 otherwise. The step has no useful derivative, so networks
+/// using it are trained with `perceptron::PerceptronTrainer` or other
+/// trainers which do not need gradients.
+pub struct StepLayer {
+    pub size: usize,
+}
+
+impl Layer for StepLayer {
+    fn input_count(&self) -> usize {
+        self.size
+    }
+
+    fn output_count(&self) -> usize {
+        self.size
+    }
+
+    fn output(&self, inputs: &[f32]) -> Vec<f32> {
+        inputs.iter().map(|x| if *x >= 0.0 { 1.0 } else { 0.0 }).collect()
+    }
+
+    #[allow(unused_variables)]
+    fn delta(&self, delta: &[f32], inputs: &[f32], outputs: &[f32]) -> Vec<f32> {
+        panic!("StepLayer is not differentiable, train it with PerceptronTrainer")
+    }
+
+    fn spec(&self) -> Option<LayerSpec> {
+        Some(LayerSpec::new("step").with("size", self.size))
+    }
+}
+
+impl WeightedLayer for StepLayer {}
+
+/// Sign activation, outputting 1 for inputs of at least zero and -1
+/// otherwise. Like `StepLayer` it is not differentiable.
+pub struct SignLayer {
+    pub size: usize,
+}
+
+impl Layer for SignLayer {
+    fn input_count(&self) -> usize {
+        self.size
+    }
+
+    fn output_count(&self) -> usize {
+        self.size
+    }
+
+    fn output(&self, inputs: &[f32]) -> Vec<f32> {
+        inputs.iter().map(|x| if *x >= 0.0 { 1.0 } else { -1.0 }).collect()
+    }
+
+    #[allow(unused_variables)]
+    fn delta(&self, delta: &[f32], inputs: &[f32], outputs: &[f32]) -> Vec<f32> {
+        panic!("SignLayer is not differentiable, train it with PerceptronTrainer")
+    }
+
+    fn spec(&self) -> Option<LayerSpec> {
+        Some(LayerSpec::new("sign").with("size", self.size))
+    }
+}
+
+impl WeightedLayer for SignLayer {}
+
+/// Masks out the padded steps of a flattened sequence of `steps`
+/// vectors of `features` values each. The input is the sequence
+/// followed by its mask, one value per step which is 1 for a real step
+/// and 0 for padding, as given by `data::SequenceDataset::masked_sequence`.
+/// The outputs and delta signals of padded steps are zeroed, so later
+/// layers neither see nor learn from them, while real steps whose
+/// values happen to be zero are kept.
+pub struct MaskLayer {
+    pub steps: usize,
+    pub features: usize,
+}
+
+impl MaskLayer {
+    pub fn new(steps: usize, features: usize) -> MaskLayer {
+        MaskLayer { steps, features }
+    }
+
+    /// Which steps of the sequence are real, as opposed to padding,
+    /// read from the mask at the end of the inputs.
+    pub fn mask(&self, inputs: &[f32]) -> Vec<bool> {
+        check_len("MaskLayer", "inputs", self.input_count(), inputs.len());
+        inputs[self.output_count()..].iter().map(|m| *m != 0.0).collect()
+    }
+
+    /// Zeroes the values of the padded steps.
+    fn apply(&self, values: &[f32], mask: &[bool]) -> Vec<f32> {
+        let values = Tensor::new(values.to_vec(), &[self.steps, self.features]);
+        let keep = Tensor::new(mask.iter().map(|m| if *m { 1.0 } else { 0.0 }).collect(), &[self.steps, 1]);
+        values.mul(&keep).to_vec()
+    }
+}
+
+impl Layer for MaskLayer {
+    fn input_count(&self) -> usize {
+        self.steps * (self.features + 1)
+    }
+
+    fn output_count(&self) -> usize {
+        self.steps * self.features
+    }
+
+    /// The output is the sequence without its mask.
+    #[allow(unused_variables)]
+    fn output_shape(&self, input_shape: &[usize]) -> Vec<usize> {
+        vec![self.steps, self.features]
+    }
+
+    fn output(&self, inputs: &[f32]) -> Vec<f32> {
+        self.apply(&inputs[..self.output_count()], &self.mask(inputs))
+    }
+
+    /// The mask gets no delta signal.
+    fn delta_from_inputs(&self, delta: &[f32], inputs: &[f32]) -> Option<Vec<f32>> {
+        check_len("MaskLayer", "delta values", self.output_count(), delta.len());
+        let mut d = self.apply(delta, &self.mask(inputs));
+        d.resize(self.input_count(), 0.0);
+        Some(d)
+    }
+
+    fn spec(&self) -> Option<LayerSpec> {
+        Some(LayerSpec::new("mask")
+            .with("steps", self.steps)
+            .with("features", self.features))
+    }
+}
+
+impl WeightedLayer for MaskLayer {}
+
+/// Dot-product self-attention over a flattened sequence of `steps`
+/// vectors of `dim` values each. Every step attends to all steps, with
+/// weights given by the softmax of the dot products between its vector
+/// and theirs, and outputs the weighted sum of their vectors. With
+/// `scaled` the dot products are divided by `sqrt(dim)`.
+///
+/// The layer has no weights of its own. Queries, keys and values are
+/// all the inputs, so what the layer attends to is learned by the
+/// layers in front of it.
+pub struct AttentionLayer {
+    pub steps: usize,
+    pub dim: usize,
+    pub scaled: bool,
+}
+
+impl AttentionLayer {
+    pub fn new(steps: usize, dim: usize) -> AttentionLayer {
+        AttentionLayer {
+            steps,
+            dim,
+            scaled: true,
+        }
+    }
+
+    fn scale(&self) -> f32 {
+        if self.scaled {
+            1.0 / (self.dim as f32).sqrt()
+        } else {
+            1.0
+        }
+    }
+
+    /// The attention weights, one row of `steps` weights per step.
+    pub fn attention(&self, inputs: &[f32]) -> Vec<f32> {
+        check_len("AttentionLayer", "inputs", self.steps * self.dim, inputs.len());
+        let scale = self.scale();
+        let mut weights = Vec::with_capacity(self.steps * self.steps);
+        for q in inputs.chunks(self.dim) {
+            let scores: Vec<f32> = inputs.chunks(self.dim).map(|k| scale * dot(q, k)).collect();
+            weights.extend(softmax(&scores));
+        }
+        weights
+    }
+}
+
+impl Layer for AttentionLayer {
+    fn input_count(&self) -> usize {
+        self.steps * self.dim
+    }
+
+    fn output_count(&self) -> usize {
+        self.steps * self.dim
     }
 
-    fn output_count(self: &DenseLayer) -> usize {
-        self.shape.1
+    /// The output is a sequence of the same shape as the input.
+    #[allow(unused_variables)]
+    fn output_shape(&self, input_shape: &[usize]) -> Vec<usize> {
+        vec![self.steps, self.dim]
     }
 
-    /// Output of this layer is a vector of weight and input dot products.
-    fn output(self: &DenseLayer, inputs: &[f32]) -> Vec<f32> {
-        assert_eq!(self.shape.0, inputs.len());
-        let neuron_weights = self.weights.chunks(self.shape.0);
-        let mut out: Vec<f32> = Vec::new();
-        for (i, w) in neuron_weights.enumerate() {
-            out.push(dot(w, inputs) + self.bias[i]);
+    fn output(&self, inputs: &[f32]) -> Vec<f32> {
+        let attention = self.attention(inputs);
+        let mut out = vec![0.0; self.steps * self.dim];
+        for (y, a) in out.chunks_mut(self.dim).zip(attention.chunks(self.steps)) {
+            for (a, v) in a.iter().zip(inputs.chunks(self.dim)) {
+                for (y, v) in y.iter_mut().zip(v) {
+                    *y += a * v;
+                }
+            }
         }
-
         out
     }
 
-    fn delta_from_inputs(self: &DenseLayer, delta: &[f32], inputs: &[f32]) -> Option<Vec<f32>> {
-        assert_eq!(self.shape.0, inputs.len());
-        assert_eq!(self.shape.1, delta.len());
-        let mut result: Vec<f32> = vec!(0.0; self.shape.0);
-
-        let neuron_weights = self.weights.chunks(self.shape.0);
+    /// The inputs act as queries, keys and values, and the delta
+    /// signal of each role is summed up.
+    fn delta_from_inputs(&self, delta: &[f32], inputs: &[f32]) -> Option<Vec<f32>> {
+        check_len("AttentionLayer", "delta values", self.input_count(), delta.len());
+        let (steps, dim) = (self.steps, self.dim);
+        let scale = self.scale();
+        let attention = self.attention(inputs);
+        let x = |i: usize| &inputs[i * dim..(i + 1) * dim];
+        let dy = |i: usize| &delta[i * dim..(i + 1) * dim];
+        let mut result = vec![0.0; steps * dim];
 
-        for (d, nw) in delta.iter().zip(neuron_weights) {
-            for (i, w) in nw.iter().enumerate() {
-                result[i] += d * w;
+        for i in 0..steps {
+            let a = &attention[i * steps..(i + 1) * steps];
+            // Through the values
+            for (j, a) in a.iter().enumerate() {
+                for (r, d) in result[j * dim..(j + 1) * dim].iter_mut().zip(dy(i)) {
+                    *r += a * d;
+                }
+            }
+            // Through the softmax to the scores
+            let da: Vec<f32> = (0..steps).map(|j| dot(dy(i), x(j))).collect();
+            let mean = dot(a, &da);
+            for j in 0..steps {
+                let ds = scale * a[j] * (da[j] - mean);
+                // The score is the dot product of query i and key j
+                for k in 0..dim {
+                    result[i * dim + k] += ds * x(j)[k];
+                    result[j * dim + k] += ds * x(i)[k];
+                }
             }
         }
-
         Some(result)
     }
 
-    /// Vector of derivatives with respect to the weights for each
-    /// neuron. Returns a vector with the same logical dimensions as
-    /// the layer's shape.
-    fn derivw(self: &DenseLayer, inputs: &[f32]) -> Option<Vec<f32>> {
-        assert_eq!(self.shape.0, inputs.len());
-        let mut derivs: Vec<f32> = Vec::new();
-        derivs.reserve(self.shape.0 * self.shape.1);
+    fn spec(&self) -> Option<LayerSpec> {
+        Some(LayerSpec::new("attention")
+            .with("steps", self.steps)
+            .with("dim", self.dim)
+            .with("scaled", self.scaled))
+    }
+}
 
-        for _ in 0..self.shape.1 {
-            for i in inputs {
-                derivs.push(*i);
-            }
+impl WeightedLayer for AttentionLayer {}
+
+/// Gives a layer a name, by which it can be found in a network with
+/// `network::find_layer`. Behaves exactly like the wrapped layer.
+pub struct NamedLayer {
+    pub name: String,
+    pub layer: Box<dyn WeightedLayer>,
+}
+
+impl NamedLayer {
+    pub fn new(name: &str, layer: Box<dyn WeightedLayer>) -> NamedLayer {
+        NamedLayer {
+            name: name.to_string(),
+            layer,
         }
-        Some(derivs)
     }
 }
 
-impl WeightedLayer for DenseLayer {
-    fn weight_count(self: &DenseLayer) -> usize {
-        self.weights.len()
+impl Layer for NamedLayer {
+    fn input_count(&self) -> usize {
+        self.layer.input_count()
     }
 
-    fn neuron_count(self: &DenseLayer) -> usize {
-        self.output_count()
-    }
-    fn weights_mut(self: &mut DenseLayer) -> Option<&mut Vec<f32>> {
-        Some(&mut self.weights)
+    fn output_count(&self) -> usize {
+        self.layer.output_count()
     }
 
-    fn bias_mut(self: &mut DenseLayer) -> Option<&mut Vec<f32>> {
-        Some(&mut self.bias)
+    fn output(&self, inputs: &[f32]) -> Vec<f32> {
+        self.layer.output(inputs)
     }
-}
-
-pub struct HyperbolicLayer {
-    pub size: usize,
-}
 
-impl WeightedLayer for HyperbolicLayer {
-    fn weight_count(&self) -> usize {
-        0
-    }
-    fn neuron_count(&self) -> usize {
-        0
+    fn output_shape(&self, input_shape: &[usize]) -> Vec<usize> {
+        self.layer.output_shape(input_shape)
     }
-    fn weights_mut(self: &mut HyperbolicLayer) -> Option<&mut Vec<f32>> {
-        None
+
+    fn output_tensor(&self, inputs: &Tensor) -> Tensor {
+        self.layer.output_tensor(inputs)
     }
 
-    fn bias_mut(self: &mut HyperbolicLayer) -> Option<&mut Vec<f32>> {
-        None
+    fn delta_from_outputs(&self, delta: &[f32], outputs: &[f32]) -> Option<Vec<f32>> {
+        self.layer.delta_from_outputs(delta, outputs)
     }
-}
 
-impl Layer for HyperbolicLayer {
-    fn input_count(self: &HyperbolicLayer) -> usize {
-        self.size
+    fn delta_from_inputs(&self, delta: &[f32], inputs: &[f32]) -> Option<Vec<f32>> {
+        self.layer.delta_from_inputs(delta, inputs)
     }
 
-    fn output_count(self: &HyperbolicLayer) -> usize {
-        self.size
+    fn delta(&self, delta: &[f32], inputs: &[f32], outputs: &[f32]) -> Vec<f32> {
+        self.layer.delta(delta, inputs, outputs)
     }
 
-    fn output(self: &HyperbolicLayer, inputs: &[f32]) -> Vec<f32> {
-        let mut out: Vec<f32> = Vec::new();
-        for x in inputs {
-            out.push(x.tanh());
-        }
-        out
+    fn neuron_delta(&self, delta: &[f32], inputs: &[f32], outputs: &[f32]) -> Vec<f32> {
+        self.layer.neuron_delta(delta, inputs, outputs)
     }
 
-    /// y = tanh(x) and dy / dx = 1 - y^2
-    fn delta_from_outputs(self: &HyperbolicLayer,
-                          delta: &[f32],
-                          outputs: &[f32])
-                          -> Option<Vec<f32>> {
-        assert_eq!(self.size, outputs.len());
-        assert_eq!(self.size, delta.len());
-        let mut derivs: Vec<f32> = vec![0.0; self.size];
-        for ((d, y), yd) in delta.iter().zip(outputs).zip(derivs.iter_mut()) {
-            *yd = d * (1.0 - y * y);
-        }
-        Some(derivs)
+    fn derivw(&self, inputs: &[f32]) -> Option<Vec<f32>> {
+        self.layer.derivw(inputs)
     }
-}
 
-pub struct SigmoidLayer {
-    pub size: usize,
-}
+    fn activation(&self) -> Option<Activation> {
+        self.layer.activation()
+    }
 
-impl Layer for SigmoidLayer {
-    fn input_count(self: &SigmoidLayer) -> usize {
-        self.size
+    fn name(&self) -> Option<&str> {
+        Some(&self.name)
     }
 
-    fn output_count(self: &SigmoidLayer) -> usize {
-        self.size
+    fn spec(&self) -> Option<LayerSpec> {
+        self.layer.spec()
     }
 
-    fn output(self: &SigmoidLayer, inputs: &[f32]) -> Vec<f32> {
-        let mut out: Vec<f32> = Vec::new();
-        for x in inputs {
-            out.push(1.0 / (1.0 + (-x).exp()));
-        }
-        out
+    fn set_training(&mut self, training: bool) {
+        self.layer.set_training(training)
     }
 
-    /// dy / dx = y ( 1 - y )
-    fn delta_from_outputs(self: &SigmoidLayer, delta: &[f32], outputs: &[f32]) -> Option<Vec<f32>> {
-        assert_eq!(self.size, outputs.len());
-        assert_eq!(self.size, delta.len());
-        let mut derivs: Vec<f32> = vec![0.0; self.size];
-        for ((d, y), yd) in delta.iter().zip(outputs).zip(derivs.iter_mut()) {
-            *yd = d * (y * (1.0 - y));
-        }
-        Some(derivs)
+    fn is_training(&self) -> bool {
+        self.layer.is_training()
     }
 }
 
-impl WeightedLayer for SigmoidLayer {
+impl WeightedLayer for NamedLayer {
     fn weight_count(&self) -> usize {
-        0
+        self.layer.weight_count()
     }
+
     fn neuron_count(&self) -> usize {
-        0
-    }
-    fn weights_mut(self: &mut SigmoidLayer) -> Option<&mut Vec<f32>> {
-        None
+        self.layer.neuron_count()
     }
 
-    fn bias_mut(self: &mut SigmoidLayer) -> Option<&mut Vec<f32>> {
-        None
+    fn weights_mut(&mut self) -> Option<WeightsMut<'_>> {
+        self.layer.weights_mut()
     }
-}
 
-pub struct RectifiedLayer {
-    pub size: usize,
-}
+    fn bias_mut(&mut self) -> Option<&mut Vec<f32>> {
+        self.layer.bias_mut()
+    }
 
-impl WeightedLayer for RectifiedLayer {
-    fn weight_count(&self) -> usize {
-        0
+    fn weights(&self) -> Option<Weights<'_>> {
+        self.layer.weights()
     }
-    fn neuron_count(&self) -> usize {
-        0
+
+    fn weight_layout(&self) -> WeightLayout {
+        self.layer.weight_layout()
     }
-    fn weights_mut(self: &mut RectifiedLayer) -> Option<&mut Vec<f32>> {
-        None
+
+    fn bias(&self) -> Option<&[f32]> {
+        self.layer.bias()
     }
 
-    fn bias_mut(self: &mut RectifiedLayer) -> Option<&mut Vec<f32>> {
-        None
+    fn bias_grad(&self, delta: &[f32]) -> Option<Vec<f32>> {
+        self.layer.bias_grad(delta)
     }
-}
 
-impl Layer for RectifiedLayer {
-    fn input_count(self: &RectifiedLayer) -> usize {
-        self.size
+    fn weight_grad(&self, inputs: &[f32], delta: &[f32]) -> Option<Vec<f32>> {
+        self.layer.weight_grad(inputs, delta)
     }
 
-    fn output_count(self: &RectifiedLayer) -> usize {
-        self.size
+    fn weight_mask(&self) -> Option<&[bool]> {
+        self.layer.weight_mask()
     }
 
-    fn output(self: &RectifiedLayer, inputs: &[f32]) -> Vec<f32> {
-        let mut out: Vec<f32> = Vec::new();
-        for x in inputs {
-            out.push(if *x < 0.0 { 0.0 } else { *x });
-        }
-        out
+    fn set_weight_mask(&mut self, mask: Option<Vec<bool>>) {
+        self.layer.set_weight_mask(mask)
     }
 
-    /// dy / dx = sigmoid function
-    fn delta_from_inputs(self: &RectifiedLayer, delta: &[f32], inputs: &[f32]) -> Option<Vec<f32>> {
-        assert_eq!(self.size, inputs.len());
-        assert_eq!(self.size, delta.len());
-        let mut derivs: Vec<f32> = Vec::new();
-        for (d, x) in delta.iter().zip(inputs) {
-            derivs.push(d * (1.0 / (1.0 + (-x).exp())));
-        }
-        Some(derivs)
+    fn update(&mut self, weight_updates: &[f32], bias_updates: &[f32]) {
+        self.layer.update(weight_updates, bias_updates)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use traits::Layer;
+    use sgd::weight_gradient;
+    use testing::assert_close;
+    use traits::{Layer, WeightedLayer};
+
+    #[test]
+    fn accumulate_and_apply_updates() {
+        let mut layer = DenseLayer::uniform(0.0, 2, 1);
+        let mut updates = LayerUpdates::zeros(&layer);
+        let step = LayerUpdates {
+            ws: vec![1.0, 2.0],
+            bs: vec![3.0],
+            count: 2,
+        };
+        updates.accumulate(&step);
+        updates.accumulate(&step);
+        assert_eq!(updates.count, 4);
+
+        updates.apply(&mut layer, 1.0 / updates.count as f32);
+        assert_eq!(layer.weights, vec![0.5, 1.0]);
+        assert_eq!(layer.bias, vec![1.5]);
+
+        updates.clear();
+        assert_eq!((updates.ws, updates.bs, updates.count), (vec![0.0; 2], vec![0.0], 0));
+    }
+
+    #[test]
+    fn activations_have_no_parameters() {
+        let sigmoid = SigmoidLayer { size: 3 };
+        assert_eq!((sigmoid.weight_count(), sigmoid.neuron_count()), (0, 0));
+        assert!(!sigmoid.has_parameters());
+        assert!(DenseLayer::uniform(0.5, 2, 1).has_parameters());
+        assert!(DenseLayer::uniform(0.5, 2, 1).tied().has_parameters());
+    }
+
+    #[test]
+    fn downcast_boxed_layers() {
+        let mut dense: Box<dyn WeightedLayer> = Box::new(DenseLayer::uniform(0.5, 2, 1));
+        assert!(dense.downcast_ref::<SigmoidLayer>().is_none());
+        dense.downcast_mut::<DenseLayer>().unwrap().weights[0] = 2.0;
+        assert_eq!(dense.downcast_ref::<DenseLayer>().unwrap().weights, vec![2.0, 0.5]);
+
+        let mut named: Box<dyn WeightedLayer> = Box::new(NamedLayer::new("head", dense));
+        assert_eq!(named.downcast_ref::<NamedLayer>().unwrap().name, "head");
+        assert_eq!(named.downcast_ref::<DenseLayer>().unwrap().weights, vec![2.0, 0.5]);
+        named.downcast_mut::<DenseLayer>().unwrap().bias[0] = 1.0;
+        assert_eq!(named.bias(), Some(&[1.0][..]));
+
+        let sigmoid: Box<dyn Layer> = Box::new(SigmoidLayer { size: 2 });
+        assert_eq!(sigmoid.downcast_ref::<SigmoidLayer>().unwrap().size, 2);
+    }
 
     #[test]
     fn dense_output() {
@@ -274,9 +2032,18 @@ mod tests {
             weights: w,
             bias: b,
             shape: (2, 3),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
         };
 
-        assert_eq!(l.output(&vec![1.0, -1.0]), vec![-1.4, -1.3, -0.7]);
+        assert_close(l.output(&[1.0, -1.0]), [-1.4, -1.3, -0.7], 1e-6);
+    }
+
+    #[test]
+    #[should_panic(expected = "dense layer expects 2 inputs but got 3")]
+    fn dense_wrong_input() {
+        DenseLayer::uniform(1.0, 2, 3).output(&[1.0, 2.0, 3.0]);
     }
 
     #[test]
@@ -287,10 +2054,13 @@ mod tests {
             weights: w,
             bias: b,
             shape: (2, 3),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
         };
 
         let x = vec![1.0, 2.0];
-        assert_eq!(l.delta_from_inputs(&vec![1.0, 1.0, 1.0], &x),
+        assert_eq!(l.delta_from_inputs(&[1.0, 1.0, 1.0], &x),
                    Some(vec![1.5, 5.5]));
     }
 
@@ -302,18 +2072,351 @@ mod tests {
             weights: w,
             bias: b,
             shape: (2, 3),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
         };
 
         let x = vec![1.0, 2.0];
         assert_eq!(l.derivw(&x), Some(vec![1.0, 2.0, 1.0, 2.0, 1.0, 2.0]));
     }
 
+    #[test]
+    fn dense_without_bias() {
+        let mut l = DenseLayer::uniform(1.0, 2, 1).without_bias();
+
+        assert_eq!(l.output(&[1.0, 2.0]), vec![3.0]);
+        assert_eq!(l.bias(), None);
+        l.update(&[1.0, 1.0], &[1.0]);
+        assert_eq!(l.output(&[1.0, 2.0]), vec![6.0]);
+        assert_eq!(l.bias, vec![0.0]);
+    }
+
+    fn fused_matches_stack(activation: Activation) {
+        use network;
+        use loss::SquaredError;
+        use std::collections::LinkedList;
+
+        let dense = || {
+            DenseLayer {
+                weights: vec![0.5, -2.0, -1.0, 0.5, 2.0, 3.0],
+                bias: vec![0.1, 0.2, -0.3],
+                shape: (2, 3),
+                mask: None,
+                use_bias: true,
+                layout: WeightLayout::NeuronMajor,
+            }
+        };
+        let mut fused: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        fused.push_back(Box::new(DenseActivationLayer::new(dense(), activation)));
+        fused.push_back(Box::new(DenseLayer::uniform(0.5, 3, 1)));
+        let mut stacked: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        stacked.push_back(Box::new(dense()));
+        match activation {
+            Activation::Tanh => stacked.push_back(Box::new(HyperbolicLayer { size: 3 })),
+            Activation::Sigmoid => stacked.push_back(Box::new(SigmoidLayer { size: 3 })),
+            Activation::Rectified => stacked.push_back(Box::new(RectifiedLayer { size: 3 })),
+            Activation::Selu => stacked.push_back(Box::new(SELULayer { size: 3 })),
+        }
+        stacked.push_back(Box::new(DenseLayer::uniform(0.5, 3, 1)));
+
+        let inputs = [1.0, -1.0, 0.5, 0.25];
+        let targets = [1.0, 0.0];
+        assert_close(network::output(&fused, &inputs[..2]), network::output(&stacked, &inputs[..2]), 1e-6);
+        assert_close(network::gradient(&fused, &SquaredError, &inputs, &targets),
+                     network::gradient(&stacked, &SquaredError, &inputs, &targets),
+                     1e-6);
+    }
+
+    #[test]
+    fn dense_activation_matches_stack() {
+        fused_matches_stack(Activation::Tanh);
+        fused_matches_stack(Activation::Sigmoid);
+        fused_matches_stack(Activation::Rectified);
+        fused_matches_stack(Activation::Selu);
+    }
+
+    #[test]
+    fn tied_gradients_accumulate() {
+        use sgd::SGDTrainer;
+        use network;
+        use loss::SquaredError;
+        use traits::SupervisedTrainer;
+        use std::collections::LinkedList;
+
+        let w = vec![0.5, -1.0, 0.25, 2.0];
+        let first = DenseLayer {
+            weights: w.clone(),
+            bias: vec![0.0, 0.0],
+            shape: (2, 2),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
+        };
+        let mut untied: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        untied.push_back(Box::new(DenseLayer::uniform(0.0, 2, 2)));
+        untied.push_back(Box::new(DenseLayer::uniform(0.0, 2, 2)));
+        for l in untied.iter_mut() {
+            l.weights_mut().unwrap().copy_from_slice(&w);
+        }
+
+        let tied = first.tied();
+        let shared = tied.weights.clone();
+        let second = tied.tie();
+        assert!(second.weights.same(&shared));
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(tied));
+        layers.push_back(Box::new(second));
+
+        let inputs = [1.0, 0.5];
+        let targets = [0.0, 1.0];
+        assert_eq!(network::output(&layers, &inputs), network::output(&untied, &inputs));
+
+        // The step of the shared weights is the sum of the steps of
+        // both layers
+        let g = network::gradient(&untied, &SquaredError, &inputs, &targets);
+        SGDTrainer::new(1, 0.1).train(&mut layers, &inputs, &targets);
+        for i in 0..4 {
+            let expected = w[i] - 0.1 * (g[i] + g[6 + i]);
+            assert!((shared.read()[i] - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn tied_layer_keeps_settings() {
+        use sgd::SGDTrainer;
+        use traits::SupervisedTrainer;
+        use std::collections::LinkedList;
+
+        let dense = || DenseLayer::random_with(2, 2, &mut SeededRng::new(6)).without_bias();
+        let tied = dense().tied();
+        let x = [1.0, -0.5];
+        assert_eq!(tied.output(&x), dense().output(&x));
+        assert!(tied.bias().is_none() && !tied.tie().use_bias);
+
+        // Training leaves the bias out
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(tied));
+        SGDTrainer::new(3, 0.1).train(&mut layers, &x, &[0.5, 2.0]);
+        let tied = layers.front().unwrap().downcast_ref::<TiedDenseLayer>().unwrap();
+        assert_eq!(tied.bias, vec![0.0, 0.0]);
+        assert_ne!(*tied.weights.read(), dense().weights);
+    }
+
+    #[test]
+    #[should_panic(expected = "a layer with a weight mask cannot be tied")]
+    fn masked_layer_is_not_tied() {
+        let mut dense = DenseLayer::uniform(1.0, 2, 2);
+        dense.set_weight_mask(Some(vec![true, false, true, true]));
+        dense.tied();
+    }
+
+    #[test]
+    fn transposed_autoencoder() {
+        use sgd::SGDTrainer;
+        use network;
+        use loss::SquaredError;
+        use traits::SupervisedTrainer;
+        use std::collections::LinkedList;
+
+        // 3 inputs encoded to 2 values
+        let w = vec![0.5, -1.0, 0.25, 2.0, 0.1, -0.3];
+        let encoder = DenseLayer {
+            weights: w.clone(),
+            bias: vec![0.0, 0.0],
+            shape: (3, 2),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
+        };
+        let decoder = DenseLayer {
+            weights: vec![0.5, 2.0, -1.0, 0.1, 0.25, -0.3],
+            bias: vec![0.0, 0.0, 0.0],
+            shape: (2, 3),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
+        };
+
+        let tied = DenseLayer { weights: w.clone(), ..DenseLayer::uniform(0.0, 3, 2) }.tied();
+        let shared = tied.weights.clone();
+        let transposed = tied.transpose();
+        assert_eq!((transposed.input_count(), transposed.output_count()), (2, 3));
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(tied));
+        layers.push_back(Box::new(transposed));
+        let mut untied: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        untied.push_back(Box::new(encoder));
+        untied.push_back(Box::new(decoder));
+
+        let x = [1.0, 0.5, -1.0];
+        assert_eq!(network::output(&layers, &x), network::output(&untied, &x));
+
+        // The decoder's gradient is transposed back before it is added
+        let g = network::gradient(&untied, &SquaredError, &x, &x);
+        let decoder_g = &g[8..14];
+        SGDTrainer::new(1, 0.1).train(&mut layers, &x, &x);
+        for n in 0..2 {
+            for i in 0..3 {
+                let expected = w[n * 3 + i] - 0.1 * (g[n * 3 + i] + decoder_g[i * 2 + n]);
+                assert!((shared.read()[n * 3 + i] - expected).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn maxout_routes_to_winner() {
+        use network;
+        use loss::SquaredError;
+        use std::collections::LinkedList;
+
+        // Two units with two pieces each, over two inputs
+        let l = MaxoutLayer {
+            weights: vec![1.0, 0.0, 0.0, 1.0, -1.0, 0.0, 0.0, -1.0],
+            bias: vec![0.0, 0.0, 0.0, 0.5],
+            inputs: 2,
+            units: 2,
+            pieces: 2,
+        };
+        let x = [2.0, 1.0];
+        assert_eq!(l.output(&x), vec![2.0, -0.5]);
+        assert_eq!(l.neuron_delta(&[1.0, 3.0], &x, &[2.0, -0.5]), vec![1.0, 0.0, 0.0, 3.0]);
+        assert_eq!(l.delta(&[1.0, 3.0], &x, &[2.0, -0.5]), vec![1.0, -3.0]);
+
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(l));
+        // Only the winning pieces get a gradient
+        let g = network::gradient(&layers, &SquaredError, &x, &[1.0, 0.0]);
+        assert_eq!(g, vec![4.0, 2.0, 0.0, 0.0, 0.0, 0.0, -2.0, -1.0, 2.0, 0.0, 0.0, -1.0]);
+    }
+
+    #[test]
+    fn rbf_gradient_matches_finite_differences() {
+        use network;
+        use loss::SquaredError;
+        use std::collections::LinkedList;
+
+        let l = RBFLayer {
+            centers: vec![0.0, 0.0, 1.0, -0.5],
+            log_widths: vec![0.0, 0.5f32.ln()],
+            shape: (2, 2),
+        };
+        assert_eq!(l.output(&[0.0, 0.0])[0], 1.0);
+
+        // A layer in front checks the delta to the inputs
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer { weights: vec![1.0, 0.5, -0.5, 1.0], ..DenseLayer::uniform(0.1, 2, 2) }));
+        layers.push_back(Box::new(l));
+        layers.push_back(Box::new(DenseLayer::uniform(0.5, 2, 1)));
+        let x = [0.5, -0.25];
+        let t = [1.0];
+
+        let g = network::gradient(&layers, &SquaredError, &x, &t);
+        let w = network::get_weights(&layers);
+        let eps = 1e-3;
+        for i in 0..w.len() {
+            let mut p = w.clone();
+            p[i] += eps;
+            network::set_weights(&mut layers, &p);
+            let up = network::loss(&layers, &SquaredError, &x, &t);
+            p[i] -= 2.0 * eps;
+            network::set_weights(&mut layers, &p);
+            let down = network::loss(&layers, &SquaredError, &x, &t);
+            assert!((g[i] - (up - down) / (2.0 * eps)).abs() < 1e-2, "parameter {}", i);
+        }
+    }
+
+    #[test]
+    fn sparse_dense_matches_dense() {
+        let l = DenseLayer {
+            weights: vec![0.5, 0.0, 0.0, 0.5, 2.0, 3.0],
+            bias: vec![0.1, 0.2, 0.3],
+            shape: (2, 3),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
+        };
+        let s = SparseDenseLayer::from_dense(&l);
+
+        assert_eq!(s.values, vec![0.5, 0.5, 2.0, 3.0]);
+        assert_eq!(s.row_offsets, vec![0, 1, 2, 4]);
+        let x = [1.0, -1.0];
+        assert_close(s.output(&x), l.output(&x), 1e-6);
+        assert_close(s.delta_from_inputs(&[1.0, 1.0, 1.0], &x).unwrap(),
+                     l.delta_from_inputs(&[1.0, 1.0, 1.0], &x).unwrap(),
+                     1e-6);
+        assert_eq!(s.to_dense().weights, l.weights);
+        let transposed = SparseDenseLayer::from_dense(&l.with_layout(WeightLayout::InputMajor));
+        assert_eq!(transposed.values, s.values);
+    }
+
+    #[test]
+    fn input_major_layout() {
+        let neuron_major = || {
+            let mut l = DenseLayer::random_with(3, 2, &mut SeededRng::new(3));
+            l.mask = Some(vec![true, false, true, true, true, false]);
+            l
+        };
+        let l = neuron_major().with_layout(WeightLayout::InputMajor);
+        let n = neuron_major();
+        // Row i of the input major weights holds the weights of input i
+        assert_eq!(l.weights[1], n.weights[3]);
+        assert_eq!(l.mask.as_ref().unwrap()[..], [true, true, false, true, true, false]);
+        assert_eq!(l.neuron_weights(), n.weights);
+        assert_eq!(l.with_layout(WeightLayout::NeuronMajor).weights, n.weights);
+
+        let l = neuron_major().with_layout(WeightLayout::InputMajor);
+        let (x, delta) = ([0.5, -1.0, 2.0], [1.0, -0.5]);
+        assert_close(l.output(&x), n.output(&x), 1e-6);
+        assert_close(l.delta_from_inputs(&delta, &x).unwrap(), n.delta_from_inputs(&delta, &x).unwrap(), 1e-6);
+        let grad = weight_gradient(&l, &x, &delta);
+        assert_close(transposed(&grad, 3, 2), weight_gradient(&n, &x, &delta), 1e-6);
+        assert_eq!(l.spec().unwrap().get("layout"), Some("inputs"));
+        assert_eq!(n.spec().unwrap().get("layout"), None);
+
+        // Code which only sees the trait finds the layout through it
+        let named = NamedLayer::new("hidden", Box::new(neuron_major().with_layout(WeightLayout::InputMajor)));
+        assert_eq!(named.weight_layout(), WeightLayout::InputMajor);
+        assert_eq!(named.neuron_weights().unwrap(), n.weights);
+        assert_close(weight_gradient(&named, &x, &delta), grad, 1e-6);
+
+        let tied = DenseLayer { mask: None, ..neuron_major() }.with_layout(WeightLayout::InputMajor).tied();
+        assert_close(tied.output(&x), n.output(&x), 1e-6);
+    }
+
+    #[test]
+    fn fused_backward() {
+        let dense = || DenseLayer::random_with(3, 2, &mut SeededRng::new(5));
+        let layers: Vec<Box<dyn WeightedLayer>> = vec![
+            Box::new(dense()),
+            Box::new(dense().with_layout(WeightLayout::InputMajor)),
+            Box::new(dense().without_bias()),
+            Box::new(DenseActivationLayer::new(dense(), Activation::Tanh)),
+            Box::new(DenseActivationLayer::new(dense().with_layout(WeightLayout::InputMajor), Activation::Rectified)),
+            Box::new(HyperbolicLayer { size: 3 }),
+        ];
+        let x = [0.5, -1.0, 2.0];
+        for l in &layers {
+            let y = l.output(&x);
+            let delta: Vec<f32> = (0..y.len()).map(|i| 1.0 - 0.75 * i as f32).collect();
+            let (input_delta, grads) = l.backward(&delta, &x, &y);
+            assert_close(input_delta, l.delta(&delta, &x, &y), 1e-6);
+            if l.has_parameters() {
+                let neuron_delta = l.neuron_delta(&delta, &x, &y);
+                assert_close(grads.weights, weight_gradient(l.as_ref(), &x, &neuron_delta), 1e-6);
+                assert_close(grads.bias, bias_gradient(l.as_ref(), &neuron_delta), 1e-6);
+            } else {
+                assert!(grads.weights.is_empty() && grads.bias.is_empty());
+            }
+        }
+    }
+
     #[test]
     fn hyperbolic_output() {
         let l = HyperbolicLayer { size: 5 };
         let expected = vec![-1.0, -0.7615942, 0.0, 0.7615942, 1.0];
 
-        assert_eq!(l.output(&vec![-999999.0, -1.0, 0.0, 1.0, 999999.0]),
+        assert_eq!(l.output(&[-999999.0, -1.0, 0.0, 1.0, 999999.0]),
                    expected);
     }
 
@@ -322,7 +2425,7 @@ mod tests {
         let l = HyperbolicLayer { size: 3 };
         let expected = vec![1.0, 0.0, -3.0];
 
-        assert_eq!(l.delta_from_outputs(&vec![1.0, 1.0, 1.0], &vec![0.0, 1.0, 2.0]),
+        assert_eq!(l.delta_from_outputs(&[1.0, 1.0, 1.0], &[0.0, 1.0, 2.0]),
                    Some(expected));
     }
 
@@ -331,7 +2434,7 @@ mod tests {
         let l = SigmoidLayer { size: 5 };
         let expected = vec![0.0, 0.26894143, 0.5, 0.7310586, 1.0];
 
-        assert_eq!(l.output(&vec![-999999.0, -1.0, 0.0, 1.0, 999999.0]),
+        assert_eq!(l.output(&[-999999.0, -1.0, 0.0, 1.0, 999999.0]),
                    expected);
     }
 
@@ -340,16 +2443,188 @@ mod tests {
         let l = SigmoidLayer { size: 3 };
         let expected = vec![0.0, 0.25, 0.0];
 
-        assert_eq!(l.delta_from_outputs(&vec![1.0, 1.0, 1.0], &vec![0.0, 0.5, 1.0]),
+        assert_eq!(l.delta_from_outputs(&[1.0, 1.0, 1.0], &[0.0, 0.5, 1.0]),
                    Some(expected));
     }
 
+    #[test]
+    fn selu_output_and_delta() {
+        let l = SELULayer { size: 3 };
+        let y = l.output(&[1.0, 0.0, -999.0]);
+
+        assert_eq!(y[0], SELU_LAMBDA);
+        assert_eq!(y[1], 0.0);
+        assert!((y[2] + SELU_LAMBDA * SELU_ALPHA).abs() < 1e-6);
+        let d = l.delta_from_outputs(&[1.0, 1.0, 1.0], &y).unwrap();
+        assert_eq!(d[0], SELU_LAMBDA);
+        assert!((d[1] - SELU_LAMBDA * SELU_ALPHA).abs() < 1e-6);
+        assert!(d[2].abs() < 1e-6);
+    }
+
+    #[test]
+    fn alpha_dropout_keeps_mean_and_variance() {
+        let size = 10000;
+        let mut l = AlphaDropoutLayer::new(size, 0.2, RngProvider::seeded(1));
+        let mut rng = SeededRng::new(2);
+        let x = normal_vector(size, &mut rng);
+
+        // Inference passes the inputs through
+        assert_eq!(l.output(&x), x);
+
+        l.set_training(true);
+        let y = l.output(&x);
+        let mean = y.iter().sum::<f32>() / size as f32;
+        let var = y.iter().map(|y| (y - mean) * (y - mean)).sum::<f32>() / size as f32;
+        assert!(mean.abs() < 0.05);
+        assert!((var - 1.0).abs() < 0.05);
+
+        let d = l.delta(&vec![1.0; size], &x, &y);
+        let dropped = d.iter().filter(|d| **d == 0.0).count() as f32 / size as f32;
+        assert!((dropped - 0.2).abs() < 0.02);
+    }
+
+    #[test]
+    fn alpha_dropout_delta_after_another_forward() {
+        let size = 20;
+        let training = || {
+            let mut l = AlphaDropoutLayer::new(size, 0.5, RngProvider::seeded(3));
+            l.set_training(true);
+            l
+        };
+        let mut rng = SeededRng::new(4);
+        let x = normal_vector(size, &mut rng);
+        let signal = normal_vector(size, &mut rng);
+
+        // A second forward pass, with another mask, comes between the
+        // first one and its backward pass
+        let l = training();
+        let y = l.output(&x);
+        let other = l.output(&x);
+        assert_ne!(y, other);
+        let d = l.delta(&signal, &x, &y);
+
+        // A layer with the same seed drops the same inputs in its
+        // first pass
+        let h = 1e-2;
+        let objective = |x: &[f32]| training().output(x).iter().zip(&signal).map(|(y, s)| y * s).sum::<f32>();
+        for i in 0..size {
+            let (mut above, mut below) = (x.clone(), x.clone());
+            above[i] += h;
+            below[i] -= h;
+            let numeric = (objective(&above) - objective(&below)) / (2.0 * h);
+            assert!((d[i] - numeric).abs() < 1e-2, "input {}: {} vs {}", i, d[i], numeric);
+        }
+    }
+
+    #[test]
+    fn reparameterize_samples_and_passes_delta() {
+        let size = 10000;
+        let mut l = ReparameterizeLayer::new(size, 0.0, RngProvider::seeded(1));
+        let mut x = vec![2.0; size];
+        x.extend(vec![(0.25f32).ln(); size]);
+
+        // Inference outputs the means
+        assert_eq!(l.output(&x), vec![2.0; size]);
+        assert_eq!(l.delta(&vec![1.0; size], &x, &vec![2.0; size])[size..], vec![0.0; size][..]);
+
+        l.set_training(true);
+        let z = l.output(&x);
+        let mean = z.iter().sum::<f32>() / size as f32;
+        let var = z.iter().map(|z| (z - mean) * (z - mean)).sum::<f32>() / size as f32;
+        assert!((mean - 2.0).abs() < 0.02);
+        assert!((var - 0.25).abs() < 0.02);
+
+        let d = l.delta(&vec![1.0; size], &x, &z);
+        assert_eq!(d[..size], vec![1.0; size][..]);
+        assert_eq!(d[size], 0.5 * (z[0] - 2.0));
+
+        // The KL gradient is added while training
+        l.kl_weight = 2.0;
+        let (dmu, dlogvar) = gaussian_kl_deriv(&x[..1], &x[size..size + 1]);
+        let dkl = l.delta(&vec![1.0; size], &x, &z);
+        assert_eq!(dkl[0], d[0] + 2.0 * dmu[0]);
+        assert_eq!(dkl[size], d[size] + 2.0 * dlogvar[0]);
+        assert_eq!(l.kl(&x), 2.0 * gaussian_kl(&x[..size], &x[size..]));
+    }
+
+    #[test]
+    fn step_and_sign_output() {
+        assert_eq!(StepLayer { size: 3 }.output(&[-1.0, 0.0, 2.0]), vec![0.0, 1.0, 1.0]);
+        assert_eq!(SignLayer { size: 3 }.output(&[-1.0, 0.0, 2.0]), vec![-1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "not differentiable")]
+    fn step_has_no_delta() {
+        StepLayer { size: 1 }.delta(&[1.0], &[0.0], &[1.0]);
+    }
+
+    #[test]
+    fn mask_zeroes_padded_steps() {
+        let l = MaskLayer::new(3, 2);
+        // The second step is padding, the third a real step of zeros
+        let x = [1.0, -1.0, 2.0, 2.0, 0.0, 0.0, 1.0, 0.0, 1.0];
+
+        assert_eq!(l.mask(&x), vec![true, false, true]);
+        assert_eq!(l.output(&x), vec![1.0, -1.0, 0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(l.delta_from_inputs(&[1.0; 6], &x),
+                   Some(vec![1.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn masked_steps_get_no_gradient() {
+        use network;
+        use loss::SquaredError;
+        use std::collections::LinkedList;
+
+        // The second step is padding which no longer equals zero, as
+        // after standardizing the inputs, and the weights of the next
+        // layer must not learn from it
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(MaskLayer::new(2, 2)));
+        layers.push_back(Box::new(DenseLayer::uniform(0.5, 4, 1)));
+
+        let g = network::gradient(&layers, &SquaredError, &[1.0, 2.0, -0.5, -0.5, 1.0, 0.0], &[0.0]);
+        assert!(g[..2].iter().all(|g| *g != 0.0));
+        assert!(g[2..4].iter().all(|g| *g == 0.0));
+    }
+
+    #[test]
+    fn attention_output() {
+        let l = AttentionLayer::new(2, 2);
+        // Orthogonal steps with a large magnitude attend to themselves
+        let x = [10.0, 0.0, 0.0, 10.0];
+        let y = l.output(&x);
+        assert!((y[0] - 10.0).abs() < 1e-3 && y[1].abs() < 1e-3);
+
+        let a = l.attention(&[1.0, 0.0, 1.0, 0.0]);
+        assert_eq!(a, vec![0.5, 0.5, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn attention_delta_matches_finite_differences() {
+        let l = AttentionLayer::new(3, 2);
+        let x = [0.5, -1.0, 1.0, 0.25, -0.5, 0.75];
+        let delta = [1.0, -0.5, 0.25, 2.0, -1.0, 0.5];
+        let d = l.delta_from_inputs(&delta, &x).unwrap();
+
+        let eps = 1e-3;
+        for i in 0..x.len() {
+            let mut up = x;
+            up[i] += eps;
+            let mut down = x;
+            down[i] -= eps;
+            let numeric = (dot(&l.output(&up), &delta) - dot(&l.output(&down), &delta)) / (2.0 * eps);
+            assert!((numeric - d[i]).abs() < 1e-2, "input {}: {} vs {}", i, numeric, d[i]);
+        }
+    }
+
     #[test]
     fn rectified_output() {
         let l = RectifiedLayer { size: 5 };
         let expected = vec![0.0, 0.0, 0.0, 1.0, 999.0];
 
-        assert_eq!(l.output(&vec![-999999.0, -1.0, 0.0, 1.0, 999.0]), expected);
+        assert_eq!(l.output(&[-999999.0, -1.0, 0.0, 1.0, 999.0]), expected);
     }
 
     #[test]
@@ -357,8 +2632,8 @@ mod tests {
         let l = RectifiedLayer { size: 5 };
         let expected = vec![0.0, 0.26894143, 0.5, 0.7310586, 1.0];
 
-        assert_eq!(l.delta_from_inputs(&vec![1.0, 1.0, 1.0, 1.0, 1.0],
-                                       &vec![-999999.0, -1.0, 0.0, 1.0, 999.0]),
+        assert_eq!(l.delta_from_inputs(&[1.0, 1.0, 1.0, 1.0, 1.0],
+                                       &[-999999.0, -1.0, 0.0, 1.0, 999.0]),
                    Some(expected));
     }
 }
@@ -0,0 +1,113 @@
+//! Learning curves: how the training and validation losses change as
+//! a model is trained on more data. A large gap between the two
+//! suggests overfitting, while two high losses suggest underfitting.
+use network;
+use traits::{LossFunction, SupervisedTrainer, WeightedLayer};
+
+use std::collections::LinkedList;
+
+/// The losses after training on a part of the training data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LearningCurvePoint {
+    /// The requested fraction of the training data
+    pub fraction: f32,
+    /// The number of training samples used
+    pub samples: usize,
+    /// Average loss per sample on the training samples used
+    pub train_loss: f32,
+    /// Average loss per sample on the validation data
+    pub validation_loss: f32,
+}
+
+/// Trains a fresh network from `build` on the first `fraction` of the
+/// training samples, for each of the fractions, and measures the
+/// losses. At least one sample is always used, so the curve is empty
+/// if there are no training samples. The training data should be
+/// shuffled beforehand so that every part of it is representative.
+#[allow(clippy::too_many_arguments)]
+pub fn learning_curve<F>(build: F,
+                         trainer: &dyn SupervisedTrainer,
+                         loss: &dyn LossFunction,
+                         train_inputs: &[f32],
+                         train_targets: &[f32],
+                         validation_inputs: &[f32],
+                         validation_targets: &[f32],
+                         fractions: &[f32])
+                         -> Vec<LearningCurvePoint>
+    where F: Fn() -> LinkedList<Box<dyn WeightedLayer>>
+{
+    let mut points = Vec::with_capacity(fractions.len());
+    for fraction in fractions {
+        let mut layers = build();
+        let input_count = layers.front().map(|l| l.input_count()).unwrap_or(0);
+        let output_count = layers.back().map(|l| l.output_count()).unwrap_or(0);
+        let total = train_inputs.len() / input_count.max(1);
+        if total == 0 {
+            break;
+        }
+        let samples = ((fraction.clamp(0.0, 1.0) * total as f32).round() as usize).clamp(1, total);
+
+        let inputs = &train_inputs[..samples * input_count];
+        let targets = &train_targets[..samples * output_count];
+        trainer.train(&mut layers, inputs, targets);
+
+        points.push(LearningCurvePoint {
+            fraction: *fraction,
+            samples,
+            train_loss: network::loss(&layers, loss, inputs, targets),
+            validation_loss: network::loss(&layers, loss, validation_inputs, validation_targets),
+        });
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::DenseLayer;
+    use loss::SquaredError;
+    use sgd::SGDTrainer;
+
+    #[test]
+    fn curve_of_linear_model() {
+        // y = 2x + 1
+        let inputs: Vec<f32> = (0..10).map(|i| i as f32 / 10.0).collect();
+        let targets: Vec<f32> = inputs.iter().map(|x| 2.0 * x + 1.0).collect();
+        let trainer = SGDTrainer::new(200, 0.05);
+
+        let points = learning_curve(|| {
+                                        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+                                        layers.push_back(Box::new(DenseLayer::uniform(0.0, 1, 1)));
+                                        layers
+                                    },
+                                    &trainer,
+                                    &SquaredError,
+                                    &inputs[..8],
+                                    &targets[..8],
+                                    &inputs[8..],
+                                    &targets[8..],
+                                    &[0.0, 0.5, 1.0]);
+
+        let samples: Vec<usize> = points.iter().map(|p| p.samples).collect();
+        assert_eq!(samples, vec![1, 4, 8]);
+        // More data generalizes better for this model
+        assert!(points[2].validation_loss < points[0].validation_loss);
+    }
+    #[test]
+    fn empty_training_set() {
+        let trainer = SGDTrainer::new(1, 0.05);
+        let points = learning_curve(|| {
+                                        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+                                        layers.push_back(Box::new(DenseLayer::uniform(0.0, 1, 1)));
+                                        layers
+                                    },
+                                    &trainer,
+                                    &SquaredError,
+                                    &[],
+                                    &[],
+                                    &[0.5],
+                                    &[2.0],
+                                    &[0.5, 1.0]);
+        assert!(points.is_empty());
+    }
+}
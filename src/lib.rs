@@ -27,7 +27,7 @@
 //! to a list:
 //!
 //! ```rust,ignore
-//! let mut layers: LinkedList<Box<WeightedLayer>> = LinkedList::new();
+//! let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
 //! // We start by a hidden "dense" layer of 6 neurons which should
 //! // accept 2 input values.
 //! layers.push_back(Box::new(DenseLayer::random(2, 6)));
@@ -75,6 +75,28 @@
 //! trainer.train(&mut layers, &inputs, &targets);
 //! ```
 //!
+//! Alternatively, the training loop can be driven one batch at a time
+//! through an iterator. This makes it possible to report progress,
+//! evaluate the network between batches or stop early:
+//!
+//! ```rust,ignore
+//! for result in trainer.iter(&mut layers, &inputs, &targets) {
+//!     if result.loss < 0.01 {
+//!         break;
+//!     }
+//! }
+//! ```
+//!
+//! Or, with the data in a `Dataset`, `fit` trains and records the loss
+//! and accuracy on the training and validation data after every epoch:
+//!
+//! ```rust,ignore
+//! let train = Dataset::new(inputs.clone(), targets.clone(), 2, 1);
+//! let history = trainer.fit(&mut layers, &train, None);
+//! println!("{:?}", history.train_loss());
+//! println!("{:?}", trainer.evaluate(&layers, &train));
+//! ```
+//!
 //! Now calculate the output for the trained network:
 //!
 //! ```rust,ignore
@@ -96,13 +118,75 @@
 //! X: [1, 0], Y: [0.96392107], T: [1]
 //! X: [1, 1], Y: [0.03710678], T: [0]
 //! ```
+#[cfg(feature = "matrixmultiply")]
+extern crate matrixmultiply;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "gpu")]
+extern crate pollster;
+// The code generated by the pyo3 macros refers to `::core`
+#[cfg(feature = "python")]
+extern crate core;
+#[cfg(feature = "python")]
+extern crate pyo3;
 extern crate rand;
+#[cfg(feature = "safetensors")]
+extern crate safetensors;
+#[cfg(feature = "profiling")]
+extern crate tracing;
+#[cfg(feature = "gpu")]
+extern crate wgpu;
 
+#[macro_use]
+mod profile;
 pub mod traits;
 pub mod layers;
 pub mod utils;
+pub mod tensor;
+pub mod random;
 pub mod sgd;
 pub mod loss;
+pub mod embedding;
+pub mod distill;
+pub mod prune;
+pub mod network;
+pub mod evolution;
+pub mod search;
+pub mod curvature;
+pub mod histogram;
+pub mod boundary;
+pub mod image;
+pub mod diagnostics;
+pub mod importance;
+pub mod learning_curve;
+pub mod perceptron;
+pub mod multiclass;
+pub mod threshold;
+pub mod hebbian;
+pub mod rbm;
+pub mod som;
+pub mod clustering;
+pub mod projection;
+pub mod data;
+pub mod recurrent;
+pub mod quantize;
+pub mod export;
+pub mod diff;
+pub mod serialize;
+pub mod typed;
+pub mod metrics;
+pub mod testing;
+pub mod curriculum;
+pub mod schedule;
+pub mod rl;
+pub mod gan;
+pub mod mdn;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 
 #[cfg(test)]
 mod tests {
@@ -1,5 +1,17 @@
 //! Loss functions for training the networks.
 use traits::{LossFunction, DifferentiableLossFunction};
+use utils::{dot, log_sum_exp, sigmoid, softmax, softplus, sum};
+
+/// How the gradients of the samples in a batch are combined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reduction {
+    /// Gradients are summed, so the size of each step grows with the
+    /// batch size.
+    Sum,
+    /// Gradients are averaged, so the same learning rate works for
+    /// any batch size.
+    Mean,
+}
 
 /// The square error is defined as `e = (y - t)^2`, with derivative
 /// `de/dy = 2 * (y - t)`.
@@ -16,3 +28,316 @@ impl DifferentiableLossFunction for SquaredError {
         2.0 * (pred - target)
     }
 }
+
+/// The absolute error `e = |y - t|`, with derivative
+/// `de/dy = sign(y - t)`, taken to be 0 where the prediction is exact.
+/// Minimizing it fits the median rather than the mean, so outliers in
+/// the targets pull the fit much less than with `SquaredError`.
+pub struct AbsoluteError;
+
+impl LossFunction for AbsoluteError {
+    fn loss1(&self, pred: f32, target: f32) -> f32 {
+        (pred - target).abs()
+    }
+}
+
+impl DifferentiableLossFunction for AbsoluteError {
+    fn deriv1(&self, pred: f32, target: f32) -> f32 {
+        if pred == target {
+            0.0
+        } else {
+            (pred - target).signum()
+        }
+    }
+}
+
+/// The Huber loss, `e = (y - t)^2 / 2` for errors up to `delta` and
+/// `e = delta * (|y - t| - delta / 2)` beyond, with derivative
+/// `de/dy = y - t` clamped to [-delta, delta]. It is smooth like
+/// `SquaredError` for small errors and robust to outliers like
+/// `AbsoluteError` for large ones.
+pub struct HuberLoss {
+    pub delta: f32,
+}
+
+impl HuberLoss {
+    pub fn new(delta: f32) -> HuberLoss {
+        assert!(delta > 0.0, "the delta of the Huber loss must be positive");
+        HuberLoss { delta }
+    }
+}
+
+impl LossFunction for HuberLoss {
+    fn loss1(&self, pred: f32, target: f32) -> f32 {
+        let error = (pred - target).abs();
+        if error <= self.delta {
+            0.5 * error * error
+        } else {
+            self.delta * (error - 0.5 * self.delta)
+        }
+    }
+}
+
+impl DifferentiableLossFunction for HuberLoss {
+    fn deriv1(&self, pred: f32, target: f32) -> f32 {
+        (pred - target).clamp(-self.delta, self.delta)
+    }
+}
+
+/// A loss function defined by a pair of closures, one for the loss
+/// of a single prediction and one for its derivative. Useful for
+/// experimenting with new losses without defining a new type.
+///
+/// ```
+/// use scarecrow::loss::ClosureLoss;
+/// use scarecrow::traits::{LossFunction, DifferentiableLossFunction};
+///
+/// // Absolute error
+/// let loss = ClosureLoss::new(|p: f32, t: f32| (p - t).abs(),
+///                             |p: f32, t: f32| (p - t).signum());
+/// assert_eq!(loss.loss1(1.0, 3.0), 2.0);
+/// assert_eq!(loss.deriv1(1.0, 3.0), -1.0);
+/// ```
+pub struct ClosureLoss<L, D> {
+    loss: L,
+    deriv: D,
+}
+
+impl<L, D> ClosureLoss<L, D>
+    where L: Fn(f32, f32) -> f32,
+          D: Fn(f32, f32) -> f32
+{
+    /// Creates a loss from a loss closure and its derivative with
+    /// respect to the prediction. Both take `(pred, target)`.
+    pub fn new(loss: L, deriv: D) -> ClosureLoss<L, D> {
+        ClosureLoss { loss, deriv }
+    }
+}
+
+impl<L, D> LossFunction for ClosureLoss<L, D>
+    where L: Fn(f32, f32) -> f32
+{
+    fn loss1(&self, pred: f32, target: f32) -> f32 {
+        (self.loss)(pred, target)
+    }
+}
+
+impl<L, D> DifferentiableLossFunction for ClosureLoss<L, D>
+    where L: Fn(f32, f32) -> f32,
+          D: Fn(f32, f32) -> f32
+{
+    fn deriv1(&self, pred: f32, target: f32) -> f32 {
+        (self.deriv)(pred, target)
+    }
+}
+
+/// The smallest probability used by losses which take the logarithm
+/// of their inputs, to avoid infinities for zero probabilities.
+const MIN_PROBABILITY: f32 = 1e-7;
+
+/// Kullback-Leibler divergence of the predicted distribution from
+/// the target distribution, `e = t * log(t / y)`, with derivative
+/// `de/dy = -t / y`. Both predictions and targets should be
+/// probabilities. Predictions are clamped to a small positive value
+/// to avoid dividing by zero, and zero targets contribute nothing.
+pub struct KLDivergence;
+
+impl LossFunction for KLDivergence {
+    fn loss1(&self, pred: f32, target: f32) -> f32 {
+        if target <= 0.0 {
+            return 0.0;
+        }
+        target * (target / pred.max(MIN_PROBABILITY)).ln()
+    }
+}
+
+impl DifferentiableLossFunction for KLDivergence {
+    fn deriv1(&self, pred: f32, target: f32) -> f32 {
+        -target / pred.max(MIN_PROBABILITY)
+    }
+}
+
+/// Categorical cross-entropy applied to the softmax of the
+/// predictions. The predictions are expected to be unnormalized
+/// log-probabilities (logits), so no softmax layer should be placed
+/// at the end of the network. The loss is `e = -sum_i t_i *
+/// log(softmax(y)_i)`, with derivative `de/dy_i = softmax(y)_i *
+/// sum(t) - t_i`.
+pub struct SoftmaxCrossEntropy;
+
+impl LossFunction for SoftmaxCrossEntropy {
+    fn loss_vec(&self, preds: &[f32], targets: &[f32]) -> f32 {
+        // log(softmax(y)_i) = y_i - log(sum_j exp(y_j))
+        let lse = log_sum_exp(preds);
+        let mut loss = 0.0;
+        for (p, t) in preds.iter().zip(targets) {
+            loss -= t * (p - lse);
+        }
+        loss
+    }
+}
+
+impl DifferentiableLossFunction for SoftmaxCrossEntropy {
+    fn deriv_vec(&self, preds: &[f32], targets: &[f32]) -> Vec<f32> {
+        let total = sum(targets);
+        let mut derivs = softmax(preds);
+        for (d, t) in derivs.iter_mut().zip(targets) {
+            *d = *d * total - t;
+        }
+        derivs
+    }
+}
+
+/// Binary cross-entropy applied to the sigmoid of the predictions,
+/// for independent yes or no outputs. The predictions are expected to
+/// be logits, so no sigmoid layer should be placed at the end of the
+/// network. The loss is `e = softplus(y) - t * y`, which equals
+/// `-t * log(sigmoid(y)) - (1 - t) * log(1 - sigmoid(y))` without
+/// taking the logarithm of a rounded probability, with derivative
+/// `de/dy = sigmoid(y) - t`.
+pub struct SigmoidCrossEntropy;
+
+impl LossFunction for SigmoidCrossEntropy {
+    fn loss1(&self, pred: f32, target: f32) -> f32 {
+        softplus(pred) - target * pred
+    }
+}
+
+impl DifferentiableLossFunction for SigmoidCrossEntropy {
+    fn deriv1(&self, pred: f32, target: f32) -> f32 {
+        sigmoid(pred) - target
+    }
+}
+
+/// The cosine distance `e = 1 - (y . t) / (|y| |t|)`, which only
+/// depends on the direction of the prediction and not on its length.
+pub struct CosineDistance;
+
+impl LossFunction for CosineDistance {
+    fn loss_vec(&self, preds: &[f32], targets: &[f32]) -> f32 {
+        let norms = dot(preds, preds).sqrt() * dot(targets, targets).sqrt();
+        if norms == 0.0 {
+            return 1.0;
+        }
+        1.0 - dot(preds, targets) / norms
+    }
+}
+
+impl DifferentiableLossFunction for CosineDistance {
+    /// `de/dy = (y . t) y / (|y|^3 |t|) - t / (|y| |t|)`
+    fn deriv_vec(&self, preds: &[f32], targets: &[f32]) -> Vec<f32> {
+        let pnorm = dot(preds, preds).sqrt();
+        let tnorm = dot(targets, targets).sqrt();
+        if pnorm == 0.0 || tnorm == 0.0 {
+            return vec![0.0; preds.len()];
+        }
+        let cross = dot(preds, targets);
+        preds.iter()
+            .zip(targets)
+            .map(|(p, t)| cross * p / (pnorm * pnorm * pnorm * tnorm) - t / (pnorm * tnorm))
+            .collect()
+    }
+}
+
+/// The KL divergence of a normal distribution with means `mu` and log
+/// variances `logvar` from the standard normal distribution,
+/// `0.5 * sum(exp(logvar) + mu^2 - 1 - logvar)`. This is the term of a
+/// variational autoencoder which keeps the codes close to the prior.
+pub fn gaussian_kl(mu: &[f32], logvar: &[f32]) -> f32 {
+    assert_eq!(mu.len(), logvar.len(), "there must be one log variance per mean");
+    mu.iter().zip(logvar).map(|(m, v)| 0.5 * (v.exp() + m * m - 1.0 - v)).sum()
+}
+
+/// The derivatives of `gaussian_kl` with respect to the means and the
+/// log variances.
+pub fn gaussian_kl_deriv(mu: &[f32], logvar: &[f32]) -> (Vec<f32>, Vec<f32>) {
+    assert_eq!(mu.len(), logvar.len(), "there must be one log variance per mean");
+    (mu.to_vec(), logvar.iter().map(|v| 0.5 * (v.exp() - 1.0)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gaussian_kl_divergence() {
+        // The standard normal distribution is the prior itself
+        assert_eq!(gaussian_kl(&[0.0, 0.0], &[0.0, 0.0]), 0.0);
+        assert!((gaussian_kl(&[1.0], &[0.0]) - 0.5).abs() < 0.00001);
+
+        let (mu, logvar) = ([0.5, -1.0], [0.3, -0.2]);
+        let (dmu, dlogvar) = gaussian_kl_deriv(&mu, &logvar);
+        let h = 0.001;
+        for i in 0..2 {
+            let mut m = mu;
+            m[i] += h;
+            assert!(((gaussian_kl(&m, &logvar) - gaussian_kl(&mu, &logvar)) / h - dmu[i]).abs() < 0.01);
+            let mut v = logvar;
+            v[i] += h;
+            assert!(((gaussian_kl(&mu, &v) - gaussian_kl(&mu, &logvar)) / h - dlogvar[i]).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn squared_error_loss_vec() {
+        assert_eq!(SquaredError.loss_vec(&[1.0, 2.0], &[0.0, 0.0]), 5.0);
+        assert_eq!(SquaredError.deriv_vec(&[1.0, 2.0], &[0.0, 0.0]), vec![2.0, 4.0]);
+    }
+
+    #[test]
+    fn robust_losses() {
+        assert_eq!(AbsoluteError.loss_vec(&[1.0, -2.0], &[0.0, 0.0]), 3.0);
+        assert_eq!(AbsoluteError.deriv_vec(&[1.0, -2.0, 0.5], &[0.0, 0.0, 0.5]), vec![1.0, -1.0, 0.0]);
+
+        let l = HuberLoss::new(1.0);
+        // Quadratic up to delta and linear beyond, meeting at delta
+        assert_eq!(l.loss1(0.5, 0.0), 0.125);
+        assert_eq!(l.loss1(1.0, 0.0), 0.5);
+        assert_eq!(l.loss1(-3.0, 0.0), 2.5);
+        assert_eq!(l.deriv_vec(&[0.5, 3.0, -3.0], &[0.0, 0.0, 0.0]), vec![0.5, 1.0, -1.0]);
+    }
+
+    #[test]
+    fn kl_divergence() {
+        let l = KLDivergence;
+
+        assert_eq!(l.loss_vec(&[0.5, 0.5], &[0.5, 0.5]), 0.0);
+        assert!((l.loss_vec(&[0.25, 0.75], &[0.5, 0.5]) - 0.14384104).abs() < 0.00001);
+        assert_eq!(l.deriv_vec(&[0.25, 0.5], &[0.5, 0.0]), vec![-2.0, 0.0]);
+        // Zero predictions are clamped instead of producing infinities
+        assert!(l.loss1(0.0, 1.0).is_finite());
+        assert!(l.deriv1(0.0, 1.0).is_finite());
+    }
+
+    #[test]
+    fn sigmoid_cross_entropy() {
+        let l = SigmoidCrossEntropy;
+
+        assert!((l.loss1(0.0, 1.0) - 2.0f32.ln()).abs() < 0.00001);
+        assert_eq!(l.deriv1(0.0, 1.0), -0.5);
+        // Confident predictions neither overflow nor lose the loss
+        assert_eq!(l.loss1(-1000.0, 1.0), 1000.0);
+        assert_eq!(l.loss1(1000.0, 1.0), 0.0);
+        assert_eq!(l.deriv1(-1000.0, 1.0), -1.0);
+    }
+
+    #[test]
+    fn softmax_cross_entropy() {
+        let l = SoftmaxCrossEntropy;
+
+        // Equal logits give equal probabilities
+        assert!((l.loss_vec(&[0.0, 0.0], &[1.0, 0.0]) - 2.0f32.ln()).abs() < 0.00001);
+        assert_eq!(l.deriv_vec(&[0.0, 0.0], &[1.0, 0.0]), vec![-0.5, 0.5]);
+        // Large logits must not overflow
+        assert!(l.loss_vec(&[1000.0, -1000.0], &[1.0, 0.0]).abs() < 0.00001);
+    }
+
+    #[test]
+    fn cosine_distance() {
+        let l = CosineDistance;
+
+        assert!(l.loss_vec(&[2.0, 0.0], &[1.0, 0.0]).abs() < 0.00001);
+        assert!((l.loss_vec(&[0.0, 1.0], &[1.0, 0.0]) - 1.0).abs() < 0.00001);
+        assert_eq!(l.deriv_vec(&[0.0, 1.0], &[1.0, 0.0]), vec![-1.0, 0.0]);
+    }
+}
@@ -0,0 +1,224 @@
+//! Mixture density networks, which predict a whole distribution of the
+//! targets instead of a single value. This models targets with several
+//! likely values for the same input, where the squared error would
+//! learn their average. The network ends with a dense layer of
+//! `MixtureDensity::output_count` neurons whose raw outputs are the
+//! parameters of a mixture of normal distributions, and is trained with
+//! `MixtureDensity` as its loss. The gradients of the means grow with
+//! the inverse square of the standard deviations, so a small learning
+//! rate keeps the training stable as the components narrow.
+use traits::{DifferentiableLossFunction, LossFunction};
+use utils::{argmax, log_softmax, log_sum_exp, normal_vector, sample_index, softmax};
+
+use rand::Rng;
+use std::f32::consts::PI;
+
+/// A mixture of `components` normal distributions over `dims`
+/// dimensions, each with its own diagonal covariance. The parameters
+/// are laid out as `components` mixture logits, then the means of every
+/// component one after another, then the log standard deviations in the
+/// same order.
+///
+/// As a loss it is the negative log-likelihood of the targets, with the
+/// softmax of the logits as the mixture weights.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MixtureDensity {
+    pub components: usize,
+    pub dims: usize,
+}
+
+/// The parameters of a mixture in a readable form.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mixture {
+    /// The probability of every component, which sum to 1
+    pub weights: Vec<f32>,
+    /// The means of every component, one after another
+    pub means: Vec<f32>,
+    /// The standard deviations of every component, one after another
+    pub stds: Vec<f32>,
+}
+
+impl MixtureDensity {
+    pub fn new(components: usize, dims: usize) -> MixtureDensity {
+        assert!(components > 0 && dims > 0, "a mixture needs components and dimensions");
+        MixtureDensity { components, dims }
+    }
+
+    /// The number of outputs the network needs for the parameters.
+    pub fn output_count(&self) -> usize {
+        self.components * (1 + 2 * self.dims)
+    }
+
+    /// Splits the outputs of the network into the logits, the means and
+    /// the log standard deviations.
+    fn parts<'a>(&self, params: &'a [f32]) -> (&'a [f32], &'a [f32], &'a [f32]) {
+        assert_eq!(params.len(), self.output_count(), "wrong number of mixture parameters");
+        let (logits, rest) = params.split_at(self.components);
+        let (means, log_stds) = rest.split_at(self.components * self.dims);
+        (logits, means, log_stds)
+    }
+
+    /// The mixture described by the outputs of the network.
+    pub fn mixture(&self, params: &[f32]) -> Mixture {
+        let (logits, means, log_stds) = self.parts(params);
+        Mixture {
+            weights: softmax(logits),
+            means: means.to_vec(),
+            stds: log_stds.iter().map(|s| s.exp()).collect(),
+        }
+    }
+
+    /// The mean of the mixture, which is what the squared error would
+    /// have predicted.
+    pub fn mean(&self, params: &[f32]) -> Vec<f32> {
+        let m = self.mixture(params);
+        let mut mean = vec![0.0; self.dims];
+        for (w, means) in m.weights.iter().zip(m.means.chunks(self.dims)) {
+            for (a, b) in mean.iter_mut().zip(means) {
+                *a += w * b;
+            }
+        }
+        mean
+    }
+
+    /// The mean of the most probable component.
+    pub fn mode(&self, params: &[f32]) -> Vec<f32> {
+        let (logits, means, _) = self.parts(params);
+        let k = argmax(logits);
+        means[k * self.dims..(k + 1) * self.dims].to_vec()
+    }
+
+    /// Draws a target from the mixture.
+    pub fn sample<R: Rng>(&self, params: &[f32], rng: &mut R) -> Vec<f32> {
+        let m = self.mixture(params);
+        let k = sample_index(&m.weights, rng);
+        let range = k * self.dims..(k + 1) * self.dims;
+        let noise = normal_vector(self.dims, rng);
+        m.means[range.clone()].iter().zip(&m.stds[range]).zip(noise).map(|((mu, s), n)| mu + s * n).collect()
+    }
+
+    /// The log-probability of the targets under every component,
+    /// including its mixture weight.
+    fn log_joint(&self, params: &[f32], targets: &[f32]) -> Vec<f32> {
+        assert_eq!(targets.len(), self.dims, "wrong number of targets for the mixture");
+        let (logits, means, log_stds) = self.parts(params);
+        let log_weights = log_softmax(logits);
+        let log_norm = 0.5 * (2.0 * PI).ln();
+        log_weights.iter()
+            .zip(means.chunks(self.dims).zip(log_stds.chunks(self.dims)))
+            .map(|(lw, (mu, ls))| {
+                let mut lp = *lw;
+                for ((t, m), s) in targets.iter().zip(mu).zip(ls) {
+                    let z = (t - m) * (-s).exp();
+                    lp -= 0.5 * z * z + s + log_norm;
+                }
+                lp
+            })
+            .collect()
+    }
+}
+
+impl LossFunction for MixtureDensity {
+    fn target_count(&self, _: usize) -> usize {
+        self.dims
+    }
+
+    fn loss_vec(&self, preds: &[f32], targets: &[f32]) -> f32 {
+        -log_sum_exp(&self.log_joint(preds, targets))
+    }
+}
+
+impl DifferentiableLossFunction for MixtureDensity {
+    /// With `g_k` the posterior probability of component `k` given the
+    /// targets, the derivatives are `w_k - g_k` for the logits,
+    /// `-g_k (t - mu) / s^2` for the means and `g_k (1 - (t - mu)^2 / s^2)`
+    /// for the log standard deviations.
+    fn deriv_vec(&self, preds: &[f32], targets: &[f32]) -> Vec<f32> {
+        let posterior = softmax(&self.log_joint(preds, targets));
+        let (logits, means, log_stds) = self.parts(preds);
+        let mut derivs: Vec<f32> = softmax(logits).iter().zip(&posterior).map(|(w, g)| w - g).collect();
+        let mut std_derivs = Vec::with_capacity(log_stds.len());
+        for (g, (mu, ls)) in posterior.iter().zip(means.chunks(self.dims).zip(log_stds.chunks(self.dims))) {
+            for ((t, m), s) in targets.iter().zip(mu).zip(ls) {
+                let z = (t - m) * (-s).exp();
+                derivs.push(-g * z * (-s).exp());
+                std_derivs.push(g * (1.0 - z * z));
+            }
+        }
+        derivs.extend(std_derivs);
+        derivs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{DenseLayer, HyperbolicLayer};
+    use loss::Reduction;
+    use network;
+    use random::{RngProvider, SeededRng};
+    use data::Dataset;
+    use sgd::SGDTrainer;
+    use traits::{SupervisedTrainer, WeightedLayer};
+
+    use std::collections::LinkedList;
+
+    #[test]
+    fn single_component_is_gaussian() {
+        let mdn = MixtureDensity::new(1, 1);
+        // A standard normal at 0 has density 1 / sqrt(2 pi)
+        assert!((mdn.loss_vec(&[0.3, 0.0, 0.0], &[0.0]) - 0.5 * (2.0 * PI).ln()).abs() < 1e-5);
+        assert_eq!(mdn.mean(&[0.3, 1.5, 0.0]), vec![1.5]);
+    }
+
+    #[test]
+    fn derivatives_match_finite_differences() {
+        let mdn = MixtureDensity::new(2, 2);
+        let params = [0.2, -0.4, 0.1, 0.5, -0.3, 0.8, 0.0, -0.2, 0.3, 0.1];
+        let targets = [0.4, 0.1];
+        let derivs = mdn.deriv_vec(&params, &targets);
+        let h = 0.001;
+        for i in 0..params.len() {
+            let mut p = params;
+            p[i] += h;
+            let numeric = (mdn.loss_vec(&p, &targets) - mdn.loss_vec(&params, &targets)) / h;
+            assert!((numeric - derivs[i]).abs() < 0.01, "parameter {}: {} vs {}", i, numeric, derivs[i]);
+        }
+    }
+
+    #[test]
+    fn learns_two_branches() {
+        // Every input has two equally likely targets, x and -x
+        let mut inputs = Vec::new();
+        let mut targets = Vec::new();
+        for i in 0..200 {
+            let x = 0.2 + 0.8 * (i / 2) as f32 / 99.0;
+            inputs.push(x);
+            let noise = 0.1 * ((i * 7 % 11) as f32 / 10.0 - 0.5);
+            targets.push(if i % 2 == 0 { x } else { -x } + noise);
+        }
+        let data = Dataset::new(inputs, targets, 1, 1);
+
+        let mdn = MixtureDensity::new(2, 1);
+        let mut rng = SeededRng::new(1);
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::random_with(1, 8, &mut rng)));
+        layers.push_back(Box::new(HyperbolicLayer { size: 8 }));
+        layers.push_back(Box::new(DenseLayer::random_with(8, mdn.output_count(), &mut rng)));
+        let mut trainer = SGDTrainer::new(500, 0.003);
+        trainer.reduction = Reduction::Mean;
+        trainer.loss = Box::new(mdn);
+        trainer.batch_size = Some(20);
+        trainer.shuffle = true;
+        trainer.rng = RngProvider::seeded(2);
+        trainer.fit(&mut layers, &data, None);
+
+        let m = mdn.mixture(&network::output(&layers, &[0.8]));
+        let mut means = m.means.clone();
+        means.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert!((means[0] + 0.8).abs() < 0.1 && (means[1] - 0.8).abs() < 0.1, "means {:?}", means);
+        assert!(m.weights.iter().all(|w| (w - 0.5).abs() < 0.15), "weights {:?}", m.weights);
+        // The average of the branches, which the squared error learns
+        assert!(mdn.mean(&network::output(&layers, &[0.8]))[0].abs() < 0.15);
+    }
+}
@@ -0,0 +1,201 @@
+//! Measures of how well a network fits a dataset, and the record of
+//! these measures over the course of training.
+use data::Dataset;
+use data::target::TargetTransform;
+use network;
+use traits::{LossFunction, WeightedLayer};
+use utils::argmax;
+
+use std::collections::LinkedList;
+
+/// How well a network fits a dataset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metrics {
+    /// The number of samples evaluated
+    pub samples: usize,
+    /// Average loss per sample
+    pub loss: f32,
+    /// Average absolute difference between the outputs and targets
+    pub mean_absolute_error: f32,
+    /// Square root of the average squared difference between the
+    /// outputs and targets, which weighs large errors more than the
+    /// mean absolute error does
+    pub root_mean_squared_error: f32,
+    /// The coefficient of determination, the fraction of the variation
+    /// of the target values around their mean which the outputs
+    /// explain. 1 for a perfect fit, 0 for always predicting the mean,
+    /// and negative for worse. If the targets do not vary it is 1 for
+    /// a perfect fit and 0 otherwise.
+    pub r_squared: f32,
+    /// Fraction of samples classified correctly. With a single output
+    /// a sample is correct if the output and target are on the same
+    /// side of 0.5, and otherwise if the largest output is that of the
+    /// largest target.
+    pub accuracy: f32,
+}
+
+/// The metrics after one epoch of training.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Epoch {
+    /// The epoch, starting from 0
+    pub epoch: usize,
+    /// The metrics on the training data
+    pub train: Metrics,
+    /// The metrics on the validation data, if any was given
+    pub validation: Option<Metrics>,
+}
+
+/// The metrics of every epoch of training, as returned by
+/// `SupervisedTrainer::fit`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct History {
+    pub epochs: Vec<Epoch>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History { epochs: Vec::new() }
+    }
+
+    /// The metrics after the final epoch.
+    pub fn last(&self) -> Option<&Epoch> {
+        self.epochs.last()
+    }
+
+    /// The training loss of every epoch.
+    pub fn train_loss(&self) -> Vec<f32> {
+        self.epochs.iter().map(|e| e.train.loss).collect()
+    }
+
+    /// The validation loss of every epoch which was validated.
+    pub fn validation_loss(&self) -> Vec<f32> {
+        self.epochs.iter().filter_map(|e| e.validation.as_ref().map(|v| v.loss)).collect()
+    }
+}
+
+/// Measures the network on every sample of the dataset.
+pub fn evaluate(layers: &LinkedList<Box<dyn WeightedLayer>>, loss: &dyn LossFunction, data: &Dataset) -> Metrics {
+    measure(layers, loss, data, None)
+}
+
+/// Measures a network trained on transformed targets on every sample
+/// of the dataset, whose targets are in their original units. The
+/// outputs are mapped back with the inverse of the transformation, so
+/// that the metrics are in the original units too.
+pub fn evaluate_transformed(layers: &LinkedList<Box<dyn WeightedLayer>>,
+                            loss: &dyn LossFunction,
+                            data: &Dataset,
+                            transform: &dyn TargetTransform)
+                            -> Metrics {
+    measure(layers, loss, data, Some(transform))
+}
+
+fn measure(layers: &LinkedList<Box<dyn WeightedLayer>>,
+           loss: &dyn LossFunction,
+           data: &Dataset,
+           transform: Option<&dyn TargetTransform>)
+           -> Metrics {
+    let target_mean = data.target_mean();
+    let mut total_loss = 0.0;
+    let mut total_error = 0.0;
+    let mut total_squared = 0.0;
+    let mut variation = 0.0;
+    let mut correct = 0;
+    for (x, t) in data.iter() {
+        let mut y = network::output(layers, x);
+        if let Some(transform) = transform {
+            transform.inverse(&mut y);
+        }
+        total_loss += loss.loss_vec(&y, t);
+        total_error += y.iter().zip(t).map(|(y, t)| (y - t).abs()).sum::<f32>() / t.len() as f32;
+        total_squared += y.iter().zip(t).map(|(y, t)| (y - t) * (y - t)).sum::<f32>() / t.len() as f32;
+        variation += t.iter().zip(&target_mean).map(|(t, m)| (t - m) * (t - m)).sum::<f32>() / t.len() as f32;
+        if is_correct(&y, t) {
+            correct += 1;
+        }
+    }
+    let samples = data.len();
+    let per_sample = |total: f32| if samples > 0 { total / samples as f32 } else { 0.0 };
+    Metrics {
+        samples,
+        loss: per_sample(total_loss),
+        mean_absolute_error: per_sample(total_error),
+        root_mean_squared_error: per_sample(total_squared).sqrt(),
+        r_squared: if variation > 0.0 {
+            1.0 - total_squared / variation
+        } else {
+            (total_squared == 0.0) as usize as f32
+        },
+        accuracy: per_sample(correct as f32),
+    }
+}
+
+fn is_correct(outputs: &[f32], targets: &[f32]) -> bool {
+    if outputs.len() == 1 {
+        (outputs[0] >= 0.5) == (targets[0] >= 0.5)
+    } else {
+        argmax(outputs) == argmax(targets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data::target::Standardize;
+    use layers::{DenseLayer, WeightLayout};
+    use loss::SquaredError;
+
+    #[test]
+    fn metrics_of_identity() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer {
+            weights: vec![1.0, 0.0, 0.0, 1.0],
+            bias: vec![0.0, 0.0],
+            shape: (2, 2),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
+        }));
+        let data = Dataset::new(vec![0.9, 0.1, 0.2, 0.6], vec![1.0, 0.0, 1.0, 0.0], 2, 2);
+
+        let m = evaluate(&layers, &SquaredError, &data);
+        assert_eq!(m.samples, 2);
+        assert_eq!(m.accuracy, 0.5);
+        assert!((m.mean_absolute_error - 0.4).abs() < 1e-6);
+        assert!((m.loss - SquaredError.loss_vec(&[0.2, 0.6], &[1.0, 0.0]) / 2.0 -
+                 SquaredError.loss_vec(&[0.9, 0.1], &[1.0, 0.0]) / 2.0)
+            .abs() < 1e-6);
+    }
+
+    #[test]
+    fn metrics_in_original_units() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(1.0, 1, 1).without_bias()));
+        let data = Dataset::new(vec![-1.0, 1.0], vec![0.0, 20.0], 1, 1);
+        let standardize = Standardize::fit(&data);
+
+        // Outputs of -1 and 1 are the standardized targets exactly
+        let m = evaluate_transformed(&layers, &SquaredError, &data, &standardize);
+        assert_eq!((m.loss, m.mean_absolute_error), (0.0, 0.0));
+        assert_eq!(evaluate(&layers, &SquaredError, &data).mean_absolute_error, 10.0);
+        assert_eq!(m.r_squared, 1.0);
+    }
+
+    #[test]
+    fn regression_metrics() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(1.0, 1, 1).without_bias()));
+        // The outputs are the inputs, which miss the last target by 2
+        let data = Dataset::new(vec![0.0, 1.0, 2.0, 3.0], vec![0.0, 1.0, 2.0, 5.0], 1, 1);
+
+        let m = evaluate(&layers, &SquaredError, &data);
+        assert_eq!((m.mean_absolute_error, m.root_mean_squared_error), (0.5, 1.0));
+        // The targets vary by 14 around their mean of 2
+        assert!((m.r_squared - (1.0 - 4.0 / 14.0)).abs() < 1e-6);
+
+        let constant = Dataset::new(vec![1.0, 1.0], vec![1.0, 1.0], 1, 1);
+        assert_eq!(evaluate(&layers, &SquaredError, &constant).r_squared, 1.0);
+        let constant = Dataset::new(vec![1.0, 1.0], vec![2.0, 2.0], 1, 1);
+        assert_eq!(evaluate(&layers, &SquaredError, &constant).r_squared, 0.0);
+    }
+}
@@ -0,0 +1,131 @@
+//! One-vs-rest classification, which turns a binary classifier into
+//! one for several classes. One network per class learns to tell its
+//! class apart from all of the others, and the class whose network is
+//! most confident wins. A single network with one output per class,
+//! such as one trained with `SoftmaxCrossEntropy`, learns the classes
+//! together and is usually the better choice; this shows how far
+//! independent binary decisions get.
+use data::Dataset;
+use network;
+use traits::{SupervisedTrainer, WeightedLayer};
+use utils::argmax;
+
+use std::collections::LinkedList;
+
+/// One binary network per class, combined into a single predictor.
+pub struct OneVsRest {
+    /// The network of every class, which outputs a single score that
+    /// is high for samples of its class
+    pub networks: Vec<LinkedList<Box<dyn WeightedLayer>>>,
+}
+
+impl OneVsRest {
+    /// Trains a network for every class of the data, whose targets
+    /// have one value per class, such as one-hot vectors. `build`
+    /// creates the network of class `k`, which must have a single
+    /// output, and `trainer` trains it on the targets of
+    /// `binary_dataset`.
+    pub fn train<F>(data: &Dataset, trainer: &dyn SupervisedTrainer, mut build: F) -> OneVsRest
+        where F: FnMut(usize) -> LinkedList<Box<dyn WeightedLayer>>
+    {
+        assert!(data.output_count > 1, "one-vs-rest needs a target value per class");
+        let networks = (0..data.output_count)
+            .map(|k| {
+                let mut layers = build(k);
+                assert_eq!(layers.back().map(|l| l.output_count()),
+                           Some(1),
+                           "the network of class {} must have a single output",
+                           k);
+                let binary = OneVsRest::binary_dataset(data, k);
+                trainer.train(&mut layers, &binary.inputs, &binary.targets);
+                layers
+            })
+            .collect();
+        OneVsRest { networks }
+    }
+
+    /// The inputs of the data with a single target, 1 for the samples
+    /// of class `class` and 0 for all others.
+    pub fn binary_dataset(data: &Dataset, class: usize) -> Dataset {
+        let targets = (0..data.len()).map(|i| (data.class(i) == class) as usize as f32).collect();
+        Dataset::new(data.inputs.clone(), targets, data.input_count, 1)
+    }
+
+    pub fn class_count(&self) -> usize {
+        self.networks.len()
+    }
+
+    /// The score of every class for every sample, one sample after
+    /// another.
+    pub fn scores(&self, inputs: &[f32]) -> Vec<f32> {
+        let per_class: Vec<Vec<f32>> = self.networks.iter().map(|n| network::predict_batch(n, inputs)).collect();
+        let samples = per_class.first().map_or(0, |s| s.len());
+        (0..samples).flat_map(|i| per_class.iter().map(move |s| s[i])).collect()
+    }
+
+    /// The class with the highest score for every sample.
+    pub fn predict(&self, inputs: &[f32]) -> Vec<usize> {
+        self.scores(inputs).chunks(self.class_count()).map(argmax).collect()
+    }
+
+    /// The fraction of the samples of the data whose class is
+    /// predicted correctly.
+    pub fn accuracy(&self, data: &Dataset) -> f32 {
+        if data.is_empty() {
+            return 0.0;
+        }
+        let correct = self.predict(&data.inputs).iter().enumerate().filter(|&(i, c)| data.class(i) == *c).count();
+        correct as f32 / data.len() as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{DenseLayer, SigmoidLayer};
+    use random::SeededRng;
+    use sgd::SGDTrainer;
+    use utils::normal_vector;
+
+    /// Samples around three centers, with one-hot targets.
+    fn blobs(samples: usize, rng: &mut SeededRng) -> Dataset {
+        let centers = [[0.0, 2.0], [-2.0, -1.0], [2.0, -1.0]];
+        let noise = normal_vector(2 * samples, rng);
+        let mut inputs = Vec::new();
+        let mut targets = Vec::new();
+        for (i, n) in noise.chunks(2).enumerate() {
+            let k = i % 3;
+            inputs.push(centers[k][0] + 0.5 * n[0]);
+            inputs.push(centers[k][1] + 0.5 * n[1]);
+            targets.extend((0..3).map(|c| (c == k) as usize as f32));
+        }
+        Dataset::new(inputs, targets, 2, 3)
+    }
+
+    #[test]
+    fn binary_targets() {
+        let data = Dataset::new(vec![0.0, 1.0, 2.0], vec![1.0, 0.0, 0.0, 1.0, 1.0, 0.0], 1, 2);
+        let binary = OneVsRest::binary_dataset(&data, 1);
+        assert_eq!(binary.targets, vec![0.0, 1.0, 0.0]);
+        assert_eq!(binary.inputs, data.inputs);
+    }
+
+    #[test]
+    fn separates_three_classes() {
+        let mut rng = SeededRng::new(1);
+        let train = blobs(150, &mut rng);
+        let test = blobs(60, &mut rng);
+        let trainer = SGDTrainer::new(200, 0.05);
+        let model = OneVsRest::train(&train, &trainer, |_| {
+            let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+            layers.push_back(Box::new(DenseLayer::random_with(2, 1, &mut rng)));
+            layers.push_back(Box::new(SigmoidLayer { size: 1 }));
+            layers
+        });
+
+        assert_eq!(model.class_count(), 3);
+        assert_eq!(model.scores(&test.inputs).len(), 3 * test.len());
+        let accuracy = model.accuracy(&test);
+        assert!(accuracy > 0.9, "accuracy {}", accuracy);
+    }
+}
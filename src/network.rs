@@ -0,0 +1,924 @@
+//! Functions operating on a whole network, i.e. a list of layers.
+use data::{column_mean, column_variance, Dataset};
+use layers::{SharedWeights, WeightLayout};
+use sgd::backward;
+use tensor::Tensor;
+use traits::{DifferentiableLossFunction, LossFunction, WeightedLayer};
+use utils::add_mut;
+
+use std::borrow::Cow;
+use std::cmp;
+use std::collections::LinkedList;
+use std::ops::{Deref, DerefMut};
+use std::sync::mpsc;
+use std::thread;
+
+/// Feeds a single sample through all layers and returns the output of
+/// the last layer.
+pub fn output(layers: &LinkedList<Box<dyn WeightedLayer>>, x: &[f32]) -> Vec<f32> {
+    let mut o = x.to_vec();
+    for (i, l) in layers.iter().enumerate() {
+        check_layer_len("forward", i, l.as_ref(), "inputs", l.input_count(), o.len());
+        o = l.output(&o);
+    }
+    o
+}
+
+/// Describes a layer by its index in the network, and by its name or
+/// kind when it has one, for error messages.
+pub(crate) fn describe_layer(index: usize, layer: &dyn WeightedLayer) -> String {
+    match (layer.name(), layer.spec()) {
+        (Some(name), _) => format!("layer {} ({})", index, name),
+        (None, Some(spec)) => format!("layer {} ({})", index, spec.kind),
+        (None, None) => format!("layer {}", index),
+    }
+}
+
+/// Panics with a message naming the layer and the phase of training,
+/// such as "forward" or "backward", unless a vector passed to the
+/// layer has the expected length.
+pub(crate) fn check_layer_len(phase: &str,
+                              index: usize,
+                              layer: &dyn WeightedLayer,
+                              what: &str,
+                              expected: usize,
+                              actual: usize) {
+    assert!(expected == actual,
+            "{} pass: {} expects {} {} but got {}",
+            phase,
+            describe_layer(index, layer),
+            expected,
+            what,
+            actual);
+}
+
+/// The outputs of a batch of samples, one after another. A network
+/// without layers outputs its inputs.
+pub fn predict_batch(layers: &LinkedList<Box<dyn WeightedLayer>>, inputs: &[f32]) -> Vec<f32> {
+    if layers.is_empty() {
+        return inputs.to_vec();
+    }
+    let input_count = layers.front().map(|l| l.input_count()).unwrap_or(0);
+    inputs.chunks(input_count).flat_map(|x| output(layers, x)).collect()
+}
+
+/// The outputs of a batch of samples like `predict_batch`, but with
+/// the batch split into contiguous parts which are fed through the
+/// network on up to `threads` threads at once. The outputs are in the
+/// same order as the inputs. With `threads` set to 0, one thread is
+/// used per available CPU. A network without layers outputs its
+/// inputs.
+pub fn predict_batch_parallel(layers: &LinkedList<Box<dyn WeightedLayer>>,
+                              inputs: &[f32],
+                              threads: usize)
+                              -> Vec<f32> {
+    if layers.is_empty() {
+        return inputs.to_vec();
+    }
+    let input_count = layers.front().map(|l| l.input_count()).unwrap_or(0);
+    let samples = inputs.len() / cmp::max(1, input_count);
+    let threads = match threads {
+        0 => thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        n => n,
+    };
+    let per_thread = cmp::max(1, samples.div_ceil(threads));
+    if threads == 1 || samples <= per_thread {
+        return predict_batch(layers, inputs);
+    }
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = inputs.chunks(per_thread * input_count)
+            .map(|part| scope.spawn(move || predict_batch(layers, part)))
+            .collect();
+        handles.into_iter()
+            .flat_map(|h| h.join().expect("a prediction thread panicked"))
+            .collect()
+    })
+}
+
+/// Feeds a single sample with a shape through all layers, keeping
+/// track of the shape of the values between layers. See
+/// `Layer::output_tensor`.
+pub fn output_tensor(layers: &LinkedList<Box<dyn WeightedLayer>>, x: &Tensor) -> Tensor {
+    let mut o: Option<Tensor> = None;
+    for l in layers.iter() {
+        o = Some(l.output_tensor(o.as_ref().unwrap_or(x)));
+    }
+    o.unwrap_or_else(|| x.clone())
+}
+
+/// Switches all layers between training and inference. See
+/// `Layer::set_training`.
+pub fn set_training(layers: &mut LinkedList<Box<dyn WeightedLayer>>, training: bool) {
+    for l in layers.iter_mut() {
+        l.set_training(training);
+    }
+}
+
+/// Keeps the layers of a network in training or inference mode for as
+/// long as it lives, and puts every layer back in the mode it was in
+/// when dropped, even on panic. The network is used through the guard.
+/// Created by `train_mode` and `eval_mode`.
+pub struct ModeGuard<'a> {
+    layers: &'a mut LinkedList<Box<dyn WeightedLayer>>,
+    previous: Vec<bool>,
+}
+
+impl<'a> ModeGuard<'a> {
+    fn new(layers: &'a mut LinkedList<Box<dyn WeightedLayer>>, training: bool) -> ModeGuard<'a> {
+        let previous = layers.iter().map(|l| l.is_training()).collect();
+        set_training(layers, training);
+        ModeGuard { layers, previous }
+    }
+}
+
+impl<'a> Deref for ModeGuard<'a> {
+    type Target = LinkedList<Box<dyn WeightedLayer>>;
+
+    fn deref(&self) -> &LinkedList<Box<dyn WeightedLayer>> {
+        self.layers
+    }
+}
+
+impl<'a> DerefMut for ModeGuard<'a> {
+    fn deref_mut(&mut self) -> &mut LinkedList<Box<dyn WeightedLayer>> {
+        self.layers
+    }
+}
+
+impl<'a> Drop for ModeGuard<'a> {
+    fn drop(&mut self) {
+        for (l, training) in self.layers.iter_mut().zip(&self.previous) {
+            l.set_training(*training);
+        }
+    }
+}
+
+/// Puts the network in training mode until the guard is dropped, such
+/// as for a custom training step.
+pub fn train_mode(layers: &mut LinkedList<Box<dyn WeightedLayer>>) -> ModeGuard<'_> {
+    ModeGuard::new(layers, true)
+}
+
+/// Puts the network in inference mode until the guard is dropped, such
+/// as to evaluate it in the middle of training without dropout.
+pub fn eval_mode(layers: &mut LinkedList<Box<dyn WeightedLayer>>) -> ModeGuard<'_> {
+    ModeGuard::new(layers, false)
+}
+
+/// The mean and variance of every output over several stochastic
+/// forward passes, as returned by `predict_with_uncertainty`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Uncertainty {
+    pub mean: Vec<f32>,
+    pub variance: Vec<f32>,
+}
+
+impl Uncertainty {
+    /// The standard deviation of every output.
+    pub fn std(&self) -> Vec<f32> {
+        self.variance.iter().map(|v| v.sqrt()).collect()
+    }
+}
+
+/// Feeds a sample through the network `passes` times with the layers
+/// in training mode, so that dropout stays on, and returns the mean and
+/// variance of every output (Monte Carlo dropout). The variance is a
+/// simple estimate of how uncertain the network is about the sample,
+/// and is zero for networks without stochastic layers. The layers are
+/// put back in their previous modes afterwards.
+pub fn predict_with_uncertainty(layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+                                x: &[f32],
+                                passes: usize)
+                                -> Uncertainty {
+    assert!(passes > 0, "at least one forward pass is needed");
+    let layers = train_mode(layers);
+    let mut mean = output(&layers, x);
+    let mut squares = vec![0.0; mean.len()];
+    // Welford's method, which avoids subtracting large sums
+    for n in 2..passes + 1 {
+        for ((m, s), y) in mean.iter_mut().zip(squares.iter_mut()).zip(output(&layers, x)) {
+            let d = y - *m;
+            *m += d / n as f32;
+            *s += d * (y - *m);
+        }
+    }
+    Uncertainty {
+        mean,
+        variance: squares.into_iter().map(|s| s / passes as f32).collect(),
+    }
+}
+
+/// The average loss per sample of the network on the given inputs and
+/// targets. Zero if there are no samples, or no layers to tell the
+/// size of a sample.
+pub fn loss(layers: &LinkedList<Box<dyn WeightedLayer>>,
+            loss: &dyn LossFunction,
+            inputs: &[f32],
+            targets: &[f32])
+            -> f32 {
+    if layers.is_empty() {
+        return 0.0;
+    }
+    let input_count = layers.front().map(|l| l.input_count()).unwrap_or(0);
+    let target_count = loss.target_count(layers.back().map(|l| l.output_count()).unwrap_or(0));
+    let mut total = 0.0;
+    let mut count = 0;
+    for (x, t) in inputs.chunks(input_count).zip(targets.chunks(target_count)) {
+        total += loss.loss_vec(&output(layers, x), t);
+        count += 1;
+    }
+    if count > 0 {
+        total / count as f32
+    } else {
+        0.0
+    }
+}
+
+/// Initializes the last layer of a regression network, which must be
+/// a layer with weights and biases and no activation, so that its
+/// outputs over the dataset start with the mean and variance of the
+/// targets. The weights of every neuron are scaled to give the
+/// variance of its target, and the bias is set to shift the mean of
+/// its output to the mean of its target. Starting from the right scale
+/// saves the many epochs it would otherwise take the network to grow
+/// into the range of the targets.
+pub fn init_regression_head(layers: &mut LinkedList<Box<dyn WeightedLayer>>, data: &Dataset) {
+    let mut head = layers.pop_back().expect("the network has no layers");
+    assert!(head.weights().is_some() && head.bias().is_some(),
+            "the last layer must have weights and biases to be a regression head");
+    let neurons = head.neuron_count();
+    assert_eq!(neurons, data.output_count, "the last layer must have one neuron per target");
+
+    // The weighted sums of the head, without its biases, for every sample
+    let bias = head.bias().unwrap().to_vec();
+    let mut sums = Vec::with_capacity(data.len() * neurons);
+    for x in data.inputs.chunks(data.input_count) {
+        sums.extend(head.output(&output(layers, x)).iter().zip(&bias).map(|(y, b)| y - b));
+    }
+    let sum_mean = column_mean(&sums, neurons);
+    let sum_variance = column_variance(&sums, neurons);
+    let target_mean = data.target_mean();
+    let target_variance = data.target_variance();
+
+    let scale: Vec<f32> = sum_variance.iter()
+        .zip(&target_variance)
+        .map(|(s, t)| if *s > 0.0 { (t / s).sqrt() } else { 1.0 })
+        .collect();
+    let shape = (head.weight_count() / neurons, neurons);
+    let layout = head.weight_layout();
+    if let Some(mut weights) = head.weights_mut() {
+        for (n, s) in scale.iter().enumerate() {
+            for i in 0..shape.0 {
+                weights[layout.index(n, i, shape)] *= s;
+            }
+        }
+    }
+    if let Some(bias) = head.bias_mut() {
+        for (((b, t), m), s) in bias.iter_mut().zip(&target_mean).zip(&sum_mean).zip(&scale) {
+            *b = t - s * m;
+        }
+    }
+    layers.push_back(head);
+}
+
+/// The layer with the given name. See `layers::NamedLayer`.
+pub fn find_layer<'a>(layers: &'a LinkedList<Box<dyn WeightedLayer>>, name: &str) -> Option<&'a dyn WeightedLayer> {
+    layers.iter().find(|l| l.name() == Some(name)).map(|l| l.as_ref())
+}
+
+/// The layer with the given name, for changing it.
+pub fn find_layer_mut<'a>(layers: &'a mut LinkedList<Box<dyn WeightedLayer>>,
+                          name: &str)
+                          -> Option<&'a mut Box<dyn WeightedLayer>> {
+    layers.iter_mut().find(|l| l.name() == Some(name))
+}
+
+/// Copies the weights and biases of every named layer in `from` to the
+/// layer of the same name in `to`, such as when reusing part of a
+/// trained network in another. Layers whose values have different
+/// sizes are skipped. Returns the names of the layers copied.
+pub fn copy_named_weights(from: &LinkedList<Box<dyn WeightedLayer>>,
+                          to: &mut LinkedList<Box<dyn WeightedLayer>>)
+                          -> Vec<String> {
+    let mut copied = Vec::new();
+    for source in from.iter() {
+        let name = match source.name() {
+            Some(name) => name,
+            None => continue,
+        };
+        let target = match find_layer_mut(to, name) {
+            Some(target) => target,
+            None => continue,
+        };
+        if source.weights().map(|w| w.len()) != target.weights().map(|w| w.len()) ||
+           source.bias().map(|b| b.len()) != target.bias().map(|b| b.len()) {
+            continue;
+        }
+        // The weights are copied neuron by neuron, as the two layers
+        // may lay them out differently
+        let neurons = target.neuron_count();
+        let layout = target.weight_layout();
+        if let (Some(w), Some(mut t)) = (source.neuron_weights(), target.weights_mut()) {
+            match w.len().checked_div(neurons) {
+                Some(per_neuron) => {
+                    for (j, w) in w.iter().enumerate() {
+                        t[layout.index(j / per_neuron, j % per_neuron, (per_neuron, neurons))] = *w;
+                    }
+                }
+                None => t.copy_from_slice(&w),
+            }
+        }
+        if let (Some(b), Some(t)) = (source.bias(), target.bias_mut()) {
+            t.copy_from_slice(b);
+        }
+        copied.push(name.to_string());
+    }
+    copied
+}
+
+/// The total number of weights and biases in the network.
+pub fn parameter_count(layers: &LinkedList<Box<dyn WeightedLayer>>) -> usize {
+    layers.iter()
+        .map(|l| l.weights().map_or(0, |w| w.len()) + l.bias().map_or(0, |b| b.len()))
+        .sum()
+}
+
+/// Whether a parameter holds the weights or the biases of its layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterKind {
+    Weights,
+    Bias,
+}
+
+/// The weights or biases of one layer, along with where they are
+/// found in the network. Created by `parameters`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter<'a> {
+    /// The index of the layer in the network
+    pub layer: usize,
+    /// The name of the layer, if it has one
+    pub layer_name: Option<&'a str>,
+    /// The kind of the layer, such as "dense", if it can be saved
+    pub layer_kind: Option<String>,
+    pub kind: ParameterKind,
+    /// (neurons, inputs per neuron) for weights which divide evenly
+    /// among the neurons, or (inputs per neuron, neurons) for weights
+    /// stored with `WeightLayout::InputMajor`, otherwise the number of
+    /// values
+    pub shape: Vec<usize>,
+    /// The values, copied if the layer shares them with other layers
+    pub values: Cow<'a, [f32]>,
+}
+
+impl<'a> Parameter<'a> {
+    /// A name which is unique within the network, such as
+    /// "encoder.weight" or "2.bias". Layers without a name are named by
+    /// their index.
+    pub fn name(&self) -> String {
+        let suffix = match self.kind {
+            ParameterKind::Weights => "weight",
+            ParameterKind::Bias => "bias",
+        };
+        match self.layer_name {
+            Some(name) => format!("{}.{}", name, suffix),
+            None => format!("{}.{}", self.layer, suffix),
+        }
+    }
+}
+
+/// Iterates over the weights and biases of every layer, in the order of
+/// `get_weights`, so that tools such as regularizers or exporters work
+/// on any network without knowing the types of its layers.
+pub fn parameters<'a>(layers: &'a LinkedList<Box<dyn WeightedLayer>>) -> impl Iterator<Item = Parameter<'a>> + 'a {
+    layers.iter().enumerate().flat_map(|(i, l)| {
+        let neurons = l.neuron_count();
+        let weights = l.weights().map(|w| {
+            let shape = if neurons > 0 && w.len().is_multiple_of(neurons) {
+                match l.weight_layout() {
+                    WeightLayout::NeuronMajor => vec![neurons, w.len() / neurons],
+                    WeightLayout::InputMajor => vec![w.len() / neurons, neurons],
+                }
+            } else {
+                vec![w.len()]
+            };
+            (ParameterKind::Weights, shape, w.into_cow())
+        });
+        let bias = l.bias().map(|b| (ParameterKind::Bias, vec![b.len()], Cow::Borrowed(b)));
+        weights.into_iter().chain(bias).map(move |(kind, shape, values)| {
+            Parameter {
+                layer: i,
+                layer_name: l.name(),
+                layer_kind: l.spec().map(|s| s.kind),
+                kind,
+                shape,
+                values,
+            }
+        })
+    })
+}
+
+/// Like `parameters`, along with the name of every parameter as given
+/// by `Parameter::name`.
+pub fn named_parameters<'a>(layers: &'a LinkedList<Box<dyn WeightedLayer>>)
+                            -> impl Iterator<Item = (String, Parameter<'a>)> + 'a {
+    parameters(layers).map(|p| (p.name(), p))
+}
+
+/// All weights and biases of the network as a single vector. For each
+/// layer in turn, its weights are followed by its biases.
+pub fn get_weights(layers: &LinkedList<Box<dyn WeightedLayer>>) -> Vec<f32> {
+    let mut result = Vec::with_capacity(parameter_count(layers));
+    for l in layers.iter() {
+        if let Some(w) = l.weights() {
+            result.extend_from_slice(&w);
+        }
+        if let Some(b) = l.bias() {
+            result.extend_from_slice(b);
+        }
+    }
+    result
+}
+
+/// Sets all weights and biases of the network from a single vector,
+/// laid out as returned by `get_weights`.
+pub fn set_weights(layers: &mut LinkedList<Box<dyn WeightedLayer>>, values: &[f32]) {
+    assert_eq!(parameter_count(layers), values.len(), "the network has a different number of parameters");
+    let mut values = values.iter();
+    for l in layers.iter_mut() {
+        if let Some(mut w) = l.weights_mut() {
+            for (w, v) in w.iter_mut().zip(values.by_ref()) {
+                *w = *v;
+            }
+        }
+        if let Some(b) = l.bias_mut() {
+            for (b, v) in b.iter_mut().zip(values.by_ref()) {
+                *b = *v;
+            }
+        }
+    }
+}
+
+/// The gradient of the average loss per sample with respect to all
+/// weights and biases of the network, laid out as returned by
+/// `get_weights`.
+pub fn gradient(layers: &LinkedList<Box<dyn WeightedLayer>>,
+                loss: &dyn DifferentiableLossFunction,
+                inputs: &[f32],
+                targets: &[f32])
+                -> Vec<f32> {
+    if layers.is_empty() {
+        return Vec::new();
+    }
+    let (mut result, count) = summed_gradient(layers, loss, inputs, targets);
+    if count > 0 {
+        for g in result.iter_mut() {
+            *g /= count as f32;
+        }
+    }
+    result
+}
+
+/// How the gradients of the samples are added up by
+/// `gradient_parallel`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParallelReduction {
+    /// Every thread sums the gradients of its part of the samples, and
+    /// the sums are added in the order in which the threads finish.
+    /// Rounding makes the last bits of the result differ between runs.
+    Unordered,
+    /// The samples are summed in blocks of a fixed size, and the sums of
+    /// the blocks are added pairwise in a fixed order, which depends on
+    /// neither the number of threads nor their timing. The result is
+    /// bit-identical from run to run.
+    Deterministic,
+}
+
+/// The number of samples whose gradients are summed one after another
+/// before the sums are reduced.
+const GRADIENT_BLOCK: usize = 16;
+
+/// The gradient of the average loss per sample like `gradient`, with
+/// the samples split among up to `threads` threads. With `threads` set
+/// to 0, one thread is used per available CPU. Layers which draw random
+/// numbers in training mode, such as dropout, still draw them in an
+/// order which depends on the threads.
+pub fn gradient_parallel(layers: &LinkedList<Box<dyn WeightedLayer>>,
+                         loss: &(dyn DifferentiableLossFunction + Sync),
+                         inputs: &[f32],
+                         targets: &[f32],
+                         threads: usize,
+                         reduction: ParallelReduction)
+                         -> Vec<f32> {
+    if layers.is_empty() {
+        return Vec::new();
+    }
+    let input_count = layers.front().map(|l| l.input_count()).unwrap_or(0);
+    let target_count = loss.target_count(layers.back().map(|l| l.output_count()).unwrap_or(0));
+    let samples = cmp::min(inputs.len() / cmp::max(1, input_count),
+                           targets.len() / cmp::max(1, target_count));
+    let threads = match threads {
+        0 => thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        n => n,
+    };
+    let blocks = samples.div_ceil(GRADIENT_BLOCK);
+    let per_thread = cmp::max(1, blocks.div_ceil(threads));
+    // The summed gradient of the samples of a block
+    let block = |b: usize| {
+        let end = cmp::min((b + 1) * GRADIENT_BLOCK, samples);
+        let range = b * GRADIENT_BLOCK..end;
+        summed_gradient(layers,
+                        loss,
+                        &inputs[range.start * input_count..range.end * input_count],
+                        &targets[range.start * target_count..range.end * target_count])
+            .0
+    };
+
+    let mut result = match reduction {
+        ParallelReduction::Deterministic => {
+            let sums: Vec<Vec<f32>> = thread::scope(|scope| {
+                let handles: Vec<_> = (0..blocks)
+                    .step_by(per_thread)
+                    .map(|first| {
+                        let last = cmp::min(first + per_thread, blocks);
+                        scope.spawn(move || (first..last).map(block).collect::<Vec<_>>())
+                    })
+                    .collect();
+                handles.into_iter()
+                    .flat_map(|h| h.join().expect("a gradient thread panicked"))
+                    .collect()
+            });
+            tree_sum(&sums).unwrap_or_else(|| vec![0.0; parameter_count(layers)])
+        }
+        ParallelReduction::Unordered => {
+            let (sender, receiver) = mpsc::channel();
+            thread::scope(|scope| {
+                for first in (0..blocks).step_by(per_thread) {
+                    let sender = sender.clone();
+                    scope.spawn(move || {
+                        let mut total = block(first);
+                        for b in first + 1..cmp::min(first + per_thread, blocks) {
+                            add_mut(&mut total, &block(b));
+                        }
+                        sender.send(total).expect("the gradients are received until every thread is done");
+                    });
+                }
+                drop(sender);
+                let mut result = vec![0.0; parameter_count(layers)];
+                for part in receiver {
+                    add_mut(&mut result, &part);
+                }
+                result
+            })
+        }
+    };
+    if samples > 0 {
+        for g in result.iter_mut() {
+            *g /= samples as f32;
+        }
+    }
+    result
+}
+
+/// Adds up the vectors pairwise, halving the list every time.
+fn tree_sum(parts: &[Vec<f32>]) -> Option<Vec<f32>> {
+    match parts.len() {
+        0 => None,
+        1 => Some(parts[0].clone()),
+        n => {
+            let (a, b) = parts.split_at(n / 2);
+            let mut total = tree_sum(a)?;
+            add_mut(&mut total, &tree_sum(b)?);
+            Some(total)
+        }
+    }
+}
+
+/// The gradient of the loss summed over the samples, laid out as
+/// returned by `get_weights`, and the number of samples.
+fn summed_gradient(layers: &LinkedList<Box<dyn WeightedLayer>>,
+                   loss: &dyn DifferentiableLossFunction,
+                   inputs: &[f32],
+                   targets: &[f32])
+                   -> (Vec<f32>, usize) {
+    let input_count = layers.front().map(|l| l.input_count()).unwrap_or(0);
+    let target_count = loss.target_count(layers.back().map(|l| l.output_count()).unwrap_or(0));
+    let offsets = parameter_offsets(layers);
+    let mut result = vec![0.0; parameter_count(layers)];
+    let mut count = 0;
+    for (x, t) in inputs.chunks(input_count).zip(targets.chunks(target_count)) {
+        for (&(weights, bias), g) in offsets.iter().zip(backward(layers, x, t, loss)) {
+            if let Some(w) = weights {
+                add_mut(&mut result[w..w + g.weights.len()], &g.weights);
+            }
+            if let Some(b) = bias {
+                add_mut(&mut result[b..b + g.bias.len()], &g.bias);
+            }
+        }
+        count += 1;
+    }
+    (result, count)
+}
+
+/// Where the weights and the biases of every layer start in the layout
+/// of `get_weights`, if the layer has any. A layer which uses weights
+/// owned by another layer of the network, such as the transpose of a
+/// `layers::TiedDenseLayer`, is given the start of those, so that the
+/// gradients of all layers sharing the weights are added up.
+fn parameter_offsets(layers: &LinkedList<Box<dyn WeightedLayer>>) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut offsets = Vec::with_capacity(layers.len());
+    let mut offset = 0;
+    for l in layers.iter() {
+        let weights = l.weights().map(|w| {
+            offset += w.len();
+            offset - w.len()
+        });
+        let bias = l.bias().map(|b| {
+            offset += b.len();
+            offset - b.len()
+        });
+        offsets.push((weights, bias));
+    }
+    let owners: Vec<(&SharedWeights, usize)> = layers.iter()
+        .zip(&offsets)
+        .filter_map(|(l, o)| Some((l.shared_weights()?, o.0?)))
+        .collect();
+    for (l, o) in layers.iter().zip(offsets.iter_mut()) {
+        if let (None, Some(shared)) = (o.0, l.shared_weights()) {
+            o.0 = owners.iter().find(|(owner, _)| owner.same(shared)).map(|(_, start)| *start);
+        }
+    }
+    offsets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{AlphaDropoutLayer, AttentionLayer, DenseLayer, NamedLayer, SigmoidLayer};
+    use random::{RngProvider, SeededRng};
+    use loss::SquaredError;
+    use testing::assert_close;
+
+    fn network() -> LinkedList<Box<dyn WeightedLayer>> {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(1.0, 2, 2)));
+        layers.push_back(Box::new(SigmoidLayer { size: 2 }));
+        layers.push_back(Box::new(DenseLayer::uniform(1.0, 2, 1)));
+        layers
+    }
+
+    #[test]
+    fn mode_guards_restore_modes() {
+        let mut layers = network();
+        layers.push_back(Box::new(AlphaDropoutLayer::new(1, 0.5, RngProvider::seeded(1))));
+        let modes = |layers: &LinkedList<Box<dyn WeightedLayer>>| layers.iter().any(|l| l.is_training());
+        {
+            let mut training = train_mode(&mut layers);
+            assert!(modes(&training));
+            {
+                let eval = eval_mode(&mut training);
+                assert!(!modes(&eval));
+                assert_eq!(predict_batch(&eval, &[1.0, 2.0]), predict_batch(&eval, &[1.0, 2.0]));
+            }
+            assert!(modes(&training));
+        }
+        assert!(!modes(&layers));
+    }
+
+    #[test]
+    fn monte_carlo_dropout() {
+        let mut layers = network();
+        let plain = predict_with_uncertainty(&mut layers, &[1.0, 2.0], 10);
+        assert_eq!(plain.mean, output(&layers, &[1.0, 2.0]));
+        assert_eq!(plain.variance, vec![0.0]);
+
+        layers.push_front(Box::new(AlphaDropoutLayer::new(2, 0.5, RngProvider::seeded(1))));
+        let u = predict_with_uncertainty(&mut layers, &[1.0, 2.0], 200);
+        assert!(u.variance[0] > 0.0);
+        assert_eq!(u.std(), vec![u.variance[0].sqrt()]);
+        // Dropout is off again afterwards
+        assert!(!layers.front().unwrap().is_training());
+    }
+
+    #[test]
+    fn parameters_with_metadata() {
+        let mut layers = network();
+        layers.push_front(Box::new(NamedLayer::new("input", Box::new(DenseLayer::uniform(0.5, 3, 2)))));
+
+        let params: Vec<_> = named_parameters(&layers).collect();
+        let names: Vec<&str> = params.iter().map(|p| p.0.as_str()).collect();
+        assert_eq!(names, vec!["input.weight", "input.bias", "1.weight", "1.bias", "3.weight", "3.bias"]);
+        let (_, ref first) = params[0];
+        assert_eq!(first.layer_kind.as_deref(), Some("dense"));
+        assert_eq!(first.kind, ParameterKind::Weights);
+        assert_eq!(first.shape, vec![2, 3]);
+        assert_eq!(first.values, &[0.5; 6][..]);
+        assert_eq!(params[5].1.shape, vec![1]);
+
+        let flat: Vec<f32> = parameters(&layers).flat_map(|p| p.values.into_owned()).collect();
+        assert_eq!(flat, get_weights(&layers));
+    }
+
+    #[test]
+    fn input_major_parameters() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(0.5, 3, 2).with_layout(WeightLayout::InputMajor)));
+        // The shape describes the values as they are stored
+        assert_eq!(parameters(&layers).next().unwrap().shape, vec![3, 2]);
+    }
+
+    #[test]
+    fn tied_layers_share_weights() {
+        let tied = DenseLayer::random_with(3, 2, &mut SeededRng::new(2)).tied();
+        let transposed = tied.transpose();
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(tied));
+        layers.push_back(Box::new(transposed));
+
+        // The layer which owns the shared weights gives them once
+        assert_eq!(parameter_count(&layers), 6 + 2 + 3);
+        let kinds: Vec<ParameterKind> = parameters(&layers).map(|p| p.kind).collect();
+        assert_eq!(kinds, vec![ParameterKind::Weights, ParameterKind::Bias, ParameterKind::Bias]);
+        let mut values = get_weights(&layers);
+        values[0] = 5.0;
+        set_weights(&mut layers, &values);
+        assert_eq!(layers.back().unwrap().shared_weights().unwrap().read()[0], 5.0);
+
+        // The gradient of the shared weights adds up that of both layers
+        let (x, t) = ([1.0, 0.0, -1.0], [0.0, 1.0, 0.0]);
+        let analytic = gradient(&layers, &SquaredError, &x, &t);
+        let h = 1e-2;
+        let numeric: Vec<f32> = (0..values.len())
+            .map(|i| {
+                let mut shifted = values.clone();
+                shifted[i] += h;
+                set_weights(&mut layers, &shifted);
+                let up = loss(&layers, &SquaredError, &x, &t);
+                shifted[i] -= 2.0 * h;
+                set_weights(&mut layers, &shifted);
+                let down = loss(&layers, &SquaredError, &x, &t);
+                (up - down) / (2.0 * h)
+            })
+            .collect();
+        assert_close(analytic, numeric, 1e-2);
+    }
+
+    #[test]
+    fn get_and_set_weights() {
+        let mut layers = network();
+
+        assert_eq!(parameter_count(&layers), 9);
+        assert_eq!(get_weights(&layers), vec![1.0; 9]);
+
+        let values: Vec<f32> = (0..9).map(|i| i as f32).collect();
+        set_weights(&mut layers, &values);
+        assert_eq!(get_weights(&layers), values);
+        assert_eq!(layers.back().unwrap().bias().unwrap(), &[8.0]);
+    }
+
+    #[test]
+    fn network_loss() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(1.0, 1, 1)));
+
+        assert_eq!(output(&layers, &[1.0]), vec![2.0]);
+        assert_eq!(loss(&layers, &SquaredError, &[1.0, 2.0], &[2.0, 1.0]), 2.0);
+    }
+
+    #[test]
+    fn parallel_gradients() {
+        let layers = network();
+        let inputs: Vec<f32> = (0..2 * 101).map(|i| (i as f32 * 0.37).sin()).collect();
+        let targets: Vec<f32> = (0..101).map(|i| (i as f32 * 0.11).cos()).collect();
+        let expected = gradient(&layers, &SquaredError, &inputs, &targets);
+
+        let deterministic = gradient_parallel(&layers, &SquaredError, &inputs, &targets, 1, ParallelReduction::Deterministic);
+        for threads in &[0, 2, 3, 8, 200] {
+            let g = gradient_parallel(&layers, &SquaredError, &inputs, &targets, *threads, ParallelReduction::Deterministic);
+            assert_eq!(g, deterministic);
+            let g = gradient_parallel(&layers, &SquaredError, &inputs, &targets, *threads, ParallelReduction::Unordered);
+            for (a, b) in g.iter().zip(&expected) {
+                assert!((a - b).abs() < 1e-5);
+            }
+        }
+        for (a, b) in deterministic.iter().zip(&expected) {
+            assert!((a - b).abs() < 1e-5);
+        }
+        assert_eq!(gradient_parallel(&layers, &SquaredError, &[], &[], 4, ParallelReduction::Deterministic),
+                   vec![0.0; 9]);
+    }
+
+    #[test]
+    fn parallel_predictions_keep_order() {
+        let layers = network();
+        let inputs: Vec<f32> = (0..2 * 101).map(|i| (i as f32 * 0.37).sin()).collect();
+        let expected = predict_batch(&layers, &inputs);
+
+        assert_eq!(expected.len(), 101);
+        for threads in &[0, 1, 3, 8, 200] {
+            assert_eq!(predict_batch_parallel(&layers, &inputs, *threads), expected);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "forward pass: layer 2 (dense) expects 3 inputs but got 2")]
+    fn mismatch_names_layer() {
+        let mut layers = network();
+        layers.pop_back();
+        layers.push_back(Box::new(DenseLayer::uniform(1.0, 3, 1)));
+        output(&layers, &[1.0, 2.0]);
+    }
+
+    #[test]
+    fn regression_head_matches_targets() {
+        let mut layers = network();
+        let data = Dataset::new(vec![0.0, 0.0, 1.0, 0.0, 0.5, 1.0, -1.0, 0.0],
+                                vec![100.0, 120.0, 90.0, 110.0],
+                                2,
+                                1);
+        init_regression_head(&mut layers, &data);
+
+        let outputs = Dataset::new(data.inputs.clone(), predict_batch(&layers, &data.inputs), 2, 1);
+        assert!((outputs.target_mean()[0] - 105.0).abs() < 1e-3);
+        assert!((outputs.target_variance()[0] / data.target_variance()[0] - 1.0).abs() < 1e-3);
+
+        // A head with input major weights is scaled neuron by neuron
+        let head = |layout| DenseLayer::random_with(2, 2, &mut SeededRng::new(7)).with_layout(layout);
+        let data = Dataset::new(vec![0.0, 0.0, 1.0, 0.0, 0.5, 1.0, -1.0, 0.0],
+                                vec![100.0, 1.0, 120.0, 2.0, 90.0, 4.0, 110.0, 3.0],
+                                2,
+                                2);
+        let mut neuron_major: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        neuron_major.push_back(Box::new(head(WeightLayout::NeuronMajor)));
+        let mut input_major: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        input_major.push_back(Box::new(head(WeightLayout::InputMajor)));
+        init_regression_head(&mut neuron_major, &data);
+        init_regression_head(&mut input_major, &data);
+        let (a, b) = (predict_batch(&neuron_major, &data.inputs), predict_batch(&input_major, &data.inputs));
+        for (a, b) in a.iter().zip(&b) {
+            assert!((a - b).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn empty_network() {
+        let layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        assert_eq!(predict_batch(&layers, &[1.0, 2.0]), vec![1.0, 2.0]);
+        assert_eq!(predict_batch_parallel(&layers, &[1.0, 2.0, 3.0], 2), vec![1.0, 2.0, 3.0]);
+        assert_eq!(loss(&layers, &SquaredError, &[1.0], &[2.0]), 0.0);
+        assert!(gradient(&layers, &SquaredError, &[1.0], &[2.0]).is_empty());
+        assert_eq!(predict_batch(&network(), &[]), Vec::<f32>::new());
+    }
+
+    #[test]
+    fn layers_by_name() {
+        let mut layers = network();
+        let hidden = layers.pop_front().unwrap();
+        layers.push_front(Box::new(NamedLayer::new("hidden", hidden)));
+
+        assert_eq!(find_layer(&layers, "hidden").map(|l| l.output_count()), Some(2));
+        assert!(find_layer(&layers, "output").is_none());
+
+        find_layer_mut(&mut layers, "hidden").unwrap().weights_mut().unwrap()[0] = 5.0;
+        assert_eq!(layers.front().unwrap().weights().unwrap()[0], 5.0);
+
+        let mut other = network();
+        let first = other.pop_front().unwrap();
+        other.push_front(Box::new(NamedLayer::new("hidden", first)));
+        assert_eq!(copy_named_weights(&layers, &mut other), vec!["hidden".to_string()]);
+        assert_eq!(get_weights(&other), get_weights(&layers));
+
+        // The weights are copied neuron by neuron between layouts
+        find_layer_mut(&mut layers, "hidden").unwrap().weights_mut().unwrap()[1] = -2.0;
+        let mut input_major: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        let dense = DenseLayer::uniform(0.0, 2, 2).with_layout(WeightLayout::InputMajor);
+        input_major.push_back(Box::new(NamedLayer::new("hidden", Box::new(dense))));
+        copy_named_weights(&layers, &mut input_major);
+        let x = [0.5, -1.0];
+        assert_eq!(output(&input_major, &x), layers.front().unwrap().output(&x));
+    }
+
+    #[test]
+    fn tensor_shapes() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(AttentionLayer::new(2, 2)));
+        let x = Tensor::new(vec![1.0, 0.0, 1.0, 0.0], &[2, 2]);
+        assert_eq!(output_tensor(&layers, &x).shape(), &[2, 2]);
+
+        layers.push_back(Box::new(DenseLayer::uniform(1.0, 4, 1)));
+        let y = output_tensor(&layers, &x);
+        assert_eq!(y.shape(), &[1]);
+        assert_close(y.to_vec(), output(&layers, &x.to_vec()), 1e-6);
+    }
+
+    #[test]
+    fn network_gradient() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(1.0, 1, 1)));
+
+        // e = (w x + b - t)^2, de/dw = 2 (w x + b - t) x, de/db = 2 (w x + b - t)
+        // The first sample has zero error, the second an error of 2.
+        assert_eq!(gradient(&layers, &SquaredError, &[1.0, 2.0], &[2.0, 1.0]),
+                   vec![8.0 / 2.0, 4.0 / 2.0]);
+    }
+}
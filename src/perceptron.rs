@@ -0,0 +1,93 @@
+//! The perceptron learning rule, the historical starting point of
+//! neural networks. It trains a single layer of threshold units
+//! without gradients.
+use network;
+use sgd::{bias_gradient, weight_gradient};
+use traits::{SupervisedTrainer, WeightedLayer};
+
+use std::collections::LinkedList;
+
+/// Trains a network of a single weighted layer, typically followed by
+/// a `StepLayer` or `SignLayer`, with the perceptron rule. For every
+/// misclassified sample the weights of each output move by
+/// `rate * (target - output) * input`, and its bias by
+/// `rate * (target - output)`. Training stops early once an epoch
+/// classifies every sample correctly, which happens for linearly
+/// separable data.
+pub struct PerceptronTrainer {
+    /// The maximum number of passes over the data
+    pub epochs: usize,
+    /// The learning rate
+    pub rate: f32,
+}
+
+impl PerceptronTrainer {
+    pub fn new(epochs: usize, rate: f32) -> PerceptronTrainer {
+        PerceptronTrainer { epochs, rate }
+    }
+}
+
+impl SupervisedTrainer for PerceptronTrainer {
+    fn train(&self, layers: &mut LinkedList<Box<dyn WeightedLayer>>, inputs: &[f32], targets: &[f32]) {
+        assert!(layers.iter().skip(1).all(|l| l.weight_count() == 0),
+                "the perceptron rule can only train the first layer of a network");
+        let input_count = layers.front().map(|l| l.input_count()).unwrap_or(0);
+        let output_count = layers.back().map(|l| l.output_count()).unwrap_or(0);
+
+        for _ in 0..self.epochs {
+            let mut errors = 0;
+            for (x, t) in inputs.chunks(input_count).zip(targets.chunks(output_count)) {
+                let y = network::output(layers, x);
+                if y.as_slice() == t {
+                    continue;
+                }
+                errors += 1;
+
+                let delta: Vec<f32> = t.iter().zip(&y).map(|(t, y)| self.rate * (t - y)).collect();
+                let first = layers.front_mut().unwrap();
+                let ws = weight_gradient(first.as_ref(), x, &delta);
+                let bs = bias_gradient(first.as_ref(), &delta);
+                first.update(&ws, &bs);
+            }
+            if errors == 0 {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{DenseLayer, SignLayer, StepLayer};
+
+    #[test]
+    fn learns_and() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(0.0, 2, 1)));
+        layers.push_back(Box::new(StepLayer { size: 1 }));
+
+        let inputs = [0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 1.0];
+        let targets = [0.0, 0.0, 0.0, 1.0];
+        PerceptronTrainer::new(100, 0.1).train(&mut layers, &inputs, &targets);
+
+        for (x, t) in inputs.chunks(2).zip(targets.iter()) {
+            assert_eq!(network::output(&layers, x), vec![*t]);
+        }
+    }
+
+    #[test]
+    fn learns_or_with_sign() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(0.0, 2, 1)));
+        layers.push_back(Box::new(SignLayer { size: 1 }));
+
+        let inputs = [0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 1.0];
+        let targets = [-1.0, 1.0, 1.0, 1.0];
+        PerceptronTrainer::new(100, 0.1).train(&mut layers, &inputs, &targets);
+
+        for (x, t) in inputs.chunks(2).zip(targets.iter()) {
+            assert_eq!(network::output(&layers, x), vec![*t]);
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Timing spans around the phases of training, compiled in with the
+//! `profiling` feature. The spans are those of the `tracing` crate, so
+//! any subscriber can record them, such as `tracing-flame` for flame
+//! graphs:
+//!
+//! * `train` at the `INFO` level, around all epochs of a trainer
+//! * `batch` at the `DEBUG` level, with the `epoch` and `batch` numbers
+//! * `forward`, `backward` and `update` at the `TRACE` level, around the
+//!   forward and backward pass of every sample and the update of the
+//!   weights after a batch
+//!
+//! Without the feature the spans compile to nothing.
+
+/// Enters a span for the rest of the enclosing block, given a level
+/// such as `TRACE` followed by the arguments of `tracing::span!`.
+macro_rules! span {
+    ($level:ident, $($args:tt)*) => {
+        #[cfg(feature = "profiling")]
+        let _span = ::tracing::span!(::tracing::Level::$level, $($args)*).entered();
+    };
+}
+
+#[cfg(all(test, feature = "profiling"))]
+mod tests {
+    use sgd::SGDTrainer;
+    use testing::Problem;
+    use traits::SupervisedTrainer;
+
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{subscriber, Event, Metadata, Subscriber};
+
+    use std::sync::{Arc, Mutex};
+
+    /// Records the names of the spans in the order they are entered.
+    #[derive(Default)]
+    struct Recorder {
+        names: Mutex<Vec<&'static str>>,
+        entered: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes) -> Id {
+            let mut names = self.names.lock().unwrap();
+            names.push(span.metadata().name());
+            Id::from_u64(names.len() as u64)
+        }
+
+        fn record(&self, _: &Id, _: &Record) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event) {}
+
+        fn enter(&self, span: &Id) {
+            let name = self.names.lock().unwrap()[span.into_u64() as usize - 1];
+            self.entered.lock().unwrap().push(name);
+        }
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn training_phases() {
+        let recorder = Recorder::default();
+        let entered = recorder.entered.clone();
+        let data = Problem::Xor.dataset();
+        let mut layers = Problem::Xor.network();
+        let mut trainer = SGDTrainer::new(2, 0.1);
+        trainer.batch_size = Some(2);
+        subscriber::with_default(recorder, || trainer.train(&mut layers, &data.inputs, &data.targets));
+
+        // Two epochs of two batches of two samples
+        let entered = entered.lock().unwrap();
+        let count = |name| entered.iter().filter(|n| **n == name).count();
+        assert_eq!(entered[0], "train");
+        assert_eq!(entered[1], "batch");
+        assert_eq!((count("train"), count("batch")), (1, 4));
+        assert_eq!((count("forward"), count("backward"), count("update")), (8, 8, 4));
+    }
+}
@@ -0,0 +1,394 @@
+//! Projections of high dimensional samples to a few dimensions, for
+//! plotting what the hidden layers of a network have learned. `Pca`
+//! finds the linear projection which keeps as much of the variance as
+//! possible, and `Tsne` places similar samples close together without
+//! caring about the distances between dissimilar ones, which shows
+//! clusters that no linear projection can. Both work on samples laid
+//! out one after another, such as those from `hidden_activations`.
+use random::RngProvider;
+use sgd::forward;
+use traits::WeightedLayer;
+use utils::normal_vector;
+
+use std::collections::LinkedList;
+
+/// The outputs of layer `layer` for every sample of the inputs, one
+/// sample after another.
+pub fn hidden_activations(layers: &LinkedList<Box<dyn WeightedLayer>>, inputs: &[f32], layer: usize) -> Vec<f32> {
+    assert!(layer < layers.len(), "the network has no layer {}", layer);
+    let input_count = layers.front().map(|l| l.input_count()).unwrap_or(0);
+    let mut result = Vec::new();
+    for x in inputs.chunks(input_count) {
+        if let Some((_, output)) = forward(layers, x).iter().nth(layer) {
+            result.extend_from_slice(output);
+        }
+    }
+    result
+}
+
+/// Principal component analysis, the directions along which samples
+/// vary the most.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pca {
+    /// The mean of the samples, which is subtracted before projecting
+    pub mean: Vec<f32>,
+    /// The principal directions of unit length, one after another, in
+    /// order of decreasing variance
+    pub components: Vec<f32>,
+    /// The variance of the samples along every component
+    pub variances: Vec<f32>,
+    /// The sum of the variances along all directions, including those
+    /// which were not kept
+    pub total_variance: f32,
+}
+
+impl Pca {
+    /// Finds the first `components` principal components of the
+    /// samples, which have `dim` values each. The sign of every
+    /// component is chosen so that its largest value is positive.
+    pub fn fit(data: &[f32], dim: usize, components: usize) -> Pca {
+        assert!(dim > 0 && data.len().is_multiple_of(dim), "the samples must have {} values each", dim);
+        assert!(components <= dim, "there are at most {} components", dim);
+        let samples = data.len() / dim;
+        let mut mean = vec![0.0f64; dim];
+        for x in data.chunks(dim) {
+            for (m, v) in mean.iter_mut().zip(x) {
+                *m += *v as f64;
+            }
+        }
+        for m in mean.iter_mut() {
+            *m /= samples.max(1) as f64;
+        }
+
+        let mut covariance = vec![0.0f64; dim * dim];
+        for x in data.chunks(dim) {
+            let centered: Vec<f64> = x.iter().zip(&mean).map(|(v, m)| *v as f64 - m).collect();
+            for (row, a) in covariance.chunks_mut(dim).zip(&centered) {
+                for (c, b) in row.iter_mut().zip(&centered) {
+                    *c += a * b;
+                }
+            }
+        }
+        for c in covariance.iter_mut() {
+            *c /= samples.saturating_sub(1).max(1) as f64;
+        }
+        let total_variance = (0..dim).map(|i| covariance[i * dim + i]).sum::<f64>() as f32;
+
+        let (values, vectors) = symmetric_eigen(covariance, dim);
+        let mut order: Vec<usize> = (0..dim).collect();
+        order.sort_by(|&a, &b| values[b].total_cmp(&values[a]));
+        let mut result = Pca {
+            mean: mean.iter().map(|m| *m as f32).collect(),
+            components: Vec::with_capacity(components * dim),
+            variances: Vec::with_capacity(components),
+            total_variance,
+        };
+        for &k in order.iter().take(components) {
+            let component: Vec<f64> = (0..dim).map(|i| vectors[i * dim + k]).collect();
+            let largest = component.iter().cloned().fold(0.0f64, |a, v| if v.abs() > a.abs() { v } else { a });
+            let sign = if largest < 0.0 { -1.0 } else { 1.0 };
+            result.components.extend(component.iter().map(|v| (sign * v) as f32));
+            result.variances.push(values[k].max(0.0) as f32);
+        }
+        result
+    }
+
+    /// The number of values of every sample.
+    pub fn dim(&self) -> usize {
+        self.mean.len()
+    }
+
+    pub fn component_count(&self) -> usize {
+        self.variances.len()
+    }
+
+    /// The fraction of the total variance along every component.
+    pub fn explained_variance_ratio(&self) -> Vec<f32> {
+        self.variances
+            .iter()
+            .map(|v| if self.total_variance > 0.0 { v / self.total_variance } else { 0.0 })
+            .collect()
+    }
+
+    /// The coordinates of every sample along the components, one
+    /// sample after another.
+    pub fn transform(&self, data: &[f32]) -> Vec<f32> {
+        let dim = self.dim();
+        assert_eq!(data.len() % dim, 0, "the samples must have {} values each", dim);
+        let mut result = Vec::with_capacity(data.len() / dim * self.component_count());
+        for x in data.chunks(dim) {
+            for c in self.components.chunks(dim) {
+                result.push(x.iter().zip(&self.mean).zip(c).map(|((v, m), c)| (v - m) * c).sum());
+            }
+        }
+        result
+    }
+}
+
+/// The eigenvalues and eigenvectors of a symmetric `n`x`n` matrix by
+/// the cyclic Jacobi method. The eigenvectors are the columns of the
+/// returned row-major matrix.
+fn symmetric_eigen(mut a: Vec<f64>, n: usize) -> (Vec<f64>, Vec<f64>) {
+    let mut v = vec![0.0; n * n];
+    for i in 0..n {
+        v[i * n + i] = 1.0;
+    }
+    let norm: f64 = a.iter().map(|x| x * x).sum();
+    for _ in 0..100 {
+        let off: f64 = (0..n).flat_map(|p| (0..n).filter(move |q| *q != p).map(move |q| (p, q)))
+            .map(|(p, q)| a[p * n + q] * a[p * n + q])
+            .sum();
+        if off <= 1e-24 * norm {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                let apq = a[p * n + q];
+                if apq == 0.0 {
+                    continue;
+                }
+                // Rotate in the plane of p and q so that a[p][q] becomes 0
+                let theta = (a[q * n + q] - a[p * n + p]) / (2.0 * apq);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for k in 0..n {
+                    let (kp, kq) = (a[k * n + p], a[k * n + q]);
+                    a[k * n + p] = c * kp - s * kq;
+                    a[k * n + q] = s * kp + c * kq;
+                }
+                for k in 0..n {
+                    let (pk, qk) = (a[p * n + k], a[q * n + k]);
+                    a[p * n + k] = c * pk - s * qk;
+                    a[q * n + k] = s * pk + c * qk;
+                }
+                for k in 0..n {
+                    let (kp, kq) = (v[k * n + p], v[k * n + q]);
+                    v[k * n + p] = c * kp - s * kq;
+                    v[k * n + q] = s * kp + c * kq;
+                }
+            }
+        }
+    }
+    ((0..n).map(|i| a[i * n + i]).collect(), v)
+}
+
+/// t-distributed stochastic neighbor embedding into two dimensions.
+/// This computes the similarities of all pairs of samples, so it is
+/// meant for the few hundred samples of a toy dataset, not for
+/// thousands.
+pub struct Tsne {
+    /// The effective number of neighbors of every sample, which must
+    /// be less than the number of samples. Typically between 5 and 50.
+    pub perplexity: f32,
+    /// The number of steps of gradient descent
+    pub iterations: usize,
+    pub learning_rate: f32,
+    /// Source of randomness for the starting positions
+    pub rng: RngProvider,
+}
+
+impl Tsne {
+    pub fn new() -> Tsne {
+        Tsne {
+            perplexity: 30.0,
+            iterations: 1000,
+            learning_rate: 200.0,
+            rng: RngProvider::from_entropy(),
+        }
+    }
+
+    /// Embeds the samples, which have `dim` values each, and returns
+    /// their positions in the plane, one sample after another.
+    pub fn fit(&self, data: &[f32], dim: usize) -> Vec<f32> {
+        assert!(dim > 0 && data.len().is_multiple_of(dim), "the samples must have {} values each", dim);
+        let n = data.len() / dim;
+        assert!((n as f32) > self.perplexity, "the perplexity must be less than the number of samples");
+        let p = self.joint_probabilities(data, dim);
+
+        let mut y: Vec<f64> = self.rng.with_rng(|rng| normal_vector(2 * n, rng)).iter().map(|v| 1e-4 * *v as f64).collect();
+        let mut update = vec![0.0f64; 2 * n];
+        let mut gains = vec![1.0f64; 2 * n];
+        let mut gradient = vec![0.0f64; 2 * n];
+        let mut q = vec![0.0f64; n * n];
+        // Exaggerating the similarities at first lets clusters form
+        // before they settle relative to each other
+        let exaggerated = (self.iterations / 4).min(250);
+        for iteration in 0..self.iterations {
+            let (exaggeration, momentum) = if iteration < exaggerated { (12.0, 0.5) } else { (1.0, 0.8) };
+            let mut z = 0.0;
+            for i in 0..n {
+                for j in 0..n {
+                    if i != j {
+                        let dx = y[2 * i] - y[2 * j];
+                        let dy = y[2 * i + 1] - y[2 * j + 1];
+                        q[i * n + j] = 1.0 / (1.0 + dx * dx + dy * dy);
+                        z += q[i * n + j];
+                    }
+                }
+            }
+            for i in 0..n {
+                let (mut gx, mut gy) = (0.0, 0.0);
+                for j in 0..n {
+                    if i != j {
+                        let force = (exaggeration * p[i * n + j] - q[i * n + j] / z) * q[i * n + j];
+                        gx += force * (y[2 * i] - y[2 * j]);
+                        gy += force * (y[2 * i + 1] - y[2 * j + 1]);
+                    }
+                }
+                gradient[2 * i] = 4.0 * gx;
+                gradient[2 * i + 1] = 4.0 * gy;
+            }
+            for ((u, gain), (g, v)) in update.iter_mut().zip(&mut gains).zip(gradient.iter().zip(y.iter_mut())) {
+                // Speed up along directions where the gradient keeps its sign
+                *gain = if (*g > 0.0) != (*u > 0.0) { *gain + 0.2 } else { (*gain * 0.8).max(0.01) };
+                *u = momentum * *u - self.learning_rate as f64 * *gain * g;
+                *v += *u;
+            }
+            for c in 0..2 {
+                let mean = y.iter().skip(c).step_by(2).sum::<f64>() / n as f64;
+                for v in y.iter_mut().skip(c).step_by(2) {
+                    *v -= mean;
+                }
+            }
+        }
+        y.iter().map(|v| *v as f32).collect()
+    }
+
+    /// The symmetric similarities of all pairs of samples, which sum to
+    /// one. The similarities of every sample to the others follow a
+    /// Gaussian whose width is chosen to give the perplexity.
+    fn joint_probabilities(&self, data: &[f32], dim: usize) -> Vec<f64> {
+        let samples: Vec<&[f32]> = data.chunks(dim).collect();
+        let n = samples.len();
+        let target = (self.perplexity as f64).ln();
+        let mut p = vec![0.0f64; n * n];
+        for (i, x) in samples.iter().enumerate() {
+            let distances: Vec<f64> = samples.iter()
+                .map(|y| x.iter().zip(y.iter()).map(|(a, b)| ((a - b) * (a - b)) as f64).sum())
+                .collect();
+            // Binary search for the precision whose entropy is ln(perplexity)
+            let (mut beta, mut low, mut high) = (1.0f64, 0.0f64, f64::INFINITY);
+            let row = &mut p[i * n..(i + 1) * n];
+            for _ in 0..50 {
+                let mut sum = 0.0;
+                for (j, (r, d)) in row.iter_mut().zip(&distances).enumerate() {
+                    *r = if j == i { 0.0 } else { (-beta * d).exp() };
+                    sum += *r;
+                }
+                if sum == 0.0 {
+                    // Too narrow for any neighbor to count
+                    high = beta;
+                    beta = (low + high) / 2.0;
+                    continue;
+                }
+                let mut entropy = 0.0;
+                for (r, d) in row.iter_mut().zip(&distances) {
+                    *r /= sum;
+                    entropy += beta * d * *r;
+                }
+                entropy += sum.ln();
+                if (entropy - target).abs() < 1e-5 {
+                    break;
+                }
+                if entropy > target {
+                    low = beta;
+                    beta = if high.is_infinite() { beta * 2.0 } else { (low + high) / 2.0 };
+                } else {
+                    high = beta;
+                    beta = (low + high) / 2.0;
+                }
+            }
+        }
+        let mut joint = vec![0.0f64; n * n];
+        for i in 0..n {
+            for j in 0..n {
+                joint[i * n + j] = ((p[i * n + j] + p[j * n + i]) / (2.0 * n as f64)).max(1e-12);
+            }
+        }
+        joint
+    }
+}
+
+impl Default for Tsne {
+    fn default() -> Tsne {
+        Tsne::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{DenseLayer, HyperbolicLayer};
+    use network;
+    use random::SeededRng;
+
+    #[test]
+    fn principal_components() {
+        // Samples along (1, 2, 2) / 3 with a little noise across it
+        let mut rng = SeededRng::new(1);
+        let t = normal_vector(100, &mut rng);
+        let noise = normal_vector(100, &mut rng);
+        let data: Vec<f32> = t.iter()
+            .zip(&noise)
+            .flat_map(|(t, e)| vec![1.0 + 3.0 * t + 0.1 * e, -2.0 + 6.0 * t, 6.0 * t - 0.1 * e])
+            .collect();
+        let pca = Pca::fit(&data, 3, 2);
+        assert_eq!((pca.dim(), pca.component_count()), (3, 2));
+        for (c, e) in pca.components[..3].iter().zip(&[1.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0]) {
+            assert!((c - e).abs() < 1e-2, "{:?}", pca.components);
+        }
+        let dot: f32 = pca.components[..3].iter().zip(&pca.components[3..]).map(|(a, b)| a * b).sum();
+        assert!(dot.abs() < 1e-5);
+        assert!(pca.explained_variance_ratio()[0] > 0.99);
+        assert!(pca.variances[0] >= pca.variances[1]);
+
+        let projected = pca.transform(&data);
+        assert_eq!(projected.len(), 2 * 100);
+        assert!(pca.transform(&pca.mean).iter().all(|v| v.abs() < 1e-5));
+    }
+
+    #[test]
+    fn eigen_of_diagonal_and_rotated() {
+        let (values, _) = symmetric_eigen(vec![3.0, 0.0, 0.0, 1.0], 2);
+        assert_eq!(values, vec![3.0, 1.0]);
+        let (mut values, vectors) = symmetric_eigen(vec![2.0, 1.0, 1.0, 2.0], 2);
+        values.sort_by(|a, b| a.total_cmp(b));
+        assert!((values[0] - 1.0).abs() < 1e-12 && (values[1] - 3.0).abs() < 1e-12);
+        assert!((vectors[0].abs() - 0.5f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn activations_of_a_hidden_layer() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(0.0, 2, 3)));
+        layers.push_back(Box::new(HyperbolicLayer { size: 3 }));
+        network::set_weights(&mut layers, &[1.0, 0.0, 0.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(hidden_activations(&layers, &[1.0, 2.0, 3.0, 4.0], 0), vec![1.0, 2.0, 3.0, 3.0, 4.0, 7.0]);
+        assert_eq!(hidden_activations(&layers, &[0.0, 0.0], 1), vec![0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn tsne_keeps_clusters_apart() {
+        let mut rng = SeededRng::new(2);
+        let noise = normal_vector(5 * 40, &mut rng);
+        let data: Vec<f32> = noise.chunks(5)
+            .enumerate()
+            .flat_map(|(i, x)| x.iter().map(|v| 0.3 * v + if i % 2 == 0 { 5.0 } else { -5.0 }).collect::<Vec<_>>())
+            .collect();
+        let mut tsne = Tsne::new();
+        tsne.perplexity = 10.0;
+        tsne.iterations = 300;
+        tsne.rng = RngProvider::seeded(3);
+        let y = tsne.fit(&data, 5);
+        assert_eq!(y.len(), 2 * 40);
+
+        // The nearest neighbor of every sample is from its own cluster
+        let points: Vec<&[f32]> = y.chunks(2).collect();
+        for (i, a) in points.iter().enumerate() {
+            let distance = |b: &[f32]| (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2);
+            let nearest = (0..40).filter(|j| *j != i).min_by(|j, k| distance(points[*j]).total_cmp(&distance(points[*k]))).unwrap();
+            assert_eq!(nearest % 2, i % 2, "{:?}", y);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Magnitude based pruning, which compresses a network by zeroing out
+//! its smallest weights.
+use traits::WeightedLayer;
+
+use std::collections::LinkedList;
+
+/// Which weights are compared when selecting weights to prune.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PruneScope {
+    /// Prune the given fraction of the weights of every layer.
+    Layer,
+    /// Prune the given fraction of all weights in the network, which
+    /// may prune some layers more than others.
+    Global,
+}
+
+/// Zeroes out the `fraction` of weights with the smallest magnitudes
+/// and masks them so that they stay zero during further training.
+/// Weights which have already been pruned count towards the fraction.
+pub fn prune(layers: &mut LinkedList<Box<dyn WeightedLayer>>, fraction: f32, scope: PruneScope) {
+    match scope {
+        PruneScope::Layer => {
+            for l in layers.iter_mut() {
+                let magnitudes: Vec<f32> = match l.weights() {
+                    Some(w) => w.iter().map(|w| w.abs()).collect(),
+                    None => continue,
+                };
+                let keep = smallest_mask(&magnitudes, fraction);
+                apply_mask(l.as_mut(), keep);
+            }
+        }
+        PruneScope::Global => {
+            let mut magnitudes = Vec::new();
+            for l in layers.iter() {
+                if let Some(w) = l.weights() {
+                    magnitudes.extend(w.iter().map(|w| w.abs()));
+                }
+            }
+            let mut keep = smallest_mask(&magnitudes, fraction).into_iter();
+            for l in layers.iter_mut() {
+                let count = l.weights().map_or(0, |w| w.len());
+                if count > 0 {
+                    let layer_keep = keep.by_ref().take(count).collect();
+                    apply_mask(l.as_mut(), layer_keep);
+                }
+            }
+        }
+    }
+}
+
+/// The fraction of weights in the network which are zero.
+pub fn sparsity(layers: &LinkedList<Box<dyn WeightedLayer>>) -> f32 {
+    let mut zeros = 0;
+    let mut total = 0;
+    for l in layers.iter() {
+        if let Some(w) = l.weights() {
+            zeros += w.iter().filter(|w| **w == 0.0).count();
+            total += w.len();
+        }
+    }
+    if total == 0 {
+        0.0
+    } else {
+        zeros as f32 / total as f32
+    }
+}
+
+/// Returns a mask which is false for the `fraction` smallest values.
+/// NaN values count as the largest, so they are pruned last.
+fn smallest_mask(magnitudes: &[f32], fraction: f32) -> Vec<bool> {
+    let prune_count = (fraction.clamp(0.0, 1.0) * magnitudes.len() as f32).round() as usize;
+    let mut order: Vec<usize> = (0..magnitudes.len()).collect();
+    order.sort_by(|a, b| magnitudes[*a].total_cmp(&magnitudes[*b]));
+
+    let mut keep = vec![true; magnitudes.len()];
+    for i in order.into_iter().take(prune_count) {
+        keep[i] = false;
+    }
+    keep
+}
+
+/// Combines the mask with any existing mask of the layer, and zeroes
+/// the masked out weights.
+fn apply_mask(layer: &mut dyn WeightedLayer, mut keep: Vec<bool>) {
+    if let Some(existing) = layer.weight_mask() {
+        for (k, e) in keep.iter_mut().zip(existing) {
+            *k = *k && *e;
+        }
+    }
+    if let Some(mut weights) = layer.weights_mut() {
+        for (w, k) in weights.iter_mut().zip(keep.iter()) {
+            if !k {
+                *w = 0.0;
+            }
+        }
+    }
+    layer.set_weight_mask(Some(keep));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{DenseLayer, SigmoidLayer, WeightLayout};
+
+    fn network() -> LinkedList<Box<dyn WeightedLayer>> {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer {
+            weights: vec![0.1, -0.2, 0.3, -0.4],
+            bias: vec![0.0, 0.0],
+            shape: (2, 2),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
+        }));
+        layers.push_back(Box::new(SigmoidLayer { size: 2 }));
+        layers.push_back(Box::new(DenseLayer {
+            weights: vec![1.0, 2.0],
+            bias: vec![0.0],
+            shape: (2, 1),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
+        }));
+        layers
+    }
+
+    #[test]
+    fn nan_is_pruned_last() {
+        assert_eq!(smallest_mask(&[f32::NAN, 0.5, 0.25], 2.0 / 3.0), vec![true, false, false]);
+    }
+
+    #[test]
+    fn prune_per_layer() {
+        let mut layers = network();
+        prune(&mut layers, 0.5, PruneScope::Layer);
+
+        let weights: Vec<Vec<f32>> = layers.iter().filter_map(|l| l.weights().map(|w| w.to_vec())).collect();
+        assert_eq!(weights, vec![vec![0.0, 0.0, 0.3, -0.4], vec![0.0, 2.0]]);
+        assert_eq!(sparsity(&layers), 0.5);
+    }
+
+    #[test]
+    fn prune_globally() {
+        let mut layers = network();
+        prune(&mut layers, 0.5, PruneScope::Global);
+
+        let weights: Vec<Vec<f32>> = layers.iter().filter_map(|l| l.weights().map(|w| w.to_vec())).collect();
+        assert_eq!(weights, vec![vec![0.0, 0.0, 0.0, -0.4], vec![1.0, 2.0]]);
+    }
+
+    #[test]
+    fn pruned_weights_stay_zero() {
+        let mut layers = network();
+        prune(&mut layers, 0.5, PruneScope::Layer);
+
+        let l = layers.front_mut().unwrap();
+        l.update(&[1.0, 1.0, 1.0, 1.0], &[0.0, 0.0]);
+        assert_eq!(l.weights().unwrap(), &[0.0, 0.0, 1.3, 0.6]);
+    }
+}
@@ -0,0 +1,140 @@
+//! Python bindings, built with the `python` feature. The module is
+//! named `scarecrow` and holds a single class, `Network`:
+//!
+//! ```python
+//! import scarecrow
+//!
+//! net = scarecrow.Network("dense inputs=2 neurons=6; tanh size=6; dense inputs=6 neurons=1")
+//! losses = net.train([[0, 0], [0, 1], [1, 0], [1, 1]], [[0], [1], [1], [0]], epochs=1000, rate=0.1)
+//! print(net.predict([[0, 1]]))
+//! ```
+//!
+//! Build the extension with [maturin](https://www.maturin.rs), such as
+//! `maturin develop --features python,pyo3/extension-module`, which
+//! builds the crate as a `cdylib` although the manifest does not ask
+//! for one.
+use data::Dataset;
+use network;
+use serialize::{self, LayerRegistry};
+use sgd::{ConfigError, SGDTrainer};
+use traits::{SupervisedTrainer, WeightedLayer};
+
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+
+use std::collections::LinkedList;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+/// A network of layers, built from an architecture spec such as
+/// "dense inputs=2 neurons=6; tanh size=6".
+#[pyclass(module = "scarecrow")]
+pub struct Network {
+    layers: LinkedList<Box<dyn WeightedLayer>>,
+}
+
+/// Joins rows of equal length into one buffer, one row after another.
+fn flatten(rows: Vec<Vec<f32>>, width: usize, what: &str) -> PyResult<Vec<f32>> {
+    if let Some(row) = rows.iter().find(|r| r.len() != width) {
+        return Err(PyValueError::new_err(format!("expected {} values per {} but got {}", width, what, row.len())));
+    }
+    Ok(rows.into_iter().flatten().collect())
+}
+
+impl Network {
+    fn input_count(&self) -> usize {
+        self.layers.front().map_or(0, |l| l.input_count())
+    }
+
+    fn output_count(&self) -> usize {
+        self.layers.back().map_or(0, |l| l.output_count())
+    }
+}
+
+#[pymethods]
+impl Network {
+    #[new]
+    fn new(spec: &str) -> PyResult<Network> {
+        let layers = LayerRegistry::new().parse_spec(spec).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(Network { layers })
+    }
+
+    /// Loads a network written by `save`.
+    #[staticmethod]
+    fn load(path: &str) -> PyResult<Network> {
+        let file = File::open(path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let layers = LayerRegistry::new().load(BufReader::new(file)).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        Ok(Network { layers })
+    }
+
+    fn save(&self, path: &str) -> PyResult<()> {
+        let file = File::create(path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        serialize::save(&self.layers, &mut BufWriter::new(file)).map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    /// The architecture spec of the network.
+    #[getter]
+    fn spec(&self) -> PyResult<String> {
+        serialize::to_spec(&self.layers).map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// All weights and biases, layer after layer.
+    #[getter]
+    fn weights(&self) -> Vec<f32> {
+        network::get_weights(&self.layers)
+    }
+
+    #[setter]
+    fn set_weights(&mut self, values: Vec<f32>) -> PyResult<()> {
+        if values.len() != network::parameter_count(&self.layers) {
+            return Err(PyValueError::new_err(format!("the network has {} parameters but got {}",
+                                                     network::parameter_count(&self.layers),
+                                                     values.len())));
+        }
+        network::set_weights(&mut self.layers, &values);
+        Ok(())
+    }
+
+    /// Trains with stochastic gradient descent and returns the loss on
+    /// the training samples after every epoch.
+    #[pyo3(signature = (inputs, targets, epochs, rate, batch_size=None))]
+    fn train(&mut self,
+             py: Python,
+             inputs: Vec<Vec<f32>>,
+             targets: Vec<Vec<f32>>,
+             epochs: usize,
+             rate: f32,
+             batch_size: Option<usize>)
+             -> PyResult<Vec<f32>> {
+        let inputs = flatten(inputs, self.input_count(), "input")?;
+        let targets = flatten(targets, self.output_count(), "target")?;
+        let data = Dataset::new(inputs, targets, self.input_count(), self.output_count());
+        let layers = &mut self.layers;
+        py.allow_threads(|| {
+                let mut trainer = SGDTrainer::new(epochs, rate);
+                trainer.batch_size = batch_size;
+                trainer.validate(layers, &data.inputs, &data.targets)?;
+                Ok(trainer.fit(layers, &data, None).train_loss())
+            })
+            .map_err(|e: ConfigError| PyValueError::new_err(e.to_string()))
+    }
+
+    /// The outputs of the network for every input.
+    fn predict(&self, py: Python, inputs: Vec<Vec<f32>>) -> PyResult<Vec<Vec<f32>>> {
+        let inputs = flatten(inputs, self.input_count(), "input")?;
+        let outputs = py.allow_threads(|| network::predict_batch_parallel(&self.layers, &inputs, 0));
+        Ok(outputs.chunks(self.output_count().max(1)).map(|o| o.to_vec()).collect())
+    }
+
+    fn __repr__(&self) -> String {
+        match serialize::to_spec(&self.layers) {
+            Ok(spec) => format!("Network({:?})", spec.trim_end().replace('\n', "; ")),
+            Err(_) => format!("<Network of {} layers>", self.layers.len()),
+        }
+    }
+}
+
+#[pymodule]
+fn scarecrow(m: &Bound<PyModule>) -> PyResult<()> {
+    m.add_class::<Network>()
+}
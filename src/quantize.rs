@@ -0,0 +1,209 @@
+//! Post-training quantization to 8-bit integers. A trained network is
+//! calibrated by running representative data through it, which gives
+//! the scale of the inputs to every layer. The weights are rounded to
+//! 8-bit values and the values between layers are rounded to 8-bit
+//! values at the calibrated scales during quantized inference.
+//!
+//! Quantization is symmetric: a value `v` is stored as the integer
+//! `round(v / scale)` clamped to [-127, 127].
+use sgd::forward;
+use traits::WeightedLayer;
+
+use std::collections::LinkedList;
+
+/// The largest magnitude of a quantized value.
+const LEVELS: f32 = 127.0;
+
+/// The quantization scales of one layer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuantScale {
+    /// The scale of the inputs to the layer
+    pub input: f32,
+    /// The scale of the weights of the layer, which is zero for layers
+    /// without weights
+    pub weight: f32,
+}
+
+/// Records the range of the inputs to every layer over representative
+/// data. The range is taken at a percentile of the absolute values
+/// rather than at the maximum, so that a few outliers do not spread
+/// the 255 levels so thin that typical values lose their precision.
+/// Values outside the range are clamped, and non-finite values are
+/// left out of it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    /// Percentile, between 0 and 100, of the absolute input values used
+    /// as the range of each layer
+    pub percentile: f32,
+}
+
+impl Calibration {
+    pub fn new() -> Calibration {
+        Calibration { percentile: 99.99 }
+    }
+
+    /// Runs the inputs through the network and returns the scales of
+    /// every layer, none for a network without layers.
+    pub fn calibrate(&self, layers: &LinkedList<Box<dyn WeightedLayer>>, inputs: &[f32]) -> Vec<QuantScale> {
+        let input_count = match layers.front() {
+            Some(l) => l.input_count(),
+            None => return Vec::new(),
+        };
+        let mut magnitudes: Vec<Vec<f32>> = vec![Vec::new(); layers.len()];
+        for x in inputs.chunks(input_count) {
+            for (m, (inputs, _)) in magnitudes.iter_mut().zip(forward(layers, x).iter()) {
+                m.extend(inputs.iter().map(|v| v.abs()).filter(|v| v.is_finite()));
+            }
+        }
+
+        layers.iter()
+            .zip(magnitudes.iter_mut())
+            .map(|(l, m)| {
+                let weight_range = l.weights().map_or(0.0, |w| w.iter().fold(0.0f32, |a, w| a.max(w.abs())));
+                QuantScale {
+                    input: scale_for(percentile(m, self.percentile)),
+                    weight: scale_for(weight_range),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Calibration {
+        Calibration::new()
+    }
+}
+
+/// The scale which maps the range to the largest quantized value.
+fn scale_for(range: f32) -> f32 {
+    if range > 0.0 {
+        range / LEVELS
+    } else {
+        0.0
+    }
+}
+
+/// The value at the given percentile, by the nearest rank.
+fn percentile(values: &mut [f32], percentile: f32) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f32::total_cmp);
+    let rank = (percentile.clamp(0.0, 100.0) / 100.0 * values.len() as f32).ceil() as usize;
+    values[rank.clamp(1, values.len()) - 1]
+}
+
+/// Quantizes values to 8-bit integers at the given scale. A scale of
+/// zero gives zeros.
+pub fn quantize(values: &[f32], scale: f32) -> Vec<i8> {
+    values.iter()
+        .map(|v| if scale > 0.0 {
+            (v / scale).round().clamp(-LEVELS, LEVELS) as i8
+        } else {
+            0
+        })
+        .collect()
+}
+
+/// The values represented by quantized integers at the given scale.
+pub fn dequantize(values: &[i8], scale: f32) -> Vec<f32> {
+    values.iter().map(|v| *v as f32 * scale).collect()
+}
+
+/// Rounds the weights of every layer to their 8-bit values. The biases
+/// are kept, as they are few and are added to the wide accumulated
+/// sums.
+pub fn quantize_weights(layers: &mut LinkedList<Box<dyn WeightedLayer>>, scales: &[QuantScale]) {
+    assert_eq!(layers.len(), scales.len(), "one scale is needed per layer");
+    for (l, s) in layers.iter_mut().zip(scales) {
+        if let Some(mut weights) = l.weights_mut() {
+            let rounded = dequantize(&quantize(&weights, s.weight), s.weight);
+            weights.copy_from_slice(&rounded);
+        }
+    }
+}
+
+/// Output of a network whose weights have been quantized with
+/// `quantize_weights`, where the inputs to every layer are also
+/// rounded to 8-bit values at their calibrated scales.
+pub fn quantized_output(layers: &LinkedList<Box<dyn WeightedLayer>>, scales: &[QuantScale], x: &[f32]) -> Vec<f32> {
+    assert_eq!(layers.len(), scales.len(), "one scale is needed per layer");
+    let mut o = x.to_vec();
+    for (l, s) in layers.iter().zip(scales) {
+        o = l.output(&dequantize(&quantize(&o, s.input), s.input));
+    }
+    o
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{DenseLayer, HyperbolicLayer};
+    use network;
+    use random::SeededRng;
+    use utils::normal_vector;
+
+    #[test]
+    fn round_trip() {
+        let q = quantize(&[0.0, 0.5, -1.0, 3.0], 1.0 / 127.0);
+        assert_eq!(q, vec![0, 64, -127, 127]);
+        assert_eq!(dequantize(&q, 1.0 / 127.0)[2], -1.0);
+        assert_eq!(quantize(&[1.0], 0.0), vec![0]);
+    }
+
+    #[test]
+    fn outliers_are_clipped() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(0.5, 1, 1)));
+        let mut inputs: Vec<f32> = (0..999).map(|i| (i % 10) as f32 / 10.0).collect();
+        inputs.push(100.0);
+
+        let scales = Calibration { percentile: 99.0 }.calibrate(&layers, &inputs);
+        assert_eq!(scales[0].input, 0.9 / 127.0);
+        assert_eq!(scales[0].weight, 0.5 / 127.0);
+
+        let scales = Calibration { percentile: 100.0 }.calibrate(&layers, &inputs);
+        assert_eq!(scales[0].input, 100.0 / 127.0);
+    }
+
+    #[test]
+    fn non_finite_inputs_are_left_out() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(0.5, 1, 1)));
+
+        let scales = Calibration { percentile: 100.0 }.calibrate(&layers, &[1.0, f32::NAN, f32::INFINITY, -2.0]);
+        assert_eq!(scales[0].input, 2.0 / 127.0);
+    }
+
+    #[test]
+    fn empty_network_has_no_scales() {
+        let layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        assert!(Calibration::new().calibrate(&layers, &[1.0, 2.0]).is_empty());
+    }
+
+    #[test]
+    fn quantized_network_is_close() {
+        let mut rng = SeededRng::new(3);
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::random_with(4, 8, &mut rng)));
+        layers.push_back(Box::new(HyperbolicLayer { size: 8 }));
+        layers.push_back(Box::new(DenseLayer::random_with(8, 2, &mut rng)));
+        let inputs = normal_vector(4 * 200, &mut rng);
+        let expected: Vec<Vec<f32>> = inputs.chunks(4).map(|x| network::output(&layers, x)).collect();
+
+        let scales = Calibration::new().calibrate(&layers, &inputs);
+        assert_eq!(scales.len(), 3);
+        assert_eq!(scales[1].weight, 0.0);
+        quantize_weights(&mut layers, &scales);
+
+        let largest = expected.iter().flatten().fold(0.0f32, |m, v| m.max(v.abs()));
+        let mut worst = 0.0f32;
+        for (x, e) in inputs.chunks(4).zip(expected) {
+            for (y, e) in quantized_output(&layers, &scales, x).iter().zip(e) {
+                worst = worst.max((y - e).abs());
+            }
+        }
+        assert!(worst < 0.05 * largest, "quantization error {} of {}", worst, largest);
+    }
+}
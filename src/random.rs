@@ -0,0 +1,152 @@
+//! Sources of randomness. All random numbers used by the crate, for
+//! initialization, shuffling and noise, come from these generators so
+//! that runs can be made reproducible by seeding them.
+use rand;
+use rand::Rng;
+
+use std::sync::{Arc, Mutex};
+
+/// A small and fast xorshift random number generator. Its state can
+/// be read and restored, which makes it possible to resume a run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeededRng {
+    state: [u32; 4],
+}
+
+impl SeededRng {
+    /// Creates a generator from a seed. The same seed always gives the
+    /// same sequence of numbers.
+    pub fn new(seed: u64) -> SeededRng {
+        // Spread the seed over the state with splitmix64, which also
+        // guarantees a nonzero state.
+        let mut x = seed;
+        let mut next = || {
+            x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+        let a = next();
+        let b = next();
+        SeededRng::from_state([a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32 | 1])
+    }
+
+    /// Creates a generator seeded from the operating system's source
+    /// of randomness.
+    pub fn from_entropy() -> SeededRng {
+        SeededRng::new(rand::thread_rng().next_u64())
+    }
+
+    /// The current internal state of the generator.
+    pub fn state(&self) -> [u32; 4] {
+        self.state
+    }
+
+    /// Creates a generator from a state returned by `state`. The state
+    /// must not be all zeros.
+    pub fn from_state(state: [u32; 4]) -> SeededRng {
+        assert!(state != [0; 4], "the state of a SeededRng must not be all zeros");
+        SeededRng { state }
+    }
+}
+
+impl Rng for SeededRng {
+    fn next_u32(&mut self) -> u32 {
+        let s = &mut self.state;
+        let t = s[0] ^ (s[0] << 11);
+        s[0] = s[1];
+        s[1] = s[2];
+        s[2] = s[3];
+        s[3] = s[3] ^ (s[3] >> 19) ^ (t ^ (t >> 8));
+        s[3]
+    }
+}
+
+/// A shared handle to a random number generator, held by trainers and
+/// other components which need randomness. Clones share the same
+/// generator, so a single seed controls a whole run.
+#[derive(Debug, Clone)]
+pub struct RngProvider {
+    rng: Arc<Mutex<SeededRng>>,
+}
+
+impl RngProvider {
+    /// A provider with a fixed seed, for reproducible runs.
+    pub fn seeded(seed: u64) -> RngProvider {
+        RngProvider::from_rng(SeededRng::new(seed))
+    }
+
+    /// A provider seeded from the operating system's source of
+    /// randomness.
+    pub fn from_entropy() -> RngProvider {
+        RngProvider::from_rng(SeededRng::from_entropy())
+    }
+
+    pub fn from_rng(rng: SeededRng) -> RngProvider {
+        RngProvider { rng: Arc::new(Mutex::new(rng)) }
+    }
+
+    /// Calls `f` with exclusive access to the generator.
+    pub fn with_rng<T, F: FnOnce(&mut SeededRng) -> T>(&self, f: F) -> T {
+        let mut rng = self.rng.lock().unwrap();
+        f(&mut rng)
+    }
+
+    /// Creates an independent generator seeded from this one. Useful
+    /// for holding on to a generator for a longer computation without
+    /// locking the provider.
+    pub fn fork(&self) -> SeededRng {
+        self.with_rng(|rng| SeededRng::new(rng.next_u64()))
+    }
+
+    /// The current state of the generator.
+    pub fn state(&self) -> [u32; 4] {
+        self.with_rng(|rng| rng.state())
+    }
+
+    /// Restores the generator to a state returned by `state`.
+    pub fn set_state(&self, state: [u32; 4]) {
+        self.with_rng(|rng| *rng = SeededRng::from_state(state));
+    }
+}
+
+impl Default for RngProvider {
+    fn default() -> RngProvider {
+        RngProvider::from_entropy()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_numbers() {
+        let mut a = SeededRng::new(42);
+        let mut b = SeededRng::new(42);
+        let mut c = SeededRng::new(43);
+
+        let xs: Vec<u32> = (0..10).map(|_| a.next_u32()).collect();
+        let ys: Vec<u32> = (0..10).map(|_| b.next_u32()).collect();
+        let zs: Vec<u32> = (0..10).map(|_| c.next_u32()).collect();
+        assert_eq!(xs, ys);
+        assert!(xs != zs);
+    }
+
+    #[test]
+    fn provider_state_roundtrip() {
+        let p = RngProvider::seeded(1);
+        let state = p.state();
+        let first: f32 = p.with_rng(|r| r.gen());
+
+        p.set_state(state);
+        assert_eq!(p.with_rng(|r| r.gen::<f32>()), first);
+
+        // Clones share the generator
+        let q = p.clone();
+        p.set_state(state);
+        q.with_rng(|r| r.gen::<f32>());
+        assert!(p.with_rng(|r| r.gen::<f32>()) != first);
+    }
+}
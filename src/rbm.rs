@@ -0,0 +1,232 @@
+//! Binary restricted Boltzmann machines, trained with contrastive
+//! divergence. Stacks of RBMs can be trained greedily, layer by layer,
+//! and converted into a network of dense sigmoid layers, which is the
+//! classic way of pretraining deep networks.
+use layers::{DenseLayer, SigmoidLayer, WeightLayout};
+use random::RngProvider;
+use traits::WeightedLayer;
+use utils::{dot, normal_vector, sigmoid};
+
+use rand::Rng;
+use std::collections::LinkedList;
+
+/// A binary RBM with a layer of visible and a layer of hidden units.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RBM {
+    /// The weights, one row of `visible` weights per hidden unit
+    pub weights: Vec<f32>,
+    pub visible_bias: Vec<f32>,
+    pub hidden_bias: Vec<f32>,
+    pub visible: usize,
+    pub hidden: usize,
+}
+
+/// Samples binary units which are on with the given probabilities.
+fn sample<R: Rng>(probabilities: &[f32], rng: &mut R) -> Vec<f32> {
+    probabilities.iter().map(|p| if rng.gen::<f32>() < *p { 1.0 } else { 0.0 }).collect()
+}
+
+impl RBM {
+    /// Creates an RBM with small gaussian random weights and zero
+    /// biases.
+    pub fn random_with<R: Rng>(visible: usize, hidden: usize, rng: &mut R) -> RBM {
+        let weights = normal_vector(visible * hidden, rng).iter().map(|w| 0.01 * w).collect();
+        RBM {
+            weights,
+            visible_bias: vec![0.0; visible],
+            hidden_bias: vec![0.0; hidden],
+            visible,
+            hidden,
+        }
+    }
+
+    /// The probabilities of the hidden units being on, given the
+    /// visible units.
+    pub fn hidden_probabilities(&self, visible: &[f32]) -> Vec<f32> {
+        assert_eq!(self.visible, visible.len());
+        self.weights
+            .chunks(self.visible)
+            .zip(&self.hidden_bias)
+            .map(|(w, c)| sigmoid(dot(w, visible) + c))
+            .collect()
+    }
+
+    /// The probabilities of the visible units being on, given the
+    /// hidden units.
+    pub fn visible_probabilities(&self, hidden: &[f32]) -> Vec<f32> {
+        assert_eq!(self.hidden, hidden.len());
+        let mut sums = self.visible_bias.clone();
+        for (w, h) in self.weights.chunks(self.visible).zip(hidden) {
+            for (s, w) in sums.iter_mut().zip(w) {
+                *s += w * h;
+            }
+        }
+        sums.into_iter().map(sigmoid).collect()
+    }
+
+    /// Samples the hidden units given the visible units.
+    pub fn sample_hidden<R: Rng>(&self, visible: &[f32], rng: &mut R) -> Vec<f32> {
+        sample(&self.hidden_probabilities(visible), rng)
+    }
+
+    /// Samples the visible units given the hidden units.
+    pub fn sample_visible<R: Rng>(&self, hidden: &[f32], rng: &mut R) -> Vec<f32> {
+        sample(&self.visible_probabilities(hidden), rng)
+    }
+
+    /// The visible probabilities after going up to the hidden units
+    /// and back, without sampling.
+    pub fn reconstruct(&self, visible: &[f32]) -> Vec<f32> {
+        self.visible_probabilities(&self.hidden_probabilities(visible))
+    }
+
+    /// The mean squared difference between the samples and their
+    /// reconstructions, per visible unit.
+    pub fn reconstruction_error(&self, data: &[f32]) -> f32 {
+        let mut total = 0.0;
+        for v in data.chunks(self.visible) {
+            for (a, b) in v.iter().zip(self.reconstruct(v)) {
+                total += (a - b) * (a - b);
+            }
+        }
+        if data.is_empty() {
+            0.0
+        } else {
+            total / data.len() as f32
+        }
+    }
+
+    /// A dense layer computing the weighted sums of the hidden units.
+    /// Followed by a `SigmoidLayer` it gives the hidden probabilities.
+    pub fn to_dense(&self) -> DenseLayer {
+        DenseLayer {
+            weights: self.weights.clone(),
+            bias: self.hidden_bias.clone(),
+            shape: (self.visible, self.hidden),
+            mask: None,
+            use_bias: true,
+            layout: WeightLayout::NeuronMajor,
+        }
+    }
+}
+
+/// Contrastive divergence training of RBMs. Each sample takes `steps`
+/// steps of Gibbs sampling, and the weights move towards the
+/// correlations of the data and away from those of the samples.
+pub struct ContrastiveDivergence {
+    /// The number of passes over the data
+    pub epochs: usize,
+    /// The learning rate
+    pub rate: f32,
+    /// The number of Gibbs sampling steps, the k of CD-k
+    pub steps: usize,
+    /// Source of randomness for the sampling
+    pub rng: RngProvider,
+}
+
+impl ContrastiveDivergence {
+    pub fn new(epochs: usize, rate: f32) -> ContrastiveDivergence {
+        ContrastiveDivergence {
+            epochs,
+            rate,
+            steps: 1,
+            rng: RngProvider::from_entropy(),
+        }
+    }
+
+    /// Trains the RBM on binary data laid out sample by sample, and
+    /// returns the reconstruction error after each epoch.
+    pub fn train(&self, rbm: &mut RBM, data: &[f32]) -> Vec<f32> {
+        let mut rng = self.rng.fork();
+        let mut errors = Vec::with_capacity(self.epochs);
+        for _ in 0..self.epochs {
+            for v0 in data.chunks(rbm.visible) {
+                let h0 = rbm.hidden_probabilities(v0);
+                let mut vk = v0.to_vec();
+                let mut hk = h0.clone();
+                for _ in 0..self.steps {
+                    let h = sample(&hk, &mut rng);
+                    vk = rbm.visible_probabilities(&h);
+                    hk = rbm.hidden_probabilities(&vk);
+                }
+
+                for ((w, (p0, pk)), c) in rbm.weights
+                    .chunks_mut(rbm.visible)
+                    .zip(h0.iter().zip(&hk))
+                    .zip(rbm.hidden_bias.iter_mut()) {
+                    for ((w, a), b) in w.iter_mut().zip(v0).zip(&vk) {
+                        *w += self.rate * (p0 * a - pk * b);
+                    }
+                    *c += self.rate * (p0 - pk);
+                }
+                for ((b, a), k) in rbm.visible_bias.iter_mut().zip(v0).zip(&vk) {
+                    *b += self.rate * (a - k);
+                }
+            }
+            errors.push(rbm.reconstruction_error(data));
+        }
+        errors
+    }
+}
+
+/// Greedily trains a stack of RBMs with the given numbers of hidden
+/// units. The first RBM is trained on the data, and every following
+/// RBM on the hidden probabilities of the one below it.
+pub fn pretrain(trainer: &ContrastiveDivergence, data: &[f32], visible: usize, hidden: &[usize]) -> Vec<RBM> {
+    let mut rbms = Vec::with_capacity(hidden.len());
+    let mut inputs = data.to_vec();
+    let mut size = visible;
+    for h in hidden {
+        let mut rbm = trainer.rng.with_rng(|rng| RBM::random_with(size, *h, rng));
+        trainer.train(&mut rbm, &inputs);
+        inputs = inputs.chunks(size).flat_map(|v| rbm.hidden_probabilities(v)).collect();
+        size = *h;
+        rbms.push(rbm);
+    }
+    rbms
+}
+
+/// Converts a stack of RBMs into a network of alternating dense and
+/// sigmoid layers, which can then be fine-tuned with backpropagation.
+pub fn to_network(rbms: &[RBM]) -> LinkedList<Box<dyn WeightedLayer>> {
+    let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+    for rbm in rbms {
+        layers.push_back(Box::new(rbm.to_dense()));
+        layers.push_back(Box::new(SigmoidLayer { size: rbm.hidden }));
+    }
+    layers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network;
+    use testing::assert_close;
+
+    #[test]
+    fn learns_to_reconstruct() {
+        let data = [1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0];
+        let mut trainer = ContrastiveDivergence::new(200, 0.1);
+        trainer.rng = RngProvider::seeded(1);
+        let mut rbm = trainer.rng.with_rng(|rng| RBM::random_with(4, 2, rng));
+        let before = rbm.reconstruction_error(&data);
+
+        let errors = trainer.train(&mut rbm, &data);
+        assert_eq!(errors.len(), 200);
+        assert!(errors[199] < before / 2.0);
+    }
+
+    #[test]
+    fn stack_converts_to_network() {
+        let data = [1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0];
+        let mut trainer = ContrastiveDivergence::new(10, 0.1);
+        trainer.rng = RngProvider::seeded(2);
+        let rbms = pretrain(&trainer, &data, 4, &[3, 2]);
+        assert_eq!((rbms[1].visible, rbms[1].hidden), (3, 2));
+
+        let layers = to_network(&rbms);
+        assert_eq!(layers.len(), 4);
+        let expected = rbms[1].hidden_probabilities(&rbms[0].hidden_probabilities(&data[..4]));
+        assert_close(network::output(&layers, &data[..4]), expected, 1e-6);
+    }
+}
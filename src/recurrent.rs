@@ -0,0 +1,235 @@
+//! Recurrent networks for sequence-to-one tasks, where a sequence of
+//! input vectors is summarized into a single output. A recurrent
+//! layer reads the sequence one step at a time, and its final hidden
+//! state is fed through ordinary layers. Training backpropagates
+//! through time.
+use data::SequenceDataset;
+use loss::Reduction;
+use sgd::{forward, SGDTrainer};
+use traits::WeightedLayer;
+use utils::{dot, normal_vector};
+
+use rand::Rng;
+use std::collections::LinkedList;
+
+/// An Elman recurrent layer, `h_t = tanh(W x_t + U h_(t-1) + b)`, with
+/// a hidden state starting at zero.
+pub struct RecurrentLayer {
+    /// Weights of the inputs, one row per hidden unit
+    pub input_weights: Vec<f32>,
+    /// Weights of the previous hidden state, one row per hidden unit
+    pub recurrent_weights: Vec<f32>,
+    pub bias: Vec<f32>,
+    pub input_count: usize,
+    pub hidden: usize,
+}
+
+impl RecurrentLayer {
+    /// Creates a layer with gaussian random weights scaled down by the
+    /// number of inputs to each unit, and zero biases.
+    pub fn random_with<R: Rng>(input_count: usize, hidden: usize, rng: &mut R) -> RecurrentLayer {
+        let scale = 1.0 / ((input_count + hidden) as f32).sqrt();
+        RecurrentLayer {
+            input_weights: normal_vector(hidden * input_count, rng).iter().map(|w| w * scale).collect(),
+            recurrent_weights: normal_vector(hidden * hidden, rng).iter().map(|w| w * scale).collect(),
+            bias: vec![0.0; hidden],
+            input_count,
+            hidden,
+        }
+    }
+
+    /// Advances the hidden state by one step.
+    pub fn step(&self, x: &[f32], h: &[f32]) -> Vec<f32> {
+        assert_eq!(self.input_count, x.len());
+        self.input_weights
+            .chunks(self.input_count)
+            .zip(self.recurrent_weights.chunks(self.hidden))
+            .zip(&self.bias)
+            .map(|((wx, wh), b)| (dot(wx, x) + dot(wh, h) + b).tanh())
+            .collect()
+    }
+
+    /// The hidden states of the layer over the steps of a sequence,
+    /// starting with the initial zero state. Steps which are masked
+    /// out are skipped, carrying the hidden state over unchanged, so
+    /// padding does not affect the result.
+    pub fn states(&self, sequence: &[f32], mask: Option<&[bool]>) -> Vec<Vec<f32>> {
+        let mut states = vec![vec![0.0; self.hidden]];
+        for (t, x) in sequence.chunks(self.input_count).enumerate() {
+            if mask.is_none_or(|m| m[t]) {
+                let h = self.step(x, states.last().unwrap());
+                states.push(h);
+            }
+        }
+        states
+    }
+
+    /// The final hidden state after reading the sequence.
+    pub fn last_state(&self, sequence: &[f32], mask: Option<&[bool]>) -> Vec<f32> {
+        self.states(sequence, mask).pop().unwrap()
+    }
+}
+
+/// The output of the network for a single sequence.
+pub fn predict(rnn: &RecurrentLayer,
+               head: &LinkedList<Box<dyn WeightedLayer>>,
+               sequence: &[f32],
+               mask: Option<&[bool]>)
+               -> Vec<f32> {
+    let h = rnn.last_state(sequence, mask);
+    forward(head, &h).into_last()
+}
+
+/// Trains a recurrent layer and the layers on top of its final hidden
+/// state on a sequence dataset. The gradient of the loss flows back
+/// through the head and then through time over the real steps of each
+/// sequence. Uses the rate, epochs, loss and reduction of the trainer,
+/// with one update per epoch. Returns the average loss per sequence
+/// for each epoch.
+pub fn train_sequences(trainer: &SGDTrainer,
+                       rnn: &mut RecurrentLayer,
+                       head: &mut LinkedList<Box<dyn WeightedLayer>>,
+                       data: &SequenceDataset)
+                       -> Vec<f32> {
+    assert_eq!(rnn.input_count, data.input_count);
+    let mut history = Vec::with_capacity(trainer.epochs);
+
+    for _ in 0..trainer.epochs {
+        let mut updates = trainer.new_updates(head);
+        let mut input_grads = vec![0.0; rnn.input_weights.len()];
+        let mut recurrent_grads = vec![0.0; rnn.recurrent_weights.len()];
+        let mut bias_grads = vec![0.0; rnn.hidden];
+        let mut total = 0.0;
+
+        for i in 0..data.len() {
+            let sequence = data.sequence(i);
+            let mask = data.mask(i);
+            let states = rnn.states(sequence, Some(mask));
+            let outputs = forward(head, states.last().unwrap());
+            let y = outputs.last();
+            total += trainer.loss.loss_vec(y, data.target(i));
+            let delta = trainer.loss.deriv_vec(y, data.target(i));
+            let mut dh = trainer.backward(head, &outputs, delta, &mut updates);
+
+            // Backpropagation through time over the real steps
+            let steps: Vec<&[f32]> = sequence.chunks(rnn.input_count)
+                .zip(mask)
+                .filter(|(_, m)| **m)
+                .map(|(x, _)| x)
+                .collect();
+            for (t, x) in steps.iter().enumerate().rev() {
+                let h = &states[t + 1];
+                let previous = &states[t];
+                let dz: Vec<f32> = dh.iter().zip(h).map(|(d, h)| d * (1.0 - h * h)).collect();
+                for (n, d) in dz.iter().enumerate() {
+                    for (g, x) in input_grads[n * rnn.input_count..(n + 1) * rnn.input_count].iter_mut().zip(x.iter()) {
+                        *g += d * x;
+                    }
+                    for (g, p) in recurrent_grads[n * rnn.hidden..(n + 1) * rnn.hidden].iter_mut().zip(previous) {
+                        *g += d * p;
+                    }
+                    bias_grads[n] += d;
+                }
+                dh = vec![0.0; rnn.hidden];
+                for (row, d) in rnn.recurrent_weights.chunks(rnn.hidden).zip(&dz) {
+                    for (a, w) in dh.iter_mut().zip(row) {
+                        *a += d * w;
+                    }
+                }
+            }
+        }
+
+        let samples = data.len();
+        trainer.apply_updates(head, &mut updates, samples);
+        let mut scale = -trainer.rate;
+        if trainer.reduction == Reduction::Mean && samples > 0 {
+            scale /= samples as f32;
+        }
+        for (w, g) in rnn.input_weights
+            .iter_mut()
+            .zip(&input_grads)
+            .chain(rnn.recurrent_weights.iter_mut().zip(&recurrent_grads))
+            .chain(rnn.bias.iter_mut().zip(&bias_grads)) {
+            *w += scale * g;
+        }
+        history.push(total / samples.max(1) as f32);
+    }
+    history
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::DenseLayer;
+    use random::SeededRng;
+
+    #[test]
+    fn padding_does_not_change_output() {
+        let rnn = RecurrentLayer::random_with(1, 3, &mut SeededRng::new(1));
+        let head: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+
+        let plain = predict(&rnn, &head, &[1.0, -1.0], None);
+        let padded = predict(&rnn, &head, &[1.0, -1.0, 0.0], Some(&[true, true, false]));
+        assert_eq!(plain, padded);
+        assert_eq!(plain, rnn.step(&[-1.0], &rnn.step(&[1.0], &[0.0; 3])));
+    }
+
+    #[test]
+    fn gradient_matches_finite_differences() {
+        use loss::SquaredError;
+        use traits::LossFunction;
+
+        let data = SequenceDataset::from_sequences(&[vec![1.0, 0.5, -1.0, 0.25], vec![0.5, 1.0]], vec![0.5, -0.5], 2, 1);
+        let mut rng = SeededRng::new(3);
+        let rnn = RecurrentLayer::random_with(2, 2, &mut rng);
+        let head = || {
+            let mut head: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+            head.push_back(Box::new(DenseLayer::uniform(0.5, 2, 1)));
+            head
+        };
+        let loss = |rnn: &RecurrentLayer| -> f32 {
+            (0..data.len())
+                .map(|i| SquaredError.loss_vec(&predict(rnn, &head(), data.sequence(i), Some(data.mask(i))), data.target(i)))
+                .sum()
+        };
+
+        // One step with rate 1 moves the weights by minus the gradient
+        let mut trained = RecurrentLayer::random_with(2, 2, &mut SeededRng::new(3));
+        train_sequences(&SGDTrainer::new(1, 1.0), &mut trained, &mut head(), &data);
+
+        let eps = 1e-3;
+        for i in 0..rnn.recurrent_weights.len() {
+            let mut up = RecurrentLayer::random_with(2, 2, &mut SeededRng::new(3));
+            up.recurrent_weights[i] += eps;
+            let mut down = RecurrentLayer::random_with(2, 2, &mut SeededRng::new(3));
+            down.recurrent_weights[i] -= eps;
+            let numeric = (loss(&up) - loss(&down)) / (2.0 * eps);
+            let analytic = rnn.recurrent_weights[i] - trained.recurrent_weights[i];
+            assert!((numeric - analytic).abs() < 1e-2, "weight {}: {} vs {}", i, numeric, analytic);
+        }
+    }
+
+    #[test]
+    fn remembers_first_step() {
+        // The target is the first value of sequences of varying length
+        let sequences = vec![vec![1.0, -1.0], vec![-1.0, 1.0, 1.0], vec![1.0], vec![-1.0, -1.0, 1.0], vec![1.0, 1.0, -1.0],
+                             vec![-1.0]];
+        let targets = vec![0.8, -0.8, 0.8, -0.8, 0.8, -0.8];
+        let data = SequenceDataset::from_sequences(&sequences, targets, 1, 1);
+
+        let mut rng = SeededRng::new(2);
+        let mut rnn = RecurrentLayer::random_with(1, 4, &mut rng);
+        let mut head: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        head.push_back(Box::new(DenseLayer::random_with(4, 1, &mut rng)));
+
+        let mut trainer = SGDTrainer::new(500, 0.05);
+        trainer.reduction = Reduction::Mean;
+        let history = train_sequences(&trainer, &mut rnn, &mut head, &data);
+
+        assert!(history[499] < history[0] / 10.0);
+        for i in 0..data.len() {
+            let y = predict(&rnn, &head, data.sequence(i), Some(data.mask(i)));
+            assert!(y[0] * data.target(i)[0] > 0.0);
+        }
+    }
+}
@@ -0,0 +1,710 @@
+//! Reinforcement learning with policy gradients and Q-learning. A
+//! network maps the observations of an `Environment` to one logit per
+//! action, the softmax of which is the policy, and `Reinforce` trains
+//! it to take the actions that lead to high rewards. Alternatively,
+//! `DQN` trains the network to output the value of every action.
+//!
+//! Three toy environments are included: a multi-armed `Bandit`, a
+//! `GridWorld` where the goal is to reach a corner, and `CartPole`.
+use loss::SoftmaxCrossEntropy;
+use network;
+use random::{RngProvider, SeededRng};
+use serialize::{self, LayerRegistry};
+use sgd::{self, LayerGrads, SGDTrainer};
+use traits::{SupervisedTrainer, WeightedLayer};
+use utils::{argmax, epsilon_greedy, normal_vector, sample_index, softmax};
+
+use rand::Rng;
+use std::collections::{LinkedList, VecDeque};
+
+/// What happens after an action is taken.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    /// The observation of the new state
+    pub observation: Vec<f32>,
+    pub reward: f32,
+    /// Whether the episode is over
+    pub done: bool,
+}
+
+/// An environment with a discrete set of actions, which is interacted
+/// with in episodes.
+pub trait Environment {
+    /// The number of values in every observation.
+    fn observation_count(&self) -> usize;
+
+    /// The number of actions, numbered from 0.
+    fn action_count(&self) -> usize;
+
+    /// Starts a new episode and returns the first observation.
+    fn reset(&mut self, rng: &mut SeededRng) -> Vec<f32>;
+
+    /// Takes an action in the current state.
+    fn step(&mut self, action: usize, rng: &mut SeededRng) -> Step;
+}
+
+/// The observations, actions and rewards of a single episode, one of
+/// each per step.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Episode {
+    pub observations: Vec<Vec<f32>>,
+    pub actions: Vec<usize>,
+    pub rewards: Vec<f32>,
+}
+
+impl Episode {
+    pub fn len(&self) -> usize {
+        self.actions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// The sum of all rewards.
+    pub fn total_reward(&self) -> f32 {
+        self.rewards.iter().sum()
+    }
+
+    /// The discounted sum of the rewards from every step to the end of
+    /// the episode.
+    pub fn returns(&self, discount: f32) -> Vec<f32> {
+        let mut returns = vec![0.0; self.rewards.len()];
+        let mut g = 0.0;
+        for (ret, r) in returns.iter_mut().zip(&self.rewards).rev() {
+            g = r + discount * g;
+            *ret = g;
+        }
+        returns
+    }
+}
+
+/// The REINFORCE policy gradient method. After every episode, the
+/// log-probability of each action taken is increased in proportion to
+/// the return which followed it.
+pub struct Reinforce {
+    /// The learning rate
+    pub rate: f32,
+    /// How much rewards are worth for every step they lie in the
+    /// future
+    pub discount: f32,
+    /// Episodes are cut off after this many steps
+    pub max_steps: usize,
+    /// The decay of a moving average of the returns, which is
+    /// subtracted from the returns to reduce the variance of the
+    /// gradients. `None` uses the returns as they are.
+    pub baseline_decay: Option<f32>,
+    /// Source of randomness for the actions and the environment
+    pub rng: RngProvider,
+    baseline: Option<f32>,
+}
+
+impl Reinforce {
+    pub fn new(rate: f32) -> Reinforce {
+        Reinforce {
+            rate,
+            discount: 0.99,
+            max_steps: 100,
+            baseline_decay: Some(0.9),
+            rng: RngProvider::from_entropy(),
+            baseline: None,
+        }
+    }
+
+    /// The probability of every action given an observation.
+    pub fn policy(&self, layers: &LinkedList<Box<dyn WeightedLayer>>, observation: &[f32]) -> Vec<f32> {
+        softmax(&network::output(layers, observation))
+    }
+
+    /// The most probable action given an observation.
+    pub fn greedy_action(&self, layers: &LinkedList<Box<dyn WeightedLayer>>, observation: &[f32]) -> usize {
+        argmax(&network::output(layers, observation))
+    }
+
+    /// Plays one episode with actions drawn from the policy, without
+    /// training.
+    pub fn run_episode(&self, layers: &LinkedList<Box<dyn WeightedLayer>>, env: &mut dyn Environment) -> Episode {
+        let mut rng = self.rng.fork();
+        let mut episode = Episode::default();
+        let mut observation = env.reset(&mut rng);
+        for _ in 0..self.max_steps {
+            let action = sample_index(&self.policy(layers, &observation), &mut rng);
+            let step = env.step(action, &mut rng);
+            episode.observations.push(observation);
+            episode.actions.push(action);
+            episode.rewards.push(step.reward);
+            observation = step.observation;
+            if step.done {
+                break;
+            }
+        }
+        episode
+    }
+
+    /// Plays one episode and takes a step of gradient ascent on the
+    /// expected return. Returns the episode.
+    pub fn train_episode(&mut self,
+                         layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+                         env: &mut dyn Environment)
+                         -> Episode {
+        let episode = self.run_episode(layers, env);
+        let returns = self.returns(&episode);
+        let layers = &mut *network::train_mode(layers);
+        let mut grads: Option<Vec<LayerGrads>> = None;
+        for ((observation, &action), g) in episode.observations.iter().zip(&episode.actions).zip(&returns) {
+            // The gradient of -g * log(softmax(y)[a]) is that of the
+            // softmax cross-entropy with a target of g at the action.
+            let mut target = vec![0.0; env.action_count()];
+            target[action] = *g;
+            let step = sgd::backward(layers, observation, &target, &SoftmaxCrossEntropy);
+            match grads {
+                Some(ref mut grads) => {
+                    for (total, s) in grads.iter_mut().zip(&step) {
+                        total.add(s);
+                    }
+                }
+                None => grads = Some(step),
+            }
+        }
+        if let Some(grads) = grads {
+            sgd::apply_gradients(layers, &grads, self.rate);
+        }
+        episode
+    }
+
+    /// Trains for a number of episodes and returns the total reward of
+    /// each.
+    pub fn train(&mut self,
+                 layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+                 env: &mut dyn Environment,
+                 episodes: usize)
+                 -> Vec<f32> {
+        (0..episodes).map(|_| self.train_episode(layers, env).total_reward()).collect()
+    }
+
+    /// The returns of an episode less the baseline, which is then moved
+    /// towards their mean.
+    fn returns(&mut self, episode: &Episode) -> Vec<f32> {
+        let mut returns = episode.returns(self.discount);
+        if let (Some(decay), false) = (self.baseline_decay, returns.is_empty()) {
+            let mean = returns.iter().sum::<f32>() / returns.len() as f32;
+            let baseline = self.baseline.unwrap_or(mean);
+            for g in returns.iter_mut() {
+                *g -= baseline;
+            }
+            self.baseline = Some(decay * baseline + (1.0 - decay) * mean);
+        }
+        returns
+    }
+}
+
+/// A single step of experience.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub observation: Vec<f32>,
+    pub action: usize,
+    pub reward: f32,
+    pub next_observation: Vec<f32>,
+    /// Whether the episode ended with this step, in which case nothing
+    /// follows `next_observation`
+    pub done: bool,
+}
+
+/// Holds the most recent transitions, from which training batches are
+/// drawn at random so that consecutive samples are not correlated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayBuffer {
+    capacity: usize,
+    transitions: VecDeque<Transition>,
+}
+
+impl ReplayBuffer {
+    pub fn new(capacity: usize) -> ReplayBuffer {
+        assert!(capacity > 0, "a replay buffer must hold at least one transition");
+        ReplayBuffer {
+            capacity,
+            transitions: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.transitions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transitions.is_empty()
+    }
+
+    /// Adds a transition, forgetting the oldest one when full.
+    pub fn push(&mut self, transition: Transition) {
+        if self.transitions.len() == self.capacity {
+            self.transitions.pop_front();
+        }
+        self.transitions.push_back(transition);
+    }
+
+    /// Draws `count` transitions uniformly at random, with
+    /// replacement.
+    pub fn sample<R: Rng>(&self, count: usize, rng: &mut R) -> Vec<&Transition> {
+        if self.transitions.is_empty() {
+            return Vec::new();
+        }
+        (0..count).map(|_| &self.transitions[rng.gen_range(0, self.transitions.len())]).collect()
+    }
+}
+
+/// Q-learning with a network which maps an observation to the value of
+/// every action, in the style of DQN. Actions are chosen epsilon-greedily
+/// and every step is stored in a replay buffer. After every step, a
+/// batch drawn from the buffer is trained towards the temporal
+/// difference targets `r + discount * max Q'(s')` with `trainer`, where
+/// `Q'` is a copy of the network which is only updated every
+/// `target_update` steps.
+pub struct DQN {
+    /// Trains the network on the batches; its `epochs` and
+    /// `batch_size` apply to every batch drawn from the buffer
+    pub trainer: SGDTrainer,
+    /// How much rewards are worth for every step they lie in the
+    /// future
+    pub discount: f32,
+    /// The probability of a random action
+    pub epsilon: f32,
+    /// `epsilon` is multiplied by this after every episode
+    pub epsilon_decay: f32,
+    /// `epsilon` is never decayed below this
+    pub min_epsilon: f32,
+    /// The number of transitions trained on after every step, which
+    /// must be positive. Training starts once the buffer holds this
+    /// many.
+    pub batch_size: usize,
+    pub buffer: ReplayBuffer,
+    /// The number of steps between copies of the network to the
+    /// target network. 0 computes the targets with the network itself.
+    /// The target network is built from `serialize::to_spec` of the
+    /// network, so every layer needs a spec unless this is 0.
+    pub target_update: usize,
+    /// Episodes are cut off after this many steps
+    pub max_steps: usize,
+    /// Source of randomness for the actions, the batches and the
+    /// environment
+    pub rng: RngProvider,
+    steps: usize,
+    target: Option<LinkedList<Box<dyn WeightedLayer>>>,
+}
+
+impl DQN {
+    pub fn new(rate: f32) -> DQN {
+        DQN {
+            trainer: SGDTrainer::new(1, rate),
+            discount: 0.99,
+            epsilon: 1.0,
+            epsilon_decay: 0.99,
+            min_epsilon: 0.05,
+            batch_size: 32,
+            buffer: ReplayBuffer::new(10_000),
+            target_update: 100,
+            max_steps: 200,
+            rng: RngProvider::from_entropy(),
+            steps: 0,
+            target: None,
+        }
+    }
+
+    /// The action with the highest value given an observation.
+    pub fn greedy_action(&self, layers: &LinkedList<Box<dyn WeightedLayer>>, observation: &[f32]) -> usize {
+        argmax(&network::output(layers, observation))
+    }
+
+    /// Plays one episode, training after every step. Returns the
+    /// episode.
+    pub fn train_episode(&mut self,
+                         layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+                         env: &mut dyn Environment)
+                         -> Episode {
+        assert!(self.batch_size > 0, "the batch size must be positive");
+        let mut rng = self.rng.fork();
+        let mut episode = Episode::default();
+        let mut observation = env.reset(&mut rng);
+        for _ in 0..self.max_steps {
+            let action = epsilon_greedy(&network::output(layers, &observation), self.epsilon, &mut rng);
+            let step = env.step(action, &mut rng);
+            self.buffer.push(Transition {
+                observation: observation.clone(),
+                action,
+                reward: step.reward,
+                next_observation: step.observation.clone(),
+                done: step.done,
+            });
+            episode.observations.push(observation);
+            episode.actions.push(action);
+            episode.rewards.push(step.reward);
+            observation = step.observation;
+
+            self.learn(layers, &mut rng);
+            if step.done {
+                break;
+            }
+        }
+        self.epsilon = (self.epsilon * self.epsilon_decay).max(self.min_epsilon);
+        episode
+    }
+
+    /// Trains for a number of episodes and returns the total reward of
+    /// each.
+    pub fn train(&mut self,
+                 layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+                 env: &mut dyn Environment,
+                 episodes: usize)
+                 -> Vec<f32> {
+        (0..episodes).map(|_| self.train_episode(layers, env).total_reward()).collect()
+    }
+
+    /// Trains on a batch from the buffer, and updates the target
+    /// network when it is due.
+    fn learn(&mut self, layers: &mut LinkedList<Box<dyn WeightedLayer>>, rng: &mut SeededRng) {
+        self.steps += 1;
+        if self.target_update > 0 && (self.target.is_none() || self.steps.is_multiple_of(self.target_update)) {
+            self.update_target(layers);
+        }
+        if self.buffer.len() < self.batch_size {
+            return;
+        }
+
+        let batch = self.buffer.sample(self.batch_size, rng);
+        let inputs: Vec<f32> = batch.iter().flat_map(|t| t.observation.iter().cloned()).collect();
+        let next: Vec<f32> = batch.iter().flat_map(|t| t.next_observation.iter().cloned()).collect();
+        let next_values = network::predict_batch(self.target.as_ref().unwrap_or(&*layers), &next);
+
+        // Only the value of the action taken moves towards its target
+        let mut targets = network::predict_batch(layers, &inputs);
+        let actions = targets.len() / batch.len();
+        for (i, t) in batch.iter().enumerate() {
+            let mut value = t.reward;
+            if !t.done {
+                let best = next_values[i * actions..(i + 1) * actions].iter().cloned().fold(f32::MIN, f32::max);
+                value += self.discount * best;
+            }
+            targets[i * actions + t.action] = value;
+        }
+        self.trainer.train(layers, &inputs, &targets);
+    }
+
+    /// Copies the weights of the network to the target network, which
+    /// is built the first time.
+    fn update_target(&mut self, layers: &LinkedList<Box<dyn WeightedLayer>>) {
+        if self.target.is_none() {
+            let spec = serialize::to_spec(layers).unwrap_or_else(|e| panic!("no target network: {}", e));
+            let target = LayerRegistry::new().parse_spec(&spec).unwrap_or_else(|e| panic!("no target network: {}", e));
+            self.target = Some(target);
+        }
+        if let Some(ref mut target) = self.target {
+            network::set_weights(target, &network::get_weights(layers));
+        }
+    }
+}
+
+/// A multi-armed bandit. Every episode is a single pull of one of the
+/// arms, which pays its mean plus gaussian noise. The observation is
+/// always a single 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bandit {
+    /// The mean reward of every arm
+    pub means: Vec<f32>,
+    /// The standard deviation of the rewards
+    pub noise: f32,
+}
+
+impl Bandit {
+    pub fn new(means: Vec<f32>, noise: f32) -> Bandit {
+        Bandit { means, noise }
+    }
+}
+
+impl Environment for Bandit {
+    fn observation_count(&self) -> usize {
+        1
+    }
+
+    fn action_count(&self) -> usize {
+        self.means.len()
+    }
+
+    fn reset(&mut self, _: &mut SeededRng) -> Vec<f32> {
+        vec![1.0]
+    }
+
+    fn step(&mut self, action: usize, rng: &mut SeededRng) -> Step {
+        Step {
+            observation: vec![1.0],
+            reward: self.means[action] + self.noise * normal_vector(1, rng)[0],
+            done: true,
+        }
+    }
+}
+
+/// A grid where every episode starts in the top left corner and ends in
+/// the bottom right one, which pays a reward of 1. Every other step
+/// costs `step_cost`. The actions move up, right, down and left, and
+/// moves off the grid stay in place. The observation is a one-hot
+/// encoding of the position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GridWorld {
+    pub width: usize,
+    pub height: usize,
+    pub step_cost: f32,
+    position: (usize, usize),
+}
+
+impl GridWorld {
+    pub fn new(width: usize, height: usize) -> GridWorld {
+        assert!(width * height > 1, "the grid needs room for both a start and a goal");
+        GridWorld {
+            width,
+            height,
+            step_cost: 0.01,
+            position: (0, 0),
+        }
+    }
+
+    fn observation(&self) -> Vec<f32> {
+        let mut observation = vec![0.0; self.width * self.height];
+        observation[self.position.1 * self.width + self.position.0] = 1.0;
+        observation
+    }
+}
+
+impl Environment for GridWorld {
+    fn observation_count(&self) -> usize {
+        self.width * self.height
+    }
+
+    fn action_count(&self) -> usize {
+        4
+    }
+
+    fn reset(&mut self, _: &mut SeededRng) -> Vec<f32> {
+        self.position = (0, 0);
+        self.observation()
+    }
+
+    fn step(&mut self, action: usize, _: &mut SeededRng) -> Step {
+        let (x, y) = self.position;
+        self.position = match action {
+            0 => (x, y.saturating_sub(1)),
+            1 => ((x + 1).min(self.width - 1), y),
+            2 => (x, (y + 1).min(self.height - 1)),
+            3 => (x.saturating_sub(1), y),
+            _ => panic!("no action {} in a grid world", action),
+        };
+        let done = self.position == (self.width - 1, self.height - 1);
+        Step {
+            observation: self.observation(),
+            reward: if done { 1.0 } else { -self.step_cost },
+            done,
+        }
+    }
+}
+
+/// The classic cart-pole balancing task. A pole is hinged to a cart on
+/// a track, and the actions push the cart left or right. Every step
+/// that the pole stays within 12 degrees of upright and the cart stays
+/// on the track pays a reward of 1. The observation is the position
+/// and velocity of the cart and the angle and angular velocity of the
+/// pole.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CartPole {
+    state: [f32; 4],
+}
+
+impl CartPole {
+    const GRAVITY: f32 = 9.8;
+    const CART_MASS: f32 = 1.0;
+    const POLE_MASS: f32 = 0.1;
+    /// Half the length of the pole
+    const POLE_LENGTH: f32 = 0.5;
+    const FORCE: f32 = 10.0;
+    /// The seconds between steps
+    const TAU: f32 = 0.02;
+    const MAX_ANGLE: f32 = 12.0 * ::std::f32::consts::PI / 180.0;
+    const MAX_POSITION: f32 = 2.4;
+
+    pub fn new() -> CartPole {
+        CartPole::default()
+    }
+}
+
+impl Environment for CartPole {
+    fn observation_count(&self) -> usize {
+        4
+    }
+
+    fn action_count(&self) -> usize {
+        2
+    }
+
+    fn reset(&mut self, rng: &mut SeededRng) -> Vec<f32> {
+        for s in self.state.iter_mut() {
+            *s = rng.gen_range(-0.05, 0.05);
+        }
+        self.state.to_vec()
+    }
+
+    fn step(&mut self, action: usize, _: &mut SeededRng) -> Step {
+        let [x, x_dot, theta, theta_dot] = self.state;
+        let force = if action == 1 { CartPole::FORCE } else { -CartPole::FORCE };
+        let total_mass = CartPole::CART_MASS + CartPole::POLE_MASS;
+        let pole_moment = CartPole::POLE_MASS * CartPole::POLE_LENGTH;
+        let (sin, cos) = theta.sin_cos();
+
+        let temp = (force + pole_moment * theta_dot * theta_dot * sin) / total_mass;
+        let theta_acc = (CartPole::GRAVITY * sin - cos * temp) /
+                        (CartPole::POLE_LENGTH * (4.0 / 3.0 - CartPole::POLE_MASS * cos * cos / total_mass));
+        let x_acc = temp - pole_moment * theta_acc * cos / total_mass;
+
+        self.state = [x + CartPole::TAU * x_dot,
+                      x_dot + CartPole::TAU * x_acc,
+                      theta + CartPole::TAU * theta_dot,
+                      theta_dot + CartPole::TAU * theta_acc];
+        let done = self.state[0].abs() > CartPole::MAX_POSITION || self.state[2].abs() > CartPole::MAX_ANGLE;
+        Step {
+            observation: self.state.to_vec(),
+            reward: 1.0,
+            done,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::DenseLayer;
+
+    fn policy(inputs: usize, actions: usize) -> LinkedList<Box<dyn WeightedLayer>> {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(0.0, inputs, actions)));
+        layers
+    }
+
+    #[test]
+    fn discounted_returns() {
+        let episode = Episode {
+            observations: vec![vec![0.0]; 3],
+            actions: vec![0; 3],
+            rewards: vec![1.0, 0.0, 2.0],
+        };
+        assert_eq!(episode.total_reward(), 3.0);
+        assert_eq!(episode.returns(0.5), vec![1.5, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn learns_best_arm() {
+        let mut layers = policy(1, 3);
+        let mut bandit = Bandit::new(vec![0.2, 1.0, 0.5], 0.1);
+        let mut reinforce = Reinforce::new(0.1);
+        reinforce.rng = RngProvider::seeded(1);
+
+        reinforce.train(&mut layers, &mut bandit, 300);
+        assert_eq!(reinforce.greedy_action(&layers, &[1.0]), 1);
+        assert!(reinforce.policy(&layers, &[1.0])[1] > 0.8);
+    }
+
+    #[test]
+    fn finds_the_goal() {
+        let mut layers = policy(9, 4);
+        let mut grid = GridWorld::new(3, 3);
+        let mut reinforce = Reinforce::new(0.1);
+        reinforce.rng = RngProvider::seeded(3);
+        reinforce.max_steps = 50;
+
+        let rewards = reinforce.train(&mut layers, &mut grid, 400);
+        assert!(rewards[rewards.len() - 20..].iter().all(|r| *r > 0.9));
+
+        // The greedy policy takes a shortest path of four steps
+        let mut rng = SeededRng::new(0);
+        let mut observation = grid.reset(&mut rng);
+        for i in 0..4 {
+            let step = grid.step(reinforce.greedy_action(&layers, &observation), &mut rng);
+            assert_eq!(step.done, i == 3);
+            observation = step.observation;
+        }
+    }
+
+    #[test]
+    fn replay_buffer_forgets_oldest() {
+        let mut buffer = ReplayBuffer::new(2);
+        for i in 0..3 {
+            buffer.push(Transition {
+                observation: vec![i as f32],
+                action: 0,
+                reward: 0.0,
+                next_observation: vec![0.0],
+                done: false,
+            });
+        }
+        assert_eq!(buffer.len(), 2);
+        let mut rng = SeededRng::new(0);
+        assert!(buffer.sample(10, &mut rng).iter().all(|t| t.observation[0] > 0.0));
+    }
+
+    #[test]
+    fn cart_pole_falls_over() {
+        let mut cart = CartPole::new();
+        let mut rng = SeededRng::new(0);
+        assert_eq!(cart.reset(&mut rng).len(), 4);
+
+        // Pushing in one direction tips the pole the other way
+        let mut steps = 0;
+        while !cart.step(1, &mut rng).done {
+            steps += 1;
+            assert!(steps < 100);
+        }
+        assert!(cart.state[2] < 0.0);
+    }
+
+    #[test]
+    fn target_network_is_kept_between_updates() {
+        let mut layers = policy(1, 2);
+        let mut bandit = Bandit::new(vec![1.0, 2.0], 0.0);
+        let mut dqn = DQN::new(0.1);
+        dqn.rng = RngProvider::seeded(0);
+        dqn.batch_size = 1;
+        dqn.target_update = 3;
+
+        dqn.train(&mut layers, &mut bandit, 2);
+        let target = network::get_weights(dqn.target.as_ref().unwrap());
+        assert_eq!(target, vec![0.0; 4]);
+        assert_ne!(network::get_weights(&layers), target);
+
+        dqn.train(&mut layers, &mut bandit, 1);
+        assert_ne!(network::get_weights(dqn.target.as_ref().unwrap()), target);
+    }
+
+    #[test]
+    #[should_panic(expected = "batch size must be positive")]
+    fn dqn_needs_a_batch() {
+        let mut dqn = DQN::new(0.1);
+        dqn.batch_size = 0;
+        dqn.train(&mut policy(1, 2), &mut Bandit::new(vec![0.0, 1.0], 0.0), 1);
+    }
+
+    #[test]
+    fn q_learning_finds_the_goal() {
+        let mut layers = policy(9, 4);
+        let mut grid = GridWorld::new(3, 3);
+        let mut dqn = DQN::new(0.05);
+        dqn.rng = RngProvider::seeded(0);
+        dqn.batch_size = 8;
+        dqn.target_update = 20;
+        dqn.discount = 0.9;
+        dqn.max_steps = 50;
+        dqn.train(&mut layers, &mut grid, 100);
+
+        let mut rng = SeededRng::new(0);
+        let mut observation = grid.reset(&mut rng);
+        for i in 0..4 {
+            let step = grid.step(dqn.greedy_action(&layers, &observation), &mut rng);
+            assert_eq!(step.done, i == 3);
+            observation = step.observation;
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Schedules which change the learning rate over the course of
+//! training.
+
+/// Reduces the learning rate by a factor when the loss has not improved
+/// for a number of epochs. `SGDTrainer::fit` monitors the validation
+/// loss, or the training loss when there is no validation data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReduceOnPlateau {
+    /// The rate is multiplied by this factor on a plateau
+    pub factor: f32,
+    /// The number of epochs without improvement which make a plateau
+    pub patience: usize,
+    /// The relative decrease of the loss which counts as an
+    /// improvement
+    pub threshold: f32,
+    /// The rate is never reduced below this
+    pub min_rate: f32,
+    /// The lowest loss so far
+    pub(crate) best: f32,
+    /// The number of epochs since the loss last improved
+    pub(crate) waited: usize,
+}
+
+impl ReduceOnPlateau {
+    pub fn new(factor: f32, patience: usize) -> ReduceOnPlateau {
+        assert!(factor > 0.0 && factor < 1.0, "the factor must be between 0 and 1");
+        ReduceOnPlateau {
+            factor,
+            patience,
+            threshold: 1e-4,
+            min_rate: 0.0,
+            best: f32::INFINITY,
+            waited: 0,
+        }
+    }
+
+    /// Records the loss of an epoch trained with the given rate, and
+    /// returns the rate of the next epoch.
+    pub fn step(&mut self, loss: f32, rate: f32) -> f32 {
+        if loss < self.best * (1.0 - self.threshold) {
+            self.best = loss;
+            self.waited = 0;
+            return rate;
+        }
+        self.waited += 1;
+        if self.waited > self.patience {
+            self.waited = 0;
+            (rate * self.factor).max(self.min_rate)
+        } else {
+            rate
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reduces_after_patience() {
+        let mut p = ReduceOnPlateau::new(0.5, 2);
+        p.min_rate = 0.3;
+        let rates: Vec<f32> = [1.0, 0.9, 0.9, 0.95, 0.9, 0.8, 0.8, 0.8, 0.8, 0.8, 0.8, 0.8]
+            .iter()
+            .scan(1.0, |rate, loss| {
+                *rate = p.step(*loss, *rate);
+                Some(*rate)
+            })
+            .collect();
+        assert_eq!(rates, vec![1.0, 1.0, 1.0, 1.0, 0.5, 0.5, 0.5, 0.5, 0.3, 0.3, 0.3, 0.3]);
+    }
+}
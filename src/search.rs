@@ -0,0 +1,159 @@
+//! Simple gradient-free trainers which perturb the weights of the
+//! network at random. They work for any layers, including ones
+//! without derivatives, and illustrate why following the gradient is
+//! so much more efficient.
+use evolution::population::mutate;
+use loss::SquaredError;
+use network;
+use random::RngProvider;
+use traits::{LossFunction, SupervisedTrainer, WeightedLayer};
+
+use rand::Rng;
+use std::collections::LinkedList;
+
+/// Random search: perturbs all weights with gaussian noise and keeps
+/// the perturbation only if it lowers the loss.
+pub struct RandomSearchTrainer {
+    /// The number of perturbations to try
+    pub iterations: usize,
+    /// The standard deviation of the perturbations
+    pub step: f32,
+    /// The loss function to minimize
+    pub loss: Box<dyn LossFunction>,
+    /// Source of randomness for the perturbations
+    pub rng: RngProvider,
+}
+
+impl RandomSearchTrainer {
+    pub fn new(iterations: usize, step: f32) -> RandomSearchTrainer {
+        RandomSearchTrainer {
+            iterations,
+            step,
+            loss: Box::new(SquaredError),
+            rng: RngProvider::from_entropy(),
+        }
+    }
+}
+
+impl SupervisedTrainer for RandomSearchTrainer {
+    fn train(&self, layers: &mut LinkedList<Box<dyn WeightedLayer>>, inputs: &[f32], targets: &[f32]) {
+        let mut rng = self.rng.fork();
+        let mut best = network::get_weights(layers);
+        let mut best_loss = network::loss(layers, self.loss.as_ref(), inputs, targets);
+
+        for _ in 0..self.iterations {
+            let mut candidate = best.clone();
+            mutate(&mut candidate, 1.0, self.step, &mut rng);
+            network::set_weights(layers, &candidate);
+            let loss = network::loss(layers, self.loss.as_ref(), inputs, targets);
+            if loss < best_loss {
+                best = candidate;
+                best_loss = loss;
+            }
+        }
+
+        network::set_weights(layers, &best);
+    }
+}
+
+/// Simulated annealing: like random search, but perturbations which
+/// increase the loss by `d` are also accepted with probability
+/// `exp(-d / T)`. The temperature `T` is multiplied by `cooling` after
+/// every iteration, so the search gradually settles down. The best
+/// weights seen are kept at the end.
+pub struct AnnealingTrainer {
+    /// The number of perturbations to try
+    pub iterations: usize,
+    /// The standard deviation of the perturbations
+    pub step: f32,
+    /// The starting temperature
+    pub temperature: f32,
+    /// Factor the temperature is multiplied with every iteration
+    pub cooling: f32,
+    /// The loss function to minimize
+    pub loss: Box<dyn LossFunction>,
+    /// Source of randomness for the perturbations
+    pub rng: RngProvider,
+}
+
+impl AnnealingTrainer {
+    pub fn new(iterations: usize, step: f32) -> AnnealingTrainer {
+        AnnealingTrainer {
+            iterations,
+            step,
+            temperature: 1.0,
+            cooling: 0.99,
+            loss: Box::new(SquaredError),
+            rng: RngProvider::from_entropy(),
+        }
+    }
+}
+
+impl SupervisedTrainer for AnnealingTrainer {
+    fn train(&self, layers: &mut LinkedList<Box<dyn WeightedLayer>>, inputs: &[f32], targets: &[f32]) {
+        let mut rng = self.rng.fork();
+        let mut current = network::get_weights(layers);
+        let mut current_loss = network::loss(layers, self.loss.as_ref(), inputs, targets);
+        let mut best = current.clone();
+        let mut best_loss = current_loss;
+        let mut temperature = self.temperature;
+
+        for _ in 0..self.iterations {
+            let mut candidate = current.clone();
+            mutate(&mut candidate, 1.0, self.step, &mut rng);
+            network::set_weights(layers, &candidate);
+            let loss = network::loss(layers, self.loss.as_ref(), inputs, targets);
+
+            let accept = loss < current_loss ||
+                         (temperature > 0.0 && rng.gen::<f32>() < (-(loss - current_loss) / temperature).exp());
+            if accept {
+                current = candidate;
+                current_loss = loss;
+                if current_loss < best_loss {
+                    best = current.clone();
+                    best_loss = current_loss;
+                }
+            }
+            temperature *= self.cooling;
+        }
+
+        network::set_weights(layers, &best);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::DenseLayer;
+
+    fn linear() -> (LinkedList<Box<dyn WeightedLayer>>, Vec<f32>, Vec<f32>) {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(0.0, 1, 1)));
+        // y = 2x + 1
+        (layers, vec![-1.0, 0.0, 1.0, 2.0], vec![-1.0, 1.0, 3.0, 5.0])
+    }
+
+    #[test]
+    fn random_search_improves() {
+        let (mut layers, inputs, targets) = linear();
+        let before = network::loss(&layers, &SquaredError, &inputs, &targets);
+
+        let mut trainer = RandomSearchTrainer::new(500, 0.3);
+        trainer.rng = RngProvider::seeded(1);
+        trainer.train(&mut layers, &inputs, &targets);
+
+        assert!(network::loss(&layers, &SquaredError, &inputs, &targets) < before * 0.1);
+    }
+
+    #[test]
+    fn annealing_improves() {
+        let (mut layers, inputs, targets) = linear();
+        let before = network::loss(&layers, &SquaredError, &inputs, &targets);
+
+        let mut trainer = AnnealingTrainer::new(500, 0.3);
+        trainer.rng = RngProvider::seeded(1);
+        trainer.train(&mut layers, &inputs, &targets);
+
+        assert!(network::loss(&layers, &SquaredError, &inputs, &targets) < before * 0.1);
+    }
+}
@@ -0,0 +1,856 @@
+//! Saving and loading of networks, and building networks from an
+//! architecture spec.
+//!
+//! A layer describes itself with a `LayerSpec`, its kind and the
+//! hyperparameters needed to construct it, written as a line such as
+//! `dense inputs=2 neurons=6`. A `LayerRegistry` maps each kind to a
+//! constructor. It knows the layers of this crate, and other crates
+//! can register their own layers to make them loadable.
+//!
+//! An architecture spec is one layer spec per line, or separated by
+//! `;`. A saved network is the line `scarecrow 1` followed by the spec
+//! of every layer, each followed by a `name` line if the layer is
+//! named, and `weights` and `bias` lines with the values of the layer,
+//! if it has any. A layer with a weight mask, such as a pruned layer,
+//! also has a `mask` line of ones for the weights which are trained
+//! and zeros for those which are not. A checkpoint is a saved network followed by the
+//! state of its training, from which `SGDTrainer::resume` continues.
+//!
+//! With the `safetensors` feature, the weights can also be stored in
+//! the safetensors format, which other tools read.
+use layers::*;
+use random::{RngProvider, SeededRng};
+use schedule::ReduceOnPlateau;
+use sgd::TrainingState;
+#[cfg(feature = "safetensors")]
+use network;
+use traits::{WeightedLayer, WeightsMut};
+
+#[cfg(feature = "safetensors")]
+use safetensors::{Dtype, SafeTensors};
+#[cfg(feature = "safetensors")]
+use safetensors::tensor::TensorView;
+
+use std::collections::{HashMap, LinkedList};
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+const HEADER: &str = "scarecrow 1";
+
+/// The kind and hyperparameters of a layer, from which a registry can
+/// construct it. See `Layer::spec`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerSpec {
+    pub kind: String,
+    /// Named hyperparameters, in order
+    pub params: Vec<(String, String)>,
+}
+
+impl LayerSpec {
+    pub fn new(kind: &str) -> LayerSpec {
+        LayerSpec {
+            kind: kind.to_string(),
+            params: Vec::new(),
+        }
+    }
+
+    /// Adds a hyperparameter.
+    pub fn with<T: ToString>(mut self, name: &str, value: T) -> LayerSpec {
+        self.params.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// The value of a hyperparameter, if given.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|p| p.0 == name).map(|p| p.1.as_str())
+    }
+
+    /// Parses a required hyperparameter.
+    pub fn parse<T: FromStr>(&self, name: &str) -> Result<T, String> {
+        let value = self.get(name).ok_or_else(|| format!("{} is missing `{}`", self.kind, name))?;
+        value.parse().map_err(|_| format!("invalid value `{}` for `{}` of {}", value, name, self.kind))
+    }
+
+    /// Parses an optional hyperparameter.
+    pub fn parse_or<T: FromStr>(&self, name: &str, default: T) -> Result<T, String> {
+        match self.get(name) {
+            Some(_) => self.parse(name),
+            None => Ok(default),
+        }
+    }
+}
+
+impl fmt::Display for LayerSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        for (name, value) in &self.params {
+            write!(f, " {}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for LayerSpec {
+    type Err = String;
+
+    /// Parses a spec such as `dense inputs=2 neurons=6`.
+    fn from_str(s: &str) -> Result<LayerSpec, String> {
+        let mut words = s.split_whitespace();
+        let kind = words.next().ok_or("the layer spec is empty")?;
+        let mut spec = LayerSpec::new(kind);
+        for word in words {
+            let mut parts = word.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) if !name.is_empty() => spec = spec.with(name, value),
+                _ => return Err(format!("expected name=value, got `{}`", word)),
+            }
+        }
+        Ok(spec)
+    }
+}
+
+/// An error in a saved network or an architecture spec.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// The line of the error, starting from 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl From<ParseError> for io::Error {
+    fn from(e: ParseError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Constructs a layer from its spec. Layers with weights may be
+/// initialized in any way, as loading replaces their values.
+pub type LayerConstructor = Box<dyn Fn(&LayerSpec) -> Result<Box<dyn WeightedLayer>, String> + Send + Sync>;
+
+/// Maps layer kinds to constructors.
+pub struct LayerRegistry {
+    constructors: HashMap<String, LayerConstructor>,
+}
+
+impl LayerRegistry {
+    /// A registry of the layers of this crate.
+    pub fn new() -> LayerRegistry {
+        let mut registry = LayerRegistry::empty();
+        registry.register("dense", |s| {
+            let layer = DenseLayer::random(s.parse("inputs")?, s.parse("neurons")?).with_layout(layout(s)?);
+            Ok(Box::new(if s.parse_or("bias", true)? {
+                layer
+            } else {
+                layer.without_bias()
+            }))
+        });
+        registry.register("dense_activation", |s| {
+            let dense = DenseLayer::random(s.parse("inputs")?, s.parse("neurons")?).with_layout(layout(s)?);
+            let dense = if s.parse_or("bias", true)? {
+                dense
+            } else {
+                dense.without_bias()
+            };
+            Ok(Box::new(DenseActivationLayer::new(dense, activation(&s.parse::<String>("activation")?)?)))
+        });
+        registry.register("maxout", |s| {
+            let layer = MaxoutLayer::random_with(s.parse("inputs")?,
+                                                 s.parse("units")?,
+                                                 s.parse("pieces")?,
+                                                 &mut SeededRng::from_entropy());
+            Ok(Box::new(layer))
+        });
+        registry.register("rbf", |s| {
+            let layer = RBFLayer::random_with(s.parse("inputs")?,
+                                              s.parse("units")?,
+                                              s.parse_or("width", 1.0)?,
+                                              &mut SeededRng::from_entropy());
+            Ok(Box::new(layer))
+        });
+        registry.register("tanh", |s| Ok(Box::new(HyperbolicLayer { size: s.parse("size")? })));
+        registry.register("sigmoid", |s| Ok(Box::new(SigmoidLayer { size: s.parse("size")? })));
+        registry.register("relu", |s| Ok(Box::new(RectifiedLayer { size: s.parse("size")? })));
+        registry.register("selu", |s| Ok(Box::new(SELULayer { size: s.parse("size")? })));
+        registry.register("step", |s| Ok(Box::new(StepLayer { size: s.parse("size")? })));
+        registry.register("sign", |s| Ok(Box::new(SignLayer { size: s.parse("size")? })));
+        registry.register("alpha_dropout", |s| {
+            Ok(Box::new(AlphaDropoutLayer::new(s.parse("size")?, s.parse("rate")?, RngProvider::from_entropy())))
+        });
+        registry.register("reparameterize", |s| {
+            Ok(Box::new(ReparameterizeLayer::new(s.parse("size")?,
+                                                 s.parse_or("kl_weight", 1.0)?,
+                                                 RngProvider::from_entropy())))
+        });
+        registry.register("mask", |s| {
+            Ok(Box::new(MaskLayer::new(s.parse("steps")?, s.parse("features")?)))
+        });
+        registry.register("attention", |s| {
+            Ok(Box::new(AttentionLayer {
+                steps: s.parse("steps")?,
+                dim: s.parse("dim")?,
+                scaled: s.parse_or("scaled", true)?,
+            }))
+        });
+        registry
+    }
+
+    /// A registry without any layers.
+    pub fn empty() -> LayerRegistry {
+        LayerRegistry { constructors: HashMap::new() }
+    }
+
+    /// Registers the constructor of a kind of layer, replacing any
+    /// previous constructor of that kind.
+    pub fn register<F>(&mut self, kind: &str, constructor: F)
+        where F: Fn(&LayerSpec) -> Result<Box<dyn WeightedLayer>, String> + Send + Sync + 'static
+    {
+        self.constructors.insert(kind.to_string(), Box::new(constructor));
+    }
+
+    /// Constructs a layer from its spec.
+    pub fn build(&self, spec: &LayerSpec) -> Result<Box<dyn WeightedLayer>, String> {
+        match self.constructors.get(&spec.kind) {
+            Some(constructor) => constructor(spec),
+            None => Err(format!("unknown layer kind `{}`", spec.kind)),
+        }
+    }
+
+    /// Builds a newly initialized network from an architecture spec
+    /// with one layer spec per line or separated by `;`. Empty lines
+    /// and text after `#` are ignored.
+    pub fn parse_spec(&self, spec: &str) -> Result<LinkedList<Box<dyn WeightedLayer>>, ParseError> {
+        let mut layers = LinkedList::new();
+        for (i, line) in spec.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            for part in line.split(';').filter(|p| !p.trim().is_empty()) {
+                let error = |message| ParseError { line: i + 1, message };
+                let layer_spec = part.parse().map_err(error)?;
+                layers.push_back(self.build(&layer_spec).map_err(error)?);
+            }
+        }
+        check_shapes(&layers).map_err(|message| ParseError { line: 0, message })?;
+        Ok(layers)
+    }
+
+    /// Loads a network written by `save`.
+    pub fn load<R: BufRead>(&self, input: R) -> io::Result<LinkedList<Box<dyn WeightedLayer>>> {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        let mut lines = input.lines().enumerate();
+        let header = match lines.next() {
+            Some((_, line)) => line?,
+            None => String::new(),
+        };
+        if header.trim() != HEADER {
+            return Err(ParseError { line: 1, message: format!("expected `{}`", HEADER) }.into());
+        }
+
+        for (i, line) in lines {
+            let line = line?;
+            let error = |message| ParseError { line: i + 1, message };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (keyword, rest) = line.split_at(line.find(' ').unwrap_or(line.len()));
+            match keyword {
+                "layer" => {
+                    let spec = rest.parse().map_err(error)?;
+                    layers.push_back(self.build(&spec).map_err(error)?);
+                }
+                "name" => {
+                    let layer = layers.pop_back().ok_or_else(|| error("name before any layer".to_string()))?;
+                    layers.push_back(Box::new(NamedLayer::new(rest.trim(), layer)));
+                }
+                "weights" | "bias" => {
+                    let layer = layers.back_mut().ok_or_else(|| error(format!("{} before any layer", keyword)))?;
+                    let values = rest.split_whitespace()
+                        .map(|v| v.parse::<f32>())
+                        .collect::<Result<Vec<f32>, _>>()
+                        .map_err(|e| error(e.to_string()))?;
+                    let target = if keyword == "weights" {
+                        layer.weights_mut()
+                    } else {
+                        layer.bias_mut().map(WeightsMut::Borrowed)
+                    };
+                    match target {
+                        Some(ref t) if t.len() != values.len() => {
+                            return Err(error(format!("expected {} {} values, got {}", t.len(), keyword, values.len()))
+                                .into())
+                        }
+                        Some(mut t) => t.copy_from_slice(&values),
+                        None => return Err(error(format!("the layer has no {}", keyword)).into()),
+                    }
+                }
+                "mask" => {
+                    let layer = layers.back_mut().ok_or_else(|| error("mask before any layer".to_string()))?;
+                    let mask = rest.split_whitespace()
+                        .map(|v| match v {
+                            "1" => Ok(true),
+                            "0" => Ok(false),
+                            _ => Err(error(format!("expected 0 or 1 in the mask, got `{}`", v))),
+                        })
+                        .collect::<Result<Vec<bool>, _>>()?;
+                    if mask.len() != layer.weight_count() {
+                        return Err(error(format!("expected {} mask values, got {}", layer.weight_count(), mask.len()))
+                            .into());
+                    }
+                    layer.set_weight_mask(Some(mask));
+                    if layer.weight_mask().is_none() {
+                        return Err(error("the layer has no weight mask".to_string()).into());
+                    }
+                }
+                _ => return Err(error(format!("unexpected `{}`", keyword)).into()),
+            }
+        }
+        check_shapes(&layers).map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))?;
+        Ok(layers)
+    }
+
+    /// Loads a network and the state of its training written by
+    /// `save_checkpoint`.
+    pub fn load_checkpoint<R: BufRead>(&self,
+                                       input: R)
+                                       -> io::Result<(LinkedList<Box<dyn WeightedLayer>>, TrainingState)> {
+        let lines = input.lines().collect::<io::Result<Vec<String>>>()?;
+        let start = lines.iter()
+            .position(|l| l.split_whitespace().next() == Some("training"))
+            .ok_or_else(|| ParseError { line: lines.len(), message: "expected a training state".to_string() })?;
+        let layers = self.load(lines[..start].join("\n").as_bytes())?;
+        let state = parse_state(&lines[start..], start + 1)?;
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        if state.updates.len() != layers.len() {
+            return Err(invalid(format!("there are updates for {} layers but the network has {}",
+                                       state.updates.len(),
+                                       layers.len())));
+        }
+        for (i, (u, l)) in state.updates.iter().zip(layers.iter()).enumerate() {
+            let zeros = LayerUpdates::zeros(l.as_ref());
+            if u.ws.len() != zeros.ws.len() || u.bs.len() != zeros.bs.len() {
+                return Err(invalid(format!("the updates of layer {} do not fit its parameters", i)));
+            }
+        }
+        Ok((layers, state))
+    }
+
+    /// Loads a network written by `to_safetensors`, which must have
+    /// stored the architecture spec of the network.
+    #[cfg(feature = "safetensors")]
+    pub fn load_safetensors(&self, data: &[u8]) -> io::Result<LinkedList<Box<dyn WeightedLayer>>> {
+        let (_, meta) = SafeTensors::read_metadata(data).map_err(safetensors_error)?;
+        let meta = meta.metadata().clone().unwrap_or_default();
+        let spec = meta.get(SPEC_KEY).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("the metadata has no `{}`", SPEC_KEY))
+        })?;
+        let mut layers = self.parse_spec(spec)?;
+        if let Some(names) = meta.get(NAMES_KEY) {
+            layers = layers.into_iter()
+                .zip(names.split('\n').chain(std::iter::repeat("")))
+                .map(|(l, name)| if name.is_empty() {
+                    l
+                } else {
+                    Box::new(NamedLayer::new(name, l)) as Box<dyn WeightedLayer>
+                })
+                .collect();
+        }
+        read_safetensors(&mut layers, data)?;
+        Ok(layers)
+    }
+}
+
+impl Default for LayerRegistry {
+    fn default() -> LayerRegistry {
+        LayerRegistry::new()
+    }
+}
+
+/// The architecture spec of a network, which `LayerRegistry::parse_spec`
+/// turns into a new network of the same shape. Fails if a layer has no
+/// spec.
+pub fn to_spec(layers: &LinkedList<Box<dyn WeightedLayer>>) -> io::Result<String> {
+    let mut spec = String::new();
+    for (i, l) in layers.iter().enumerate() {
+        spec.push_str(&layer_spec(i, l.as_ref())?.to_string());
+        spec.push('\n');
+    }
+    Ok(spec)
+}
+
+/// Writes the specs and values of all layers, together with their
+/// weight masks, which can be loaded with `LayerRegistry::load`. Fails
+/// if a layer has no spec.
+pub fn save<W: Write>(layers: &LinkedList<Box<dyn WeightedLayer>>, out: &mut W) -> io::Result<()> {
+    writeln!(out, "{}", HEADER)?;
+    for (i, l) in layers.iter().enumerate() {
+        writeln!(out, "layer {}", layer_spec(i, l.as_ref())?)?;
+        if let Some(name) = l.name() {
+            writeln!(out, "name {}", name)?;
+        }
+        if let Some(w) = l.weights() {
+            write_values(out, "weights", &w)?;
+        }
+        if let Some(m) = l.weight_mask() {
+            let mask: Vec<u8> = m.iter().map(|keep| *keep as u8).collect();
+            write_values(out, "mask", &mask)?;
+        }
+        if let Some(b) = l.bias() {
+            write_values(out, "bias", b)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the network like `save`, followed by the state of its
+/// training, which can be loaded with `LayerRegistry::load_checkpoint`.
+pub fn save_checkpoint<W: Write>(layers: &LinkedList<Box<dyn WeightedLayer>>,
+                                 state: &TrainingState,
+                                 out: &mut W)
+                                 -> io::Result<()> {
+    save(layers, out)?;
+    let training = LayerSpec::new("training")
+        .with("epoch", state.epoch)
+        .with("batch", state.batch)
+        .with("rate", state.rate)
+        .with("pending", state.pending)
+        .with("loss", state.epoch_loss)
+        .with("converged", state.converged);
+    writeln!(out, "{}", training)?;
+    if let Some(ref p) = state.plateau {
+        let plateau = LayerSpec::new("plateau")
+            .with("factor", p.factor)
+            .with("patience", p.patience)
+            .with("threshold", p.threshold)
+            .with("min_rate", p.min_rate)
+            .with("best", p.best)
+            .with("waited", p.waited);
+        writeln!(out, "{}", plateau)?;
+    }
+    write_values(out, "rng", &state.rng)?;
+    if let Some(ref rng) = state.augmentation_rng {
+        write_values(out, "augmentation_rng", rng)?;
+    }
+    write_values(out, "order", &state.order)?;
+    for u in &state.updates {
+        writeln!(out, "{}", LayerSpec::new("updates").with("count", u.count))?;
+        write_values(out, "weights", &u.ws)?;
+        write_values(out, "bias", &u.bs)?;
+    }
+    Ok(())
+}
+
+/// Parses the training state of a checkpoint, which starts with the
+/// `training` line at line `first`.
+fn parse_state(lines: &[String], first: usize) -> Result<TrainingState, ParseError> {
+    let training: LayerSpec = lines[0].parse().map_err(|message| ParseError { line: first, message })?;
+    let header = || -> Result<TrainingState, String> {
+        Ok(TrainingState {
+            epoch: training.parse("epoch")?,
+            batch: training.parse("batch")?,
+            rate: training.parse("rate")?,
+            rng: [0; 4],
+            augmentation_rng: None,
+            order: Vec::new(),
+            updates: Vec::new(),
+            pending: training.parse("pending")?,
+            epoch_loss: training.parse("loss")?,
+            converged: training.parse("converged")?,
+            plateau: None,
+        })
+    };
+    let mut state = header().map_err(|message| ParseError { line: first, message })?;
+
+    for (i, line) in lines.iter().enumerate().skip(1) {
+        let error = |message| ParseError { line: first + i, message };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (keyword, rest) = line.split_at(line.find(' ').unwrap_or(line.len()));
+        match keyword {
+            "plateau" => state.plateau = Some(plateau_state(line).map_err(error)?),
+            "rng" => state.rng = generator_state(rest).map_err(error)?,
+            "augmentation_rng" => state.augmentation_rng = Some(generator_state(rest).map_err(error)?),
+            "order" => state.order = parse_values(rest).map_err(error)?,
+            "updates" => {
+                let spec: LayerSpec = line.parse().map_err(error)?;
+                state.updates.push(LayerUpdates {
+                    ws: Vec::new(),
+                    bs: Vec::new(),
+                    count: spec.parse("count").map_err(error)?,
+                });
+            }
+            "weights" | "bias" => {
+                let updates = state.updates.last_mut().ok_or_else(|| error(format!("{} before any updates", keyword)))?;
+                let values = parse_values(rest).map_err(error)?;
+                if keyword == "weights" {
+                    updates.ws = values;
+                } else {
+                    updates.bs = values;
+                }
+            }
+            _ => return Err(error(format!("unexpected `{}`", keyword))),
+        }
+    }
+    if state.rng == [0; 4] {
+        return Err(ParseError { line: first, message: "the state of the generator is missing".to_string() });
+    }
+    Ok(state)
+}
+
+/// A plateau schedule along with its progress.
+fn plateau_state(line: &str) -> Result<ReduceOnPlateau, String> {
+    let spec: LayerSpec = line.parse()?;
+    let factor: f32 = spec.parse("factor")?;
+    if !(factor > 0.0 && factor < 1.0) {
+        return Err("the factor of a plateau schedule must be between 0 and 1".to_string());
+    }
+    Ok(ReduceOnPlateau {
+        threshold: spec.parse("threshold")?,
+        min_rate: spec.parse("min_rate")?,
+        best: spec.parse("best")?,
+        waited: spec.parse("waited")?,
+        ..ReduceOnPlateau::new(factor, spec.parse("patience")?)
+    })
+}
+
+/// The state of a `SeededRng`, four numbers which are not all zero.
+fn generator_state(values: &str) -> Result<[u32; 4], String> {
+    match parse_values::<u32>(values)?[..] {
+        [0, 0, 0, 0] => Err("the state of a generator must not be all zeros".to_string()),
+        [a, b, c, d] => Ok([a, b, c, d]),
+        _ => Err("the state of a generator has four numbers".to_string()),
+    }
+}
+
+fn parse_values<T: FromStr>(values: &str) -> Result<Vec<T>, String>
+    where T::Err: fmt::Display
+{
+    values.split_whitespace().map(|v| v.parse().map_err(|e: T::Err| format!("invalid value `{}`: {}", v, e))).collect()
+}
+
+/// The metadata keys of the architecture spec and the layer names in
+/// safetensors data.
+#[cfg(feature = "safetensors")]
+const SPEC_KEY: &str = "scarecrow_spec";
+#[cfg(feature = "safetensors")]
+const NAMES_KEY: &str = "scarecrow_names";
+
+#[cfg(feature = "safetensors")]
+fn safetensors_error(e: safetensors::SafeTensorError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+}
+
+/// The weights and biases of every layer in the safetensors format, as
+/// 32 bit floats named like `network::named_parameters`, such as
+/// "0.weight" or "encoder.bias". When every layer has a spec, the
+/// architecture spec and the names of the layers are stored in the
+/// metadata, so that `LayerRegistry::load_safetensors` can construct
+/// the network again.
+#[cfg(feature = "safetensors")]
+pub fn to_safetensors(layers: &LinkedList<Box<dyn WeightedLayer>>) -> io::Result<Vec<u8>> {
+    let tensors: Vec<(String, Vec<usize>, Vec<u8>)> = network::named_parameters(layers)
+        .map(|(name, p)| (name, p.shape, p.values.iter().flat_map(|v| v.to_le_bytes()).collect()))
+        .collect();
+    let views = tensors.iter()
+        .map(|(name, shape, bytes)| {
+            TensorView::new(Dtype::F32, shape.clone(), bytes).map(|v| (name.as_str(), v))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(safetensors_error)?;
+
+    let metadata = to_spec(layers).ok().map(|spec| {
+        let names: Vec<&str> = layers.iter().map(|l| l.name().unwrap_or("")).collect();
+        let mut metadata = HashMap::new();
+        metadata.insert(SPEC_KEY.to_string(), spec);
+        metadata.insert(NAMES_KEY.to_string(), names.join("\n"));
+        metadata
+    });
+    safetensors::serialize(views, &metadata).map_err(safetensors_error)
+}
+
+/// Sets the weights and biases of the network from safetensors data,
+/// matching them by their names as given by `to_safetensors`. Tensors
+/// which the network has no parameter for are ignored. Fails if a
+/// parameter is missing, or is not a tensor of 32 bit floats of the
+/// same size.
+#[cfg(feature = "safetensors")]
+pub fn read_safetensors(layers: &mut LinkedList<Box<dyn WeightedLayer>>, data: &[u8]) -> io::Result<()> {
+    let tensors = SafeTensors::deserialize(data).map_err(safetensors_error)?;
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    for (i, l) in layers.iter_mut().enumerate() {
+        let prefix = l.name().map_or(i.to_string(), |n| n.to_string());
+        let has_weights = l.weights().is_some();
+        let has_bias = l.bias().is_some();
+        for (suffix, wanted) in [("weight", has_weights), ("bias", has_bias)].iter() {
+            if !wanted {
+                continue;
+            }
+            let name = format!("{}.{}", prefix, suffix);
+            let tensor = tensors.tensor(&name).map_err(|_| invalid(format!("there is no tensor `{}`", name)))?;
+            if tensor.dtype() != Dtype::F32 {
+                return Err(invalid(format!("`{}` has type {:?} instead of F32", name, tensor.dtype())));
+            }
+            let values: Vec<f32> = tensor.data()
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            let target = if *suffix == "weight" {
+                l.weights_mut()
+            } else {
+                l.bias_mut().map(WeightsMut::Borrowed)
+            };
+            match target {
+                Some(mut t) if t.len() == values.len() => t.copy_from_slice(&values),
+                Some(t) => return Err(invalid(format!("`{}` has {} values instead of {}", name, values.len(), t.len()))),
+                None => return Err(invalid(format!("the parameters of `{}` cannot be set", name))),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn layer_spec(index: usize, layer: &dyn WeightedLayer) -> io::Result<LayerSpec> {
+    layer.spec().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput,
+                       format!("layer {} has no spec to be saved by, like tied, transposed and sparse layers",
+                               index))
+    })
+}
+
+fn write_values<W: Write, T: fmt::Display>(out: &mut W, keyword: &str, values: &[T]) -> io::Result<()> {
+    write!(out, "{}", keyword)?;
+    for v in values {
+        write!(out, " {}", v)?;
+    }
+    writeln!(out)
+}
+
+/// Checks that the outputs of every layer fit the inputs of the next.
+fn check_shapes(layers: &LinkedList<Box<dyn WeightedLayer>>) -> Result<(), String> {
+    for (i, (a, b)) in layers.iter().zip(layers.iter().skip(1)).enumerate() {
+        if a.output_count() != b.input_count() {
+            return Err(format!("layer {} outputs {} values but layer {} expects {}",
+                               i,
+                               a.output_count(),
+                               i + 1,
+                               b.input_count()));
+        }
+    }
+    Ok(())
+}
+
+fn activation(name: &str) -> Result<Activation, String> {
+    Activation::from_name(name).ok_or_else(|| format!("unknown activation `{}`", name))
+}
+
+/// The optional weight layout of a dense layer.
+fn layout(spec: &LayerSpec) -> Result<WeightLayout, String> {
+    match spec.get("layout") {
+        Some(name) => WeightLayout::from_name(name).ok_or_else(|| format!("unknown weight layout `{}`", name)),
+        None => Ok(WeightLayout::NeuronMajor),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network;
+    use traits::Layer;
+
+    #[test]
+    #[cfg(feature = "safetensors")]
+    fn safetensors_round_trip() {
+        let registry = LayerRegistry::new();
+        let mut rng = SeededRng::new(1);
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(NamedLayer::new("hidden", Box::new(DenseLayer::random_with(2, 3, &mut rng)))));
+        layers.push_back(Box::new(HyperbolicLayer { size: 3 }));
+        layers.push_back(Box::new(DenseLayer::random_with(3, 1, &mut rng)));
+        let data = to_safetensors(&layers).unwrap();
+
+        let tensors = SafeTensors::deserialize(&data).unwrap();
+        let mut names = tensors.names();
+        names.sort();
+        assert_eq!(names, vec!["2.bias", "2.weight", "hidden.bias", "hidden.weight"]);
+        assert_eq!(tensors.tensor("hidden.weight").unwrap().shape(), &[3, 2]);
+
+        let loaded = registry.load_safetensors(&data).unwrap();
+        assert_eq!(loaded.front().unwrap().name(), Some("hidden"));
+        assert_eq!(network::get_weights(&loaded), network::get_weights(&layers));
+
+        let mut other = registry.parse_spec(&to_spec(&layers).unwrap()).unwrap();
+        assert!(read_safetensors(&mut other, &data).is_err());
+        let mut other: LinkedList<Box<dyn WeightedLayer>> = other.into_iter()
+            .enumerate()
+            .map(|(i, l)| if i == 0 { Box::new(NamedLayer::new("hidden", l)) as Box<dyn WeightedLayer> } else { l })
+            .collect();
+        read_safetensors(&mut other, &data).unwrap();
+        assert_eq!(network::get_weights(&other), network::get_weights(&layers));
+    }
+
+    #[test]
+    fn spec_round_trip() {
+        let spec: LayerSpec = "dense inputs=2 neurons=6".parse().unwrap();
+        assert_eq!(spec, LayerSpec::new("dense").with("inputs", 2).with("neurons", 6));
+        assert_eq!(spec.to_string(), "dense inputs=2 neurons=6");
+        assert_eq!(spec.parse::<usize>("neurons"), Ok(6));
+        assert!(spec.parse::<usize>("units").is_err());
+        assert!("dense inputs".parse::<LayerSpec>().is_err());
+    }
+
+    #[test]
+    fn parse_architecture() {
+        let registry = LayerRegistry::new();
+        let layers = registry.parse_spec("dense inputs=2 neurons=6; tanh size=6\n\
+                                          # the output\n\
+                                          dense inputs=6 neurons=1 bias=false\n\
+                                          sigmoid size=1")
+            .unwrap();
+        assert_eq!(layers.len(), 4);
+        assert_eq!(layers.back().unwrap().spec(), Some(LayerSpec::new("sigmoid").with("size", 1)));
+        assert_eq!(layers.iter().nth(2).unwrap().bias(), None);
+
+        let err = registry.parse_spec("dense inputs=2 neurons=6\nconv size=3").err().unwrap();
+        assert_eq!(err.line, 2);
+        assert!(registry.parse_spec("dense inputs=2 neurons=6; tanh size=5").is_err());
+        assert!(registry.parse_spec("dense inputs=2 neurons=6 layout=diagonal").is_err());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let registry = LayerRegistry::new();
+        let layers = registry.parse_spec("dense inputs=2 neurons=3\n\
+                                          relu size=3\n\
+                                          dense_activation inputs=3 neurons=2 activation=sigmoid layout=inputs\n\
+                                          maxout inputs=2 units=1 pieces=2\n\
+                                          rbf inputs=1 units=2")
+            .unwrap();
+
+        let mut saved = Vec::new();
+        save(&layers, &mut saved).unwrap();
+        let loaded = registry.load(&saved[..]).unwrap();
+
+        assert_eq!(network::get_weights(&loaded), network::get_weights(&layers));
+        assert_eq!(to_spec(&loaded).unwrap(), to_spec(&layers).unwrap());
+        assert_eq!(network::output(&loaded, &[0.5, -1.0]), network::output(&layers, &[0.5, -1.0]));
+    }
+
+    #[test]
+    fn masks_are_saved() {
+        let registry = LayerRegistry::new();
+        let mut layers = registry.parse_spec("dense inputs=2 neurons=2; alpha_dropout size=2 rate=0.1").unwrap();
+        layers.front_mut().unwrap().set_weight_mask(Some(vec![true, false, false, true]));
+
+        let mut saved = Vec::new();
+        save(&layers, &mut saved).unwrap();
+        assert!(String::from_utf8(saved.clone()).unwrap().contains("\nmask 1 0 0 1\n"));
+        let loaded = registry.load(&saved[..]).unwrap();
+        assert_eq!(loaded.front().unwrap().weight_mask(), Some(&[true, false, false, true][..]));
+        assert_eq!(to_spec(&loaded).unwrap(), to_spec(&layers).unwrap());
+
+        let load = |mask: &str| registry.load(format!("scarecrow 1\nlayer dense inputs=1 neurons=2\n{}\n", mask).as_bytes());
+        assert!(load("mask 1 0").is_ok());
+        assert!(load("mask 1").is_err());
+        assert!(load("mask 1 2").is_err());
+        assert!(registry.load(&b"scarecrow 1\nlayer tanh size=1\nmask\n"[..]).is_err());
+    }
+
+    #[test]
+    fn layers_without_spec() {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(0.5, 2, 2).tied()));
+        let err = save(&layers, &mut Vec::new()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("layer 0 has no spec"));
+    }
+
+    #[test]
+    fn names_are_saved() {
+        let mut layers = LayerRegistry::new().parse_spec("dense inputs=2 neurons=1").unwrap();
+        let dense = layers.pop_back().unwrap();
+        layers.push_back(Box::new(NamedLayer::new("output", dense)));
+
+        let mut saved = Vec::new();
+        save(&layers, &mut saved).unwrap();
+        let loaded = LayerRegistry::new().load(&saved[..]).unwrap();
+        assert_eq!(network::find_layer(&loaded, "output").and_then(|l| l.weights()),
+                   layers.back().unwrap().weights());
+    }
+
+    #[test]
+    fn invalid_files() {
+        let registry = LayerRegistry::new();
+        assert!(registry.load(&b"layer tanh size=2\n"[..]).is_err());
+        let err = registry.load(&b"scarecrow 1\nlayer dense inputs=1 neurons=1\nweights 1 2\n"[..]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("line 3"));
+        assert!(registry.load(&b"scarecrow 1\nlayer tanh size=1\nbias 1\n"[..]).is_err());
+    }
+
+    #[test]
+    fn invalid_checkpoints() {
+        let registry = LayerRegistry::new();
+        let network = "scarecrow 1\nlayer dense inputs=1 neurons=1\nweights 1\nbias 0\n";
+        let training = "training epoch=1 batch=0 rate=0.1 pending=0 loss=0 converged=false\n";
+        let load = |rest: &str| registry.load_checkpoint(format!("{}{}", network, rest).as_bytes());
+
+        let (_, state) = load(&format!("{}rng 1 2 3 4\norder\nupdates count=0\nweights 0\nbias 0\n", training)).unwrap();
+        assert_eq!((state.epoch, state.rng, state.augmentation_rng), (1, [1, 2, 3, 4], None));
+        assert!(load("").err().unwrap().to_string().contains("expected a training state"));
+        let err = load(&format!("{}rng 0 0 0 0\n", training)).err().unwrap();
+        assert!(err.to_string().starts_with("line 6"));
+        assert!(load(&format!("{}rng 1 2 3 4\n", training)).is_err());
+        assert!(load(&format!("{}rng 1 2 3 4\nupdates count=0\nweights 0 0\nbias 0\n", training)).is_err());
+        assert!(load("training epoch=1\nrng 1 2 3 4\n").is_err());
+
+        let plateau = "plateau factor=0.5 patience=2 threshold=0.0001 min_rate=0 best=inf waited=1\n";
+        let rest = "rng 1 2 3 4\nupdates count=0\nweights 0\nbias 0\n";
+        let (_, state) = load(&format!("{}{}{}", training, plateau, rest)).unwrap();
+        assert_eq!(state.plateau.map(|p| (p.patience, p.best, p.waited)), Some((2, f32::INFINITY, 1)));
+        assert!(load(&format!("{}{}{}", training, plateau.replace("0.5", "2"), rest)).is_err());
+    }
+
+    struct Doubler {
+        size: usize,
+    }
+
+    impl Layer for Doubler {
+        fn input_count(&self) -> usize {
+            self.size
+        }
+        fn output_count(&self) -> usize {
+            self.size
+        }
+        fn output(&self, inputs: &[f32]) -> Vec<f32> {
+            inputs.iter().map(|x| 2.0 * x).collect()
+        }
+        fn spec(&self) -> Option<LayerSpec> {
+            Some(LayerSpec::new("doubler").with("size", self.size))
+        }
+    }
+
+    impl WeightedLayer for Doubler {}
+
+    #[test]
+    fn custom_layers() {
+        let mut registry = LayerRegistry::new();
+        assert!(registry.parse_spec("doubler size=2").is_err());
+
+        registry.register("doubler", |s| Ok(Box::new(Doubler { size: s.parse("size")? })));
+        let layers = registry.parse_spec("doubler size=2").unwrap();
+        let mut saved = Vec::new();
+        save(&layers, &mut saved).unwrap();
+        let loaded = registry.load(&saved[..]).unwrap();
+        assert_eq!(network::output(&loaded, &[1.0, 2.0]), vec![2.0, 4.0]);
+    }
+}
@@ -1,10 +1,23 @@
 //! Implementation of stochastic gradient descent.
 use loss::*;
 use utils::*;
-use layers::{LayerUpdates, LayerOut};
+use data::Dataset;
+use data::augment::Augmentation;
+use data::target::TargetTransform;
+use layers::LayerUpdates;
+use metrics::{self, Epoch, History, Metrics};
+use network;
+use random::RngProvider;
+use schedule::ReduceOnPlateau;
 use traits::{WeightedLayer, DifferentiableLossFunction, SupervisedTrainer};
 
+use rand::Rng;
+
+use std::borrow::Cow;
+use std::cmp;
 use std::collections::LinkedList;
+use std::error;
+use std::fmt;
 
 /// Stochastic gradient descent trainer.
 pub struct SGDTrainer {
@@ -12,96 +25,841 @@ pub struct SGDTrainer {
     pub rate: f32,
     /// The number of iterations to train
     pub epochs: usize,
+    /// The number of samples per batch. `None` means that the entire
+    /// dataset is used as a single batch.
+    pub batch_size: Option<usize>,
     /// The loss function to use
-    pub loss: Box<DifferentiableLossFunction>,
+    pub loss: Box<dyn DifferentiableLossFunction>,
+    /// How the gradients of a batch are combined
+    pub reduction: Reduction,
+    /// Source of randomness used during training
+    pub rng: RngProvider,
+    /// Transformation of the targets, such as `Standardize`. The
+    /// network learns the transformed targets, while `evaluate` and
+    /// `predict` work in the units of the original targets. `iter`
+    /// trains on the targets as given.
+    pub target_transform: Option<Box<dyn TargetTransform>>,
+    /// Indices of the layers which are frozen, whose weights and biases
+    /// are left as they are during training
+    pub frozen: Vec<usize>,
+    /// Reduces the learning rate during `fit` when the loss stops
+    /// improving
+    pub plateau: Option<ReduceOnPlateau>,
+    /// Whether to visit the samples in a new random order every epoch,
+    /// drawn from `rng`, so that the batches differ between epochs.
+    /// Off by default, which visits the samples in the order given.
+    pub shuffle: bool,
+    /// Perturbs the inputs of every batch before the forward pass, such
+    /// as with `augment::GaussianJitter`. Applied only while training;
+    /// `evaluate` and `predict` see the inputs as given.
+    pub augmentation: Option<Augmentation>,
+    /// The number of batches whose steps are summed before the weights
+    /// are updated, which trains like a batch this many times larger
+    /// without holding it at once. With `Reduction::Mean` the steps are
+    /// averaged over all of their samples. The last batches of an epoch
+    /// are applied even if there are fewer. 1 updates after every batch.
+    pub accumulate: usize,
+    /// Stops training after the first epoch whose average training loss
+    /// per sample is below this, rather than running all `epochs`. The
+    /// average is over the losses of its batches, as in `BatchResult`.
+    pub stop_loss: Option<f32>,
+}
+
+/// Everything besides the weights that decides how training goes on:
+/// where an `SGDIter` is in its epochs, the steps accumulated for the
+/// next update, the learning rate and its plateau schedule, and the
+/// states of the generators of the trainer and of its augmentation.
+/// Taken with `SGDIter::state` and continued with `SGDTrainer::resume`
+/// or `SGDTrainer::fit_resumable`, training goes on exactly as if it
+/// had not been stopped. Layers which draw random numbers, such as
+/// `AlphaDropoutLayer`, are covered if they share the `rng` of the
+/// trainer. `serialize::save_checkpoint` writes it with the network.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrainingState {
+    /// The epoch of the next batch, starting from 0
+    pub epoch: usize,
+    /// The index of the next batch within its epoch
+    pub batch: usize,
+    /// The learning rate of the next batch, which differs from that of
+    /// the trainer if it was changed with `SGDIter::set_rate`
+    pub rate: f32,
+    /// The state of the `rng` of the trainer
+    pub rng: [u32; 4],
+    /// The state of the `rng` of the augmentation, if there is one
+    pub augmentation_rng: Option<[u32; 4]>,
+    /// The order of the samples in the current epoch, if shuffled
+    pub order: Vec<usize>,
+    /// The steps accumulated since the last update, one per layer
+    pub updates: Vec<LayerUpdates>,
+    /// The number of batches whose steps are accumulated
+    pub pending: usize,
+    /// The summed loss of the samples of the current epoch so far
+    pub epoch_loss: f32,
+    /// Whether an epoch ended with a loss below `stop_loss`
+    pub converged: bool,
+    /// The plateau schedule of the trainer, with the best loss so far
+    /// and the epochs since it improved
+    pub plateau: Option<ReduceOnPlateau>,
+}
+
+/// The outcome of training on a single batch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchResult {
+    /// The epoch the batch belongs to, starting from 0.
+    pub epoch: usize,
+    /// The index of the batch within its epoch, starting from 0.
+    pub batch: usize,
+    /// The average loss per sample in the batch, calculated before
+    /// the weights were updated.
+    pub loss: f32,
+}
+
+/// A trainer configuration or training data which cannot be trained
+/// on, as found by `SGDTrainer::validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// The learning rate is not positive and finite
+    Rate(f32),
+    /// The number of epochs is zero
+    Epochs,
+    /// The network has no layers
+    EmptyNetwork,
+    /// There are no samples to train on
+    EmptyDataset,
+    /// The batch size is zero or larger than the number of samples
+    BatchSize { batch_size: usize, samples: usize },
+    /// The output of a layer does not fit the input of the next one
+    Layers { layer: usize, outputs: usize, inputs: usize },
+    /// The inputs do not divide into samples for the first layer
+    Inputs { values: usize, input_count: usize },
+    /// The targets do not divide into samples for the last layer
+    Targets { values: usize, output_count: usize },
+    /// The numbers of input and target samples differ
+    Samples { inputs: usize, targets: usize },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Rate(rate) => write!(f, "the learning rate must be positive and finite, not {}", rate),
+            ConfigError::Epochs => write!(f, "at least one epoch is needed"),
+            ConfigError::EmptyNetwork => write!(f, "the network has no layers"),
+            ConfigError::EmptyDataset => write!(f, "there are no samples to train on"),
+            ConfigError::BatchSize { batch_size, samples } => {
+                write!(f,
+                       "the batch size {} must be between 1 and the number of samples, {}",
+                       batch_size,
+                       samples)
+            }
+            ConfigError::Layers { layer, outputs, inputs } => {
+                write!(f,
+                       "layer {} gives {} outputs but layer {} expects {} inputs",
+                       layer,
+                       outputs,
+                       layer + 1,
+                       inputs)
+            }
+            ConfigError::Inputs { values, input_count } => {
+                write!(f,
+                       "{} input values do not divide into samples of {} inputs",
+                       values,
+                       input_count)
+            }
+            ConfigError::Targets { values, output_count } => {
+                write!(f,
+                       "{} target values do not divide into samples of {} outputs",
+                       values,
+                       output_count)
+            }
+            ConfigError::Samples { inputs, targets } => {
+                write!(f, "there are {} input samples but {} target samples", inputs, targets)
+            }
+        }
+    }
+}
+
+impl error::Error for ConfigError {}
+
+/// Iterator which trains the network one batch at a time. Created
+/// by `SGDTrainer::iter`.
+pub struct SGDIter<'a> {
+    trainer: &'a SGDTrainer,
+    layers: &'a mut LinkedList<Box<dyn WeightedLayer>>,
+    inputs: &'a [f32],
+    targets: &'a [f32],
+    epoch: usize,
+    batch: usize,
+    rate: f32,
+    /// The order of the samples in the current epoch, if shuffled
+    order: Vec<usize>,
+    /// Steps accumulated over the batches since the last update
+    updates: LinkedList<LayerUpdates>,
+    pending: usize,
+    /// The summed loss of the samples of the current epoch so far
+    epoch_loss: f32,
+    /// Whether the loss fell below `stop_loss`
+    converged: bool,
+    /// The plateau schedule of the trainer, which `fit` steps after
+    /// every epoch
+    plateau: Option<ReduceOnPlateau>,
 }
 
 impl SGDTrainer {
     pub fn new(epochs: usize, rate: f32) -> SGDTrainer {
         SGDTrainer {
-            rate: rate,
-            epochs: epochs,
+            rate,
+            epochs,
+            batch_size: None,
             loss: Box::new(SquaredError),
+            reduction: Reduction::Sum,
+            rng: RngProvider::from_entropy(),
+            target_transform: None,
+            frozen: Vec::new(),
+            plateau: None,
+            shuffle: false,
+            augmentation: None,
+            accumulate: 1,
+            stop_loss: None,
         }
     }
 
-    fn weight_step(&self, layer: &Box<WeightedLayer>, inputs: &[f32], delta: &[f32]) -> Vec<f32> {
-        let mut step = vec!(0.0; layer.weight_count());
-        if let Some(derivs) = layer.derivw(inputs) {
-            assert_eq!(derivs.len(), step.len());
-            assert_eq!(delta.len(), layer.neuron_count());
-            // Iterate per neuron and the contributions from later
-            // layers.
-            for (i, w) in step.iter_mut().enumerate() {
-                // Neuron index
-                let ni = i / layer.input_count();
-                *w -= self.rate * delta[ni] * derivs[i];
+    /// Stops training once the average training loss of an epoch drops
+    /// below `threshold`, see `stop_loss`.
+    pub fn stop_when_loss_below(mut self, threshold: f32) -> SGDTrainer {
+        self.stop_loss = Some(threshold);
+        self
+    }
+
+    /// Returns an iterator which performs one training step per
+    /// call to `next`, yielding the result of each batch. This lets
+    /// the caller control the training loop, for example to stop
+    /// early or to evaluate the network between batches. Yields
+    /// nothing for a network without layers or data without samples,
+    /// which `try_train` reports as errors.
+    pub fn iter<'a>(&'a self,
+                    layers: &'a mut LinkedList<Box<dyn WeightedLayer>>,
+                    inputs: &'a [f32],
+                    targets: &'a [f32])
+                    -> SGDIter<'a> {
+        let updates = self.new_updates(layers);
+        SGDIter {
+            trainer: self,
+            layers,
+            inputs,
+            targets,
+            epoch: 0,
+            batch: 0,
+            rate: self.rate,
+            order: Vec::new(),
+            updates,
+            pending: 0,
+            epoch_loss: 0.0,
+            converged: false,
+            plateau: self.plateau,
+        }
+    }
+
+    /// Continues training from a state taken with `SGDIter::state`, on
+    /// the same inputs and targets and with the same configuration.
+    /// Restores the generators of the trainer and its augmentation.
+    pub fn resume<'a>(&'a self,
+                      layers: &'a mut LinkedList<Box<dyn WeightedLayer>>,
+                      inputs: &'a [f32],
+                      targets: &'a [f32],
+                      state: &TrainingState)
+                      -> SGDIter<'a> {
+        assert_eq!(state.updates.len(), layers.len(), "the state must have updates for every layer");
+        self.rng.set_state(state.rng);
+        if let (Some(a), Some(s)) = (self.augmentation.as_ref(), state.augmentation_rng) {
+            a.rng.set_state(s);
+        }
+        SGDIter {
+            trainer: self,
+            layers,
+            inputs,
+            targets,
+            epoch: state.epoch,
+            batch: state.batch,
+            rate: state.rate,
+            order: state.order.clone(),
+            updates: state.updates.iter().cloned().collect(),
+            pending: state.pending,
+            epoch_loss: state.epoch_loss,
+            converged: state.converged,
+            plateau: state.plateau,
+        }
+    }
+
+    /// Like `fit`, and continues from `state` if it is given. After
+    /// every epoch, `checkpoint` is called with the network and the
+    /// state to continue from, such as to save them with
+    /// `serialize::save_checkpoint`. Resumed from such a state, with the
+    /// same data and configuration, training ends as if it had not been
+    /// stopped, and the history holds the epochs after the state.
+    pub fn fit_resumable<F>(&self,
+                            layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+                            train: &Dataset,
+                            validation: Option<&Dataset>,
+                            state: Option<&TrainingState>,
+                            mut checkpoint: F)
+                            -> History
+        where F: FnMut(&LinkedList<Box<dyn WeightedLayer>>, &TrainingState)
+    {
+        span!(INFO, "train", epochs = self.epochs);
+        let mut history = History::new();
+        let targets = self.transform_targets(layers, &train.targets);
+        let mut iter = match state {
+            Some(state) => self.resume(layers, &train.inputs, &targets, state),
+            None => self.iter(layers, &train.inputs, &targets),
+        };
+        while let Some(batch) = iter.next() {
+            if iter.epoch > batch.epoch {
+                let epoch = {
+                    let layers = network::eval_mode(iter.layers);
+                    Epoch {
+                        epoch: batch.epoch,
+                        train: self.evaluate(&layers, train),
+                        validation: validation.map(|v| self.evaluate(&layers, v)),
+                    }
+                };
+                iter.step_plateau(epoch.validation.as_ref().unwrap_or(&epoch.train).loss);
+                history.epochs.push(epoch);
+                checkpoint(iter.layers(), &iter.state());
             }
         }
-        step
+        history
     }
 
-    fn bias_step(&self, layer: &Box<WeightedLayer>, delta: &[f32]) -> Vec<f32> {
-        let mut step = vec!(0.0; layer.neuron_count());
-        // Iterate per neuron bias and contributions from later layers
-        for (b, ud) in step.iter_mut().zip(delta) {
-            *b -= self.rate * ud;
+    /// Checks the configuration of the trainer, that the layers fit
+    /// together, and that the inputs and targets fit the network,
+    /// before any training is done.
+    pub fn validate(&self,
+                    layers: &LinkedList<Box<dyn WeightedLayer>>,
+                    inputs: &[f32],
+                    targets: &[f32])
+                    -> Result<(), ConfigError> {
+        if !(self.rate > 0.0 && self.rate.is_finite()) {
+            return Err(ConfigError::Rate(self.rate));
+        }
+        if self.epochs == 0 {
+            return Err(ConfigError::Epochs);
+        }
+        if layers.is_empty() {
+            return Err(ConfigError::EmptyNetwork);
+        }
+        for (i, (a, b)) in layers.iter().zip(layers.iter().skip(1)).enumerate() {
+            if a.output_count() != b.input_count() {
+                return Err(ConfigError::Layers {
+                    layer: i,
+                    outputs: a.output_count(),
+                    inputs: b.input_count(),
+                });
+            }
+        }
+
+        let input_count = layers.front().map(|l| l.input_count()).unwrap_or(0);
+        let output_count = self.loss.target_count(layers.back().map(|l| l.output_count()).unwrap_or(0));
+        if input_count == 0 || !inputs.len().is_multiple_of(input_count) {
+            return Err(ConfigError::Inputs {
+                values: inputs.len(),
+                input_count,
+            });
+        }
+        if output_count == 0 || !targets.len().is_multiple_of(output_count) {
+            return Err(ConfigError::Targets {
+                values: targets.len(),
+                output_count,
+            });
+        }
+        let samples = inputs.len() / input_count;
+        if samples != targets.len() / output_count {
+            return Err(ConfigError::Samples {
+                inputs: samples,
+                targets: targets.len() / output_count,
+            });
+        }
+        if samples == 0 {
+            return Err(ConfigError::EmptyDataset);
+        }
+        match self.batch_size {
+            Some(batch_size) if batch_size == 0 || batch_size > samples => {
+                Err(ConfigError::BatchSize { batch_size, samples })
+            }
+            _ => Ok(()),
         }
-        step
     }
-}
 
-impl SupervisedTrainer for SGDTrainer {
-    fn train(&self, layers: &mut LinkedList<Box<WeightedLayer>>, inputs: &[f32], targets: &[f32]) {
+    /// Validates the configuration and data with `validate`, and
+    /// trains only if they are valid.
+    pub fn try_train(&self,
+                     layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+                     inputs: &[f32],
+                     targets: &[f32])
+                     -> Result<(), ConfigError> {
+        self.validate(layers, inputs, targets)?;
+        self.train(layers, inputs, targets);
+        Ok(())
+    }
+
+    /// The targets after the target transformation, if there is one.
+    fn transform_targets<'a>(&self,
+                             layers: &LinkedList<Box<dyn WeightedLayer>>,
+                             targets: &'a [f32])
+                             -> Cow<'a, [f32]> {
+        match (self.target_transform.as_ref(), layers.back()) {
+            (Some(t), Some(l)) => Cow::Owned(t.forward_all(targets, self.loss.target_count(l.output_count()))),
+            _ => Cow::Borrowed(targets),
+        }
+    }
+
+    /// Creates zeroed updates matching the shape of the layers.
+    pub(crate) fn new_updates(&self, layers: &LinkedList<Box<dyn WeightedLayer>>) -> LinkedList<LayerUpdates> {
+        layers.iter().map(|l| LayerUpdates::zeros(l.as_ref())).collect()
+    }
+
+    /// Propagates the delta signal of the loss backwards through the
+    /// layers and adds the resulting steps to the updates. Returns the
+    /// delta signal at the inputs of the first layer.
+    pub(crate) fn backward(&self,
+                           layers: &LinkedList<Box<dyn WeightedLayer>>,
+                           activations: &Activations,
+                           delta: Vec<f32>,
+                           updates: &mut LinkedList<LayerUpdates>)
+                           -> Vec<f32> {
+        self.backward_at(self.rate, layers, activations, delta, updates)
+    }
+
+    /// Like `backward`, with steps of the given learning rate instead of
+    /// that of the trainer.
+    fn backward_at(&self,
+                   rate: f32,
+                   layers: &LinkedList<Box<dyn WeightedLayer>>,
+                   activations: &Activations,
+                   delta: Vec<f32>,
+                   updates: &mut LinkedList<LayerUpdates>)
+                   -> Vec<f32> {
+        span!(TRACE, "backward");
+        let mut delta_signal = delta;
+        for (i, ((l, (inputs, output)), lu)) in layers.iter()
+            .zip(activations.iter())
+            .zip(updates.iter_mut())
+            .enumerate()
+            .rev() {
+            network::check_layer_len("backward", i, l.as_ref(), "delta values", l.output_count(), delta_signal.len());
+            let (input_delta, grads) = l.backward(&delta_signal, inputs, output);
+            for (u, g) in lu.ws.iter_mut().zip(&grads.weights).chain(lu.bs.iter_mut().zip(&grads.bias)) {
+                *u += g * -rate;
+            }
+            delta_signal = input_delta;
+        }
+        delta_signal
+    }
+
+    /// Applies updates accumulated over `samples` samples to the
+    /// layers, averaging them if the reduction is `Mean`.
+    pub(crate) fn apply_updates(&self,
+                                layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+                                updates: &mut LinkedList<LayerUpdates>,
+                                samples: usize) {
+        span!(TRACE, "update");
+        let scale = if self.reduction == Reduction::Mean && samples > 0 {
+            1.0 / samples as f32
+        } else {
+            1.0
+        };
+        for (i, (l, lu)) in layers.iter_mut().zip(updates.iter()).enumerate() {
+            if self.frozen.contains(&i) {
+                continue;
+            }
+            network::check_layer_len("update", i, l.as_ref(), "weight updates", l.weight_count(), lu.ws.len());
+            lu.apply(l.as_mut(), scale);
+        }
+    }
+
+    /// Adds the steps of a single batch with the given learning rate to
+    /// the updates, without applying them, and returns the average loss
+    /// per sample. The layers are in training mode during the batch,
+    /// and are switched back to their previous mode afterwards.
+    fn accumulate_batch(&self,
+                        rate: f32,
+                        layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+                        inputs: &[f32],
+                        targets: &[f32],
+                        updates: &mut LinkedList<LayerUpdates>)
+                        -> f32 {
         let input_count = layers.front().map(|l| l.input_count()).unwrap_or(0);
-        let output_count = layers.back().map(|l| l.output_count()).unwrap_or(0);
-
-        for _ in 0..self.epochs {
-            let mut updates: LinkedList<LayerUpdates> = LinkedList::new();
-            for l in layers.iter() {
-                let ws = vec![0.0; l.weight_count()];
-                let bs = vec![0.0; l.neuron_count()];
-                updates.push_back(LayerUpdates { ws: ws, bs: bs });
+        let target_count = self.loss.target_count(layers.back().map(|l| l.output_count()).unwrap_or(0));
+
+        let layers = &mut *network::train_mode(layers);
+
+        let mut total_loss = 0.0;
+        let mut samples = 0;
+        for (x, t) in inputs.chunks(input_count).zip(targets.chunks(target_count)) {
+            let activations = forward(layers, x);
+
+            // Calculate error differential
+            let y = activations.last();
+            total_loss += self.loss.loss_vec(y, t);
+            let delta_signal = self.loss.deriv_vec(y, t);
+            samples += 1;
+
+            self.backward_at(rate, layers, &activations, delta_signal, updates);
+        }
+
+        for lu in updates.iter_mut() {
+            lu.count += samples;
+        }
+
+        if samples > 0 {
+            total_loss / samples as f32
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Gradient of the loss with respect to the weights of a layer, given
+/// the inputs to the layer and the delta signal at its neurons, as
+/// returned by `Layer::neuron_delta`.
+pub fn weight_gradient<L: WeightedLayer + ?Sized>(layer: &L, inputs: &[f32], delta: &[f32]) -> Vec<f32> {
+    if let Some(grad) = layer.weight_grad(inputs, delta) {
+        return grad;
+    }
+    match layer.derivw(inputs) {
+        Some(derivs) => {
+            assert_eq!(derivs.len(), layer.weight_count(), "derivw gave a different number of values than weights");
+            assert_eq!(delta.len(), layer.neuron_count(), "the delta signal must have one value per neuron");
+            // One row of derivatives per neuron, scaled by the delta
+            // signal of that neuron.
+            Matrix::view(&derivs, layer.neuron_count(), layer.input_count()).scale_rows(delta).into_vec()
+        }
+        None => vec![0.0; layer.weight_count()],
+    }
+}
+
+/// Gradient of the loss with respect to the biases of a layer, given
+/// the delta signal at its neurons.
+pub fn bias_gradient<L: WeightedLayer + ?Sized>(layer: &L, delta: &[f32]) -> Vec<f32> {
+    if let Some(grad) = layer.bias_grad(delta) {
+        return grad;
+    }
+    let mut grad = vec!(0.0; layer.neuron_count());
+    // Iterate per neuron bias and contributions from later layers
+    for (b, ud) in grad.iter_mut().zip(delta) {
+        *b += ud;
+    }
+    grad
+}
+
+/// Gradients of the loss with respect to the weights and biases of a
+/// single layer. Layers without weights or biases have empty gradients.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerGrads {
+    pub weights: Vec<f32>,
+    pub bias: Vec<f32>,
+}
+
+impl LayerGrads {
+    /// Adds the gradients of the same layer for another sample or
+    /// another loss.
+    pub fn add(&mut self, other: &LayerGrads) {
+        add_mut(&mut self.weights, &other.weights);
+        add_mut(&mut self.bias, &other.bias);
+    }
+
+    /// Multiplies the gradients by `factor`, such as to average them or
+    /// to weigh one loss against another.
+    pub fn scale(&mut self, factor: f32) {
+        for g in self.weights.iter_mut().chain(self.bias.iter_mut()) {
+            *g *= factor;
+        }
+    }
+}
+
+/// The gradients of the loss of a single sample with respect to the
+/// weights and biases of every layer, in the order of the layers. The
+/// layers are left as they are, so that the gradients can be inspected,
+/// combined with those of other samples or losses, and applied with
+/// `apply_gradients` or by an optimizer of one's own.
+pub fn backward(layers: &LinkedList<Box<dyn WeightedLayer>>,
+                x: &[f32],
+                t: &[f32],
+                loss: &dyn DifferentiableLossFunction)
+                -> Vec<LayerGrads> {
+    if layers.is_empty() {
+        return Vec::new();
+    }
+    let activations = forward(layers, x);
+    let delta = loss.deriv_vec(activations.last(), t);
+    backward_outputs(layers, &activations, delta).0
+}
+
+/// Like `backward`, but starts from `delta`, the derivative of some
+/// loss with respect to the outputs of the network. Also returns the
+/// derivative of the loss with respect to the inputs, which lets the
+/// gradient flow on into a network which produced them.
+pub fn backward_delta(layers: &LinkedList<Box<dyn WeightedLayer>>,
+                      x: &[f32],
+                      delta: &[f32])
+                      -> (Vec<LayerGrads>, Vec<f32>) {
+    if layers.is_empty() {
+        return (Vec::new(), delta.to_vec());
+    }
+    backward_outputs(layers, &forward(layers, x), delta.to_vec())
+}
+
+fn backward_outputs(layers: &LinkedList<Box<dyn WeightedLayer>>,
+                    activations: &Activations,
+                    mut delta: Vec<f32>)
+                    -> (Vec<LayerGrads>, Vec<f32>) {
+    span!(TRACE, "backward");
+    let mut grads = Vec::with_capacity(layers.len());
+    for (i, (l, (inputs, output))) in layers.iter().zip(activations.iter()).enumerate().rev() {
+        network::check_layer_len("backward", i, l.as_ref(), "delta values", l.output_count(), delta.len());
+        let (input_delta, layer_grads) = l.backward(&delta, inputs, output);
+        grads.push(layer_grads);
+        delta = input_delta;
+    }
+    grads.reverse();
+    (grads, delta)
+}
+
+/// Takes a step of gradient descent with the learning rate, given the
+/// gradients of every layer as returned by `backward`.
+pub fn apply_gradients(layers: &mut LinkedList<Box<dyn WeightedLayer>>, grads: &[LayerGrads], rate: f32) {
+    assert_eq!(layers.len(), grads.len(), "there must be gradients for every layer");
+    for (l, g) in layers.iter_mut().zip(grads) {
+        let ws: Vec<f32> = g.weights.iter().map(|w| -rate * w).collect();
+        let bs: Vec<f32> = g.bias.iter().map(|b| -rate * b).collect();
+        l.update(&ws, &bs);
+    }
+}
+
+/// The activations of a single sample, recorded by `forward` for use
+/// in a backward pass. Every activation is stored once, as the inputs
+/// of a layer are the output of the one before it, and the inputs of
+/// the sample are borrowed.
+pub struct Activations<'a> {
+    inputs: &'a [f32],
+    outputs: Vec<Vec<f32>>,
+}
+
+impl<'a> Activations<'a> {
+    /// The number of layers.
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    /// The inputs of layer `i`.
+    pub fn inputs(&self, i: usize) -> &[f32] {
+        if i == 0 {
+            self.inputs
+        } else {
+            &self.outputs[i - 1]
+        }
+    }
+
+    /// The output of layer `i`.
+    pub fn output(&self, i: usize) -> &[f32] {
+        &self.outputs[i]
+    }
+
+    /// The output of the network, which for a network without layers
+    /// is its inputs.
+    pub fn last(&self) -> &[f32] {
+        self.outputs.last().map_or(self.inputs, |o| o)
+    }
+
+    /// Takes the output of the network without copying it.
+    pub fn into_last(mut self) -> Vec<f32> {
+        self.outputs.pop().unwrap_or_else(|| self.inputs.to_vec())
+    }
+
+    /// The inputs and output of every layer, in the order of the
+    /// layers.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&[f32], &[f32])> + ExactSizeIterator {
+        (0..self.len()).map(move |i| (self.inputs(i), self.output(i)))
+    }
+}
+
+/// Feeds a single sample through the layers, recording the output of
+/// every layer for use in a backward pass.
+pub fn forward<'a>(layers: &LinkedList<Box<dyn WeightedLayer>>, x: &'a [f32]) -> Activations<'a> {
+    span!(TRACE, "forward");
+    let mut activations = Activations {
+        inputs: x,
+        outputs: Vec::with_capacity(layers.len()),
+    };
+    for (i, l) in layers.iter().enumerate() {
+        let out = {
+            let inputs = activations.inputs(i);
+            network::check_layer_len("forward", i, l.as_ref(), "inputs", l.input_count(), inputs.len());
+            l.output(inputs)
+        };
+        network::check_layer_len("forward", i, l.as_ref(), "outputs", l.output_count(), out.len());
+        activations.outputs.push(out);
+    }
+    activations
+}
+
+impl<'a> SGDIter<'a> {
+    /// The learning rate of the coming batches, which starts as that of
+    /// the trainer.
+    pub fn rate(&self) -> f32 {
+        self.rate
+    }
+
+    /// Changes the learning rate of the coming batches, such as to
+    /// follow a schedule.
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate;
+    }
+
+    /// The network being trained.
+    pub fn layers(&self) -> &LinkedList<Box<dyn WeightedLayer>> {
+        self.layers
+    }
+
+    /// The state to continue training from with `SGDTrainer::resume`,
+    /// such as after a restart of the process.
+    pub fn state(&self) -> TrainingState {
+        TrainingState {
+            epoch: self.epoch,
+            batch: self.batch,
+            rate: self.rate,
+            rng: self.trainer.rng.state(),
+            augmentation_rng: self.trainer.augmentation.as_ref().map(|a| a.rng.state()),
+            order: self.order.clone(),
+            updates: self.updates.iter().cloned().collect(),
+            pending: self.pending,
+            epoch_loss: self.epoch_loss,
+            converged: self.converged,
+            plateau: self.plateau,
+        }
+    }
+
+    /// Records the loss of the epoch just finished with the plateau
+    /// schedule of the trainer, if it has one, which sets the rate of
+    /// the coming batches. `fit` calls it with the validation loss, or
+    /// the training loss when there is no validation data.
+    pub fn step_plateau(&mut self, loss: f32) {
+        if let Some(ref mut plateau) = self.plateau {
+            self.rate = plateau.step(loss, self.rate);
+        }
+    }
+
+    /// Whether training stopped early because the average loss of an
+    /// epoch fell below the `stop_loss` of the trainer.
+    pub fn converged(&self) -> bool {
+        self.converged
+    }
+}
+
+impl<'a> Iterator for SGDIter<'a> {
+    type Item = BatchResult;
+
+    fn next(&mut self) -> Option<BatchResult> {
+        let input_count = self.layers.front().map(|l| l.input_count()).unwrap_or(0);
+        let target_count = self.trainer.loss.target_count(self.layers.back().map(|l| l.output_count()).unwrap_or(0));
+        // Nothing can be learned without layers or samples
+        if self.epoch >= self.trainer.epochs || self.converged || input_count == 0 ||
+           self.inputs.len() < input_count {
+            return None;
+        }
+
+        span!(DEBUG, "batch", epoch = self.epoch, batch = self.batch);
+        let samples = self.inputs.len() / input_count;
+        let batch_size = cmp::max(1, self.trainer.batch_size.unwrap_or(samples));
+
+        let start = cmp::min(self.batch * batch_size, samples);
+        let end = cmp::min(start + batch_size, samples);
+        let (inputs, targets) = if self.trainer.shuffle {
+            if self.batch == 0 {
+                self.order = (0..samples).collect();
+                let order = &mut self.order;
+                self.trainer.rng.with_rng(|rng| rng.shuffle(order));
+            }
+            let mut inputs = Vec::with_capacity((end - start) * input_count);
+            let mut targets = Vec::with_capacity((end - start) * target_count);
+            for i in &self.order[start..end] {
+                inputs.extend_from_slice(&self.inputs[i * input_count..(i + 1) * input_count]);
+                targets.extend_from_slice(&self.targets[i * target_count..(i + 1) * target_count]);
+            }
+            (Cow::Owned(inputs), Cow::Owned(targets))
+        } else {
+            (Cow::Borrowed(&self.inputs[start * input_count..end * input_count]),
+             Cow::Borrowed(&self.targets[start * target_count..end * target_count]))
+        };
+        let inputs = match self.trainer.augmentation {
+            Some(ref a) => Cow::Owned(a.apply_batch(&inputs, input_count)),
+            None => inputs,
+        };
+        let loss = self.trainer.accumulate_batch(self.rate, self.layers, &inputs, &targets, &mut self.updates);
+        self.pending += 1;
+        if self.pending >= self.trainer.accumulate || end >= samples {
+            let count = self.updates.front().map_or(0, |lu| lu.count);
+            self.trainer.apply_updates(self.layers, &mut self.updates, count);
+            for lu in self.updates.iter_mut() {
+                lu.clear();
             }
+            self.pending = 0;
+        }
+
+        let result = BatchResult {
+            epoch: self.epoch,
+            batch: self.batch,
+            loss,
+        };
 
-            for (x, t) in inputs.chunks(input_count).zip(targets.chunks(output_count)) {
-                // Forward pass
-                let mut outputs: LinkedList<LayerOut> = LinkedList::new();
-                for l in layers.iter() {
-                    let inputs = outputs.back().map_or(x.to_vec(), |o| o.output.clone());
-                    let out = l.output(&inputs);
-                    outputs.push_back(LayerOut {
-                        inputs: inputs,
-                        output: out,
-                    });
-                }
-
-                // Calculate error differential
-                let mut delta_signal;
-                {
-                    let y = outputs.back().map(|o| &o.output).unwrap();
-                    delta_signal = self.loss.deriv(y, t);
-                }
-
-                // backward pass
-                for ((l, lo), lu) in layers.iter_mut()
-                    .rev()
-                    .zip(outputs.iter().rev())
-                    .zip(updates.iter_mut().rev()) {
-                    let ws = self.weight_step(&l, &lo.inputs, &delta_signal);
-                    add_mut(&mut lu.ws, &ws);
-
-                    let bs = self.bias_step(&l, &delta_signal);
-                    add_mut(&mut lu.bs, &bs);
-
-                    delta_signal = l.delta(&delta_signal, &lo.inputs, &lo.output);
-                }
+        self.epoch_loss += loss * (end - start) as f32;
+        if end >= samples {
+            if let Some(threshold) = self.trainer.stop_loss {
+                self.converged = self.epoch_loss / (samples as f32) < threshold;
             }
+            self.epoch += 1;
+            self.batch = 0;
+            self.epoch_loss = 0.0;
+        } else {
+            self.batch += 1;
+        }
+
+        Some(result)
+    }
+}
+
+impl SupervisedTrainer for SGDTrainer {
+    fn train(&self, layers: &mut LinkedList<Box<dyn WeightedLayer>>, inputs: &[f32], targets: &[f32]) {
+        span!(INFO, "train", epochs = self.epochs);
+        let targets = self.transform_targets(layers, targets);
+        for _ in self.iter(layers, inputs, &targets) {}
+    }
+
+    fn fit(&self,
+           layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+           train: &Dataset,
+           validation: Option<&Dataset>)
+           -> History {
+        self.fit_resumable(layers, train, validation, None, |_, _| {})
+    }
+
+    fn evaluate(&self, layers: &LinkedList<Box<dyn WeightedLayer>>, data: &Dataset) -> Metrics {
+        match self.target_transform {
+            Some(ref t) => metrics::evaluate_transformed(layers, self.loss.as_ref(), data, t.as_ref()),
+            None => metrics::evaluate(layers, self.loss.as_ref(), data),
+        }
+    }
 
-            // update batch
-            for (l, lu) in layers.iter_mut().zip(updates.iter()) {
-                l.update(&lu.ws, &lu.bs);
+    fn predict(&self, layers: &LinkedList<Box<dyn WeightedLayer>>, inputs: &[f32]) -> Vec<f32> {
+        let mut outputs = network::predict_batch(layers, inputs);
+        if let (Some(t), Some(l)) = (self.target_transform.as_ref(), layers.back()) {
+            for y in outputs.chunks_mut(l.output_count()) {
+                t.inverse(y);
             }
         }
+        outputs
     }
 }
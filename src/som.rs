@@ -0,0 +1,187 @@
+//! Self-organizing maps, which arrange prototype vectors on a 2D grid
+//! so that neighbouring prototypes match similar inputs.
+use random::RngProvider;
+use utils::normal_vector;
+
+use rand::Rng;
+use std::io;
+use std::io::Write;
+
+/// A rectangular grid of prototype vectors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SOM {
+    pub width: usize,
+    pub height: usize,
+    /// The length of the prototype vectors, and of the inputs
+    pub dim: usize,
+    /// The prototypes, row by row and column by column
+    pub prototypes: Vec<f32>,
+}
+
+impl SOM {
+    /// Creates a map with gaussian random prototypes.
+    pub fn random_with<R: Rng>(width: usize, height: usize, dim: usize, rng: &mut R) -> SOM {
+        SOM {
+            width,
+            height,
+            dim,
+            prototypes: normal_vector(width * height * dim, rng),
+        }
+    }
+
+    /// The prototype at the given column and row.
+    pub fn prototype(&self, col: usize, row: usize) -> &[f32] {
+        let i = (row * self.width + col) * self.dim;
+        &self.prototypes[i..i + self.dim]
+    }
+
+    /// The best matching unit, the (column, row) of the prototype
+    /// closest to the input.
+    pub fn bmu(&self, x: &[f32]) -> (usize, usize) {
+        assert_eq!(self.dim, x.len());
+        let mut best = 0;
+        let mut best_distance = f32::INFINITY;
+        for (i, p) in self.prototypes.chunks(self.dim).enumerate() {
+            let d = squared_distance(p, x);
+            if d < best_distance {
+                best = i;
+                best_distance = d;
+            }
+        }
+        (best % self.width, best / self.width)
+    }
+
+    /// The unified distance matrix: for every unit, the mean distance
+    /// between its prototype and those of the units next to it. High
+    /// values mark borders between clusters.
+    pub fn u_matrix(&self) -> Vec<f32> {
+        let mut result = Vec::with_capacity(self.width * self.height);
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let p = self.prototype(col, row);
+                let mut total = 0.0;
+                let mut count = 0;
+                let neighbours = [(col.wrapping_sub(1), row), (col + 1, row), (col, row.wrapping_sub(1)), (col, row + 1)];
+                for (c, r) in neighbours.iter() {
+                    if *c < self.width && *r < self.height {
+                        total += squared_distance(p, self.prototype(*c, *r)).sqrt();
+                        count += 1;
+                    }
+                }
+                result.push(if count > 0 { total / count as f32 } else { 0.0 });
+            }
+        }
+        result
+    }
+
+    /// Writes the U-matrix as CSV with one line per row of the map.
+    pub fn write_u_matrix_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for row in self.u_matrix().chunks(self.width) {
+            let values: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+            writeln!(out, "{}", values.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+fn squared_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+/// Trains a SOM by pulling the best matching unit, and its neighbours
+/// on the grid, towards every sample. The pull falls off as a
+/// gaussian of the grid distance. Both the learning rate and the
+/// neighbourhood radius decay linearly towards zero over training.
+pub struct SOMTrainer {
+    /// The number of passes over the data
+    pub epochs: usize,
+    /// The starting learning rate
+    pub rate: f32,
+    /// The starting neighbourhood radius, in grid units
+    pub radius: f32,
+    /// Source of randomness for the order of the samples
+    pub rng: RngProvider,
+}
+
+impl SOMTrainer {
+    pub fn new(epochs: usize, rate: f32, radius: f32) -> SOMTrainer {
+        SOMTrainer {
+            epochs,
+            rate,
+            radius,
+            rng: RngProvider::from_entropy(),
+        }
+    }
+
+    /// Trains the map on the inputs, laid out sample by sample.
+    pub fn train(&self, som: &mut SOM, inputs: &[f32]) {
+        let samples: Vec<&[f32]> = inputs.chunks(som.dim).collect();
+        let mut order: Vec<usize> = (0..samples.len()).collect();
+        let mut rng = self.rng.fork();
+        let steps = (self.epochs * samples.len()).max(1) as f32;
+        let mut step = 0;
+        for _ in 0..self.epochs {
+            rng.shuffle(&mut order);
+            for i in order.iter() {
+                let x = samples[*i];
+                let progress = 1.0 - step as f32 / steps;
+                let rate = self.rate * progress;
+                // Keep a minimal radius so the best matching unit is
+                // always updated
+                let radius = (self.radius * progress).max(0.5);
+                let (bc, br) = som.bmu(x);
+
+                for row in 0..som.height {
+                    for col in 0..som.width {
+                        let dc = col as f32 - bc as f32;
+                        let dr = row as f32 - br as f32;
+                        let influence = (-(dc * dc + dr * dr) / (2.0 * radius * radius)).exp();
+                        let start = (row * som.width + col) * som.dim;
+                        for (p, x) in som.prototypes[start..start + som.dim].iter_mut().zip(x) {
+                            *p += rate * influence * (x - *p);
+                        }
+                    }
+                }
+                step += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use random::SeededRng;
+
+    #[test]
+    fn separates_clusters() {
+        let mut rng = SeededRng::new(1);
+        let mut som = SOM::random_with(4, 1, 2, &mut rng);
+        let mut trainer = SOMTrainer::new(50, 0.5, 2.0);
+        trainer.rng = RngProvider::seeded(2);
+
+        let inputs = [0.0, 0.0, 0.1, 0.0, 5.0, 5.0, 5.0, 5.1];
+        trainer.train(&mut som, &inputs);
+
+        // The clusters end up at opposite ends of the map
+        let a = som.bmu(&[0.0, 0.0]);
+        let b = som.bmu(&[5.0, 5.0]);
+        assert_eq!(a.1, 0);
+        assert!((a.0 as i32 - b.0 as i32).abs() >= 2);
+    }
+
+    #[test]
+    fn u_matrix_of_line() {
+        let som = SOM {
+            width: 3,
+            height: 1,
+            dim: 1,
+            prototypes: vec![0.0, 1.0, 3.0],
+        };
+        assert_eq!(som.u_matrix(), vec![1.0, 1.5, 2.0]);
+
+        let mut csv = Vec::new();
+        som.write_u_matrix_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "1,1.5,2\n");
+    }
+}
@@ -0,0 +1,386 @@
+//! An optional wrapper which carries the shape of `f32` values, such
+//! as the steps and features of a sequence, alongside them. Layers,
+//! losses and trainers work on flat slices and do not take tensors;
+//! the tensor methods of their traits, such as `Layer::output_tensor`,
+//! `LossFunction::loss_tensor` and `SupervisedTrainer::train_tensors`,
+//! wrap the slice methods and borrow the values of a contiguous
+//! tensor rather than copying them.
+use std::borrow::Cow;
+use std::ops::{Index, IndexMut};
+
+/// Values with a shape and strides. The strides are the distance in
+/// `data` between consecutive elements along each axis, which lets
+/// views such as a transpose share the values instead of moving them.
+#[derive(Debug, Clone)]
+pub struct Tensor {
+    data: Vec<f32>,
+    shape: Vec<usize>,
+    strides: Vec<usize>,
+}
+
+/// The strides of a row-major tensor of the given shape, where the
+/// last axis is contiguous.
+fn row_major_strides(shape: &[usize]) -> Vec<usize> {
+    let mut strides = vec![1; shape.len()];
+    for i in (0..shape.len().saturating_sub(1)).rev() {
+        strides[i] = strides[i + 1] * shape[i + 1];
+    }
+    strides
+}
+
+impl Tensor {
+    /// A row-major tensor of the given shape. The number of values
+    /// must match the shape.
+    pub fn new(data: Vec<f32>, shape: &[usize]) -> Tensor {
+        assert_eq!(data.len(),
+                   shape.iter().product::<usize>(),
+                   "{} values do not fit the shape {:?}",
+                   data.len(),
+                   shape);
+        Tensor {
+            data,
+            shape: shape.to_vec(),
+            strides: row_major_strides(shape),
+        }
+    }
+
+    /// A tensor of zeros.
+    pub fn zeros(shape: &[usize]) -> Tensor {
+        Tensor::new(vec![0.0; shape.iter().product()], shape)
+    }
+
+    /// A tensor with a single axis.
+    pub fn from_slice(data: &[f32]) -> Tensor {
+        Tensor::new(data.to_vec(), &[data.len()])
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn strides(&self) -> &[usize] {
+        &self.strides
+    }
+
+    /// The number of axes.
+    pub fn ndim(&self) -> usize {
+        self.shape.len()
+    }
+
+    /// The number of values.
+    pub fn len(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the values are stored in row-major order, so that they
+    /// can be used as a flat slice.
+    pub fn is_contiguous(&self) -> bool {
+        self.strides == row_major_strides(&self.shape)
+    }
+
+    /// The values as a flat row-major slice, if they are stored that
+    /// way. See `contiguous`.
+    pub fn as_slice(&self) -> Option<&[f32]> {
+        if self.is_contiguous() {
+            Some(&self.data)
+        } else {
+            None
+        }
+    }
+
+    /// The values in row-major order, borrowed if they are stored that
+    /// way and copied otherwise. This is how a tensor is passed to the
+    /// functions which take a flat slice.
+    pub fn values(&self) -> Cow<'_, [f32]> {
+        match self.as_slice() {
+            Some(data) => Cow::Borrowed(data),
+            None => Cow::Owned(self.to_vec()),
+        }
+    }
+
+    /// The values in row-major order.
+    pub fn to_vec(&self) -> Vec<f32> {
+        match self.as_slice() {
+            Some(data) => data.to_vec(),
+            None => (0..self.len()).map(|i| self.data[self.offset(&self.unravel(i))]).collect(),
+        }
+    }
+
+    /// A copy of the tensor with its values stored in row-major order.
+    pub fn contiguous(&self) -> Tensor {
+        Tensor::new(self.to_vec(), &self.shape)
+    }
+
+    /// The same values with another shape of the same size. Copies the
+    /// values if they are not contiguous.
+    pub fn reshape(&self, shape: &[usize]) -> Tensor {
+        assert_eq!(self.len(),
+                   shape.iter().product::<usize>(),
+                   "cannot reshape {:?} to {:?}",
+                   self.shape,
+                   shape);
+        Tensor::new(self.to_vec(), shape)
+    }
+
+    /// Swaps two axes without moving the values.
+    pub fn transpose(&self, a: usize, b: usize) -> Tensor {
+        assert!(a < self.ndim() && b < self.ndim(), "axis out of range for {:?}", self.shape);
+        let mut t = self.clone();
+        t.shape.swap(a, b);
+        t.strides.swap(a, b);
+        t
+    }
+
+    /// The sub-tensor at `index` along the first axis, such as one
+    /// step of a sequence.
+    pub fn row(&self, index: usize) -> Tensor {
+        assert!(self.ndim() > 0 && index < self.shape[0],
+                "row {} out of range for {:?}",
+                index,
+                self.shape);
+        let shape = &self.shape[1..];
+        let data = (0..shape.iter().product())
+            .map(|i| {
+                let mut at = vec![index];
+                at.extend(unravel(shape, i));
+                self.data[self.offset(&at)]
+            })
+            .collect();
+        Tensor::new(data, shape)
+    }
+
+    /// Element-wise sum, broadcasting the shapes. See `broadcast`.
+    pub fn add(&self, other: &Tensor) -> Tensor {
+        self.broadcast(other, |a, b| a + b)
+    }
+
+    /// Element-wise difference, broadcasting the shapes.
+    pub fn sub(&self, other: &Tensor) -> Tensor {
+        self.broadcast(other, |a, b| a - b)
+    }
+
+    /// Element-wise product, broadcasting the shapes.
+    pub fn mul(&self, other: &Tensor) -> Tensor {
+        self.broadcast(other, |a, b| a * b)
+    }
+
+    /// Combines two tensors element by element. The shapes are aligned
+    /// at their last axes, and along each axis they must either agree
+    /// or one of them must be 1, in which case its values are repeated
+    /// along that axis. A missing axis counts as 1. For example, shape
+    /// (steps, 1) combines with (steps, features), and (features,)
+    /// with (samples, features).
+    pub fn broadcast<F: Fn(f32, f32) -> f32>(&self, other: &Tensor, f: F) -> Tensor {
+        let shape = broadcast_shape(&self.shape, &other.shape)
+            .unwrap_or_else(|| panic!("cannot broadcast {:?} with {:?}", self.shape, other.shape));
+        let data = (0..shape.iter().product())
+            .map(|i| {
+                let index = unravel(&shape, i);
+                f(self.data[self.broadcast_offset(&index)], other.data[other.broadcast_offset(&index)])
+            })
+            .collect();
+        Tensor::new(data, &shape)
+    }
+
+    /// The sum along an axis, which is removed from the shape.
+    pub fn sum_axis(&self, axis: usize) -> Tensor {
+        self.reduce_axis(axis, 0.0, |a, b| a + b)
+    }
+
+    /// The mean along an axis, which is removed from the shape.
+    pub fn mean_axis(&self, axis: usize) -> Tensor {
+        let n = self.shape[axis] as f32;
+        let mut t = self.sum_axis(axis);
+        for v in t.data.iter_mut() {
+            *v /= n;
+        }
+        t
+    }
+
+    /// The maximum along an axis, which is removed from the shape.
+    pub fn max_axis(&self, axis: usize) -> Tensor {
+        self.reduce_axis(axis, f32::NEG_INFINITY, f32::max)
+    }
+
+    fn reduce_axis<F: Fn(f32, f32) -> f32>(&self, axis: usize, init: f32, f: F) -> Tensor {
+        assert!(axis < self.ndim(), "axis {} out of range for {:?}", axis, self.shape);
+        let mut shape = self.shape.clone();
+        shape.remove(axis);
+        let data = (0..shape.iter().product())
+            .map(|i| {
+                let mut index = unravel(&shape, i);
+                index.insert(axis, 0);
+                (0..self.shape[axis]).fold(init, |acc, j| {
+                    index[axis] = j;
+                    f(acc, self.data[self.offset(&index)])
+                })
+            })
+            .collect();
+        Tensor::new(data, &shape)
+    }
+
+    /// The position in `data` of the element at an index into a
+    /// broadcast shape with at least as many axes as this tensor.
+    fn broadcast_offset(&self, index: &[usize]) -> usize {
+        let index = &index[index.len() - self.ndim()..];
+        index.iter()
+            .zip(&self.shape)
+            .zip(&self.strides)
+            .map(|((i, n), s)| if *n == 1 { 0 } else { i * s })
+            .sum()
+    }
+
+    /// The position in `data` of the element at the given index.
+    fn offset(&self, index: &[usize]) -> usize {
+        assert_eq!(index.len(), self.ndim(), "index {:?} does not match {:?}", index, self.shape);
+        index.iter()
+            .zip(&self.shape)
+            .zip(&self.strides)
+            .map(|((i, n), s)| {
+                assert!(i < n, "index {:?} out of range for {:?}", index, self.shape);
+                i * s
+            })
+            .sum()
+    }
+
+    /// The index of the `i`th element in row-major order.
+    fn unravel(&self, i: usize) -> Vec<usize> {
+        unravel(&self.shape, i)
+    }
+}
+
+/// The index of the `i`th element of a row-major tensor of the given
+/// shape.
+pub(crate) fn unravel(shape: &[usize], mut i: usize) -> Vec<usize> {
+    let mut index = vec![0; shape.len()];
+    for (ix, n) in index.iter_mut().zip(shape).rev() {
+        *ix = i % n;
+        i /= n;
+    }
+    index
+}
+
+/// The shape which two shapes broadcast to, if they are compatible.
+pub fn broadcast_shape(a: &[usize], b: &[usize]) -> Option<Vec<usize>> {
+    let ndim = a.len().max(b.len());
+    let dim = |s: &[usize], i: usize| if i + s.len() < ndim { 1 } else { s[i + s.len() - ndim] };
+    (0..ndim)
+        .map(|i| match (dim(a, i), dim(b, i)) {
+            (x, y) if x == y => Some(x),
+            (1, y) => Some(y),
+            (x, 1) => Some(x),
+            _ => None,
+        })
+        .collect()
+}
+
+impl Index<&[usize]> for Tensor {
+    type Output = f32;
+
+    fn index(&self, index: &[usize]) -> &f32 {
+        &self.data[self.offset(index)]
+    }
+}
+
+impl IndexMut<&[usize]> for Tensor {
+    fn index_mut(&mut self, index: &[usize]) -> &mut f32 {
+        let offset = self.offset(index);
+        &mut self.data[offset]
+    }
+}
+
+/// Tensors are equal if they have the same shape and values, however
+/// the values are stored.
+impl PartialEq for Tensor {
+    fn eq(&self, other: &Tensor) -> bool {
+        self.shape == other.shape && self.to_vec() == other.to_vec()
+    }
+}
+
+impl From<Vec<f32>> for Tensor {
+    fn from(data: Vec<f32>) -> Tensor {
+        let len = data.len();
+        Tensor::new(data, &[len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn row_major_indexing() {
+        let mut t = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[2, 3]);
+        assert_eq!(t.strides(), &[3, 1]);
+        assert_eq!(t[&[1, 0][..]], 4.0);
+
+        t[&[0, 2][..]] = 9.0;
+        assert_eq!(t.as_slice().unwrap(), &[1.0, 2.0, 9.0, 4.0, 5.0, 6.0]);
+    }
+
+    #[test]
+    fn transpose_is_a_view() {
+        let t = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[2, 3]).transpose(0, 1);
+        assert_eq!(t.shape(), &[3, 2]);
+        assert!(!t.is_contiguous());
+        assert_eq!(t.as_slice(), None);
+        assert_eq!(t[&[2, 1][..]], 6.0);
+        assert_eq!(t.to_vec(), vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+        assert!(matches!(t.values(), Cow::Owned(_)));
+        assert!(matches!(t.contiguous().values(), Cow::Borrowed(_)));
+        assert!(t.contiguous().is_contiguous());
+        assert_eq!(t.contiguous(), t);
+    }
+
+    #[test]
+    fn reshape_and_rows() {
+        let t = Tensor::from((0..12).map(|v| v as f32).collect::<Vec<f32>>()).reshape(&[2, 3, 2]);
+        assert_eq!(t.row(1), Tensor::new(vec![6.0, 7.0, 8.0, 9.0, 10.0, 11.0], &[3, 2]));
+        assert_eq!(t.transpose(0, 2).row(0).to_vec(), vec![0.0, 6.0, 2.0, 8.0, 4.0, 10.0]);
+    }
+
+    #[test]
+    fn broadcasting() {
+        let x = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[2, 3]);
+        let row = Tensor::from(vec![10.0, 20.0, 30.0]);
+        let column = Tensor::new(vec![0.0, 1.0], &[2, 1]);
+
+        assert_eq!(x.add(&row).to_vec(), vec![11.0, 22.0, 33.0, 14.0, 25.0, 36.0]);
+        assert_eq!(row.sub(&x).to_vec(), vec![9.0, 18.0, 27.0, 6.0, 15.0, 24.0]);
+        assert_eq!(x.mul(&column).to_vec(), vec![0.0, 0.0, 0.0, 4.0, 5.0, 6.0]);
+        assert_eq!(column.add(&row).shape(), &[2, 3]);
+        assert_eq!(x.transpose(0, 1).mul(&column.reshape(&[2])).to_vec(),
+                   vec![0.0, 4.0, 0.0, 5.0, 0.0, 6.0]);
+
+        assert_eq!(broadcast_shape(&[2, 3], &[3, 1]), None);
+        assert_eq!(broadcast_shape(&[4, 1, 3], &[2, 1]), Some(vec![4, 2, 3]));
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot broadcast")]
+    fn incompatible_shapes() {
+        Tensor::zeros(&[2, 3]).add(&Tensor::zeros(&[2]));
+    }
+
+    #[test]
+    fn reductions() {
+        let x = Tensor::new(vec![1.0, 5.0, 3.0, 4.0, 2.0, 6.0], &[2, 3]);
+
+        assert_eq!(x.sum_axis(0), Tensor::from(vec![5.0, 7.0, 9.0]));
+        assert_eq!(x.sum_axis(1), Tensor::from(vec![9.0, 12.0]));
+        assert_eq!(x.mean_axis(1), Tensor::from(vec![3.0, 4.0]));
+        assert_eq!(x.max_axis(0), Tensor::from(vec![4.0, 5.0, 6.0]));
+        assert_eq!(x.transpose(0, 1).max_axis(0), Tensor::from(vec![5.0, 6.0]));
+        assert_eq!(x.sum_axis(0).sum_axis(0).shape(), &[] as &[usize]);
+    }
+
+    #[test]
+    #[should_panic(expected = "do not fit")]
+    fn shape_must_match() {
+        Tensor::new(vec![1.0, 2.0, 3.0], &[2, 2]);
+    }
+}
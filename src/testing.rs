@@ -0,0 +1,789 @@
+//! Support for testing layers, trainers and loss functions: small
+//! problems with fixed data and reference networks which any working
+//! trainer should solve, so that a test can assert that training
+//! converges below some loss within a number of epochs.
+//!
+//! Authors of new layers can also check that a layer keeps the
+//! contracts of the `Layer` and `WeightedLayer` traits, including that
+//! its gradients match finite differences of its output, with
+//! `assert_layer_contract`.
+//!
+//! `assert_close` compares floats, or slices of them, within a
+//! tolerance, for results whose last bits depend on the order of the
+//! arithmetic.
+//!
+//! For changes which should not alter any result, the `Fixture`
+//! networks have hardcoded weights and recorded outputs, gradients and
+//! trained weights, which `assert_fixture` compares to the last few
+//! bits: exactly for `Fixture::Maxout`, and within `FIXTURE_ULPS` for
+//! the fixtures whose results depend on the math library.
+//!
+//! ```
+//! use scarecrow::sgd::SGDTrainer;
+//! use scarecrow::testing::{assert_converges, Problem};
+//!
+//! let trainer = SGDTrainer::new(1000, 0.1);
+//! assert_converges(Problem::Xor, &trainer, 0.02);
+//! ```
+use data::Dataset;
+use layers::{DenseLayer, HyperbolicLayer, MaxoutLayer, RBFLayer, RectifiedLayer, SigmoidLayer};
+use loss::{Reduction, SquaredError};
+use network;
+use random::SeededRng;
+use sgd::{bias_gradient, weight_gradient, SGDTrainer};
+use traits::{SupervisedTrainer, WeightedLayer};
+use utils::{dot, normal_vector};
+
+use rand::Rng;
+
+use std::collections::LinkedList;
+use std::f32::consts::PI;
+use std::slice;
+
+/// The seed of the data and networks of the problems.
+const SEED: u64 = 1;
+
+/// A problem with fixed data and a reference network to solve it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    /// The four samples of exclusive or
+    Xor,
+    /// Two interleaved half circles with a little noise, labelled 0
+    /// and 1
+    TwoMoons,
+    /// Regression of `sin(x)` for x in [-π, π]
+    Sine,
+}
+
+impl Problem {
+    /// The samples of the problem, which are the same on every call.
+    pub fn dataset(&self) -> Dataset {
+        match *self {
+            Problem::Xor => xor(),
+            Problem::TwoMoons => two_moons(100, 0.1, &mut SeededRng::new(SEED)),
+            Problem::Sine => sine(32),
+        }
+    }
+
+    /// A network which is known to solve the problem, with weights
+    /// from a fixed seed.
+    pub fn network(&self) -> LinkedList<Box<dyn WeightedLayer>> {
+        let mut rng = SeededRng::new(SEED);
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        let (inputs, hidden) = match *self {
+            Problem::Xor => (2, 6),
+            Problem::TwoMoons => (2, 12),
+            Problem::Sine => (1, 12),
+        };
+        layers.push_back(Box::new(DenseLayer::random_with(inputs, hidden, &mut rng)));
+        layers.push_back(Box::new(HyperbolicLayer { size: hidden }));
+        layers.push_back(Box::new(DenseLayer::random_with(hidden, 1, &mut rng)));
+        if *self != Problem::Sine {
+            layers.push_back(Box::new(SigmoidLayer { size: 1 }));
+        }
+        layers
+    }
+}
+
+/// The four samples of exclusive or.
+pub fn xor() -> Dataset {
+    Dataset::new(vec![0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 1.0],
+                 vec![0.0, 1.0, 1.0, 0.0],
+                 2,
+                 1)
+}
+
+/// Points on two interleaved half circles, alternating between the
+/// upper one, labelled 0, and the lower one, labelled 1. Gaussian
+/// noise with standard deviation `scale` is added to the points.
+pub fn two_moons<R: Rng>(samples: usize, scale: f32, rng: &mut R) -> Dataset {
+    let noise = normal_vector(2 * samples, rng);
+    let mut inputs = Vec::with_capacity(2 * samples);
+    let mut targets = Vec::with_capacity(samples);
+    for i in 0..samples {
+        let angle = PI * (i / 2) as f32 / samples.div_ceil(2) as f32;
+        let (x, y) = if i % 2 == 0 {
+            (angle.cos(), angle.sin())
+        } else {
+            (1.0 - angle.cos(), 0.5 - angle.sin())
+        };
+        inputs.push(x + scale * noise[2 * i]);
+        inputs.push(y + scale * noise[2 * i + 1]);
+        targets.push((i % 2) as f32);
+    }
+    Dataset::new(inputs, targets, 2, 1)
+}
+
+/// `sin(x)` at evenly spaced points in [-π, π].
+pub fn sine(samples: usize) -> Dataset {
+    let inputs: Vec<f32> = (0..samples).map(|i| -PI + 2.0 * PI * i as f32 / (samples.max(2) - 1) as f32).collect();
+    let targets = inputs.iter().map(|x| x.sin()).collect();
+    Dataset::new(inputs, targets, 1, 1)
+}
+
+/// `sin(x)` at evenly spaced points in [-π, π], like `sine`, with
+/// Gaussian noise of standard deviation `scale` added to the targets,
+/// for regression on measurements.
+pub fn noisy_sine<R: Rng>(samples: usize, scale: f32, rng: &mut R) -> Dataset {
+    let mut data = sine(samples);
+    for (t, n) in data.targets.iter_mut().zip(normal_vector(samples, rng)) {
+        *t += scale * n;
+    }
+    data
+}
+
+/// Trains the network on the data and returns the average squared
+/// error per sample afterwards.
+pub fn final_loss(layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+                  trainer: &dyn SupervisedTrainer,
+                  data: &Dataset)
+                  -> f32 {
+    trainer.train(layers, &data.inputs, &data.targets);
+    network::loss(layers, &SquaredError, &data.inputs, &data.targets)
+}
+
+/// Trains the reference network of the problem and panics unless the
+/// average squared error per sample ends up below `max_loss`. The
+/// number of epochs is that of the trainer.
+pub fn assert_converges(problem: Problem, trainer: &dyn SupervisedTrainer, max_loss: f32) {
+    let mut layers = problem.network();
+    assert_network_converges(&mut layers, trainer, &problem.dataset(), max_loss);
+}
+
+/// Trains the network on the data and panics unless the average
+/// squared error per sample ends up below `max_loss`. For testing
+/// networks with new layers on the data of a `Problem`.
+pub fn assert_network_converges(layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+                                trainer: &dyn SupervisedTrainer,
+                                data: &Dataset,
+                                max_loss: f32) {
+    let loss = final_loss(layers, trainer, data);
+    assert!(loss < max_loss, "training did not converge: loss {} is not below {}", loss, max_loss);
+}
+
+/// The step of the finite differences of the gradient checks.
+const EPSILON: f32 = 1e-3;
+
+/// Checks the layer against the contracts of the layer traits on the
+/// inputs of a single sample, and returns a description of every one
+/// that is broken:
+///
+/// * the output has `output_count` values
+/// * `delta` has `input_count` values, and `neuron_delta` has
+///   `neuron_count` values if the layer has neurons
+/// * the weights and their derivatives have `weight_count` values, and
+///   the bias gradient has as many values as there are biases
+/// * the delta signal at the inputs, and the gradients of the weights
+///   and biases used by the trainers, match finite differences of the
+///   output
+/// * `backward` gives the same delta signal and gradients as the
+///   methods it fuses
+///
+/// Gradients match if they differ by at most `tolerance`, relative to
+/// their magnitude when it is larger than 1. The layer is put in
+/// inference mode, and its weights are left as they were.
+pub fn layer_violations(layer: &mut dyn WeightedLayer, inputs: &[f32], tolerance: f32) -> Vec<String> {
+    assert_eq!(inputs.len(), layer.input_count(), "the inputs must fit the layer");
+    layer.set_training(false);
+    let mut violations = Vec::new();
+    let mut expect_len = |what: &str, len: usize, expected: usize| if len != expected {
+        violations.push(format!("{} has {} values instead of {}", what, len, expected));
+        false
+    } else {
+        true
+    };
+
+    let output = layer.output(inputs);
+    if !expect_len("output", output.len(), layer.output_count()) {
+        return violations;
+    }
+    let delta = normal_vector(output.len(), &mut SeededRng::new(SEED + 1));
+    let input_delta = layer.delta(&delta, inputs, &output);
+    let neuron_delta = layer.neuron_delta(&delta, inputs, &output);
+    let inputs_fit = expect_len("delta", input_delta.len(), layer.input_count());
+    let neurons_fit = layer.neuron_count() == 0 ||
+                      expect_len("neuron_delta", neuron_delta.len(), layer.neuron_count());
+    let mut weights_fit = true;
+    if let Some(w) = layer.weights() {
+        weights_fit &= expect_len("weights", w.len(), layer.weight_count());
+    }
+    if let Some(d) = layer.derivw(inputs) {
+        weights_fit &= expect_len("derivw", d.len(), layer.weight_count());
+    }
+    if let Some(b) = layer.bias() {
+        weights_fit &= expect_len("bias gradient", bias_gradient(layer, &neuron_delta).len(), b.len());
+    }
+
+    // The delta and gradients are those of dot(output, delta)
+    let objective = |layer: &dyn WeightedLayer, x: &[f32]| dot(&layer.output(x), &delta);
+    let mut compare = |what: String, numeric: f32, analytic: f32| {
+        if !close(numeric, analytic, tolerance) {
+            violations.push(format!("{} is {} but finite differences give {}", what, analytic, numeric));
+        }
+    };
+
+    if inputs_fit {
+        let mut x = inputs.to_vec();
+        for (i, analytic) in input_delta.iter().enumerate() {
+            x[i] = inputs[i] + EPSILON;
+            let up = objective(layer, &x);
+            x[i] = inputs[i] - EPSILON;
+            let down = objective(layer, &x);
+            x[i] = inputs[i];
+            compare(format!("delta of input {}", i), (up - down) / (2.0 * EPSILON), *analytic);
+        }
+    }
+
+    if neurons_fit && weights_fit {
+        let weight_grad = weight_gradient(layer, inputs, &neuron_delta);
+        let mask = layer.weight_mask().map(|m| m.to_vec());
+        let weights = layer.weights_mut().map_or(Vec::new(), |w| w.to_vec());
+        for (j, analytic) in weight_grad.iter().enumerate().take(weights.len()) {
+            if mask.as_ref().is_some_and(|m| !m[j]) {
+                continue;
+            }
+            let numeric = finite_difference(layer, inputs, &objective, weights[j], |l, v| l.weights_mut().unwrap()[j] = v);
+            compare(format!("gradient of weight {}", j), numeric, *analytic);
+        }
+
+        let bias_grad = bias_gradient(layer, &neuron_delta);
+        let bias = layer.bias_mut().map_or(Vec::new(), |b| b.to_vec());
+        for (j, analytic) in bias_grad.iter().enumerate().take(bias.len()) {
+            let numeric = finite_difference(layer, inputs, &objective, bias[j], |l, v| l.bias_mut().unwrap()[j] = v);
+            compare(format!("gradient of bias {}", j), numeric, *analytic);
+        }
+    }
+
+    let (fused_delta, fused) = layer.backward(&delta, inputs, &output);
+    let mut compare_fused = |what: &str, fused: &[f32], separate: &[f32]| {
+        let same = fused.len() == separate.len() && fused.iter().zip(separate).all(|(a, b)| close(*a, *b, tolerance));
+        if !same {
+            violations.push(format!("backward gives {} {:?} instead of {:?}", what, fused, separate));
+        }
+    };
+    compare_fused("the delta", &fused_delta, &input_delta);
+    if layer.has_parameters() && neurons_fit && weights_fit {
+        compare_fused("weight gradients", &fused.weights, &weight_gradient(layer, inputs, &neuron_delta));
+        compare_fused("bias gradients", &fused.bias, &bias_gradient(layer, &neuron_delta));
+    }
+    violations
+}
+
+/// The derivative of the objective with respect to one parameter of
+/// the layer, which has the given value and is changed with `set`. The
+/// value is restored afterwards.
+fn finite_difference<O, S>(layer: &mut dyn WeightedLayer, inputs: &[f32], objective: &O, value: f32, set: S) -> f32
+    where O: Fn(&dyn WeightedLayer, &[f32]) -> f32,
+          S: Fn(&mut dyn WeightedLayer, f32)
+{
+    set(layer, value + EPSILON);
+    let up = objective(layer, inputs);
+    set(layer, value - EPSILON);
+    let down = objective(layer, inputs);
+    set(layer, value);
+    (up - down) / (2.0 * EPSILON)
+}
+
+/// Checks the layer with `layer_violations` on a few random inputs
+/// from a fixed seed, and panics with every broken contract.
+pub fn assert_layer_contract(layer: &mut dyn WeightedLayer) {
+    let mut rng = SeededRng::new(SEED);
+    for sample in 0..8 {
+        let inputs = normal_vector(layer.input_count(), &mut rng);
+        let violations = layer_violations(layer, &inputs, 1e-2);
+        assert!(violations.is_empty(),
+                "the layer breaks its contract on sample {}, {:?}:\n{}",
+                sample,
+                inputs,
+                violations.join("\n"));
+    }
+}
+
+/// Whether two values differ by at most `tolerance`, relative to their
+/// magnitude when it is larger than 1.
+fn close(a: f32, b: f32, tolerance: f32) -> bool {
+    (a - b).abs() <= tolerance * a.abs().max(b.abs()).max(1.0)
+}
+
+/// Values which `assert_close` compares: a single `f32`, or a slice,
+/// array or vector of them.
+pub trait Close {
+    fn values(&self) -> &[f32];
+}
+
+impl Close for f32 {
+    fn values(&self) -> &[f32] {
+        slice::from_ref(self)
+    }
+}
+
+impl Close for [f32] {
+    fn values(&self) -> &[f32] {
+        self
+    }
+}
+
+impl<const N: usize> Close for [f32; N] {
+    fn values(&self) -> &[f32] {
+        self
+    }
+}
+
+impl Close for Vec<f32> {
+    fn values(&self) -> &[f32] {
+        self
+    }
+}
+
+impl<T: Close + ?Sized> Close for &T {
+    fn values(&self) -> &[f32] {
+        (**self).values()
+    }
+}
+
+/// Panics unless `actual` and `expected` have as many values and every
+/// pair differs by at most `tolerance`, relative to their magnitude
+/// when it is larger than 1. Unlike `assert_eq!` this holds up when the
+/// arithmetic is reordered, such as by a faster matrix product, which
+/// changes the rounding of the last bits.
+///
+/// ```
+/// use scarecrow::testing::assert_close;
+///
+/// assert_close(0.1 + 0.2, 0.3, 1e-6);
+/// assert_close(&vec![1.0, 2.0], [1.0, 2.0 + 1e-7], 1e-6);
+/// ```
+#[track_caller]
+pub fn assert_close<A: Close, B: Close>(actual: A, expected: B, tolerance: f32) {
+    let (a, b) = (actual.values(), expected.values());
+    assert!(a.len() == b.len(), "expected {} values but got {}: {:?}", b.len(), a.len(), a);
+    let differ: Vec<usize> = (0..a.len()).filter(|&i| !close(a[i], b[i], tolerance)).collect();
+    assert!(differ.is_empty(),
+            "values at {:?} differ by more than {}:\n  actual {:?}\nexpected {:?}",
+            differ,
+            tolerance,
+            a,
+            b);
+}
+
+/// A small network with hardcoded weights, together with the outputs,
+/// gradients and trained weights it is known to produce. Unlike the
+/// convergence tests, `assert_fixture` compares these to the last few
+/// bits, so any change to the arithmetic of a layer or of the trainer
+/// shows up.
+///
+/// `Maxout` only adds and multiplies, which IEEE 754 rounds the same
+/// everywhere, so its results are compared exactly. The others go
+/// through `tanh` or `exp`, including the sigmoid which the rectifier
+/// passes back, whose last bits depend on the math library. They were
+/// recorded on x86_64 Linux, and are compared within `FIXTURE_ULPS`.
+///
+/// The fixtures cover the layers with weights of their own and the
+/// activations which feed them, each trained by `SGDTrainer` in a
+/// single batch and sample by sample. The other built-in layers are
+/// checked against finite differences by `assert_layer_contract`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fixture {
+    /// Dense layers around a rectifier
+    Rectified,
+    /// Dense layers with a hyperbolic tangent and a sigmoid
+    Hyperbolic,
+    /// A maxout layer followed by a dense layer
+    Maxout,
+    /// A radial basis function layer followed by a dense layer
+    RadialBasis,
+}
+
+/// The expected results of a fixture on its dataset, with the squared
+/// error as the loss.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Golden {
+    /// The outputs of every sample, one after another
+    pub outputs: &'static [f32],
+    /// The gradient of the average loss per sample, laid out as
+    /// `network::get_weights`
+    pub gradient: &'static [f32],
+    /// The weights after one epoch of `SGDTrainer` with a rate of 0.5
+    /// and the whole dataset as a single batch
+    pub batch_step: &'static [f32],
+    /// The weights after one epoch of `SGDTrainer` with a rate of 0.5,
+    /// a batch size of 1 and `Reduction::Mean`
+    pub sample_steps: &'static [f32],
+}
+
+/// The learning rate of the trained weights of the fixtures.
+const FIXTURE_RATE: f32 = 0.5;
+
+/// How many units in the last place of the largest value of a result
+/// the results of a fixture which is not exact may differ by, to allow
+/// for math libraries which round `tanh` and `exp` differently.
+pub const FIXTURE_ULPS: f32 = 16.0;
+
+impl Fixture {
+    /// Every fixture.
+    pub fn all() -> [Fixture; 4] {
+        [Fixture::Rectified, Fixture::Hyperbolic, Fixture::Maxout, Fixture::RadialBasis]
+    }
+
+    /// Whether the results of the fixture are the same on every
+    /// platform, and are compared exactly.
+    pub fn is_exact(&self) -> bool {
+        *self == Fixture::Maxout
+    }
+
+    /// The network of the fixture, with its hardcoded weights.
+    pub fn network(&self) -> LinkedList<Box<dyn WeightedLayer>> {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        let weights: &[f32] = match *self {
+            Fixture::Rectified => {
+                layers.push_back(Box::new(DenseLayer::uniform(0.0, 2, 3)));
+                layers.push_back(Box::new(RectifiedLayer { size: 3 }));
+                layers.push_back(Box::new(DenseLayer::uniform(0.0, 3, 1)));
+                &[0.5, -0.25, -0.75, 0.5, 0.25, 0.125, 0.0, 0.25, 0.5, 1.5, -0.5, 0.75, 0.25]
+            }
+            Fixture::Hyperbolic => {
+                layers.push_back(Box::new(DenseLayer::uniform(0.0, 2, 2)));
+                layers.push_back(Box::new(HyperbolicLayer { size: 2 }));
+                layers.push_back(Box::new(DenseLayer::uniform(0.0, 2, 1)));
+                layers.push_back(Box::new(SigmoidLayer { size: 1 }));
+                &[0.5, -1.0, 0.25, 0.75, 0.125, -0.25, 1.5, -0.5, 0.25]
+            }
+            Fixture::Maxout => {
+                layers.push_back(Box::new(MaxoutLayer {
+                    weights: vec![0.0; 8],
+                    bias: vec![0.0; 4],
+                    inputs: 2,
+                    units: 2,
+                    pieces: 2,
+                }));
+                layers.push_back(Box::new(DenseLayer::uniform(0.0, 2, 1)));
+                &[0.5, 0.25, -0.5, 1.0, 0.75, -0.25, 0.0, 0.5, 0.0, -0.25, 0.125, 0.0, 1.0, -0.5, 0.25]
+            }
+            Fixture::RadialBasis => {
+                layers.push_back(Box::new(RBFLayer {
+                    centers: vec![0.0; 4],
+                    log_widths: vec![0.0; 2],
+                    shape: (2, 2),
+                }));
+                layers.push_back(Box::new(DenseLayer::uniform(0.0, 2, 1)));
+                &[0.0, 0.5, 1.0, -0.5, 0.0, 0.25, 0.75, -0.5, 0.125]
+            }
+        };
+        network::set_weights(&mut layers, weights);
+        layers
+    }
+
+    /// The samples of the fixture, which are the same for all of them.
+    pub fn dataset(&self) -> Dataset {
+        Dataset::new(vec![1.0, -0.5, 0.25, 0.75], vec![0.5, -0.25], 2, 1)
+    }
+
+    /// The recorded results of the fixture.
+    pub fn golden(&self) -> Golden {
+        match *self {
+            Fixture::Rectified => Golden {
+                outputs: &[1.703125, 0.5234375],
+                gradient: &[1.3159811, -0.1662786, -0.25174275, -0.07974903, 0.6959125, -0.013764322, 1.7374487,
+                            -0.42798883, 0.9823626, 0.7519531, 0.3383789, 1.3347168, 1.9765625],
+                batch_step: &[-0.81598115, -0.0837214, -0.49825725, 0.57974905, -0.44591248, 0.13876432, -1.7374487,
+                              0.6779888, -0.48236263, 0.7480469, -0.8383789, -0.5847168, -1.7265625],
+                sample_steps: &[-0.62770224, 0.4811155, -0.6392221, 0.15685451, -0.36087132, 0.39388776, -0.98433274,
+                                0.114129454, -0.1421981, 0.7480469, 0.116318285, 0.05587335, 0.063281655],
+            },
+            Fixture::Hyperbolic => Golden {
+                outputs: &[0.8379604, 0.34924617],
+                gradient: &[0.06391549, 0.10862202, -0.0348359, -0.03451452, 0.18441243, -0.07934945, -0.025798786,
+                            0.032360908, 0.18208185],
+                batch_step: &[0.4360845, -1.1086221, 0.2848359, 0.78451455, -0.059412435, -0.17065054, 1.5257988,
+                              -0.5323609, 0.06791815],
+                sample_steps: &[0.43924522, -1.0991399, 0.28364593, 0.78094465, -0.04676962, -0.17541035, 1.5256152,
+                                -0.5325601, 0.07291061],
+            },
+            Fixture::Maxout => Golden {
+                outputs: &[0.125, 0.4375],
+                gradient: &[-0.375, 0.1875, 0.171875, 0.515625, 0.1875, -0.09375, -0.0859375, -0.2578125, -0.375,
+                            0.6875, 0.1875, -0.34375, 0.1171875, -0.1171875, 0.3125],
+                batch_step: &[0.875, 0.0625, -0.671875, 0.484375, 0.5625, -0.15625, 0.0859375, 0.7578125, 0.375,
+                              -0.9375, -0.0625, 0.34375, 0.8828125, -0.3828125, -0.0625],
+                sample_steps: &[0.43048763, -1.2710371, -0.5, 1.0, 0.5625, -0.15625, 0.048713684, 0.64614105,
+                                -1.4030495, -0.25, -0.0625, 0.19485474, 0.14199448, -0.7095642, -0.9338379],
+            },
+            Fixture::RadialBasis => Golden {
+                outputs: &[-0.0990904, 0.5670813],
+                gradient: &[-0.021374136, 0.30921546, 0.097560294, -0.1626005, -0.25862926, -0.27642083, 0.54718375,
+                            -0.17015782, 0.21799088],
+                batch_step: &[0.021374136, 0.19078454, 0.9024397, -0.33739948, 0.25862926, 0.52642083, 0.20281625,
+                              -0.32984218, -0.092990875],
+                sample_steps: &[0.086195774, -0.053103507, 1.0462027, -0.57700455, 0.16283457, 0.1190923, -0.89366674,
+                                -0.9259049, -1.2284415],
+            },
+        }
+    }
+
+    /// The results of the fixture with the code as it is, in the order
+    /// of the fields of `Golden`.
+    fn results(&self) -> [Vec<f32>; 4] {
+        let data = self.dataset();
+        let layers = self.network();
+        let outputs = network::predict_batch(&layers, &data.inputs);
+        let gradient = network::gradient(&layers, &SquaredError, &data.inputs, &data.targets);
+
+        let mut layers = self.network();
+        SGDTrainer::new(1, FIXTURE_RATE).train(&mut layers, &data.inputs, &data.targets);
+        let batch_step = network::get_weights(&layers);
+
+        let mut layers = self.network();
+        let mut trainer = SGDTrainer::new(1, FIXTURE_RATE);
+        trainer.batch_size = Some(1);
+        trainer.reduction = Reduction::Mean;
+        trainer.train(&mut layers, &data.inputs, &data.targets);
+        [outputs, gradient, batch_step, network::get_weights(&layers)]
+    }
+}
+
+/// Panics unless the fixture reproduces every one of its recorded
+/// results, exactly if the fixture `is_exact` and otherwise within
+/// `FIXTURE_ULPS`. The message holds the new values, to be recorded in
+/// `Fixture::golden` after an intended change of the arithmetic.
+pub fn assert_fixture(fixture: Fixture) {
+    let golden = fixture.golden();
+    let expected = [golden.outputs, golden.gradient, golden.batch_step, golden.sample_steps];
+    let names = ["outputs", "gradient", "batch_step", "sample_steps"];
+    for ((name, expected), actual) in names.iter().zip(&expected).zip(&fixture.results()) {
+        let matches = if fixture.is_exact() {
+            actual == expected
+        } else {
+            within_ulps(actual, expected, FIXTURE_ULPS)
+        };
+        assert!(matches,
+                "{:?}: `{}` differs from the recorded\n    {:?}\nand is now\n    {:?}",
+                fixture,
+                name,
+                expected,
+                actual);
+    }
+}
+
+/// Whether the values differ by at most `ulps` units in the last place
+/// of the largest of the expected values. Measuring against the largest
+/// value allows for small values which are the difference of larger
+/// ones, and carry their rounding.
+fn within_ulps(actual: &[f32], expected: &[f32], ulps: f32) -> bool {
+    let largest = expected.iter().fold(0.0f32, |m, v| m.max(v.abs()));
+    let tolerance = ulps * f32::EPSILON * largest;
+    actual.len() == expected.len() && actual.iter().zip(expected).all(|(a, e)| (a - e).abs() <= tolerance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layers::{Activation, AlphaDropoutLayer, AttentionLayer, DenseActivationLayer, MaskLayer, NamedLayer,
+                 ReparameterizeLayer, SELULayer, SparseDenseLayer, WeightLayout};
+    use random::RngProvider;
+    use traits::{Layer, Weights, WeightsMut};
+
+    #[test]
+    fn datasets_are_fixed() {
+        assert_eq!(Problem::TwoMoons.dataset(), Problem::TwoMoons.dataset());
+        assert_eq!(Problem::TwoMoons.dataset().len(), 100);
+        let sine = Problem::Sine.dataset();
+        assert_eq!(sine.input(0), &[-PI]);
+        assert!(sine.target(31)[0].abs() < 1e-6);
+
+        let noisy = noisy_sine(32, 0.1, &mut SeededRng::new(SEED));
+        assert_eq!(noisy.inputs, sine.inputs);
+        let offsets: Vec<f32> = noisy.targets.iter().zip(&sine.targets).map(|(n, t)| n - t).collect();
+        assert!(offsets.iter().all(|o| *o != 0.0 && o.abs() < 0.5), "{:?}", offsets);
+    }
+
+    #[test]
+    fn reference_networks_converge() {
+        assert_converges(Problem::Xor, &SGDTrainer::new(1000, 0.1), 0.01);
+        assert_converges(Problem::TwoMoons, &SGDTrainer::new(500, 0.1), 0.01);
+        assert_converges(Problem::Sine, &SGDTrainer::new(1000, 0.003), 0.01);
+    }
+
+    #[test]
+    fn builtin_layers_keep_contract() {
+        let mut rng = SeededRng::new(2);
+        let mut layers: Vec<Box<dyn WeightedLayer>> = vec![Box::new(DenseLayer::random_with(3, 2, &mut rng)),
+                                                           Box::new(DenseLayer::random_with(3, 2, &mut rng).without_bias()),
+                                                           Box::new(DenseLayer::random_with(3, 2, &mut rng)
+                                                               .with_layout(WeightLayout::InputMajor)),
+                                                           Box::new(MaxoutLayer::random_with(3, 2, 3, &mut rng)),
+                                                           Box::new(RBFLayer::random_with(3, 2, 1.0, &mut rng)),
+                                                           Box::new(HyperbolicLayer { size: 3 }),
+                                                           Box::new(SigmoidLayer { size: 3 }),
+                                                           Box::new(SELULayer { size: 3 }),
+                                                           Box::new(AttentionLayer::new(2, 2))];
+        layers.push(Box::new(SparseDenseLayer::from_dense(&DenseLayer::random_with(3, 2, &mut rng))));
+        layers.push(Box::new(DenseActivationLayer::new(DenseLayer::random_with(3, 2, &mut rng), Activation::Tanh)));
+        layers.push(Box::new(NamedLayer::new("dense", Box::new(DenseLayer::random_with(3, 2, &mut rng)))));
+        let tied = DenseLayer::random_with(3, 2, &mut rng).tied();
+        layers.push(Box::new(tied.transpose()));
+        layers.push(Box::new(tied));
+        layers.push(Box::new(MaskLayer::new(2, 2)));
+        layers.push(Box::new(AlphaDropoutLayer::new(3, 0.5, RngProvider::seeded(2))));
+        layers.push(Box::new(ReparameterizeLayer::new(3, 1.0, RngProvider::seeded(2))));
+        // The layers which draw noise are checked in inference, as
+        // `layer_violations` sets them to. Left out are `StepLayer`
+        // and `SignLayer`, which are not differentiable, and the
+        // rectifier, which is checked below.
+        for l in layers.iter_mut() {
+            assert_layer_contract(l.as_mut());
+        }
+
+        // The rectifier passes back the derivative of softplus, its
+        // smooth approximation, instead of its own
+        let violations = layer_violations(&mut RectifiedLayer { size: 2 }, &[-1.0, 1.0], 1e-2);
+        assert_eq!(violations.len(), 2);
+    }
+
+    /// Claims a derivative of 1 like a linear layer, but squares.
+    struct Square;
+
+    impl Layer for Square {
+        fn input_count(&self) -> usize {
+            1
+        }
+
+        fn output_count(&self) -> usize {
+            1
+        }
+
+        fn output(&self, inputs: &[f32]) -> Vec<f32> {
+            vec![inputs[0] * inputs[0]]
+        }
+
+        fn delta_from_outputs(&self, delta: &[f32], _outputs: &[f32]) -> Option<Vec<f32>> {
+            Some(delta.to_vec())
+        }
+    }
+
+    impl WeightedLayer for Square {}
+
+    /// Scales its single input by a weight per output and adds a bias
+    /// shared by all outputs.
+    struct SharedBias {
+        weights: Vec<f32>,
+        bias: Vec<f32>,
+    }
+
+    impl Layer for SharedBias {
+        fn input_count(&self) -> usize {
+            1
+        }
+
+        fn output_count(&self) -> usize {
+            self.weights.len()
+        }
+
+        fn output(&self, inputs: &[f32]) -> Vec<f32> {
+            self.weights.iter().map(|w| inputs[0] * w + self.bias[0]).collect()
+        }
+
+        fn delta_from_inputs(&self, delta: &[f32], _inputs: &[f32]) -> Option<Vec<f32>> {
+            Some(vec![dot(delta, &self.weights)])
+        }
+
+        fn derivw(&self, inputs: &[f32]) -> Option<Vec<f32>> {
+            Some(vec![inputs[0]; self.weights.len()])
+        }
+    }
+
+    impl WeightedLayer for SharedBias {
+        fn weight_count(&self) -> usize {
+            self.weights.len()
+        }
+
+        fn neuron_count(&self) -> usize {
+            self.weights.len()
+        }
+
+        fn weights_mut(&mut self) -> Option<WeightsMut<'_>> {
+            Some(WeightsMut::Borrowed(&mut self.weights))
+        }
+
+        fn bias_mut(&mut self) -> Option<&mut Vec<f32>> {
+            Some(&mut self.bias)
+        }
+
+        fn weights(&self) -> Option<Weights<'_>> {
+            Some(Weights::Borrowed(&self.weights))
+        }
+
+        fn bias(&self) -> Option<&[f32]> {
+            Some(&self.bias)
+        }
+
+        fn bias_grad(&self, delta: &[f32]) -> Option<Vec<f32>> {
+            Some(vec![delta.iter().sum()])
+        }
+    }
+
+    #[test]
+    fn shared_bias() {
+        let mut layer = SharedBias {
+            weights: vec![0.5, -1.0, 2.0],
+            bias: vec![0.1],
+        };
+        assert_layer_contract(&mut layer);
+
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(layer));
+        SGDTrainer::new(10, 0.1).train(&mut layers, &[1.0], &[1.6, 0.0, 3.1]);
+        assert_eq!(layers.front().unwrap().bias().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn broken_layer() {
+        let violations = layer_violations(&mut Square, &[2.0], 1e-2);
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].starts_with("delta of input 0"), "{}", violations[0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "did not converge")]
+    fn too_few_epochs() {
+        assert_converges(Problem::Xor, &SGDTrainer::new(1, 0.1), 0.01);
+    }
+
+    #[test]
+    fn fixtures_match_golden() {
+        for fixture in &Fixture::all() {
+            assert_fixture(*fixture);
+        }
+        // The outputs of the rectifier only add and multiply, so they
+        // can be checked by hand: the first sample reaches it as
+        // [0.625, -0.75, 0.6875]
+        assert!(Fixture::Maxout.is_exact());
+        assert_eq!(Fixture::Rectified.golden().outputs[0], 0.25 + 1.5 * 0.625 + 0.75 * 0.6875);
+
+        // The last bits of the fixtures which go through `tanh` or
+        // `exp` may differ, but by no more than `FIXTURE_ULPS`
+        let largest = 1.5f32;
+        assert!(within_ulps(&[largest, 0.25 + 8.0 * f32::EPSILON], &[largest, 0.25], FIXTURE_ULPS));
+        assert!(!within_ulps(&[largest, 0.25 + 1e-5], &[largest, 0.25], FIXTURE_ULPS));
+        assert!(!within_ulps(&[largest], &[largest, 0.25], FIXTURE_ULPS));
+    }
+
+    #[test]
+    fn close_values() {
+        assert_close(1.0, 1.0 + 1e-7, 1e-6);
+        assert_close(&[1000.0, -2.0][..], vec![1000.0001, -2.0], 1e-6);
+        assert!(!close(0.5, 0.5 + 1e-5, 1e-6));
+        assert!(!close(f32::NAN, f32::NAN, 1e-6));
+    }
+
+    #[test]
+    #[should_panic(expected = "values at [1] differ")]
+    fn values_differ() {
+        assert_close([1.0, 2.0], [1.0, 2.1], 1e-3);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected 1 values but got 2")]
+    fn lengths_differ() {
+        assert_close([1.0, 2.0], 1.0, 1e-3);
+    }
+}
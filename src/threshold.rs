@@ -0,0 +1,273 @@
+//! Tuning the decision threshold of a binary classifier. A network
+//! with a single output in [0, 1] classifies samples as positive when
+//! the output is at least 0.5, which is rarely the best choice when one
+//! class is much more common than the other. `tune_threshold` sweeps
+//! over the outputs on validation data and picks the threshold which
+//! maximizes a `Criterion`, and `BinaryClassifier` keeps it together
+//! with the network.
+use data::Dataset;
+use network;
+use serialize::{self, LayerRegistry, ParseError};
+use traits::WeightedLayer;
+
+use std::collections::LinkedList;
+use std::io;
+use std::io::{BufRead, Write};
+
+/// The counts of correct and incorrect decisions of a binary
+/// classifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Confusion {
+    pub true_positives: usize,
+    pub false_positives: usize,
+    pub true_negatives: usize,
+    pub false_negatives: usize,
+}
+
+impl Confusion {
+    /// Counts the decisions of classifying the samples with a score of
+    /// at least `threshold` as positive.
+    pub fn new(scores: &[f32], labels: &[bool], threshold: f32) -> Confusion {
+        assert_eq!(scores.len(), labels.len(), "there must be a label for every score");
+        let mut c = Confusion::default();
+        for (s, l) in scores.iter().zip(labels) {
+            match (*s >= threshold, *l) {
+                (true, true) => c.true_positives += 1,
+                (true, false) => c.false_positives += 1,
+                (false, false) => c.true_negatives += 1,
+                (false, true) => c.false_negatives += 1,
+            }
+        }
+        c
+    }
+
+    /// The fraction of the positive decisions which are correct.
+    pub fn precision(&self) -> f32 {
+        ratio(self.true_positives, self.true_positives + self.false_positives)
+    }
+
+    /// The fraction of the positive samples which are found, also
+    /// known as the sensitivity or the true positive rate.
+    pub fn recall(&self) -> f32 {
+        ratio(self.true_positives, self.true_positives + self.false_negatives)
+    }
+
+    /// The fraction of the negative samples which are classified as
+    /// negative, the true negative rate.
+    pub fn specificity(&self) -> f32 {
+        ratio(self.true_negatives, self.true_negatives + self.false_positives)
+    }
+
+    /// The harmonic mean of the precision and the recall.
+    pub fn f1(&self) -> f32 {
+        ratio(2 * self.true_positives,
+              2 * self.true_positives + self.false_positives + self.false_negatives)
+    }
+
+    /// Youden's J statistic, the recall plus the specificity minus 1.
+    /// It is 0 for guessing and 1 for a perfect classifier.
+    pub fn youden_j(&self) -> f32 {
+        self.recall() + self.specificity() - 1.0
+    }
+}
+
+/// `a / b`, or 0 if `b` is 0.
+fn ratio(a: usize, b: usize) -> f32 {
+    if b == 0 {
+        0.0
+    } else {
+        a as f32 / b as f32
+    }
+}
+
+/// What a threshold is chosen to maximize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Criterion {
+    /// The F1 score, which balances precision and recall and ignores
+    /// the true negatives
+    #[default]
+    F1,
+    /// Youden's J, which weighs the error rates of both classes the
+    /// same however rare either class is
+    YoudenJ,
+}
+
+impl Criterion {
+    pub fn score(&self, confusion: &Confusion) -> f32 {
+        match *self {
+            Criterion::F1 => confusion.f1(),
+            Criterion::YoudenJ => confusion.youden_j(),
+        }
+    }
+}
+
+/// Tries every score as the threshold and returns the one which
+/// maximizes the criterion, together with the value of the criterion.
+/// Of equally good thresholds the highest is returned. Returns a
+/// threshold of 0.5 if there are no scores. Panics if a score is NaN.
+pub fn tune_threshold(scores: &[f32], labels: &[bool], criterion: Criterion) -> (f32, f32) {
+    assert_eq!(scores.len(), labels.len(), "there must be a label for every score");
+    assert!(!scores.iter().any(|s| s.is_nan()), "the scores must not be NaN");
+    let mut order: Vec<usize> = (0..scores.len()).collect();
+    order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+
+    // Lowering the threshold past a score turns its sample positive
+    let positives = labels.iter().filter(|&&l| l).count();
+    let mut c = Confusion {
+        true_negatives: labels.len() - positives,
+        false_negatives: positives,
+        ..Confusion::default()
+    };
+    let mut best = (0.5, f32::NEG_INFINITY);
+    for (n, &i) in order.iter().enumerate() {
+        if labels[i] {
+            c.true_positives += 1;
+            c.false_negatives -= 1;
+        } else {
+            c.false_positives += 1;
+            c.true_negatives -= 1;
+        }
+        let last_of_score = order.get(n + 1).is_none_or(|&j| scores[j] != scores[i]);
+        let value = criterion.score(&c);
+        if last_of_score && value > best.1 {
+            best = (scores[i], value);
+        }
+    }
+    if best.1 == f32::NEG_INFINITY {
+        best.1 = criterion.score(&c);
+    }
+    best
+}
+
+/// A network with a single output and the threshold from which it
+/// classifies a sample as positive.
+pub struct BinaryClassifier {
+    pub layers: LinkedList<Box<dyn WeightedLayer>>,
+    pub threshold: f32,
+}
+
+impl BinaryClassifier {
+    /// A classifier with the usual threshold of 0.5.
+    pub fn new(layers: LinkedList<Box<dyn WeightedLayer>>) -> BinaryClassifier {
+        assert_eq!(layers.back().map(|l| l.output_count()), Some(1), "a binary classifier has a single output");
+        BinaryClassifier { layers, threshold: 0.5 }
+    }
+
+    /// The output of the network for every sample.
+    pub fn scores(&self, inputs: &[f32]) -> Vec<f32> {
+        network::predict_batch(&self.layers, inputs)
+    }
+
+    /// The class of every sample, 1 if its score is at least the
+    /// threshold and 0 otherwise, like `Dataset::class`.
+    pub fn predict_class(&self, inputs: &[f32]) -> Vec<usize> {
+        self.scores(inputs).iter().map(|s| (*s >= self.threshold) as usize).collect()
+    }
+
+    /// Sets the threshold to the one which maximizes the criterion on
+    /// the validation data, whose classes are given by
+    /// `Dataset::class`. Returns the value of the criterion.
+    pub fn tune(&mut self, validation: &Dataset, criterion: Criterion) -> f32 {
+        let labels: Vec<bool> = (0..validation.len()).map(|i| validation.class(i) == 1).collect();
+        let (threshold, value) = tune_threshold(&self.scores(&validation.inputs), &labels, criterion);
+        self.threshold = threshold;
+        value
+    }
+
+    /// Writes the network like `serialize::save`, followed by the
+    /// threshold.
+    pub fn save<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        serialize::save(&self.layers, out)?;
+        writeln!(out, "threshold {}", self.threshold)
+    }
+
+    /// Loads a classifier written by `save`.
+    pub fn load<R: BufRead>(registry: &LayerRegistry, input: R) -> io::Result<BinaryClassifier> {
+        let lines = input.lines().collect::<io::Result<Vec<String>>>()?;
+        let end = lines.iter()
+            .rposition(|l| l.split_whitespace().next() == Some("threshold"))
+            .ok_or_else(|| ParseError { line: lines.len(), message: "expected a threshold".to_string() })?;
+        let layers = registry.load(lines[..end].join("\n").as_bytes())?;
+        let value = lines[end].trim()["threshold".len()..].trim();
+        let threshold = value.parse()
+            .map_err(|_| ParseError { line: end + 1, message: format!("invalid threshold `{}`", value) })?;
+        if lines[end + 1..].iter().any(|l| !l.trim().is_empty()) {
+            return Err(ParseError { line: end + 2, message: "unexpected lines after the threshold".to_string() }.into());
+        }
+        if layers.back().map(|l| l.output_count()) != Some(1) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "a binary classifier has a single output"));
+        }
+        Ok(BinaryClassifier { layers, threshold })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confusion_counts() {
+        let c = Confusion::new(&[0.9, 0.6, 0.4, 0.2], &[true, false, true, false], 0.5);
+        assert_eq!(c,
+                   Confusion {
+                       true_positives: 1,
+                       false_positives: 1,
+                       true_negatives: 1,
+                       false_negatives: 1,
+                   });
+        assert_eq!((c.precision(), c.recall(), c.specificity(), c.f1(), c.youden_j()), (0.5, 0.5, 0.5, 0.5, 0.0));
+        assert_eq!(Confusion::default().f1(), 0.0);
+    }
+
+    #[test]
+    fn tuned_thresholds() {
+        // Both positives score above 0.3 but below the usual 0.5
+        let scores = [0.45, 0.35, 0.3, 0.3, 0.1, 0.05];
+        let labels = [true, true, false, false, false, false];
+        assert_eq!(Confusion::new(&scores, &labels, 0.5).f1(), 0.0);
+        assert_eq!(tune_threshold(&scores, &labels, Criterion::F1), (0.35, 1.0));
+        assert_eq!(tune_threshold(&scores, &labels, Criterion::YoudenJ), (0.35, 1.0));
+
+        // Tied scores are never split by a threshold
+        let (threshold, f1) = tune_threshold(&[0.8, 0.5, 0.5], &[true, true, false], Criterion::F1);
+        assert_eq!((threshold, f1), (0.5, Confusion::new(&[0.8, 0.5, 0.5], &[true, true, false], 0.5).f1()));
+
+        // Both 0.9 and 0.6 give an F1 of 2/3, but Youden's J prefers
+        // finding both positives at the cost of two of the six negatives
+        let scores = [0.9, 0.8, 0.7, 0.6, 0.5, 0.4, 0.3, 0.2];
+        let labels = [true, false, false, true, false, false, false, false];
+        assert_eq!(tune_threshold(&scores, &labels, Criterion::F1).0, 0.9);
+        assert_eq!(tune_threshold(&scores, &labels, Criterion::YoudenJ).0, 0.6);
+        assert_eq!(tune_threshold(&[], &[], Criterion::F1), (0.5, 0.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "must not be NaN")]
+    fn nan_scores_are_rejected() {
+        tune_threshold(&[0.5, f32::NAN], &[true, false], Criterion::F1);
+    }
+
+    #[test]
+    fn classifier_keeps_its_threshold() {
+        let registry = LayerRegistry::new();
+        let mut layers = registry.parse_spec("dense inputs=1 neurons=1; sigmoid size=1").unwrap();
+        network::set_weights(&mut layers, &[4.0, -3.0]);
+        let mut classifier = BinaryClassifier::new(layers);
+        // The class changes at an input of 0.3, below the 0.75 where
+        // the output crosses 0.5
+        let validation = Dataset::new(vec![0.0, 0.2, 0.4, 0.6, 0.8], vec![0.0, 0.0, 1.0, 1.0, 1.0], 1, 1);
+        assert_eq!(classifier.predict_class(&validation.inputs), vec![0, 0, 0, 0, 1]);
+        assert_eq!(classifier.tune(&validation, Criterion::F1), 1.0);
+        assert_eq!(classifier.predict_class(&validation.inputs), vec![0, 0, 1, 1, 1]);
+
+        let mut saved = Vec::new();
+        classifier.save(&mut saved).unwrap();
+        let loaded = BinaryClassifier::load(&registry, &saved[..]).unwrap();
+        assert_eq!(loaded.threshold, classifier.threshold);
+        assert_eq!(loaded.predict_class(&validation.inputs), vec![0, 0, 1, 1, 1]);
+
+        let mut network = Vec::new();
+        serialize::save(&classifier.layers, &mut network).unwrap();
+        assert!(BinaryClassifier::load(&registry, &network[..]).is_err());
+    }
+}
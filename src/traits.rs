@@ -1,15 +1,85 @@
 //! The traits that make up neural network.
+//!
+//! ## Implementing a layer
+//!
+//! A layer defined outside of the crate implements `Layer` and
+//! `WeightedLayer` like the layers of the `layers` module, and is
+//! trained, saved and checked like them. `examples/custom_layer.rs`
+//! does so for a layer which squares its inputs. What the trainers
+//! expect of the methods is:
+//!
+//! * `output` maps `input_count` values to `output_count` values.
+//! * `delta_from_outputs` or `delta_from_inputs`, whichever is easier,
+//!   takes the delta signal at the outputs, the derivative of the loss
+//!   with respect to every output, and returns the delta signal at the
+//!   inputs, with `delta[j] * dy[j]/dx[i]` summed over the outputs `j`
+//!   for every input `i`. The other returns None.
+//! * A layer with weights has `neuron_count` neurons, each of which
+//!   adds up its inputs weighted by its own weights. `neuron_delta`
+//!   turns the delta signal at the outputs into that at the weighted
+//!   sums, the identity unless the layer applies a function to them.
+//! * `derivw` returns the derivative of every weighted sum with respect
+//!   to the weights, one row of `input_count` values per neuron, which
+//!   for a plain weighted sum is the inputs themselves. The gradient of
+//!   a weight is its derivative times the delta signal of its neuron.
+//!   Layers whose weights do not fit this override `weight_grad`, and
+//!   layers whose biases are not one per neuron override `bias_grad`.
+//! * `weights_mut` and `bias_mut` give the trainers the values to
+//!   update, with `weight_count` weights. `weights` and `weights_mut`
+//!   wrap a field of the layer in `Weights::Borrowed` and
+//!   `WeightsMut::Borrowed`.
+//!
+//! An activation without weights needs only the first point, and an
+//! empty `impl WeightedLayer`. `testing::assert_layer_contract` checks
+//! all of it against finite differences of `output`. To save the layer
+//! and load it again, `spec` returns its kind and hyperparameters and
+//! `serialize::LayerRegistry::register` adds a constructor for the
+//! kind.
+use std::any::Any;
+use std::borrow::Cow;
 use std::collections::LinkedList;
+use std::ops::{Deref, DerefMut};
+use std::sync::{RwLockReadGuard, RwLockWriteGuard};
+
+use data::Dataset;
+use layers::{Activation, NamedLayer, SharedWeights, WeightLayout};
+use loss::SquaredError;
+use metrics::{self, Epoch, History, Metrics};
+use network;
+use serialize::LayerSpec;
+use sgd::{bias_gradient, weight_gradient, LayerGrads};
+use tensor::Tensor;
+use utils::sum;
 
 /// A single layer in a neural network.
-pub trait Layer {
+///
+/// Layers are `Any`, so that a boxed layer can be turned back into its
+/// concrete type with `downcast_ref`.
+pub trait Layer: Any + Send + Sync {
     /// Expected number of inputs.
     fn input_count(&self) -> usize;
     /// Expected number of outputs.
     fn output_count(&self) -> usize;
 
     /// Output of the layer.
-    fn output(&self, &[f32]) -> Vec<f32>;
+    fn output(&self, inputs: &[f32]) -> Vec<f32>;
+
+    /// The shape of the output for an input of the given shape. The
+    /// default is a single axis, which suits layers that mix all of
+    /// their inputs. Layers which keep the structure of their input,
+    /// such as a sequence, return that shape instead.
+    #[allow(unused_variables)]
+    fn output_shape(&self, input_shape: &[usize]) -> Vec<usize> {
+        vec![self.output_count()]
+    }
+
+    /// Output of the layer for an input which carries its shape. The
+    /// output has the shape given by `output_shape`.
+    fn output_tensor(&self, inputs: &Tensor) -> Tensor {
+        assert_eq!(inputs.len(), self.input_count(), "the input has shape {:?}", inputs.shape());
+        let output = self.output(&inputs.values());
+        Tensor::new(output, &self.output_shape(inputs.shape()))
+    }
 
     /// Propagates the delta signal through this layer. Multiplies the
     /// signal with the derivative of the layer with respect to its
@@ -44,25 +114,194 @@ pub trait Layer {
         self.delta_from_outputs(delta, outputs).or(self.delta_from_inputs(delta, inputs)).unwrap()
     }
 
+    /// Converts the delta signal at the outputs of the layer to the
+    /// delta signal at the weighted sums of its neurons, which is what
+    /// the weight and bias gradients are computed from. Layers which
+    /// apply a function after summing, such as a fused activation,
+    /// multiply in its derivative here. The default returns the delta
+    /// signal unchanged.
+    #[allow(unused_variables)]
+    fn neuron_delta(&self, delta: &[f32], inputs: &[f32], outputs: &[f32]) -> Vec<f32> {
+        delta.to_vec()
+    }
+
     /// Derivative of the layer with respect to its weights. The input
-    /// argument is the input to the layer. Returns None if not
-    /// implemented for this layer.
-    fn derivw(&self, &[f32]) -> Option<Vec<f32>> {
+    /// argument is the input to the layer. The derivatives of the
+    /// weighted sum of every neuron follow each other, with
+    /// `weight_count` values in all. Returns None if not implemented
+    /// for this layer.
+    #[allow(unused_variables)]
+    fn derivw(&self, inputs: &[f32]) -> Option<Vec<f32>> {
         None
     }
+
+    /// The element-wise activation function applied by the layer, if
+    /// it is an activation layer.
+    fn activation(&self) -> Option<Activation> {
+        None
+    }
+
+    /// The name of the layer, if it has been given one with
+    /// `layers::NamedLayer`. Names let a layer be found without knowing
+    /// its position in the network.
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    /// The kind and hyperparameters of the layer, which are saved along
+    /// with its values so that a `serialize::LayerRegistry` can
+    /// construct it again. Layers which return None cannot be saved.
+    fn spec(&self) -> Option<LayerSpec> {
+        None
+    }
+
+    /// Switches the layer between training and inference, for layers
+    /// such as dropout which behave differently while training. Layers
+    /// are in inference mode unless set otherwise.
+    #[allow(unused_variables)]
+    fn set_training(&mut self, training: bool) {}
+
+    /// Whether the layer is in training mode. Layers which behave the
+    /// same either way are always in inference mode.
+    fn is_training(&self) -> bool {
+        false
+    }
 }
 
-/// A layer containing weights which can be trained.
+/// A layer of a network which is trained. Every method has a default
+/// for a layer without weights or biases, so that layers which only
+/// transform their inputs, such as activations, implement it with an
+/// empty `impl` and need not pretend to have any. The trainers skip
+/// such layers, which `has_parameters` tells apart.
 pub trait WeightedLayer: Layer {
-    fn weight_count(&self) -> usize;
-    fn neuron_count(&self) -> usize;
-    fn weights_mut(&mut self) -> Option<&mut Vec<f32>>;
-    fn bias_mut(&mut self) -> Option<&mut Vec<f32>>;
+    /// The number of weights, zero for layers without weights.
+    fn weight_count(&self) -> usize {
+        0
+    }
+
+    /// The number of neurons, which each have a weighted sum of the
+    /// inputs, zero for layers without weights.
+    fn neuron_count(&self) -> usize {
+        0
+    }
+
+    fn weights_mut(&mut self) -> Option<WeightsMut<'_>> {
+        None
+    }
+
+    fn bias_mut(&mut self) -> Option<&mut Vec<f32>> {
+        None
+    }
+
+    /// Gradient of the loss with respect to the biases, given the delta
+    /// signal at the neurons as returned by `neuron_delta`. Returns None
+    /// for layers where every neuron adds its own bias to its weighted
+    /// sum, whose bias gradient is the delta signal itself. Layers which
+    /// share or scale their biases, such as a convolution with one bias
+    /// per channel, override it.
+    #[allow(unused_variables)]
+    fn bias_grad(&self, delta: &[f32]) -> Option<Vec<f32>> {
+        None
+    }
+
+    /// Gradient of the loss with respect to the weights, given the
+    /// inputs and the delta signal at the neurons. Returns None for
+    /// layers whose `derivw` has one row per neuron, which is scaled by
+    /// the delta signal of that neuron. Layers whose weights are laid
+    /// out otherwise override it.
+    #[allow(unused_variables)]
+    fn weight_grad(&self, inputs: &[f32], delta: &[f32]) -> Option<Vec<f32>> {
+        None
+    }
+
+    /// Propagates the delta signal at the outputs back through the
+    /// layer, and returns the delta signal at its inputs together with
+    /// the gradients of its weights and biases, which are empty for a
+    /// layer without parameters. The default computes them one after
+    /// another with `neuron_delta`, `delta` and the gradient functions
+    /// of `sgd`. Layers which can compute them in a single sweep over
+    /// their weights override it.
+    fn backward(&self, delta: &[f32], inputs: &[f32], outputs: &[f32]) -> (Vec<f32>, LayerGrads) {
+        let grads = if self.has_parameters() {
+            let neuron_delta = self.neuron_delta(delta, inputs, outputs);
+            LayerGrads {
+                weights: weight_gradient(self, inputs, &neuron_delta),
+                bias: bias_gradient(self, &neuron_delta),
+            }
+        } else {
+            LayerGrads {
+                weights: Vec::new(),
+                bias: Vec::new(),
+            }
+        };
+        (self.delta(delta, inputs, outputs), grads)
+    }
+
+    /// Whether the layer has weights or biases to train.
+    fn has_parameters(&self) -> bool {
+        self.weight_count() > 0 || self.bias().is_some_and(|b| !b.is_empty())
+    }
+
+    /// The weights of the layer, if it has any.
+    fn weights(&self) -> Option<Weights<'_>> {
+        None
+    }
+
+    /// The weights which the layer shares with other layers, if it
+    /// uses weights which it does not own. Only the layer which owns
+    /// shared weights gives them through `weights`, and the gradients
+    /// of the others are added to its gradient by `network::gradient`.
+    fn shared_weights(&self) -> Option<&SharedWeights> {
+        None
+    }
+
+    /// How the weights of the layer are laid out. The default is one
+    /// row of weights per neuron, the layout of `derivw`.
+    fn weight_layout(&self) -> WeightLayout {
+        WeightLayout::NeuronMajor
+    }
+
+    /// The weights with one row of `weight_count / neuron_count` values
+    /// per neuron, whatever their layout, for code which reads the
+    /// weights of every neuron, such as `export::write_layer_csv`.
+    fn neuron_weights(&self) -> Option<Cow<'_, [f32]>> {
+        let weights = self.weights()?;
+        Some(match (self.neuron_count(), self.weight_layout()) {
+            (0, _) | (_, WeightLayout::NeuronMajor) => weights.into_cow(),
+            (neurons, layout) => Cow::Owned(layout.neuron_rows(&weights, (weights.len() / neurons, neurons)).into_owned()),
+        })
+    }
+
+    /// The biases of the layer, if it has any.
+    fn bias(&self) -> Option<&[f32]> {
+        None
+    }
+
+    /// Mask of weights which may be updated. Masked out weights are
+    /// kept at zero, which is used to prune weights.
+    fn weight_mask(&self) -> Option<&[bool]> {
+        None
+    }
+
+    /// Sets the mask of weights which may be updated. `None` means
+    /// that all weights are updated. Does nothing for layers without
+    /// weights.
+    #[allow(unused_variables)]
+    fn set_weight_mask(&mut self, mask: Option<Vec<bool>>) {}
+
     fn update(&mut self, weight_updates: &[f32], bias_updates: &[f32]) {
-        if let Some(weights) = self.weights_mut() {
+        let mask = self.weight_mask().map(|m| m.to_vec());
+        if let Some(mut weights) = self.weights_mut() {
             for (w, dw) in weights.iter_mut().zip(weight_updates) {
                 *w += *dw;
             }
+            if let Some(mask) = mask {
+                for (w, keep) in weights.iter_mut().zip(mask) {
+                    if !keep {
+                        *w = 0.0;
+                    }
+                }
+            }
         }
         if let Some(biases) = self.bias_mut() {
             for (b, db) in biases.iter_mut().zip(bias_updates) {
@@ -72,10 +311,146 @@ pub trait WeightedLayer: Layer {
     }
 }
 
+impl dyn Layer {
+    pub fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    pub fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    /// The layer as its concrete type, if it is a `T`.
+    pub fn downcast_ref<T: Layer>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+
+    /// The layer as its concrete type, for changing it.
+    pub fn downcast_mut<T: Layer>(&mut self) -> Option<&mut T> {
+        self.as_any_mut().downcast_mut()
+    }
+}
+
+impl dyn WeightedLayer {
+    pub fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    pub fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    /// The layer as its concrete type, if it is a `T`, such as to read
+    /// the weights of a `DenseLayer` after training. Looks through a
+    /// `NamedLayer` to the layer it names, unless `T` is `NamedLayer`.
+    pub fn downcast_ref<T: WeightedLayer>(&self) -> Option<&T> {
+        match self.as_any().downcast_ref::<NamedLayer>() {
+            Some(named) if !self.as_any().is::<T>() => named.layer.downcast_ref(),
+            _ => self.as_any().downcast_ref(),
+        }
+    }
+
+    /// The layer as its concrete type, for changing it. Looks through
+    /// a `NamedLayer` like `downcast_ref`.
+    pub fn downcast_mut<T: WeightedLayer>(&mut self) -> Option<&mut T> {
+        if self.as_any().is::<NamedLayer>() && !self.as_any().is::<T>() {
+            return self.as_any_mut().downcast_mut::<NamedLayer>().and_then(|n| n.layer.downcast_mut());
+        }
+        self.as_any_mut().downcast_mut()
+    }
+}
+
+/// The weights of a layer, as given by `WeightedLayer::weights`. They
+/// are usually a field of the layer, while weights shared with other
+/// layers are read through a lock, which is held until the value is
+/// dropped.
+#[derive(Debug)]
+pub enum Weights<'a> {
+    Borrowed(&'a [f32]),
+    Locked(RwLockReadGuard<'a, Vec<f32>>),
+}
+
+impl<'a> Weights<'a> {
+    /// The weights as a `Cow`, which copies them if they are locked.
+    pub fn into_cow(self) -> Cow<'a, [f32]> {
+        match self {
+            Weights::Borrowed(w) => Cow::Borrowed(w),
+            Weights::Locked(w) => Cow::Owned(w.to_vec()),
+        }
+    }
+}
+
+impl<'a> Deref for Weights<'a> {
+    type Target = [f32];
+
+    fn deref(&self) -> &[f32] {
+        match *self {
+            Weights::Borrowed(w) => w,
+            Weights::Locked(ref w) => w,
+        }
+    }
+}
+
+impl<'a, 'b> PartialEq<Weights<'b>> for Weights<'a> {
+    fn eq(&self, other: &Weights<'b>) -> bool {
+        **self == **other
+    }
+}
+
+impl<'a, 'b> PartialEq<&'b [f32]> for Weights<'a> {
+    fn eq(&self, other: &&'b [f32]) -> bool {
+        **self == **other
+    }
+}
+
+impl<'a, 'b, const N: usize> PartialEq<&'b [f32; N]> for Weights<'a> {
+    fn eq(&self, other: &&'b [f32; N]) -> bool {
+        **self == other[..]
+    }
+}
+
+/// The weights of a layer for changing them, as given by
+/// `WeightedLayer::weights_mut`. Shared weights are written through a
+/// lock, which is held until the value is dropped.
+#[derive(Debug)]
+pub enum WeightsMut<'a> {
+    Borrowed(&'a mut Vec<f32>),
+    Locked(RwLockWriteGuard<'a, Vec<f32>>),
+}
+
+impl<'a> Deref for WeightsMut<'a> {
+    type Target = Vec<f32>;
+
+    fn deref(&self) -> &Vec<f32> {
+        match *self {
+            WeightsMut::Borrowed(ref w) => w,
+            WeightsMut::Locked(ref w) => w,
+        }
+    }
+}
+
+impl<'a> DerefMut for WeightsMut<'a> {
+    fn deref_mut(&mut self) -> &mut Vec<f32> {
+        match *self {
+            WeightsMut::Borrowed(ref mut w) => w,
+            WeightsMut::Locked(ref mut w) => w,
+        }
+    }
+}
+
 /// A loss function - also known as an error function.
+///
+/// Element-wise losses implement `loss1`, and get the vector
+/// versions for free. Losses which need access to the whole
+/// prediction vector, such as cross-entropy over a softmax, instead
+/// override `loss_vec`.
 pub trait LossFunction {
-    /// Loss, or error, for a single prediction vs target.
-    fn loss1(&self, f32, f32) -> f32;
+    /// Loss, or error, for a single prediction vs target. Will panic
+    /// if the loss is not element-wise.
+    #[allow(unused_variables)]
+    fn loss1(&self, pred: f32, target: f32) -> f32 {
+        panic!("loss1 is not defined for a vector-valued loss, use loss_vec")
+    }
 
     /// The loss, or error, of the predictions vs the targets.
     fn loss(&self, preds: &[f32], targets: &[f32]) -> Vec<f32> {
@@ -85,13 +460,37 @@ pub trait LossFunction {
         }
         loss
     }
+
+    /// The total loss of a prediction vector vs a target vector. The
+    /// default implementation sums up the element-wise losses.
+    fn loss_vec(&self, preds: &[f32], targets: &[f32]) -> f32 {
+        sum(&self.loss(preds, targets))
+    }
+
+    /// The number of targets of a sample for a network with
+    /// `output_count` outputs. Most losses compare every output to a
+    /// target of its own, but some, such as `mdn::MixtureDensity`,
+    /// predict the parameters of a distribution of fewer targets.
+    fn target_count(&self, output_count: usize) -> usize {
+        output_count
+    }
+
+    /// The total loss of a prediction tensor vs a target tensor of the
+    /// same shape.
+    fn loss_tensor(&self, preds: &Tensor, targets: &Tensor) -> f32 {
+        assert_eq!(preds.shape(), targets.shape(), "predictions and targets differ in shape");
+        self.loss_vec(&preds.values(), &targets.values())
+    }
 }
 
 /// A loss function which can be differentiated.
 pub trait DifferentiableLossFunction: LossFunction {
     /// The derivative of a single loss value with respect to the
-    /// prediction.
-    fn deriv1(&self, f32, f32) -> f32;
+    /// prediction. Will panic if the loss is not element-wise.
+    #[allow(unused_variables)]
+    fn deriv1(&self, pred: f32, target: f32) -> f32 {
+        panic!("deriv1 is not defined for a vector-valued loss, use deriv_vec")
+    }
 
     /// The derivative of the loss with respect to the predictions.
     fn deriv(&self, preds: &[f32], targets: &[f32]) -> Vec<f32> {
@@ -101,9 +500,55 @@ pub trait DifferentiableLossFunction: LossFunction {
         }
         derivs
     }
+
+    /// The derivative of `loss_vec` with respect to each of the
+    /// predictions. The default implementation uses the element-wise
+    /// derivatives.
+    fn deriv_vec(&self, preds: &[f32], targets: &[f32]) -> Vec<f32> {
+        self.deriv(preds, targets)
+    }
 }
 
 /// A training algorithm for a neural network.
 pub trait SupervisedTrainer {
-    fn train(&self, layers: &mut LinkedList<Box<WeightedLayer>>, inputs: &[f32], targets: &[f32]);
+    fn train(&self, layers: &mut LinkedList<Box<dyn WeightedLayer>>, inputs: &[f32], targets: &[f32]);
+
+    /// Trains on the dataset and returns the metrics on the training
+    /// and validation data over the course of training. The default
+    /// trains in one go and records a single epoch; trainers which
+    /// run in epochs record every one of them.
+    fn fit(&self,
+           layers: &mut LinkedList<Box<dyn WeightedLayer>>,
+           train: &Dataset,
+           validation: Option<&Dataset>)
+           -> History {
+        self.train(layers, &train.inputs, &train.targets);
+        History {
+            epochs: vec![Epoch {
+                             epoch: 0,
+                             train: self.evaluate(layers, train),
+                             validation: validation.map(|v| self.evaluate(layers, v)),
+                         }],
+        }
+    }
+
+    /// Measures the network on the dataset, with the loss the trainer
+    /// minimizes. The default uses the squared error.
+    fn evaluate(&self, layers: &LinkedList<Box<dyn WeightedLayer>>, data: &Dataset) -> Metrics {
+        metrics::evaluate(layers, &SquaredError, data)
+    }
+
+    /// The outputs of the network for the inputs, one sample after
+    /// another.
+    fn predict(&self, layers: &LinkedList<Box<dyn WeightedLayer>>, inputs: &[f32]) -> Vec<f32> {
+        network::predict_batch(layers, inputs)
+    }
+
+    /// Trains on tensors whose first axis is the sample, such as a
+    /// batch of images of shape (samples, height, width, channels).
+    fn train_tensors(&self, layers: &mut LinkedList<Box<dyn WeightedLayer>>, inputs: &Tensor, targets: &Tensor) {
+        assert!(inputs.ndim() > 0 && targets.ndim() > 0, "the first axis must be the sample");
+        assert_eq!(inputs.shape()[0], targets.shape()[0], "the number of inputs and targets differ");
+        self.train(layers, &inputs.values(), &targets.values());
+    }
 }
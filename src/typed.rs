@@ -0,0 +1,211 @@
+//! A strongly typed way of defining networks, where the number of
+//! inputs and outputs of every layer is part of its type. Connecting
+//! layers whose shapes do not match is a compile error instead of a
+//! panic during training:
+//!
+//! ```
+//! use scarecrow::typed::{self, Dense, Sigmoid, Tanh};
+//!
+//! let network = typed::input::<2>()
+//!     .push(Dense::<2, 6>::random())
+//!     .push(Tanh)
+//!     .push(Dense::<6, 1>::random())
+//!     .push(Sigmoid);
+//! let y: [f32; 1] = network.output(&[0.0, 1.0]);
+//! # assert!(y[0] > 0.0 && y[0] < 1.0);
+//! ```
+//!
+//! Layers whose shapes do not match do not compile:
+//!
+//! ```compile_fail
+//! use scarecrow::typed::{self, Dense};
+//!
+//! let network = typed::input::<2>()
+//!     .push(Dense::<2, 6>::random())
+//!     .push(Dense::<5, 1>::random());
+//! ```
+//!
+//! Nor does wrapping a layer without checking its shape:
+//!
+//! ```compile_fail
+//! use scarecrow::layers::DenseLayer;
+//! use scarecrow::typed::Dense;
+//!
+//! let layer = Dense::<2, 6>(DenseLayer::random(5, 1));
+//! ```
+//!
+//! The typed network is a thin wrapper of the usual list of
+//! layers, which is available with `layers` and `into_layers` for
+//! everything else in the crate. The list cannot be changed in place,
+//! as adding or removing a layer would break the types.
+use layers::{DenseLayer, HyperbolicLayer, RectifiedLayer, SELULayer, SigmoidLayer};
+use network;
+use traits::{SupervisedTrainer, WeightedLayer};
+
+use std::collections::LinkedList;
+use std::convert::TryInto;
+
+/// A layer with `I` inputs and `O` outputs.
+pub trait TypedLayer<const I: usize, const O: usize> {
+    /// The untyped layer.
+    fn into_layer(self) -> Box<dyn WeightedLayer>;
+}
+
+/// A dense layer of `O` neurons with `I` inputs each. The layer is
+/// private, so that its shape always matches the type.
+pub struct Dense<const I: usize, const O: usize>(DenseLayer);
+
+impl<const I: usize, const O: usize> Dense<I, O> {
+    /// Wraps a dense layer, such as one with trained weights. Panics if
+    /// the layer does not have `I` inputs and `O` neurons.
+    pub fn from_layer(layer: DenseLayer) -> Dense<I, O> {
+        assert_eq!(layer.shape, (I, O), "the layer does not have the shape of the type");
+        Dense(layer)
+    }
+
+    /// The wrapped layer.
+    pub fn layer(&self) -> &DenseLayer {
+        &self.0
+    }
+
+    /// See `DenseLayer::random`.
+    pub fn random() -> Dense<I, O> {
+        Dense(DenseLayer::random(I, O))
+    }
+
+    /// See `DenseLayer::uniform`.
+    pub fn uniform(val: f32) -> Dense<I, O> {
+        Dense(DenseLayer::uniform(val, I, O))
+    }
+}
+
+impl<const I: usize, const O: usize> TypedLayer<I, O> for Dense<I, O> {
+    fn into_layer(self) -> Box<dyn WeightedLayer> {
+        Box::new(self.0)
+    }
+}
+
+/// Hyperbolic tangent of the outputs of the previous layer.
+pub struct Tanh;
+
+/// Logistic sigmoid of the outputs of the previous layer.
+pub struct Sigmoid;
+
+/// Rectified linear activation of the outputs of the previous layer.
+pub struct Relu;
+
+/// Scaled exponential linear activation of the outputs of the previous layer.
+pub struct Selu;
+
+impl<const N: usize> TypedLayer<N, N> for Tanh {
+    fn into_layer(self) -> Box<dyn WeightedLayer> {
+        Box::new(HyperbolicLayer { size: N })
+    }
+}
+
+impl<const N: usize> TypedLayer<N, N> for Sigmoid {
+    fn into_layer(self) -> Box<dyn WeightedLayer> {
+        Box::new(SigmoidLayer { size: N })
+    }
+}
+
+impl<const N: usize> TypedLayer<N, N> for Relu {
+    fn into_layer(self) -> Box<dyn WeightedLayer> {
+        Box::new(RectifiedLayer { size: N })
+    }
+}
+
+impl<const N: usize> TypedLayer<N, N> for Selu {
+    fn into_layer(self) -> Box<dyn WeightedLayer> {
+        Box::new(SELULayer { size: N })
+    }
+}
+
+/// A network with `I` inputs and `O` outputs.
+pub struct TypedNetwork<const I: usize, const O: usize> {
+    layers: LinkedList<Box<dyn WeightedLayer>>,
+}
+
+/// A network without layers, whose output is its `I` inputs, to which
+/// layers are added with `push`.
+pub fn input<const I: usize>() -> TypedNetwork<I, I> {
+    TypedNetwork { layers: LinkedList::new() }
+}
+
+impl<const I: usize, const O: usize> TypedNetwork<I, O> {
+    /// Adds a layer which takes the outputs of the network as inputs.
+    pub fn push<const N: usize, L: TypedLayer<O, N>>(self, layer: L) -> TypedNetwork<I, N> {
+        let mut layers = self.layers;
+        layers.push_back(layer.into_layer());
+        TypedNetwork { layers }
+    }
+
+    pub fn output(&self, x: &[f32; I]) -> [f32; O] {
+        network::output(&self.layers, x)
+            .try_into()
+            .expect("a layer gave a different number of outputs than its type")
+    }
+
+    /// Trains the network on the samples with the trainer.
+    pub fn train<T: SupervisedTrainer>(&mut self, trainer: &T, inputs: &[[f32; I]], targets: &[[f32; O]]) {
+        assert_eq!(inputs.len(), targets.len(), "the number of inputs and targets differ");
+        trainer.train(&mut self.layers, inputs.as_flattened(), targets.as_flattened());
+    }
+
+    pub fn layers(&self) -> &LinkedList<Box<dyn WeightedLayer>> {
+        &self.layers
+    }
+
+    /// Sets the weights and biases of the network, laid out as returned
+    /// by `network::get_weights`. The layers themselves cannot be
+    /// changed, so that their shapes always match the type.
+    pub fn set_weights(&mut self, values: &[f32]) {
+        network::set_weights(&mut self.layers, values);
+    }
+
+    pub fn into_layers(self) -> LinkedList<Box<dyn WeightedLayer>> {
+        self.layers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sgd::SGDTrainer;
+
+    #[test]
+    fn builds_untyped_network() {
+        let network = input::<3>().push(Dense::<3, 2>::uniform(1.0)).push(Relu).push(Dense::<2, 1>::uniform(0.5));
+        assert_eq!(network.layers().len(), 3);
+        assert_eq!(network.output(&[1.0, 1.0, -4.0]), [0.5]);
+        assert_eq!(network.output(&[1.0, 1.0, 1.0]), [4.5]);
+        assert_eq!(input::<4>().output(&[1.0, 2.0, 3.0, 4.0]), [1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn wraps_layer_of_matching_shape() {
+        let dense = Dense::<3, 2>::from_layer(DenseLayer::uniform(0.5, 3, 2));
+        assert_eq!(dense.layer().shape, (3, 2));
+    }
+
+    #[test]
+    #[should_panic(expected = "the layer does not have the shape of the type")]
+    fn rejects_layer_of_other_shape() {
+        Dense::<2, 6>::from_layer(DenseLayer::random(5, 1));
+    }
+
+    #[test]
+    fn sets_weights() {
+        let mut network = input::<2>().push(Dense::<2, 1>::uniform(0.0));
+        network.set_weights(&[1.0, 2.0, 0.5]);
+        assert_eq!(network.output(&[1.0, 1.0]), [3.5]);
+    }
+
+    #[test]
+    fn trains_on_arrays() {
+        let mut network = input::<1>().push(Dense::<1, 1>::uniform(0.0));
+        network.train(&SGDTrainer::new(200, 0.1), &[[0.0], [1.0]], &[[1.0], [3.0]]);
+        let y = network.output(&[2.0]);
+        assert!((y[0] - 5.0).abs() < 0.1, "{:?}", y);
+    }
+}
@@ -1,16 +1,18 @@
 //! Miscellaneous utility functions.
-use rand;
+use rand::Rng;
 use rand::distributions::{Normal, IndependentSample};
 
+use std::borrow::Cow;
+
 /// Returns a vector with the given size where each element is a
-/// random gaussian number, with mean 0 and standard deviation 1.0.
-pub fn normal_vector(size: usize) -> Vec<f32> {
+/// random gaussian number, with mean 0 and standard deviation 1.0,
+/// drawn from the given generator.
+pub fn normal_vector<R: Rng>(size: usize, rng: &mut R) -> Vec<f32> {
     let normal = Normal::new(0.0, 1.0);
-    let mut rng = rand::thread_rng();
 
     let mut result: Vec<f32> = vec![0.0; size];
     for x in result.iter_mut() {
-        *x = normal.ind_sample(&mut rng) as f32;
+        *x = normal.ind_sample(rng) as f32;
     }
     result
 }
@@ -31,6 +33,89 @@ pub fn dot(x: &[f32], y: &[f32]) -> f32 {
     result
 }
 
+/// Sums up a vector with Kahan's compensated summation, which carries
+/// the rounding error of every addition into the next one. The error
+/// does not grow with the length of the vector, unlike that of `sum`.
+pub fn kahan_sum(v: &[f32]) -> f32 {
+    kahan(v.iter().cloned())
+}
+
+/// The dot product of two vectors of equal length, summed with
+/// `kahan_sum`.
+pub fn kahan_dot(x: &[f32], y: &[f32]) -> f32 {
+    assert_eq!(x.len(), y.len());
+    kahan(x.iter().zip(y).map(|(a, b)| a * b))
+}
+
+fn kahan<I: Iterator<Item = f32>>(values: I) -> f32 {
+    let mut total = 0.0f32;
+    let mut compensation = 0.0f32;
+    for v in values {
+        let y = v - compensation;
+        let t = total + y;
+        compensation = (t - total) - y;
+        total = t;
+    }
+    total
+}
+
+/// The number of values below which pairwise reductions sum in a loop.
+const PAIRWISE_BLOCK: usize = 32;
+
+/// Sums up a vector by splitting it in halves and adding their sums,
+/// whose error grows with the logarithm of the length of the vector.
+/// Always adds in the same order for the same length, so the result
+/// is reproducible.
+pub fn pairwise_sum(v: &[f32]) -> f32 {
+    if v.len() <= PAIRWISE_BLOCK {
+        return sum(v);
+    }
+    let (a, b) = v.split_at(v.len() / 2);
+    pairwise_sum(a) + pairwise_sum(b)
+}
+
+/// The dot product of two vectors of equal length, summed pairwise
+/// like `pairwise_sum`.
+pub fn pairwise_dot(x: &[f32], y: &[f32]) -> f32 {
+    assert_eq!(x.len(), y.len());
+    if x.len() <= PAIRWISE_BLOCK {
+        return dot(x, y);
+    }
+    let mid = x.len() / 2;
+    pairwise_dot(&x[..mid], &y[..mid]) + pairwise_dot(&x[mid..], &y[mid..])
+}
+
+/// How long vectors are summed, for callers which choose between speed
+/// and a smaller, reproducible rounding error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Summation {
+    /// One value after another, like `sum` and `dot`
+    #[default]
+    Naive,
+    /// Compensated, like `kahan_sum` and `kahan_dot`
+    Kahan,
+    /// In halves, like `pairwise_sum` and `pairwise_dot`
+    Pairwise,
+}
+
+impl Summation {
+    pub fn sum(&self, v: &[f32]) -> f32 {
+        match *self {
+            Summation::Naive => sum(v),
+            Summation::Kahan => kahan_sum(v),
+            Summation::Pairwise => pairwise_sum(v),
+        }
+    }
+
+    pub fn dot(&self, x: &[f32], y: &[f32]) -> f32 {
+        match *self {
+            Summation::Naive => dot(x, y),
+            Summation::Kahan => kahan_dot(x, y),
+            Summation::Pairwise => pairwise_dot(x, y),
+        }
+    }
+}
+
 /// Element-wise addition of two vectors. They must be of equal length.
 pub fn add(x: &[f32], y: &[f32]) -> Vec<f32> {
     assert_eq!(x.len(), y.len());
@@ -41,6 +126,17 @@ pub fn add(x: &[f32], y: &[f32]) -> Vec<f32> {
     x
 }
 
+/// Element-wise subtraction of two vectors, `x - y`. They must be of
+/// equal length.
+pub fn sub(x: &[f32], y: &[f32]) -> Vec<f32> {
+    assert_eq!(x.len(), y.len());
+    let mut x = x.to_vec();
+    for (a, b) in x.iter_mut().zip(y) {
+        *a -= *b;
+    }
+    x
+}
+
 /// Addition of vector and scalar.
 pub fn add_scalar(x: &[f32], y: f32) -> Vec<f32> {
     let mut x = x.to_vec();
@@ -77,9 +173,260 @@ pub fn product_mut(x: &mut [f32], y: &[f32]) {
     }
 }
 
+/// The logistic function `1 / (1 + exp(-x))`, computed without
+/// overflowing `exp` for inputs of large magnitude.
+pub fn sigmoid(x: f32) -> f32 {
+    if x >= 0.0 {
+        1.0 / (1.0 + (-x).exp())
+    } else {
+        let e = x.exp();
+        e / (1.0 + e)
+    }
+}
+
+/// `log(1 + exp(x))`, the smooth approximation of the rectifier,
+/// without overflow for large inputs or loss of precision for small
+/// ones.
+pub fn softplus(x: f32) -> f32 {
+    x.max(0.0) + (-x.abs()).exp().ln_1p()
+}
+
+/// `log(sigmoid(x))`, which stays finite where the logarithm of the
+/// rounded sigmoid would be minus infinity.
+pub fn log_sigmoid(x: f32) -> f32 {
+    -softplus(-x)
+}
+
+/// `log(sum_i exp(x_i))`, with the maximum value subtracted before
+/// exponentiating to avoid overflow. Minus infinity for an empty vector
+/// or one where every value is minus infinity.
+pub fn log_sum_exp(x: &[f32]) -> f32 {
+    let max = x.iter().fold(f32::NEG_INFINITY, |m, v| m.max(*v));
+    if max == f32::NEG_INFINITY || max.is_nan() {
+        return max;
+    }
+    max + x.iter().map(|v| (v - max).exp()).sum::<f32>().ln()
+}
+
+/// Softmax of a vector, `exp(x_i) / sum_j exp(x_j)`. The maximum
+/// value is subtracted before exponentiating to avoid overflow.
+pub fn softmax(x: &[f32]) -> Vec<f32> {
+    let max = x.iter().fold(f32::NEG_INFINITY, |m, v| m.max(*v));
+    let mut result: Vec<f32> = x.iter().map(|v| (v - max).exp()).collect();
+    let total = sum(&result);
+    for v in result.iter_mut() {
+        *v /= total;
+    }
+    result
+}
+
+/// The logarithm of the softmax of a vector, `x_i - log_sum_exp(x)`,
+/// which stays finite for probabilities too small for an `f32`.
+pub fn log_softmax(x: &[f32]) -> Vec<f32> {
+    let lse = log_sum_exp(x);
+    x.iter().map(|v| v - lse).collect()
+}
+
+/// Softmax of a vector after dividing it by a temperature. Higher
+/// temperatures give softer, more uniform distributions, while a
+/// temperature of 1 is the same as `softmax`.
+pub fn softmax_with_temperature(x: &[f32], temperature: f32) -> Vec<f32> {
+    let scaled: Vec<f32> = x.iter().map(|v| v / temperature).collect();
+    softmax(&scaled)
+}
+
+/// The index of the largest value. Ties go to the first index.
+pub fn argmax(x: &[f32]) -> usize {
+    let mut best = 0;
+    for (i, v) in x.iter().enumerate() {
+        if *v > x[best] {
+            best = i;
+        }
+    }
+    best
+}
+
+/// Samples an index with the given probabilities, which should sum
+/// to 1. Any remaining probability mass goes to the last index with a
+/// nonzero probability, so an index with zero probability is never
+/// sampled.
+pub fn sample_index<R: Rng>(probabilities: &[f32], rng: &mut R) -> usize {
+    assert!(!probabilities.is_empty(), "cannot sample from an empty distribution");
+    let r: f32 = rng.gen();
+    let mut cumulative = 0.0;
+    for (i, p) in probabilities.iter().enumerate() {
+        cumulative += p;
+        if r < cumulative {
+            return i;
+        }
+    }
+    probabilities.iter().rposition(|&p| p > 0.0).unwrap_or(probabilities.len() - 1)
+}
+
+/// Samples an index from the softmax of the logits at the given
+/// temperature. A temperature of zero always picks the largest logit.
+pub fn sample_with_temperature<R: Rng>(logits: &[f32], temperature: f32, rng: &mut R) -> usize {
+    if temperature <= 0.0 {
+        argmax(logits)
+    } else {
+        sample_index(&softmax_with_temperature(logits, temperature), rng)
+    }
+}
+
+/// Picks a uniformly random index with probability `epsilon`, and the
+/// index of the largest value otherwise.
+pub fn epsilon_greedy<R: Rng>(values: &[f32], epsilon: f32, rng: &mut R) -> usize {
+    assert!(!values.is_empty(), "cannot pick an index from no values");
+    if rng.gen::<f32>() < epsilon {
+        rng.gen_range(0, values.len())
+    } else {
+        argmax(values)
+    }
+}
+
+/// A row-major matrix. The values are either owned, or borrowed from
+/// somewhere else such as the weights of a layer, which is what
+/// `Matrix::view` creates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix<'a> {
+    pub rows: usize,
+    pub cols: usize,
+    pub data: Cow<'a, [f32]>,
+}
+
+impl Matrix<'static> {
+    pub fn new(data: Vec<f32>, rows: usize, cols: usize) -> Matrix<'static> {
+        assert_eq!(data.len(), rows * cols, "the values do not fit a {}x{} matrix", rows, cols);
+        Matrix {
+            rows,
+            cols,
+            data: Cow::Owned(data),
+        }
+    }
+
+    pub fn zeros(rows: usize, cols: usize) -> Matrix<'static> {
+        Matrix::new(vec![0.0; rows * cols], rows, cols)
+    }
+
+    /// The outer product of two vectors, `a b^T`, with one row per
+    /// value of `a`.
+    pub fn outer(a: &[f32], b: &[f32]) -> Matrix<'static> {
+        let mut data = Vec::with_capacity(a.len() * b.len());
+        for x in a {
+            data.extend(b.iter().map(|y| x * y));
+        }
+        Matrix::new(data, a.len(), b.len())
+    }
+}
+
+impl<'a> Matrix<'a> {
+    /// A matrix which borrows its values.
+    pub fn view(data: &'a [f32], rows: usize, cols: usize) -> Matrix<'a> {
+        assert_eq!(data.len(), rows * cols, "the values do not fit a {}x{} matrix", rows, cols);
+        Matrix {
+            rows,
+            cols,
+            data: Cow::Borrowed(data),
+        }
+    }
+
+    /// Row `i` of the matrix.
+    pub fn row(&self, i: usize) -> &[f32] {
+        &self.data[i * self.cols..(i + 1) * self.cols]
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> f32 {
+        self.data[row * self.cols + col]
+    }
+
+    /// The transpose, with the rows and columns swapped.
+    pub fn transpose(&self) -> Matrix<'static> {
+        let mut data = Vec::with_capacity(self.data.len());
+        for c in 0..self.cols {
+            data.extend((0..self.rows).map(|r| self.get(r, c)));
+        }
+        Matrix::new(data, self.cols, self.rows)
+    }
+
+    /// The matrix product `self other`.
+    pub fn matmul(&self, other: &Matrix) -> Matrix<'static> {
+        assert_eq!(self.cols, other.rows, "cannot multiply {}x{} by {}x{}", self.rows, self.cols, other.rows, other.cols);
+        let data = gemm(&self.data, &other.data, self.rows, self.cols, other.cols);
+        Matrix::new(data, self.rows, other.cols)
+    }
+
+    /// The product of the matrix and a column vector.
+    pub fn matvec(&self, x: &[f32]) -> Vec<f32> {
+        assert_eq!(self.cols, x.len(), "cannot multiply {}x{} by a vector of {}", self.rows, self.cols, x.len());
+        gemm(&self.data, x, self.rows, self.cols, 1)
+    }
+
+    /// The product of a row vector and the matrix, `x^T self`, which is
+    /// the same as multiplying the transpose with `x`.
+    pub fn vecmat(&self, x: &[f32]) -> Vec<f32> {
+        Matrix::view(x, 1, x.len()).matmul(self).into_vec()
+    }
+
+    /// Multiplies every value in row `i` with `scales[i]`.
+    pub fn scale_rows(&self, scales: &[f32]) -> Matrix<'static> {
+        assert_eq!(self.rows, scales.len());
+        let mut data = self.data.to_vec();
+        for (row, s) in data.chunks_mut(self.cols).zip(scales) {
+            for v in row.iter_mut() {
+                *v *= s;
+            }
+        }
+        Matrix::new(data, self.rows, self.cols)
+    }
+
+    /// The values, row by row.
+    pub fn into_vec(self) -> Vec<f32> {
+        self.data.into_owned()
+    }
+}
+
+/// The product of an `m`x`k` and a `k`x`n` row-major matrix.
+#[cfg(not(feature = "matrixmultiply"))]
+fn gemm(a: &[f32], b: &[f32], m: usize, k: usize, n: usize) -> Vec<f32> {
+    let mut c = vec![0.0; m * n];
+    if n == 1 {
+        for (c, a) in c.iter_mut().zip(a.chunks(k)) {
+            *c = dot(a, b);
+        }
+        return c;
+    }
+    for (out, a) in c.chunks_mut(n).zip(a.chunks(k)) {
+        for (x, b) in a.iter().zip(b.chunks(n)) {
+            for (o, y) in out.iter_mut().zip(b) {
+                *o += x * y;
+            }
+        }
+    }
+    c
+}
+
+/// The product of an `m`x`k` and a `k`x`n` row-major matrix.
+#[cfg(feature = "matrixmultiply")]
+fn gemm(a: &[f32], b: &[f32], m: usize, k: usize, n: usize) -> Vec<f32> {
+    assert!(a.len() == m * k && b.len() == k * n);
+    let mut c = vec![0.0; m * n];
+    // The strides are given in elements, as (row stride, column stride)
+    unsafe {
+        ::matrixmultiply::sgemm(m, k, n,
+                                1.0,
+                                a.as_ptr(), k as isize, 1,
+                                b.as_ptr(), n as isize, 1,
+                                0.0,
+                                c.as_mut_ptr(), n as isize, 1);
+    }
+    c
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use random::SeededRng;
+    use testing::assert_close;
 
     #[test]
     fn dot_test() {
@@ -104,6 +451,14 @@ mod tests {
         assert_eq!(add(&a, &b), vec![5.0, 7.0, 9.0]);
     }
 
+    #[test]
+    fn sub_test() {
+        let a = vec![1.0, 2.0, 3.0];
+        let b = vec![4.0, 5.0, 6.0];
+
+        assert_eq!(sub(&a, &b), vec![-3.0, -3.0, -3.0]);
+    }
+
     #[test]
     fn product_test() {
         let a = vec![1.0, 2.0, 3.0];
@@ -112,8 +467,127 @@ mod tests {
         assert_eq!(product(&a, &b), vec![4.0, 10.0, 18.0]);
     }
 
+    #[test]
+    fn softmax_test() {
+        let s = softmax(&[1.0, 2.0, 3.0]);
+
+        assert!((sum(&s) - 1.0).abs() < 0.00001);
+        assert!((s[2] - 0.66524096).abs() < 0.00001);
+        // Large values must not overflow
+        assert_eq!(softmax(&[1000.0, 1000.0]), vec![0.5, 0.5]);
+    }
+
+    #[test]
+    fn compensated_sums() {
+        // Adding many small values to a large one loses them all
+        let mut v = vec![1.0e8f32];
+        v.extend(vec![1.0; 10000]);
+        assert_eq!(sum(&v), 1.0e8);
+        assert_eq!(kahan_sum(&v), 1.0e8 + 10000.0);
+
+        let x = vec![0.1f32; 100000];
+        let exact = 10000.0;
+        assert!((pairwise_sum(&x) - exact).abs() < (sum(&x) - exact).abs());
+        assert!((kahan_dot(&x, &vec![1.0; 100000]) - exact).abs() < 1e-2);
+        assert_eq!(pairwise_dot(&x, &vec![1.0; 100000]), pairwise_sum(&x));
+
+        for s in [Summation::Naive, Summation::Kahan, Summation::Pairwise].iter() {
+            assert_eq!(s.sum(&[1.0, 2.0, 3.0]), 6.0);
+            assert_eq!(s.dot(&[1.0, 2.0], &[3.0, 4.0]), 11.0);
+        }
+    }
+
+    #[test]
+    fn stable_primitives() {
+        assert_eq!(sigmoid(0.0), 0.5);
+        assert_eq!(sigmoid(-999999.0), 0.0);
+        assert_eq!(sigmoid(999999.0), 1.0);
+        assert!((sigmoid(-2.0) + sigmoid(2.0) - 1.0).abs() < 1e-6);
+
+        assert!((softplus(0.0) - 2.0f32.ln()).abs() < 1e-6);
+        assert_eq!(softplus(1000.0), 1000.0);
+        assert_eq!(softplus(-1000.0), 0.0);
+        assert_eq!(log_sigmoid(-1000.0), -1000.0);
+        assert!(log_sigmoid(1000.0).abs() < 1e-6);
+
+        assert!((log_sum_exp(&[0.0, 0.0]) - 2.0f32.ln()).abs() < 1e-6);
+        assert_eq!(log_sum_exp(&[1000.0, 1000.0]), 1000.0 + 2.0f32.ln());
+        assert_eq!(log_sum_exp(&[]), f32::NEG_INFINITY);
+        assert_eq!(log_softmax(&[1000.0, -1000.0]), vec![0.0, -2000.0]);
+    }
+
+    #[test]
+    fn softmax_with_temperature_test() {
+        let x = [1.0, 2.0, 3.0];
+
+        assert_eq!(softmax_with_temperature(&x, 1.0), softmax(&x));
+        let soft = softmax_with_temperature(&x, 10.0);
+        assert!(soft[2] < softmax(&x)[2]);
+        assert!((sum(&soft) - 1.0).abs() < 0.00001);
+    }
+
+    #[test]
+    fn sampling_test() {
+        let mut rng = SeededRng::new(1);
+        let mut counts = [0; 3];
+        for _ in 0..1000 {
+            counts[sample_index(&[0.2, 0.0, 0.8], &mut rng)] += 1;
+        }
+        assert_eq!(counts[1], 0);
+        assert!(counts[0] > 150 && counts[0] < 250);
+        // The mass missing from the sum goes to the last possible index
+        for _ in 0..100 {
+            assert_eq!(sample_index(&[0.0, 0.5, 0.0], &mut rng), 1);
+        }
+
+        assert_eq!(argmax(&[1.0, 3.0, 3.0]), 1);
+        assert_eq!(sample_with_temperature(&[1.0, 3.0, 2.0], 0.0, &mut rng), 1);
+        assert_eq!(epsilon_greedy(&[1.0, 3.0, 2.0], 0.0, &mut rng), 1);
+
+        // The same seed gives the same samples
+        let (mut r1, mut r2) = (SeededRng::new(5), SeededRng::new(5));
+        let a: Vec<usize> = (0..10).map(|_| sample_with_temperature(&[1.0, 1.0], 1.0, &mut r1)).collect();
+        let b: Vec<usize> = (0..10).map(|_| sample_with_temperature(&[1.0, 1.0], 1.0, &mut r2)).collect();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot pick an index from no values")]
+    fn epsilon_greedy_without_values() {
+        epsilon_greedy(&[], 1.0, &mut SeededRng::new(1));
+    }
+
     #[test]
     fn normal_vector_test() {
-        assert_eq!(normal_vector(9).len(), 9);
+        let mut rng = SeededRng::new(1);
+        let v = normal_vector(9, &mut rng);
+
+        assert_eq!(v.len(), 9);
+        assert_eq!(v, normal_vector(9, &mut SeededRng::new(1)));
+    }
+
+    #[test]
+    fn matrix_products() {
+        let a = Matrix::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 2, 3);
+        let b = Matrix::new(vec![1.0, 0.0, 0.0, 1.0, 1.0, 1.0], 3, 2);
+
+        assert_eq!(a.matmul(&b), Matrix::new(vec![4.0, 5.0, 10.0, 11.0], 2, 2));
+        assert_eq!(a.matvec(&[1.0, 0.0, -1.0]), vec![-2.0, -2.0]);
+        assert_close(a.vecmat(&[1.0, -1.0]), a.transpose().matvec(&[1.0, -1.0]), 1e-6);
+    }
+
+    #[test]
+    fn matrix_transpose_and_outer() {
+        let weights = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let a = Matrix::view(&weights, 2, 3);
+        let t = a.transpose();
+
+        assert_eq!((t.rows, t.cols), (3, 2));
+        assert_eq!(t.row(0), &[1.0, 4.0]);
+        assert_eq!(t.transpose(), a);
+
+        let o = Matrix::outer(&[1.0, 2.0], &[3.0, 4.0, 5.0]);
+        assert_eq!(o.into_vec(), vec![3.0, 4.0, 5.0, 6.0, 8.0, 10.0]);
+        assert_eq!(a.scale_rows(&[0.0, 2.0]).row(1), &[8.0, 10.0, 12.0]);
     }
 }
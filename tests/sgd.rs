@@ -1,10 +1,18 @@
 extern crate scarecrow;
 
+use scarecrow::data::Dataset;
+use scarecrow::data::augment::{Augmentation, GaussianJitter};
+use scarecrow::data::target::{LogTransform, Standardize};
 use scarecrow::traits::*;
 use scarecrow::layers::*;
+use scarecrow::loss::Reduction;
 use scarecrow::sgd::*;
+use scarecrow::random::{RngProvider, SeededRng};
+use scarecrow::utils::normal_vector;
 
 use std::collections::LinkedList;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 #[test]
 fn train_xor() {
@@ -13,11 +21,13 @@ fn train_xor() {
     let targets = vec![0.0, 1.0, 1.0, 0.0];
 
     // Store layers in a list
-    let mut layers: LinkedList<Box<WeightedLayer>> = LinkedList::new();
+    let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+    // A fixed seed makes the test reproducible
+    let mut rng = SeededRng::new(1);
 
-    layers.push_back(Box::new(DenseLayer::random(2, 6)));
+    layers.push_back(Box::new(DenseLayer::random_with(2, 6, &mut rng)));
     layers.push_back(Box::new(HyperbolicLayer { size: 6 }));
-    layers.push_back(Box::new(DenseLayer::random(6, 1)));
+    layers.push_back(Box::new(DenseLayer::random_with(6, 1, &mut rng)));
     layers.push_back(Box::new(SigmoidLayer { size: 1 }));
 
     // Calculate initial output
@@ -33,7 +43,9 @@ fn train_xor() {
         // assert!(o[0] - 0.502 < 0.01);
     }
 
-    let trainer = SGDTrainer::new(1000, 0.1);
+    // The epochs are an upper bound. An average loss below 0.0025 over
+    // the four samples puts each of them below the 0.01 checked below.
+    let trainer = SGDTrainer::new(1000, 0.1).stop_when_loss_below(0.0025);
 
     trainer.train(&mut layers, &inputs, &targets);
 
@@ -50,3 +62,453 @@ fn train_xor() {
         assert!(trainer.loss.loss1(o[0], t[0]) < 0.01);
     }
 }
+
+#[test]
+fn stop_when_loss_below() {
+    let inputs = vec![0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 1.0];
+    let targets = vec![0.0, 1.0, 1.0, 0.0];
+    let mut rng = SeededRng::new(1);
+    let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+    layers.push_back(Box::new(DenseLayer::random_with(2, 6, &mut rng)));
+    layers.push_back(Box::new(HyperbolicLayer { size: 6 }));
+    layers.push_back(Box::new(DenseLayer::random_with(6, 1, &mut rng)));
+    layers.push_back(Box::new(SigmoidLayer { size: 1 }));
+
+    let mut trainer = SGDTrainer::new(1000, 0.1).stop_when_loss_below(0.01);
+    trainer.batch_size = Some(2);
+    let mut iter = trainer.iter(&mut layers, &inputs, &targets);
+    let mut epochs = Vec::new();
+    for result in iter.by_ref() {
+        if result.batch == 0 {
+            epochs.push(0.0);
+        }
+        *epochs.last_mut().unwrap() += result.loss / 2.0;
+    }
+    assert!(iter.converged());
+    assert!(epochs.len() < 1000);
+    // Only the last epoch is below the threshold
+    assert!(epochs[epochs.len() - 1] < 0.01);
+    assert!(epochs[..epochs.len() - 1].iter().all(|&l| l >= 0.01));
+
+    // Without a threshold every epoch is trained
+    let trainer = SGDTrainer::new(3, 0.1);
+    let mut iter = trainer.iter(&mut layers, &inputs, &targets);
+    assert_eq!(iter.by_ref().count(), 3);
+    assert!(!iter.converged());
+}
+
+#[test]
+fn iterate_batches() {
+    let inputs = vec![0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 1.0];
+    let targets = vec![0.0, 1.0, 1.0, 0.0];
+
+    let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+
+    layers.push_back(Box::new(DenseLayer::random(2, 6)));
+    layers.push_back(Box::new(HyperbolicLayer { size: 6 }));
+    layers.push_back(Box::new(DenseLayer::random(6, 1)));
+    layers.push_back(Box::new(SigmoidLayer { size: 1 }));
+
+    let mut trainer = SGDTrainer::new(3, 0.1);
+    trainer.batch_size = Some(3);
+
+    let results: Vec<BatchResult> = trainer.iter(&mut layers, &inputs, &targets).collect();
+
+    // Four samples in batches of three gives two batches per epoch
+    let steps: Vec<(usize, usize)> = results.iter().map(|r| (r.epoch, r.batch)).collect();
+    assert_eq!(steps, vec![(0, 0), (0, 1), (1, 0), (1, 1), (2, 0), (2, 1)]);
+    for r in results {
+        assert!(r.loss >= 0.0);
+    }
+
+    // Stopping early leaves the rest of the epochs untouched
+    assert_eq!(trainer.iter(&mut layers, &inputs, &targets).take(1).count(), 1);
+}
+
+#[test]
+fn mean_reduction_is_independent_of_dataset_size() {
+    let inputs = vec![0.0, 1.0, 2.0];
+    let targets = vec![1.0, 3.0, 5.0];
+    let doubled_inputs: Vec<f32> = inputs.iter().chain(inputs.iter()).cloned().collect();
+    let doubled_targets: Vec<f32> = targets.iter().chain(targets.iter()).cloned().collect();
+
+    let mut trainer = SGDTrainer::new(10, 0.1);
+    trainer.reduction = Reduction::Mean;
+
+    let mut once: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+    once.push_back(Box::new(DenseLayer::uniform(0.0, 1, 1)));
+    trainer.train(&mut once, &inputs, &targets);
+
+    let mut twice: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+    twice.push_back(Box::new(DenseLayer::uniform(0.0, 1, 1)));
+    trainer.train(&mut twice, &doubled_inputs, &doubled_targets);
+
+    let x = [1.5];
+    let a = once.front().unwrap().output(&x)[0];
+    let b = twice.front().unwrap().output(&x)[0];
+    assert!((a - b).abs() < 0.0001);
+}
+
+#[test]
+fn fit_records_every_epoch() {
+    // y = 2x + 1
+    let train = Dataset::new(vec![0.0, 0.5, 1.0, 1.5], vec![1.0, 2.0, 3.0, 4.0], 1, 1);
+    let validation = Dataset::new(vec![0.25, 1.25], vec![1.5, 3.5], 1, 1);
+
+    let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+    layers.push_back(Box::new(DenseLayer::uniform(0.0, 1, 1)));
+
+    let mut trainer = SGDTrainer::new(50, 0.05);
+    trainer.batch_size = Some(3);
+    let history = trainer.fit(&mut layers, &train, Some(&validation));
+
+    assert_eq!(history.epochs.len(), 50);
+    assert_eq!(history.epochs[49].epoch, 49);
+    let losses = history.validation_loss();
+    assert_eq!(losses.len(), 50);
+    assert!(losses[49] < losses[0]);
+
+    let last = history.last().unwrap();
+    assert_eq!(trainer.evaluate(&layers, &validation), *last.validation.as_ref().unwrap());
+    assert_eq!(last.train.samples, 4);
+
+    let predictions = trainer.predict(&layers, &validation.inputs);
+    assert_eq!(predictions.len(), 2);
+    assert!((predictions[1] - 3.5).abs() < 0.2, "{:?}", predictions);
+}
+
+#[test]
+fn invalid_configuration() {
+    let inputs = vec![0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 1.0];
+    let targets = vec![0.0, 1.0, 1.0, 0.0];
+    let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+    layers.push_back(Box::new(DenseLayer::uniform(0.5, 2, 3)));
+    layers.push_back(Box::new(DenseLayer::uniform(0.5, 3, 1)));
+
+    let mut trainer = SGDTrainer::new(10, 0.1);
+    assert_eq!(trainer.validate(&layers, &inputs, &targets), Ok(()));
+    assert_eq!(trainer.validate(&layers, &inputs[..7], &targets),
+               Err(ConfigError::Inputs { values: 7, input_count: 2 }));
+    assert_eq!(trainer.validate(&layers, &inputs, &targets[..3]),
+               Err(ConfigError::Samples { inputs: 4, targets: 3 }));
+
+    trainer.batch_size = Some(5);
+    assert_eq!(trainer.validate(&layers, &inputs, &targets),
+               Err(ConfigError::BatchSize { batch_size: 5, samples: 4 }));
+    trainer.batch_size = None;
+
+    trainer.rate = f32::NAN;
+    let before = layers.front().unwrap().weights().unwrap().to_vec();
+    let error = trainer.try_train(&mut layers, &inputs, &targets).unwrap_err();
+    assert_eq!(error.to_string(), "the learning rate must be positive and finite, not NaN");
+    assert_eq!(layers.front().unwrap().weights().unwrap(), &before[..]);
+    trainer.rate = 0.1;
+
+    layers.push_back(Box::new(DenseLayer::uniform(0.5, 2, 1)));
+    assert_eq!(trainer.validate(&layers, &inputs, &targets),
+               Err(ConfigError::Layers { layer: 1, outputs: 1, inputs: 2 }));
+}
+
+#[test]
+fn empty_network_and_dataset() {
+    let trainer = SGDTrainer::new(10, 0.1);
+    let mut empty: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+    assert_eq!(trainer.try_train(&mut empty, &[1.0], &[1.0]), Err(ConfigError::EmptyNetwork));
+    // Training without layers or samples does nothing
+    trainer.train(&mut empty, &[1.0], &[1.0]);
+    assert_eq!(trainer.iter(&mut empty, &[1.0], &[1.0]).count(), 0);
+
+    let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+    layers.push_back(Box::new(DenseLayer::uniform(0.5, 2, 1)));
+    assert_eq!(trainer.try_train(&mut layers, &[], &[]), Err(ConfigError::EmptyDataset));
+    assert_eq!(trainer.iter(&mut layers, &[], &[]).count(), 0);
+    assert_eq!(layers.front().unwrap().weights().unwrap(), &[0.5, 0.5]);
+}
+
+#[test]
+fn transformed_targets() {
+    // y = 1000x + 5000, far from the scale of the initial outputs
+    let train = Dataset::new(vec![0.0, 0.5, 1.0, 1.5, 2.0],
+                             vec![5000.0, 5500.0, 6000.0, 6500.0, 7000.0],
+                             1,
+                             1);
+    let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+    layers.push_back(Box::new(DenseLayer::uniform(0.0, 1, 1)));
+
+    let mut trainer = SGDTrainer::new(200, 0.05);
+    trainer.target_transform = Some(Box::new(Standardize::fit(&train)));
+    let history = trainer.fit(&mut layers, &train, None);
+
+    // The metrics and predictions are in the original units
+    let last = &history.last().unwrap().train;
+    assert!(last.mean_absolute_error < 10.0, "{:?}", last);
+    let y = trainer.predict(&layers, &[1.0]);
+    assert!((y[0] - 6000.0).abs() < 10.0, "{:?}", y);
+
+    trainer.target_transform = Some(Box::new(LogTransform));
+    trainer.train(&mut layers, &train.inputs, &train.targets);
+    let y = trainer.predict(&layers, &[1.0]);
+    assert!((y[0] - 6000.0).abs() < 100.0, "{:?}", y);
+}
+
+#[test]
+fn shuffled_epochs() {
+    let inputs: Vec<f32> = (0..8).map(|i| i as f32 / 8.0).collect();
+    let targets: Vec<f32> = inputs.iter().map(|x| 2.0 * x + 1.0).collect();
+    let run = |shuffle: bool, seed: u64| {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(0.0, 1, 1)));
+        let mut trainer = SGDTrainer::new(3, 0.1);
+        trainer.batch_size = Some(2);
+        trainer.shuffle = shuffle;
+        trainer.rng = RngProvider::seeded(seed);
+        trainer.iter(&mut layers, &inputs, &targets).map(|r| r.loss).collect::<Vec<f32>>()
+    };
+
+    // The same seed gives the same batches, another seed other ones
+    assert_eq!(run(true, 1), run(true, 1));
+    assert_ne!(run(true, 1), run(true, 2));
+    assert_ne!(run(true, 1), run(false, 1));
+    assert_eq!(run(true, 1).len(), 12);
+}
+
+#[test]
+fn augmentation_only_while_training() {
+    let data = Dataset::new(vec![0.0, 0.5, 1.0, 1.5], vec![1.0, 2.0, 3.0, 4.0], 1, 1);
+    let run = |augmentation: Option<Augmentation>| {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(0.0, 1, 1)));
+        let mut trainer = SGDTrainer::new(5, 0.1);
+        trainer.batch_size = Some(2);
+        trainer.augmentation = augmentation;
+        trainer.train(&mut layers, &data.inputs, &data.targets);
+        (trainer, layers)
+    };
+
+    let seen = Arc::new(AtomicUsize::new(0));
+    let counter = seen.clone();
+    let (trainer, layers) = run(Some(Augmentation::new(vec![Box::new(move |_: &mut [f32], _: &mut SeededRng| {
+        counter.fetch_add(1, Ordering::SeqCst);
+    })])));
+    assert_eq!(seen.load(Ordering::SeqCst), 20);
+    trainer.evaluate(&layers, &data);
+    trainer.predict(&layers, &data.inputs);
+    assert_eq!(seen.load(Ordering::SeqCst), 20);
+
+    let (_, plain) = run(None);
+    let (_, jittered) = run(Some(Augmentation::new(vec![Box::new(GaussianJitter { std: 0.1 })])));
+    assert_ne!(plain.front().unwrap().weights(), jittered.front().unwrap().weights());
+}
+
+#[test]
+fn resume_from_checkpoint() {
+    let mut rng = SeededRng::new(7);
+    let inputs = normal_vector(20, &mut rng);
+    let targets: Vec<f32> = inputs.chunks(2).map(|x| x[0] - 0.5 * x[1]).collect();
+    let trainer = |seed: u64| {
+        let mut trainer = SGDTrainer::new(3, 0.05);
+        trainer.batch_size = Some(3);
+        trainer.accumulate = 2;
+        trainer.shuffle = true;
+        trainer.rng = RngProvider::seeded(seed);
+        let mut augmentation = Augmentation::new(vec![Box::new(GaussianJitter { std: 0.1 })]);
+        augmentation.rng = RngProvider::seeded(seed + 1);
+        trainer.augmentation = Some(augmentation);
+        trainer
+    };
+    let network = || {
+        let mut layers = scarecrow::serialize::LayerRegistry::new()
+            .parse_spec("dense inputs=2 neurons=4; tanh size=4; dense inputs=4 neurons=1")
+            .unwrap();
+        scarecrow::network::set_weights(&mut layers, &normal_vector(17, &mut SeededRng::new(8)));
+        layers
+    };
+
+    let mut layers = network();
+    let trainer_a = trainer(1);
+    let all: Vec<BatchResult> = trainer_a.iter(&mut layers, &inputs, &targets).collect();
+    let trained = scarecrow::network::get_weights(&layers);
+
+    // Stop in the middle of the second epoch, with a step accumulated
+    let mut interrupted = network();
+    let mut saved = Vec::new();
+    {
+        let trainer_b = trainer(1);
+        let mut iter = trainer_b.iter(&mut interrupted, &inputs, &targets);
+        let first: Vec<BatchResult> = iter.by_ref().take(7).collect();
+        assert_eq!(first[..], all[..7]);
+        let state = iter.state();
+        assert_eq!((state.epoch, state.batch, state.pending), (1, 3, 1));
+        scarecrow::serialize::save_checkpoint(iter.layers(), &state, &mut saved).unwrap();
+    }
+
+    // The generators of a new trainer start elsewhere until resumed
+    let (mut restored, state) = scarecrow::serialize::LayerRegistry::new().load_checkpoint(&saved[..]).unwrap();
+    let trainer_c = trainer(99);
+    let rest: Vec<BatchResult> = trainer_c.resume(&mut restored, &inputs, &targets, &state).collect();
+    assert_eq!(rest[..], all[7..]);
+    assert_eq!(scarecrow::network::get_weights(&restored), trained);
+}
+
+#[test]
+fn resume_fit_with_plateau() {
+    let mut rng = SeededRng::new(7);
+    let inputs = normal_vector(20, &mut rng);
+    let targets: Vec<f32> = inputs.chunks(2).map(|x| x[0] - 0.5 * x[1]).collect();
+    let data = Dataset::new(inputs, targets, 2, 1);
+    let trainer = || {
+        let mut trainer = SGDTrainer::new(8, 0.5);
+        trainer.batch_size = Some(4);
+        trainer.shuffle = true;
+        trainer.rng = RngProvider::seeded(1);
+        // Reduces the rate after every epoch without improvement
+        trainer.plateau = Some(scarecrow::schedule::ReduceOnPlateau::new(0.5, 0));
+        trainer
+    };
+    let network = || {
+        let mut layers = scarecrow::serialize::LayerRegistry::new()
+            .parse_spec("dense inputs=2 neurons=4; tanh size=4; dense inputs=4 neurons=1")
+            .unwrap();
+        scarecrow::network::set_weights(&mut layers, &normal_vector(17, &mut SeededRng::new(8)));
+        layers
+    };
+
+    // Keep the checkpoint of the third epoch
+    let mut layers = network();
+    let mut checkpoints = Vec::new();
+    let all = trainer().fit_resumable(&mut layers, &data, None, None, |layers, state| {
+        let mut saved = Vec::new();
+        scarecrow::serialize::save_checkpoint(layers, state, &mut saved).unwrap();
+        checkpoints.push((saved, state.rate));
+    });
+    let rates: Vec<f32> = checkpoints.iter().map(|c| c.1).collect();
+    assert!(rates.windows(2).any(|r| r[1] < r[0]), "the schedule never reduced the rate: {:?}", rates);
+
+    let (mut restored, state) = scarecrow::serialize::LayerRegistry::new().load_checkpoint(&checkpoints[2].0[..]).unwrap();
+    assert!(state.plateau.is_some());
+    let rest = trainer().fit_resumable(&mut restored, &data, None, Some(&state), |_, _| {});
+    assert_eq!(rest.epochs[..], all.epochs[3..]);
+    assert_eq!(scarecrow::network::get_weights(&restored), scarecrow::network::get_weights(&layers));
+}
+
+#[test]
+fn accumulated_batches() {
+    let inputs: Vec<f32> = (0..8).map(|i| i as f32 / 8.0).collect();
+    let targets: Vec<f32> = inputs.iter().map(|x| 2.0 * x + 1.0).collect();
+    let run = |batch_size: usize, accumulate: usize| {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(0.0, 1, 1)));
+        let mut trainer = SGDTrainer::new(3, 0.1);
+        trainer.batch_size = Some(batch_size);
+        trainer.reduction = Reduction::Mean;
+        trainer.accumulate = accumulate;
+        trainer.train(&mut layers, &inputs, &targets);
+        scarecrow::network::get_weights(&layers)
+    };
+
+    // Two batches of two accumulated train like a batch of four
+    let large = run(4, 1);
+    for (a, b) in run(2, 2).iter().zip(&large) {
+        assert!((a - b).abs() < 1e-6);
+    }
+    assert_ne!(run(2, 1), large);
+    // The last, partial group of an epoch is applied too
+    assert_eq!(run(2, 3).len(), 2);
+    assert_ne!(run(2, 3), vec![0.0, 0.0]);
+}
+
+#[test]
+fn backward_and_apply_gradients() {
+    let network = || {
+        let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+        layers.push_back(Box::new(DenseLayer::uniform(0.5, 2, 2)));
+        layers.push_back(Box::new(SigmoidLayer { size: 2 }));
+        layers.push_back(Box::new(DenseLayer::uniform(0.5, 2, 1)));
+        layers
+    };
+    let (x, t) = ([1.0, -2.0], [0.3]);
+
+    let mut layers = network();
+    let before = scarecrow::network::get_weights(&layers);
+    let grads = backward(&layers, &x, &t, &scarecrow::loss::SquaredError);
+    assert_eq!(scarecrow::network::get_weights(&layers), before);
+    assert_eq!(grads.len(), 3);
+    assert_eq!((grads[0].weights.len(), grads[0].bias.len()), (4, 2));
+    assert!(grads[1].weights.is_empty() && grads[1].bias.is_empty());
+
+    // One step matches that of the trainer
+    apply_gradients(&mut layers, &grads, 0.1);
+    let mut trained = network();
+    SGDTrainer::new(1, 0.1).train(&mut trained, &x, &t);
+    assert_eq!(scarecrow::network::get_weights(&layers),
+               scarecrow::network::get_weights(&trained));
+
+    // Gradients of two losses combine
+    let mut combined = backward(&trained, &x, &t, &scarecrow::loss::SquaredError);
+    let other = backward(&trained, &x, &t, &scarecrow::loss::SquaredError);
+    for (g, o) in combined.iter_mut().zip(&other) {
+        g.add(o);
+        g.scale(0.5);
+    }
+    assert_eq!(combined, other);
+}
+
+#[test]
+fn forward_records_activations() {
+    let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+    layers.push_back(Box::new(DenseLayer::uniform(0.5, 2, 2)));
+    layers.push_back(Box::new(RectifiedLayer { size: 2 }));
+    let x = [1.0, -3.0];
+
+    let activations = forward(&layers, &x);
+    assert_eq!(activations.len(), 2);
+    // Every layer's inputs are the output of the one before it
+    assert_eq!(activations.inputs(0).as_ptr(), x.as_ptr());
+    assert_eq!(activations.inputs(1).as_ptr(), activations.output(0).as_ptr());
+    assert_eq!(activations.output(0), &[-0.5, -0.5]);
+    assert_eq!(activations.last(), &[0.0, 0.0]);
+    assert_eq!(activations.iter().next_back(), Some((&[-0.5, -0.5][..], &[0.0, 0.0][..])));
+    assert_eq!(activations.into_last(), vec![0.0, 0.0]);
+
+    let empty = LinkedList::new();
+    assert_eq!(forward(&empty, &x).into_last(), x.to_vec());
+}
+
+#[test]
+fn variational_autoencoder() {
+    // Points on a parabola, which a single latent value can describe
+    let mut points = Vec::new();
+    for i in 0..256 {
+        let x = -1.0 + 2.0 * i as f32 / 255.0;
+        points.extend_from_slice(&[x, x * x]);
+    }
+    let data = Dataset::new(points.clone(), points, 2, 2);
+
+    let mut rng = SeededRng::new(3);
+    let mut layers: LinkedList<Box<dyn WeightedLayer>> = LinkedList::new();
+    layers.push_back(Box::new(DenseLayer::random_with(2, 16, &mut rng)));
+    layers.push_back(Box::new(HyperbolicLayer { size: 16 }));
+    layers.push_back(Box::new(DenseLayer::random_with(16, 2, &mut rng)));
+    layers.push_back(Box::new(ReparameterizeLayer::new(1, 0.1, RngProvider::seeded(4))));
+    layers.push_back(Box::new(DenseLayer::random_with(1, 16, &mut rng)));
+    layers.push_back(Box::new(HyperbolicLayer { size: 16 }));
+    layers.push_back(Box::new(DenseLayer::random_with(16, 2, &mut rng)));
+
+    let mut trainer = SGDTrainer::new(300, 0.05);
+    trainer.batch_size = Some(16);
+    trainer.reduction = Reduction::Mean;
+    trainer.shuffle = true;
+    trainer.rng = RngProvider::seeded(5);
+    let history = trainer.fit(&mut layers, &data, None);
+    let losses = history.train_loss();
+    assert!(losses[losses.len() - 1] < 0.1 * losses[0]);
+
+    // Codes drawn from the prior decode to points near the parabola
+    let decoder = layers.split_off(4);
+    let mut off = 0.0;
+    for z in normal_vector(100, &mut rng) {
+        let p = scarecrow::network::output(&decoder, &[z]);
+        off += (p[1] - p[0] * p[0]).abs() / 100.0;
+    }
+    assert!(off < 0.15, "decoded points are {} off the parabola", off);
+}